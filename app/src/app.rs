@@ -1,6 +1,6 @@
 use crate::tables::{MinutesPerPing, NewPing, Ping, Tag};
 use anyhow::{Context, Error, Result};
-use beeps_core::{merge::Merge, Document, NodeId, Replica};
+use beeps_core::{split::Split, Document, NodeId, Replica};
 use diesel::prelude::*;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use std::path::PathBuf;
@@ -21,7 +21,7 @@ impl From<&Document> for UiDocument {
             .iter()
             .map(|ping| PingWithTag {
                 ping: *ping,
-                tag: doc.get_tag(ping).cloned(),
+                tag: doc.get_tags(ping).next().cloned(),
             })
             .collect();
 
@@ -0,0 +1,128 @@
+use crate::error::Error;
+use chrono::{Duration, Utc};
+use rand::{distr::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+/// What an `account_tokens` row is good for. Both purposes share a table
+/// (and the same single-use, expiring shape) since they're otherwise
+/// identical: prove whoever holds the token controls this account's email.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// Redeeming the token lets the holder set a new password.
+    Reset,
+
+    /// Redeeming the token confirms the account's email.
+    Verify,
+}
+
+impl Purpose {
+    /// How long a token of this purpose stays valid. A reset token is
+    /// short-lived, since it's usually redeemed within minutes of being
+    /// emailed; a verification link is more likely to sit unread for a
+    /// while, so it gets much longer.
+    fn ttl(self) -> Duration {
+        match self {
+            Self::Reset => Duration::minutes(30),
+            Self::Verify => Duration::days(7),
+        }
+    }
+
+    /// The string stored in `account_tokens.purpose`.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Reset => "reset",
+            Self::Verify => "verify",
+        }
+    }
+}
+
+/// How many random characters to generate for a token. Comparable in
+/// entropy to `session::generate_refresh_token`, which this otherwise
+/// mirrors.
+const TOKEN_LEN: usize = 48;
+
+/// A freshly-issued token, ready to be emailed to the account.
+pub struct Issued {
+    /// The plaintext token. Shown to the caller exactly once; only its hash
+    /// is kept.
+    pub token: String,
+}
+
+/// Issue a fresh, single-use token for `purpose`, invalidating any
+/// outstanding token of the same purpose for this account so only the most
+/// recently requested link still works.
+///
+/// ## Errors
+///
+/// If the underlying queries fail.
+pub async fn issue(
+    conn: &mut PgConnection,
+    account_id: i64,
+    purpose: Purpose,
+) -> Result<Issued, Error> {
+    sqlx::query!(
+        "UPDATE account_tokens SET consumed_at = NOW() \
+        WHERE account_id = $1 AND purpose = $2 AND consumed_at IS NULL",
+        account_id,
+        purpose.as_str(),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let token = generate_token();
+
+    sqlx::query!(
+        "INSERT INTO account_tokens (account_id, purpose, token_hash, expires_at) \
+        VALUES ($1, $2, $3, $4)",
+        account_id,
+        purpose.as_str(),
+        hash(&token),
+        Utc::now() + purpose.ttl(),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(Issued { token })
+}
+
+/// Redeem a token for `purpose`, consuming it so it can't be used again.
+/// Returns the account it was issued to, or `None` if the token is
+/// unknown, already consumed, expired, or issued for a different purpose.
+///
+/// ## Errors
+///
+/// If the underlying queries fail.
+pub async fn redeem(
+    conn: &mut PgConnection,
+    token: &str,
+    purpose: Purpose,
+) -> Result<Option<i64>, Error> {
+    let found = sqlx::query!(
+        "UPDATE account_tokens SET consumed_at = NOW() \
+        WHERE token_hash = $1 AND purpose = $2 AND consumed_at IS NULL AND expires_at > NOW() \
+        RETURNING account_id",
+        hash(token),
+        purpose.as_str(),
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(found.map(|row| row.account_id))
+}
+
+/// Generate a fresh opaque token.
+fn generate_token() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// Hash a token the same way on issue and redemption, so the plaintext
+/// never sits in the database. High-entropy and single-use, like a refresh
+/// token, so an unsalted hash is fine; see `session::hash`.
+fn hash(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
@@ -0,0 +1,75 @@
+use beeps_core::sync::authz::{Req, Resp};
+use std::sync::Arc;
+
+/// An external hook consulted before a registration or push is accepted, so
+/// operators can enforce quotas, moderation, or allow-lists without forking
+/// the server.
+#[async_trait::async_trait]
+pub trait Authorizer: Send + Sync {
+    /// Decide whether to let a request through. Implementations should fail
+    /// closed, to a clear deny, rather than panic.
+    async fn authorize(&self, req: &Req) -> Resp;
+}
+
+/// The default authorizer: allows everything. This is what the server uses
+/// when no external authorization service is configured.
+#[derive(Debug, Clone, Default)]
+pub struct AllowAll;
+
+#[async_trait::async_trait]
+impl Authorizer for AllowAll {
+    async fn authorize(&self, _req: &Req) -> Resp {
+        Resp {
+            allow: true,
+            reason: None,
+        }
+    }
+}
+
+/// An authorizer backed by an external HTTP service. The service receives
+/// the `Req` as a JSON body and must respond with a `Resp`.
+#[derive(Debug, Clone)]
+pub struct HttpAuthorizer {
+    /// The HTTP client used to call out to the authorization service.
+    client: reqwest::Client,
+
+    /// Where the authorization service lives.
+    url: String,
+}
+
+impl HttpAuthorizer {
+    /// Point at an external authorization service.
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Authorizer for HttpAuthorizer {
+    async fn authorize(&self, req: &Req) -> Resp {
+        let result = self.client.post(&self.url).json(req).send().await;
+
+        match result {
+            Ok(response) => response.json::<Resp>().await.unwrap_or_else(|problem| {
+                tracing::error!(?problem, "couldn't parse authorizer response");
+                Resp {
+                    allow: false,
+                    reason: Some("authorizer returned an unreadable response".to_string()),
+                }
+            }),
+            Err(problem) => {
+                tracing::error!(?problem, "couldn't reach authorizer");
+                Resp {
+                    allow: false,
+                    reason: Some("couldn't reach the authorization service".to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// A shared handle to whichever `Authorizer` the server is configured with.
+pub type SharedAuthorizer = Arc<dyn Authorizer>;
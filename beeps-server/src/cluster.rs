@@ -0,0 +1,221 @@
+use crate::jwt;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// Maps a document to the node authoritative for its CRDT state and HLC
+/// sequencing, via rendezvous (highest random weight) hashing: unlike a
+/// fixed modulo split, adding or removing a node only reshuffles the
+/// documents that land on that node, not every document in the deployment.
+#[derive(Debug, Clone)]
+pub struct ClusterMetadata {
+    /// This node's own address, as it appears in `nodes`, so `is_local` can
+    /// check ownership without a round trip.
+    self_node: String,
+
+    /// Every node in the deployment, including `self_node`.
+    nodes: Vec<String>,
+}
+
+impl ClusterMetadata {
+    /// Describe a deployment of `nodes` (base URLs, e.g.
+    /// `http://beeps-b:3000`), with `self_node` being this node's own
+    /// address. `self_node` is added to `nodes` if it isn't already there.
+    #[must_use]
+    pub fn new(self_node: String, mut nodes: Vec<String>) -> Self {
+        if !nodes.contains(&self_node) {
+            nodes.push(self_node.clone());
+        }
+
+        Self { self_node, nodes }
+    }
+
+    /// The node authoritative for `document_id`.
+    #[must_use]
+    pub fn owner(&self, document_id: i64) -> &str {
+        self.nodes
+            .iter()
+            .max_by_key(|node| Self::weight(node, document_id))
+            .unwrap_or(&self.self_node)
+    }
+
+    /// Whether this node is authoritative for `document_id`.
+    #[must_use]
+    pub fn is_local(&self, document_id: i64) -> bool {
+        self.owner(document_id) == self.self_node
+    }
+
+    /// `document_id`'s rendezvous weight for a candidate node: the node
+    /// with the highest weight owns it.
+    ///
+    /// Every node in the deployment has to agree on this for routing (and
+    /// the CRDT/HLC authority that follows it) to stay correct, so we
+    /// can't use `std`'s `DefaultHasher` here: its docs explicitly disclaim
+    /// stability across Rust versions, meaning a rolling deploy — or even
+    /// just a toolchain bump on redeploy — could change every node's
+    /// output simultaneously or mid-rollout, splitting the cluster's
+    /// opinion of who owns a document. FNV-1a is small enough to hand-roll
+    /// and, unlike `DefaultHasher`, fully specified and fixed forever.
+    fn weight(node: &str, document_id: i64) -> u64 {
+        fnv1a(node.as_bytes().iter().chain(&document_id.to_be_bytes()))
+    }
+}
+
+/// FNV-1a's 64-bit offset basis and prime. See
+/// <http://www.isthe.com/chongo/tech/comp/fnv/>.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hash `bytes` with FNV-1a: unlike `DefaultHasher`, this algorithm is
+/// fully specified and will never change output across a Rust version.
+fn fnv1a<'a>(bytes: impl Iterator<Item = &'a u8>) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+
+    hash
+}
+
+/// Middleware that keeps a document's pushes on whichever node owns it.
+/// Peeks the document ID out of the request's bearer token (without fully
+/// verifying it; that still happens via `Claims` wherever the request ends
+/// up being handled) and, if another node owns it, proxies the request
+/// there and relays its response instead of running the local handler.
+///
+/// Meant for the push routes specifically, where the document ID is known
+/// up front from the token. `login` doesn't have a document ID until after
+/// it's looked the account up, so it handles cluster routing itself instead
+/// of going through this middleware.
+pub async fn route_pushes_to_owner(
+    State(cluster): State<Option<Arc<ClusterMetadata>>>,
+    State(http_client): State<reqwest::Client>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(cluster) = cluster else {
+        return next.run(request).await;
+    };
+
+    let Some(document_id) = bearer_document_id(&request) else {
+        return next.run(request).await;
+    };
+
+    if cluster.is_local(document_id) {
+        return next.run(request).await;
+    }
+
+    proxy(&http_client, cluster.owner(document_id), request).await
+}
+
+/// Peek at the document ID claimed by a request's bearer token, if it has
+/// one, without verifying the token's signature.
+fn bearer_document_id(request: &Request) -> Option<i64> {
+    let value = request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let token = value.strip_prefix("Bearer ")?;
+    jwt::peek_document_id(token)
+}
+
+/// Forward `request` to `owner` as-is and relay its response back verbatim.
+async fn proxy(http_client: &reqwest::Client, owner: &str, request: Request) -> Response {
+    let method = request.method().clone();
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let headers = request.headers().clone();
+
+    let body = match to_bytes(request.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(problem) => {
+            tracing::error!(?problem, "couldn't buffer request to proxy to owning node");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let url = format!("{}{path_and_query}", owner.trim_end_matches('/'));
+    let mut proxied = http_client.request(method, url).body(body);
+    for (name, value) in &headers {
+        // `reqwest` sets its own `host`; forwarding the original would
+        // point it at the wrong server.
+        if *name != header::HOST {
+            proxied = proxied.header(name, value);
+        }
+    }
+
+    match proxied.send().await {
+        Ok(resp) => {
+            let mut builder = Response::builder().status(resp.status());
+            if let Some(response_headers) = builder.headers_mut() {
+                *response_headers = resp.headers().clone();
+            }
+
+            let body = resp.bytes().await.unwrap_or_default();
+            builder
+                .body(Body::from(body))
+                .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+        Err(problem) => {
+            tracing::error!(?problem, owner, "couldn't reach owning node");
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_a_known_test_vector() {
+        // The FNV test suite's vector for the empty string.
+        assert_eq!(fnv1a([].iter()), 0xcbf2_9ce4_8422_2325);
+    }
+
+    #[test]
+    fn weight_is_fixed_across_calls() {
+        // Same inputs must always hash the same way, since every node in
+        // the cluster has to agree on ownership without talking to each
+        // other about it.
+        assert_eq!(
+            ClusterMetadata::weight("http://beeps-a:3000", 42),
+            ClusterMetadata::weight("http://beeps-a:3000", 42)
+        );
+    }
+
+    #[test]
+    fn owner_is_consistent_regardless_of_node_order() {
+        let forward = ClusterMetadata::new(
+            "http://beeps-a:3000".to_string(),
+            vec![
+                "http://beeps-a:3000".to_string(),
+                "http://beeps-b:3000".to_string(),
+                "http://beeps-c:3000".to_string(),
+            ],
+        );
+        let reversed = ClusterMetadata::new(
+            "http://beeps-a:3000".to_string(),
+            vec![
+                "http://beeps-c:3000".to_string(),
+                "http://beeps-b:3000".to_string(),
+                "http://beeps-a:3000".to_string(),
+            ],
+        );
+
+        for document_id in 0..50 {
+            assert_eq!(forward.owner(document_id), reversed.owner(document_id));
+        }
+    }
+}
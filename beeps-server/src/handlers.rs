@@ -1,10 +1,23 @@
 #![expect(clippy::missing_docs_in_private_items)]
 
+pub mod batch;
+pub mod chunked_pull;
+pub mod confirm_email;
+pub mod confirm_reset;
+pub mod enroll_totp;
 pub mod health;
 pub mod login;
+pub mod merkle;
+pub mod oauth;
+pub mod poll;
 pub mod pull;
 pub mod push;
+pub mod push_subscription;
+pub mod refresh;
 pub mod register;
+pub mod request_reset;
+pub mod session;
+pub mod subscribe;
 pub mod whoami;
 
 #[cfg(test)]
@@ -0,0 +1,407 @@
+use crate::authz::SharedAuthorizer;
+use crate::bail_if;
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::handlers::{poll, pull};
+use crate::jwt::Claims;
+use crate::merkle;
+use crate::metrics::Metrics;
+use crate::state::Subscriptions;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use beeps_core::document::Part;
+use beeps_core::orset::OrSetPart;
+use beeps_core::sync::authz::PartCounts;
+use beeps_core::sync::batch::{self, PartResult};
+use beeps_core::sync::{authz, version_vector};
+use beeps_core::{Document, Hlc};
+use chrono::{DateTime, Utc};
+use sqlx::types::Json as SqlJson;
+use sqlx::{query, Acquire, QueryBuilder};
+use std::collections::BTreeSet;
+
+/// Push a batch of parts and, in the same round trip, pull back everything
+/// the caller is still missing. Modeled on Garage's K2V batch API: a client
+/// that's been offline does one call instead of a push followed by a pull.
+///
+/// Unlike `push::handler`, a part stamped with another replica's clock is
+/// rejected (and reported back in `Resp::pushed`) rather than failing the
+/// whole request, so one bad `Tag` part doesn't cost the caller every other
+/// part in the same batch.
+#[tracing::instrument]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    State(subscriptions): State<Subscriptions>,
+    State(authorizer): State<SharedAuthorizer>,
+    State(metrics): State<Metrics>,
+    Json(req): Json<batch::Req>,
+) -> Result<Json<batch::Resp>, Error> {
+    bail_if!(
+        req.parts.len() > batch::MAX_PARTS,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        &format!(
+            "batch carried {} parts, more than the {} allowed in one request",
+            req.parts.len(),
+            batch::MAX_PARTS
+        )
+    );
+
+    let mut results = Vec::with_capacity(req.parts.len());
+    let mut document_for_subscribers = Document::default();
+    let mut minutes_per_pings = vec![];
+    let mut pings = vec![];
+    let mut tags = vec![];
+    let mut clocks = vec![];
+
+    for part in &req.parts {
+        // Validation: every clocked part must carry this replica's own
+        // server-assigned node ID, so a buggy or compromised client can't
+        // forge history under another replica's identity. Unlike
+        // `push::handler`, only the offending part is dropped.
+        if let Some(clock) = part.clock() {
+            if i32::from(*clock.node()) != claims.node_id {
+                results.push(PartResult::Rejected(
+                    "part was stamped with a clock for a different replica".to_string(),
+                ));
+                continue;
+            }
+            clocks.push(clock.clone());
+        }
+
+        document_for_subscribers.merge_part(part.clone());
+        match part.clone() {
+            Part::MinutesPerPing(value) => minutes_per_pings.push(value),
+            Part::Ping(ping) => pings.push(ping),
+            Part::Tag(tag) => tags.push(tag),
+        }
+        results.push(PartResult::Applied);
+    }
+
+    let ops_merged = minutes_per_pings.len() + pings.len() + tags.len();
+
+    // Validation: let an external authorizer veto the push, same as
+    // `push::handler`. This is a request-level decision, so (unlike the
+    // per-part clock check above) it still fails the whole batch.
+    let decision = authorizer
+        .authorize(&authz::Req {
+            email: Some(claims.sub.clone()),
+            document_id: Some(claims.document_id),
+            parts: Some(PartCounts {
+                minutes_per_ping: minutes_per_pings.len(),
+                pings: pings.len(),
+                tags: tags.len(),
+            }),
+        })
+        .await;
+    bail_if!(
+        !decision.allow,
+        StatusCode::FORBIDDEN,
+        &decision
+            .reason
+            .unwrap_or_else(|| "Push was not authorized".to_string())
+    );
+
+    let mut tx = conn.begin().await?;
+
+    if !minutes_per_pings.is_empty() {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO minutes_per_pings (document_id, minutes_per_ping, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(minutes_per_pings, |mut b, value| {
+            let clock = value.clock();
+
+            b.push_bind(claims.document_id)
+                .push_bind(i32::from(*value.value()))
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(clock));
+        });
+        // `minutes_per_ping` is an LWW-register: only overwrite the stored
+        // row if the incoming clock actually wins, matching `Lww::merge`.
+        query.push(
+            "ON CONFLICT (document_id) DO UPDATE SET \
+             minutes_per_ping = EXCLUDED.minutes_per_ping, \
+             timestamp = EXCLUDED.timestamp, \
+             counter = EXCLUDED.counter, \
+             node = EXCLUDED.node, \
+             merkle_key = EXCLUDED.merkle_key \
+             WHERE (EXCLUDED.timestamp, EXCLUDED.counter, EXCLUDED.node) > \
+             (minutes_per_pings.timestamp, minutes_per_pings.counter, minutes_per_pings.node)",
+        );
+        query.build().execute(&mut *tx).await?;
+    }
+
+    if !pings.is_empty() {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO pings (document_id, ping, observed, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(pings, |mut b, part| {
+            let clock = *part.id();
+            let (ping, observed) = ping_columns(&part);
+
+            b.push_bind(claims.document_id)
+                .push_bind(ping)
+                .push_bind(observed)
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(&clock));
+        });
+        // Each row is its own immutable OR-Set instance, so unlike
+        // `minutes_per_pings` there's no "newer write" to lose by ignoring a
+        // duplicate.
+        query.push("ON CONFLICT (document_id, timestamp, counter, node) DO NOTHING");
+        query.build().execute(&mut *tx).await?;
+    }
+
+    if !tags.is_empty() {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO tags (document_id, ping, tag, observed, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(tags, |mut b, (ping, part)| {
+            let clock = *part.id();
+            let (tag, observed) = tag_columns(&part);
+
+            b.push_bind(claims.document_id)
+                .push_bind(ping)
+                .push_bind(tag)
+                .push_bind(observed)
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(&clock));
+        });
+        // Each row is its own immutable OR-Set instance (an add or a remove
+        // operation), so unlike `minutes_per_pings` there's no "newer write"
+        // to lose by ignoring a duplicate.
+        query.push("ON CONFLICT (document_id, ping, timestamp, counter, node) DO NOTHING");
+        query.build().execute(&mut *tx).await?;
+    }
+
+    if ops_merged > 0 {
+        query!(
+            "UPDATE documents SET updated_at = NOW() WHERE id = $1",
+            claims.document_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Wake up anyone long-polling `/poll` for this document, same as
+        // `push::handler`.
+        query(&format!("NOTIFY {}", poll::channel(claims.document_id)))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    // Keep the Merkle anti-entropy tree in sync with what we just wrote; see
+    // `push::handler` for the staleness trade-off this accepts.
+    for clock in &clocks {
+        merkle::update(&mut conn, claims.document_id, clock).await?;
+    }
+
+    if ops_merged > 0 {
+        subscriptions
+            .notify(claims.document_id, document_for_subscribers)
+            .await;
+    }
+
+    metrics.document_synced(ops_merged as u64);
+
+    let mut vector = version_vector::VersionVector::new();
+    for clock in &clocks {
+        version_vector::observe(&mut vector, clock);
+    }
+
+    let (parts, pulled_vector, more) =
+        pull::parts_since(&mut conn, claims.document_id, &req.vector, Some(pull::PAGE_SIZE)).await?;
+    version_vector::merge(&mut vector, &pulled_vector);
+
+    Ok(Json(batch::Resp {
+        pushed: results,
+        parts,
+        vector,
+        more,
+    }))
+}
+
+/// Split an `OrSetPart` into the `tag`/`observed` columns `tags` stores it
+/// under. See `push::tag_columns`, which this mirrors.
+fn tag_columns(part: &OrSetPart<String>) -> (Option<String>, Option<SqlJson<BTreeSet<Hlc>>>) {
+    match part {
+        OrSetPart::Add(_, value) => (Some(value.clone()), None),
+        OrSetPart::Remove(_, observed) => (None, Some(SqlJson(observed.clone()))),
+    }
+}
+
+/// Split an `OrSetPart` into the `ping`/`observed` columns `pings` stores it
+/// under. See `push::ping_columns`, which this mirrors.
+fn ping_columns(
+    part: &OrSetPart<DateTime<Utc>>,
+) -> (Option<DateTime<Utc>>, Option<SqlJson<BTreeSet<Hlc>>>) {
+    match part {
+        OrSetPart::Add(_, value) => (Some(*value), None),
+        OrSetPart::Remove(_, observed) => (None, Some(SqlJson(observed.clone()))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use beeps_core::split::Split;
+    use beeps_core::NodeId;
+    use chrono::Utc;
+    use sqlx::{Pool, Postgres};
+
+    fn authorizer() -> SharedAuthorizer {
+        std::sync::Arc::new(crate::authz::AllowAll)
+    }
+
+    fn metrics(pool: &Pool<Postgres>) -> Metrics {
+        Metrics::new(pool.clone(), None).unwrap()
+    }
+
+    fn req(document_id: i64, document: Document) -> batch::Req {
+        batch::Req {
+            document_id,
+            parts: document.split().collect(),
+            vector: version_vector::VersionVector::new(),
+        }
+    }
+
+    /// Fold a batch response's `parts` back into a whole `Document`, for
+    /// assertions that want to compare against one.
+    fn merged(resp: &batch::Resp) -> Document {
+        let mut document = Document::default();
+        for part in resp.parts.clone() {
+            document.merge_part(part);
+        }
+        document
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_pushes_and_pulls_in_one_round_trip(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut document = Document::default();
+        document.set_minutes_per_ping(60, Hlc::new(NodeId::min()));
+
+        let Json(resp) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document.clone())),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(resp.pushed, vec![PartResult::Applied]);
+        assert_eq!(
+            *merged(&resp).minutes_per_ping.value(),
+            *document.minutes_per_ping.value()
+        );
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_rejects_a_part_with_a_foreign_clock_without_failing_the_batch(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let node = NodeId::try_from(doc.node_id).unwrap();
+        let mut document = Document::default();
+        document.set_minutes_per_ping(60, Hlc::new(NodeId::max()));
+        let now = Utc::now();
+        document.add_ping(now, Hlc::new(node));
+
+        let Json(resp) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            resp.pushed,
+            vec![
+                PartResult::Rejected(
+                    "part was stamped with a clock for a different replica".to_string()
+                ),
+                PartResult::Applied,
+            ]
+        );
+        assert!(merged(&resp).pings.contains(&now));
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_rejects_a_batch_over_the_size_limit(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut clock = Hlc::new(NodeId::min());
+        let mut oversized = req(doc.document_id, Document::default());
+        oversized.parts = (0..=batch::MAX_PARTS)
+            .map(|i| {
+                clock = clock.next();
+                Part::Ping(OrSetPart::Add(
+                    clock,
+                    Utc::now() + chrono::Duration::seconds(i as i64),
+                ))
+            })
+            .collect();
+
+        let res = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(oversized),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(res.0, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_batch_apply_matches_sequential_merge_part(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let node = NodeId::try_from(doc.node_id).unwrap();
+        let mut document = Document::default();
+        document.set_minutes_per_ping(60, Hlc::new(node));
+        let now = Utc::now();
+        document.add_ping(now, Hlc::new(node));
+        document.add_tag(now, "one".to_string(), Hlc::new(node).next());
+        document.add_tag(now, "two".to_string(), Hlc::new(node).next().next());
+
+        let mut by_merge_part = Document::default();
+        for part in document.clone().split() {
+            by_merge_part.merge_part(part);
+        }
+
+        let Json(resp) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        assert!(resp.pushed.iter().all(|result| *result == PartResult::Applied));
+        assert_eq!(merged(&resp), by_merge_part);
+    }
+}
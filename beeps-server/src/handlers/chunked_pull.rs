@@ -0,0 +1,178 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::handlers::pull;
+use crate::jwt::Claims;
+use axum::Json;
+use beeps_core::sync::chunked_pull::{Chunk, Req, Resp};
+use beeps_core::sync::version_vector::VersionVector;
+use beeps_core::{chunking, split::Split, Document};
+
+/// Content-defined-chunked variant of `pull`: reconstruct the document as it
+/// stands right now, split it into chunks the same way `chunking::chunk_split`
+/// always does, and send back only the bytes of whichever chunks the caller
+/// didn't already report having in `req.known_hashes`.
+///
+/// ## Errors
+///
+/// If reconstructing the document or querying the database fails.
+#[tracing::instrument]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    Json(req): Json<Req>,
+) -> Result<Json<Resp>, Error> {
+    let (parts, _, _) =
+        pull::parts_since(&mut conn, claims.document_id, &VersionVector::new(), None).await?;
+    let mut document = Document::default();
+    for part in parts {
+        document.merge_part(part);
+    }
+
+    let chunks = chunking::chunk_split(document)
+        .into_iter()
+        .map(|chunk| Chunk {
+            hash: chunk.hash,
+            bytes: (!req.known_hashes.contains(&chunk.hash)).then_some(chunk.bytes),
+        })
+        .collect();
+
+    Ok(Json(Resp { chunks }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        handlers::{push, test::TestDoc},
+        state::Subscriptions,
+    };
+    use axum::extract::State;
+    use beeps_core::{sync::version_vector, Hlc, NodeId};
+    use std::collections::HashMap;
+
+    fn authorizer() -> crate::authz::SharedAuthorizer {
+        std::sync::Arc::new(crate::authz::AllowAll)
+    }
+
+    fn metrics(pool: &sqlx::Pool<sqlx::Postgres>) -> crate::metrics::Metrics {
+        crate::metrics::Metrics::new(pool.clone(), None).unwrap()
+    }
+
+    /// Wrap a document up as a push request against the given document ID.
+    fn push_req(document_id: i64, document: Document) -> beeps_core::sync::push::Req {
+        beeps_core::sync::push::Req {
+            document_id,
+            parts: document.split().collect(),
+            vector: version_vector::VersionVector::new(),
+        }
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_reassembling_every_chunk_reproduces_the_document(
+        pool: sqlx::Pool<sqlx::Postgres>,
+    ) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut document = Document::default();
+        document.set_minutes_per_ping(90, Hlc::new(NodeId::min()));
+        document.add_ping(chrono::Utc::now(), Hlc::new(NodeId::min()));
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let Json(resp) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(Req {
+                document_id: doc.document_id,
+                known_hashes: std::collections::BTreeSet::new(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert!(!resp.chunks.is_empty());
+        assert!(resp.chunks.iter().all(|chunk| chunk.bytes.is_some()));
+
+        let bytes = resp.reassemble(&HashMap::new()).unwrap();
+        let parts: Vec<beeps_core::document::Part> = bytes
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        let mut rebuilt = Document::default();
+        for part in parts {
+            rebuilt.merge_part(part);
+        }
+
+        let (stored_parts, _, _) = pull::parts_since(
+            &mut pool.acquire().await.unwrap(),
+            doc.document_id,
+            &VersionVector::new(),
+            None,
+        )
+        .await
+        .unwrap();
+        let mut stored = Document::default();
+        for part in stored_parts {
+            stored.merge_part(part);
+        }
+
+        assert_eq!(rebuilt, stored);
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_a_known_hash_comes_back_without_bytes(pool: sqlx::Pool<sqlx::Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut document = Document::default();
+        document.set_minutes_per_ping(90, Hlc::new(NodeId::min()));
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let Json(first) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(Req {
+                document_id: doc.document_id,
+                known_hashes: std::collections::BTreeSet::new(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let known_hashes = first.chunks.iter().map(|chunk| chunk.hash).collect();
+
+        let Json(second) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(Req {
+                document_id: doc.document_id,
+                known_hashes,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(second.chunks.len(), first.chunks.len());
+        assert!(second.chunks.iter().all(|chunk| chunk.bytes.is_none()));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::account_tokens::{self, Purpose};
+use crate::bail;
+use crate::conn::Conn;
+use crate::error::Error;
+use axum::Json;
+use beeps_core::sync::reset::{ConfirmEmailReq, ConfirmEmailResp};
+use sqlx::Acquire;
+
+#[tracing::instrument(skip(conn, req))]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    Json(req): Json<ConfirmEmailReq>,
+) -> Result<Json<ConfirmEmailResp>, Error> {
+    let mut tx = conn.begin().await?;
+
+    let Some(account_id) = account_tokens::redeem(&mut tx, &req.token, Purpose::Verify).await?
+    else {
+        bail!("This verification link is invalid or has expired");
+    };
+
+    sqlx::query!(
+        "UPDATE accounts SET email_verified_at = NOW() WHERE id = $1",
+        account_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ConfirmEmailResp {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use sqlx::Pool;
+
+    #[test_log::test(sqlx::test)]
+    async fn test_success_marks_verified(pool: Pool<sqlx::Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let account = sqlx::query!("SELECT id FROM accounts WHERE email = $1", doc.email)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let issued = account_tokens::issue(
+            &mut pool.acquire().await.unwrap(),
+            account.id,
+            Purpose::Verify,
+        )
+        .await
+        .unwrap();
+
+        handler(
+            Conn(pool.acquire().await.unwrap()),
+            Json(ConfirmEmailReq {
+                token: issued.token,
+            }),
+        )
+        .await
+        .unwrap();
+
+        let updated = sqlx::query!(
+            "SELECT email_verified_at FROM accounts WHERE id = $1",
+            account.id
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert!(updated.email_verified_at.is_some());
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_bad_token(mut conn: sqlx::pool::PoolConnection<sqlx::Postgres>) {
+        TestDoc::create(&mut conn).await;
+
+        let (status, message) = handler(
+            Conn(conn),
+            Json(ConfirmEmailReq {
+                token: "not-a-real-token".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(message, "This verification link is invalid or has expired");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::account_tokens::{self, Purpose};
+use crate::bail;
+use crate::conn::Conn;
+use crate::error::Error;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use axum::Json;
+use beeps_core::sync::reset::{ConfirmReq, ConfirmResp};
+use sqlx::Acquire;
+
+#[tracing::instrument(skip(conn, req))]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    Json(req): Json<ConfirmReq>,
+) -> Result<Json<ConfirmResp>, Error> {
+    let mut tx = conn.begin().await?;
+
+    let Some(account_id) = account_tokens::redeem(&mut tx, &req.token, Purpose::Reset).await?
+    else {
+        bail!("This reset link is invalid or has expired");
+    };
+
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(req.new_password.as_bytes(), &salt)?
+        .to_string();
+
+    sqlx::query!(
+        "UPDATE accounts SET password = $1 WHERE id = $2",
+        hash,
+        account_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ConfirmResp {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use argon2::{PasswordHash, PasswordVerifier};
+    use sqlx::{Pool, Postgres};
+
+    #[test_log::test(sqlx::test)]
+    async fn test_success_changes_password(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let account = sqlx::query!("SELECT id FROM accounts WHERE email = $1", doc.email)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let issued = account_tokens::issue(
+            &mut pool.acquire().await.unwrap(),
+            account.id,
+            Purpose::Reset,
+        )
+        .await
+        .unwrap();
+
+        handler(
+            Conn(pool.acquire().await.unwrap()),
+            Json(ConfirmReq {
+                token: issued.token,
+                new_password: "new password".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let updated = sqlx::query!("SELECT password FROM accounts WHERE id = $1", account.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let hash = PasswordHash::new(&updated.password).unwrap();
+        assert!(Argon2::default()
+            .verify_password(b"new password", &hash)
+            .is_ok());
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_bad_token(mut conn: sqlx::pool::PoolConnection<Postgres>) {
+        TestDoc::create(&mut conn).await;
+
+        let (status, message) = handler(
+            Conn(conn),
+            Json(ConfirmReq {
+                token: "not-a-real-token".to_string(),
+                new_password: "new password".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(message, "This reset link is invalid or has expired");
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_token_is_single_use(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let account = sqlx::query!("SELECT id FROM accounts WHERE email = $1", doc.email)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let issued = account_tokens::issue(
+            &mut pool.acquire().await.unwrap(),
+            account.id,
+            Purpose::Reset,
+        )
+        .await
+        .unwrap();
+
+        handler(
+            Conn(pool.acquire().await.unwrap()),
+            Json(ConfirmReq {
+                token: issued.token.clone(),
+                new_password: "new password".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let (status, message) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            Json(ConfirmReq {
+                token: issued.token,
+                new_password: "another password".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(message, "This reset link is invalid or has expired");
+    }
+}
@@ -0,0 +1,41 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::jwt::Claims;
+use crate::totp;
+use axum::Json;
+use beeps_core::sync::totp::EnrollResp;
+
+#[tracing::instrument(skip(conn, claims), fields(claims.sub = %claims.sub))]
+pub async fn handler(claims: Claims, Conn(mut conn): Conn) -> Result<Json<EnrollResp>, Error> {
+    let secret = totp::Secret::generate();
+
+    sqlx::query!(
+        "UPDATE accounts SET totp_secret = $1 WHERE email = $2",
+        secret.to_base32(),
+        claims.sub.clone(),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Json(EnrollResp {
+        secret: secret.to_base32(),
+        uri: secret.otpauth_uri("beeps", &claims.sub),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use sqlx::{pool::PoolConnection, Postgres};
+
+    #[test_log::test(sqlx::test)]
+    async fn test_success(mut conn: PoolConnection<Postgres>) {
+        let doc = TestDoc::create(&mut conn).await;
+
+        let Json(resp) = handler(doc.claims(), Conn(conn)).await.unwrap();
+
+        assert!(resp.uri.contains(&resp.secret));
+        assert!(totp::Secret::from_base32(&resp.secret).is_some());
+    }
+}
@@ -1,26 +1,57 @@
 use crate::bail;
+use crate::bail_if;
+use crate::cluster::ClusterMetadata;
 use crate::conn::Conn;
 use crate::error::Error;
 use crate::jwt;
+use crate::session;
+use crate::state::{LoginRateLimiterByEmail, LoginRateLimiterByIp};
+use crate::totp;
 use argon2::{password_hash, Argon2, PasswordHash, PasswordVerifier};
-use axum::{extract::State, Json};
-use beeps_core::sync::login::{Req, Resp};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    Json,
+};
+use beeps_core::sync::login::{self, Req, Resp};
 use jsonwebtoken::EncodingKey;
-use sqlx::query;
+use sqlx::{query, Acquire};
+use std::net::SocketAddr;
+use std::sync::Arc;
 
 /// This should be the same for both missing accounts and incorrect passwords so
 /// as not to give additional information about what accounts exist to someone
 /// probing the system.
 static BAD_LOGIN_MESSAGE: &str = "incorrect email or password";
 
-#[tracing::instrument(skip(conn, req, encoding_key), fields(req.email = %req.email))]
+#[expect(clippy::too_many_arguments)]
+#[tracing::instrument(
+    skip(conn, req, encoding_key, http_client, rate_limiter_by_email, rate_limiter_by_ip),
+    fields(req.email = %req.email)
+)]
 pub async fn handler(
     Conn(mut conn): Conn,
     State(encoding_key): State<EncodingKey>,
+    State(cluster): State<Option<Arc<ClusterMetadata>>>,
+    State(http_client): State<reqwest::Client>,
+    State(LoginRateLimiterByEmail(rate_limiter_by_email)): State<LoginRateLimiterByEmail>,
+    State(LoginRateLimiterByIp(rate_limiter_by_ip)): State<LoginRateLimiterByIp>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Json(req): Json<Req>,
 ) -> Result<Json<Resp>, Error> {
+    bail_if!(
+        !rate_limiter_by_email.check(&req.email).await,
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too many login attempts, try again later"
+    );
+    bail_if!(
+        !rate_limiter_by_ip.check(&addr.ip().to_string()).await,
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too many login attempts, try again later"
+    );
+
     let account = sqlx::query!(
-        "SELECT id, email, password FROM accounts WHERE email = $1 LIMIT 1",
+        "SELECT id, email, password, totp_secret, totp_last_step, email_verified_at FROM accounts WHERE email = $1 LIMIT 1",
         req.email
     )
     .fetch_optional(&mut *conn)
@@ -38,6 +69,27 @@ pub async fn handler(
         return Err(Error::Internal);
     }
 
+    // The password's good, but if two-factor is enabled we still need a
+    // valid TOTP code before handing out a token.
+    let mut accepted_totp_step = None;
+    if let Some(secret) = &account.totp_secret {
+        let secret = totp::Secret::from_base32(secret).ok_or(Error::Internal)?;
+        let last_accepted_step = account.totp_last_step.map(|step| step as u64);
+
+        accepted_totp_step = req.totp.as_deref().and_then(|code| {
+            secret.verify(code, std::time::SystemTime::now(), last_accepted_step)
+        });
+
+        if accepted_totp_step.is_none() {
+            return Ok(Json(Resp::TotpRequired));
+        }
+    }
+
+    bail_if!(
+        account.email_verified_at.is_none(),
+        "Please verify your email before logging in"
+    );
+
     let document = query!(
         "SELECT id FROM documents WHERE owner_id = $1 LIMIT 1",
         account.id,
@@ -45,11 +97,90 @@ pub async fn handler(
     .fetch_one(&mut *conn)
     .await?;
 
-    Ok(Json(Resp {
-        jwt: jwt::issue(&encoding_key, &account.email, document.id)?,
+    // This node only learns which document an account owns after looking
+    // the account up locally, so (unlike push/pull, which get it straight
+    // from the token) there's no way to route the request to its owner
+    // before doing at least this much work. Once we know, hand the whole
+    // request off if we're not it: the replica/session rows login writes
+    // below need to live alongside the document's CRDT state.
+    if let Some(cluster) = &cluster {
+        if !cluster.is_local(document.id) {
+            return proxy_login(&http_client, cluster.owner(document.id), &req).await;
+        }
+    }
+
+    let mut tx = conn.begin().await?;
+
+    if let Some(step) = accepted_totp_step {
+        sqlx::query!(
+            "UPDATE accounts SET totp_last_step = $1 WHERE id = $2",
+            step as i64,
+            account.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Every login is a new device coming online, so it gets its own
+    // server-issued replica node ID rather than reusing one that might
+    // still be in use elsewhere.
+    let replica = query!(
+        "INSERT INTO replicas (account_id) VALUES ($1) RETURNING id, node_id",
+        account.id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let created =
+        session::create(&mut tx, account.id, replica.id, req.device_label.as_deref()).await?;
+
+    tx.commit().await?;
+
+    let (jwt, expires_at) = jwt::issue(
+        &encoding_key,
+        &account.email,
+        document.id,
+        replica.node_id,
+        created.id,
+    )?;
+
+    Ok(Json(Resp::Ok {
+        jwt,
+        expires_at,
+        refresh_token: created.refresh_token,
+        document_id: document.id,
     }))
 }
 
+/// Forward a login request to the node that owns the account's document,
+/// and relay its response back verbatim.
+async fn proxy_login(
+    http_client: &reqwest::Client,
+    owner: &str,
+    req: &Req,
+) -> Result<Json<Resp>, Error> {
+    let url = format!("{}{}", owner.trim_end_matches('/'), login::PATH);
+
+    let resp = http_client
+        .post(url)
+        .json(req)
+        .send()
+        .await
+        .map_err(|problem| {
+            tracing::error!(?problem, owner, "couldn't reach owning node for login");
+            Error::Internal
+        })?;
+
+    resp.json().await.map_err(|problem| {
+        tracing::error!(
+            ?problem,
+            owner,
+            "owning node sent an unreadable login response"
+        );
+        Error::Internal
+    })
+}
+
 #[cfg(test)]
 mod test {
     use axum::http::StatusCode;
@@ -58,6 +189,7 @@ mod test {
     use sqlx::{pool::PoolConnection, Postgres};
 
     use crate::handlers::test::TestDoc;
+    use crate::rate_limit::RateLimiter;
 
     use super::*;
 
@@ -69,6 +201,16 @@ mod test {
         DecodingKey::from_secret(b"secret".as_ref())
     }
 
+    /// A rate limiter generous enough that no test trips it by accident; the
+    /// limiter's own behavior is covered by `rate_limit`'s tests.
+    fn rate_limiter() -> RateLimiter {
+        RateLimiter::new(1000, chrono::Duration::minutes(5))
+    }
+
+    fn addr() -> std::net::SocketAddr {
+        std::net::SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
     #[test_log::test(sqlx::test)]
     async fn test_success(mut conn: PoolConnection<Postgres>) {
         let doc = TestDoc::create(&mut conn).await;
@@ -76,17 +218,27 @@ mod test {
         let resp = handler(
             Conn(conn),
             State(encoding_key()),
+            State(None),
+            State(reqwest::Client::new()),
+            State(LoginRateLimiterByEmail(rate_limiter())),
+            State(LoginRateLimiterByIp(rate_limiter())),
+            ConnectInfo(addr()),
             Json(Req {
                 email: doc.email.clone(),
                 password: doc.password.clone(),
+                totp: None,
+                device_label: None,
             }),
         )
         .await
         .unwrap();
 
+        let Resp::Ok { jwt, .. } = resp else {
+            panic!("expected a successful login, got {resp:?}");
+        };
+
         let token =
-            jsonwebtoken::decode::<Claims>(&resp.jwt, &decoding_key(), &Validation::default())
-                .unwrap();
+            jsonwebtoken::decode::<Claims>(&jwt, &decoding_key(), &Validation::default()).unwrap();
 
         assert_eq!(token.claims.sub, doc.email);
     }
@@ -98,9 +250,16 @@ mod test {
         let resp = handler(
             Conn(conn),
             State(encoding_key()),
+            State(None),
+            State(reqwest::Client::new()),
+            State(LoginRateLimiterByEmail(rate_limiter())),
+            State(LoginRateLimiterByIp(rate_limiter())),
+            ConnectInfo(addr()),
             Json(Req {
                 email: "honk@example.com".to_string(),
                 password: doc.password.clone(),
+                totp: None,
+                device_label: None,
             }),
         )
         .await
@@ -120,9 +279,16 @@ mod test {
         let resp = handler(
             Conn(conn),
             State(encoding_key()),
+            State(None),
+            State(reqwest::Client::new()),
+            State(LoginRateLimiterByEmail(rate_limiter())),
+            State(LoginRateLimiterByIp(rate_limiter())),
+            ConnectInfo(addr()),
             Json(Req {
                 email: doc.email.clone(),
                 password: "bad password".to_string(),
+                totp: None,
+                device_label: None,
             }),
         )
         .await
@@ -134,4 +300,76 @@ mod test {
             (StatusCode::BAD_REQUEST, BAD_LOGIN_MESSAGE.to_string())
         );
     }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_totp_required(mut conn: PoolConnection<Postgres>) {
+        let doc = TestDoc::create(&mut conn).await;
+        let secret = crate::totp::Secret::generate();
+
+        sqlx::query!(
+            "UPDATE accounts SET totp_secret = $1 WHERE email = $2",
+            secret.to_base32(),
+            doc.email,
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+        let resp = handler(
+            Conn(conn),
+            State(encoding_key()),
+            State(None),
+            State(reqwest::Client::new()),
+            State(LoginRateLimiterByEmail(rate_limiter())),
+            State(LoginRateLimiterByIp(rate_limiter())),
+            ConnectInfo(addr()),
+            Json(Req {
+                email: doc.email.clone(),
+                password: doc.password.clone(),
+                totp: None,
+                device_label: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(resp, Resp::TotpRequired));
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_totp_success(mut conn: PoolConnection<Postgres>) {
+        let doc = TestDoc::create(&mut conn).await;
+        let secret = crate::totp::Secret::generate();
+
+        sqlx::query!(
+            "UPDATE accounts SET totp_secret = $1 WHERE email = $2",
+            secret.to_base32(),
+            doc.email,
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+        let code = secret.current_code();
+
+        let resp = handler(
+            Conn(conn),
+            State(encoding_key()),
+            State(None),
+            State(reqwest::Client::new()),
+            State(LoginRateLimiterByEmail(rate_limiter())),
+            State(LoginRateLimiterByIp(rate_limiter())),
+            ConnectInfo(addr()),
+            Json(Req {
+                email: doc.email.clone(),
+                password: doc.password.clone(),
+                totp: Some(code),
+                device_label: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(resp, Resp::Ok { .. }));
+    }
 }
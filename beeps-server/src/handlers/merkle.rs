@@ -0,0 +1,148 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::jwt::Claims;
+use crate::merkle;
+use axum::Json;
+use beeps_core::sync::merkle::{Req, Resp};
+
+#[tracing::instrument]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    Json(req): Json<Req>,
+) -> Result<Json<Resp>, Error> {
+    let stored = merkle::hash_at(&mut conn, claims.document_id, &req.path).await?;
+
+    if stored.as_ref() == Some(&req.expected_hash) {
+        return Ok(Json(Resp::Identical));
+    }
+
+    if merkle::is_leaf(&req.path) {
+        let leaves = merkle::leaves(&mut conn, claims.document_id, &req.path).await?;
+        return Ok(Json(Resp::Leaves(leaves)));
+    }
+
+    let children = merkle::children(&mut conn, claims.document_id, &req.path).await?;
+    Ok(Json(Resp::Children(children)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        handlers::{push, test::TestDoc},
+        state::Subscriptions,
+    };
+    use axum::extract::State;
+    use beeps_core::{sync::version_vector, Document, Hlc, NodeId};
+
+    fn authorizer() -> crate::authz::SharedAuthorizer {
+        std::sync::Arc::new(crate::authz::AllowAll)
+    }
+
+    fn metrics(pool: &sqlx::Pool<sqlx::Postgres>) -> crate::metrics::Metrics {
+        crate::metrics::Metrics::new(pool.clone(), None).unwrap()
+    }
+
+    /// Wrap a document up as a push request against the given document ID.
+    fn push_req(document_id: i64, document: Document) -> beeps_core::sync::push::Req {
+        beeps_core::sync::push::Req {
+            document_id,
+            parts: document.split().collect(),
+            vector: version_vector::VersionVector::new(),
+        }
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_root_is_identical_once_the_right_hash_is_known(pool: sqlx::Pool<sqlx::Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut document = Document::default();
+        document.set_minutes_per_ping(90, Hlc::new(NodeId::min()));
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let Json(wrong) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(Req {
+                document_id: doc.document_id,
+                path: String::new(),
+                expected_hash: vec![],
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(!matches!(wrong, Resp::Identical));
+
+        let root_hash = merkle::hash_at(&mut pool.acquire().await.unwrap(), doc.document_id, "")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let Json(right) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(Req {
+                document_id: doc.document_id,
+                path: String::new(),
+                expected_hash: root_hash,
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(right, Resp::Identical));
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_a_mismatched_leaf_reveals_its_clocks(pool: sqlx::Pool<sqlx::Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let clock = Hlc::new(NodeId::min());
+        let mut document = Document::default();
+        document.set_minutes_per_ping(90, clock.clone());
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let leaf_path = merkle::merkle_key(&clock)
+            .iter()
+            .take(2)
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        let Json(resp) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(Req {
+                document_id: doc.document_id,
+                path: leaf_path,
+                expected_hash: vec![],
+            }),
+        )
+        .await
+        .unwrap();
+
+        match resp {
+            Resp::Leaves(clocks) => assert_eq!(clocks, vec![clock]),
+            other => panic!("expected Leaves, got {other:?}"),
+        }
+    }
+}
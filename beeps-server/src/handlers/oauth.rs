@@ -0,0 +1,198 @@
+use crate::bail_if;
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::jwt;
+use crate::oidc::{self, OidcConfig};
+use crate::session;
+use crate::state::{AllowRegistration, OAuthFlows};
+use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+use argon2::Argon2;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use beeps_core::sync::oauth::{CallbackReq, CallbackResp, StartReq, StartResp};
+use jsonwebtoken::EncodingKey;
+use rand::{distr::Alphanumeric, Rng};
+use sqlx::Acquire;
+use std::sync::Arc;
+
+/// Build the provider's authorize URL for a fresh login attempt, stashing
+/// the PKCE verifier (and the device label, if any) so the callback can
+/// redeem them.
+#[tracing::instrument(skip(oidc, flows, req))]
+pub async fn start(
+    State(oidc): State<Option<Arc<OidcConfig>>>,
+    State(flows): State<OAuthFlows>,
+    Query(req): Query<StartReq>,
+) -> Result<Json<StartResp>, Error> {
+    let oidc = oidc.ok_or_else(sso_not_configured)?;
+
+    let state = oidc::random_state();
+    let (authorize_url, code_verifier) = oidc.authorize_url(&state);
+    flows
+        .issue(&state, &code_verifier, req.device_label.as_deref())
+        .await;
+
+    Ok(Json(StartResp { authorize_url }))
+}
+
+/// Finish a login attempt: exchange the code for the provider's tokens,
+/// verify the ID token, and map the verified email onto a local account
+/// (auto-provisioning it if registration is open).
+#[tracing::instrument(skip(conn, oidc, flows, http_client, encoding_key, req), fields(req.state = %req.state))]
+pub async fn callback(
+    Conn(mut conn): Conn,
+    State(oidc): State<Option<Arc<OidcConfig>>>,
+    State(flows): State<OAuthFlows>,
+    State(AllowRegistration(allow_registration)): State<AllowRegistration>,
+    State(http_client): State<reqwest::Client>,
+    State(encoding_key): State<EncodingKey>,
+    Query(req): Query<CallbackReq>,
+) -> Result<Json<CallbackResp>, Error> {
+    let oidc = oidc.ok_or_else(sso_not_configured)?;
+
+    let (code_verifier, device_label) = flows
+        .redeem(&req.state)
+        .await
+        .ok_or_else(|| Error::custom("this login attempt is missing or has expired"))?;
+
+    let email = oidc
+        .verify_callback(&http_client, &req.code, &code_verifier)
+        .await
+        .map_err(|problem| {
+            tracing::warn!(?problem, "OIDC callback failed");
+            Error::custom("could not complete single sign-on login")
+        })?;
+
+    let domain = email.rsplit_once('@').map(|(_, domain)| domain);
+    bail_if!(
+        !oidc.allowed_email_domains.is_empty()
+            && !domain.is_some_and(|domain| oidc
+                .allowed_email_domains
+                .iter()
+                .any(|allowed| allowed == domain)),
+        StatusCode::FORBIDDEN,
+        "this email domain is not allowed to log in via single sign-on"
+    );
+
+    let mut tx = conn.begin().await?;
+
+    let account = sqlx::query!("SELECT id FROM accounts WHERE email = $1 LIMIT 1", email)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let account_id = match account {
+        Some(account) => account.id,
+        None => {
+            bail_if!(
+                !allow_registration,
+                StatusCode::FORBIDDEN,
+                "no account exists for this email, and registration is closed"
+            );
+
+            // Nobody logs into this account with a password, so hash a
+            // random one nobody could ever guess rather than loosening the
+            // column to allow NULL.
+            let argon2 = Argon2::default();
+            let salt = SaltString::generate(&mut OsRng);
+            let unusable_password: String = rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+
+            // The OIDC provider already vouched for this address as part of
+            // the sign-in, so there's no separate email to confirm here.
+            let created = sqlx::query!(
+                "INSERT INTO accounts (email, password, email_verified_at) VALUES ($1, $2, NOW()) RETURNING id",
+                email,
+                argon2
+                    .hash_password(unusable_password.as_bytes(), &salt)?
+                    .to_string(),
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            created.id
+        }
+    };
+
+    let document_id = match sqlx::query!(
+        "SELECT id FROM documents WHERE owner_id = $1 LIMIT 1",
+        account_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        Some(document) => document.id,
+        // Password registration doesn't create a document either; see the
+        // `// TODO` in `register::handler`.
+        None => 0,
+    };
+
+    // Every login is a new device coming online, so it gets its own
+    // server-issued replica node ID rather than reusing one that might
+    // still be in use elsewhere.
+    let replica = sqlx::query!(
+        "INSERT INTO replicas (account_id) VALUES ($1) RETURNING id, node_id",
+        account_id,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let created = session::create(&mut tx, account_id, replica.id, device_label.as_deref()).await?;
+
+    tx.commit().await?;
+
+    let (jwt, expires_at) = jwt::issue(
+        &encoding_key,
+        &email,
+        document_id,
+        replica.node_id,
+        created.id,
+    )?;
+
+    Ok(Json(CallbackResp {
+        jwt,
+        expires_at,
+        refresh_token: created.refresh_token,
+    }))
+}
+
+/// The error returned when a caller hits the OIDC routes but the server
+/// wasn't started with `--oidc-issuer`.
+fn sso_not_configured() -> Error {
+    Error::custom_with_status(
+        "single sign-on is not configured on this server",
+        StatusCode::NOT_FOUND,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The happy paths need a real (or mocked) OIDC provider to exercise
+    // discovery, code exchange, and ID token verification, which nothing
+    // else in this server's test suite does either (see `authz::HttpAuthorizer`).
+
+    #[test_log::test(tokio::test)]
+    async fn test_start_without_oidc_configured() {
+        let res = start(
+            State(None),
+            State(OAuthFlows::default()),
+            Query(StartReq::default()),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(
+            res,
+            (
+                StatusCode::NOT_FOUND,
+                "single sign-on is not configured on this server".to_string()
+            )
+        );
+    }
+}
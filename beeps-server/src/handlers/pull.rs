@@ -1,59 +1,189 @@
 use crate::conn::Conn;
 use crate::error::Error;
 use crate::jwt::Claims;
+use crate::metrics::Metrics;
+use axum::extract::State;
 use axum::Json;
-use beeps_core::{document::Part, merge::Merge, sync::pull, Document, Hlc, Lww};
+use beeps_core::{
+    document::Part,
+    orset::OrSetPart,
+    sync::{pull, version_vector},
+    Hlc, Lww,
+};
 use chrono::{DateTime, Utc};
-use sqlx::{query_as, FromRow};
+use sqlx::{FromRow, QueryBuilder};
+use std::collections::BTreeSet;
 use tokio_stream::StreamExt;
 
 #[tracing::instrument]
-pub async fn handler(Conn(mut conn): Conn, claims: Claims) -> Result<Json<pull::Resp>, Error> {
-    let mut doc = Document::default();
+pub async fn handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    State(metrics): State<Metrics>,
+    Json(req): Json<pull::Req>,
+) -> Result<Json<pull::Resp>, Error> {
+    let (parts, vector, more) =
+        parts_since(&mut conn, claims.document_id, &req.vector, Some(PAGE_SIZE)).await?;
+
+    metrics.document_synced(parts.len() as u64);
+
+    Ok(Json(pull::Resp {
+        parts,
+        vector,
+        more,
+    }))
+}
 
-    // minutes per ping
+/// How many rows of each clocked table (`minutes_per_pings`, `pings` and
+/// `tags`, each limited separately) a single page of `parts_since` returns
+/// at most, when it's given a limit. Keeps a pull
+/// response bounded in size for a device that's been offline long enough to
+/// have a huge backlog, instead of trying to bind every row of a massive
+/// backlog into one query and one response. A client that gets back
+/// `more: true` should pull again with the returned vector, which already
+/// covers this page, to fetch the next one.
+pub(crate) const PAGE_SIZE: i64 = 5_000;
+
+/// Every part of `document_id` not already covered by `vector`, plus the
+/// version vector covering everything returned, and whether more parts were
+/// left unsent because `limit` was reached. Passing an empty `vector` and no
+/// `limit` fetches the whole document in one page, which is how
+/// `handlers::subscribe` gets a new subscriber caught up before it starts
+/// relaying further pushes.
+pub(crate) async fn parts_since(
+    conn: &mut sqlx::PgConnection,
+    document_id: i64,
+    vector: &version_vector::VersionVector,
+    limit: Option<i64>,
+) -> Result<(Vec<Part>, version_vector::VersionVector, bool), Error> {
+    let mut parts = Vec::new();
+    let mut observed = version_vector::VersionVector::new();
+    let mut more = false;
+
+    // minutes per ping. Filtered at the database, rather than fetched whole
+    // and discarded in application code, so a client that's already caught
+    // up doesn't pay to transfer rows it's only going to throw away. Ordered
+    // by the same `(timestamp, counter, node)` triple `push_cursor_filter`
+    // compares against, so a `limit` cuts off at a point the next page's
+    // filter can pick back up from exactly.
     {
-        let mut minutes_per_pings = query_as!(
-            MinutesPerPingRow,
-            "SELECT minutes_per_ping, timestamp, counter, node FROM minutes_per_pings WHERE document_id = $1",
-            claims.document_id,
-        )
-        .fetch(&mut *conn);
+        let mut query = QueryBuilder::new(
+            "SELECT minutes_per_ping, timestamp, counter, node FROM minutes_per_pings WHERE document_id = ",
+        );
+        query.push_bind(document_id);
+        push_cursor_filter(&mut query, vector);
+        query.push(" ORDER BY timestamp, counter, node");
+        push_limit(&mut query, limit);
+
+        let mut minutes_per_pings = query
+            .build_query_as::<MinutesPerPingRow>()
+            .fetch(&mut *conn);
 
+        let mut returned: i64 = 0;
         while let Some(row) = minutes_per_pings.try_next().await? {
-            doc.merge_part(row.try_into()?);
+            let part: Part = row.try_into()?;
+            observe_part(&mut observed, &part);
+            parts.push(part);
+            returned += 1;
         }
+        more |= limit.is_some_and(|limit| returned == limit);
     }
 
-    // pings
+    // pings. Same cursor-filtered, paginated approach as minutes_per_pings.
     {
-        let mut pings = query_as!(
-            PingRow,
-            "SELECT ping FROM pings WHERE document_id = $1",
-            claims.document_id
-        )
-        .fetch(&mut *conn);
+        let mut query = QueryBuilder::new(
+            "SELECT ping, observed, timestamp, counter, node FROM pings WHERE document_id = ",
+        );
+        query.push_bind(document_id);
+        push_cursor_filter(&mut query, vector);
+        query.push(" ORDER BY timestamp, counter, node");
+        push_limit(&mut query, limit);
 
+        let mut pings = query.build_query_as::<PingRow>().fetch(&mut *conn);
+
+        let mut returned: i64 = 0;
         while let Some(row) = pings.try_next().await? {
-            doc.merge_part(row.into());
+            let part: Part = row.try_into()?;
+            observe_part(&mut observed, &part);
+            parts.push(part);
+            returned += 1;
         }
+        more |= limit.is_some_and(|limit| returned == limit);
     }
 
-    // tags
+    // tags. Same cursor-filtered, paginated approach as minutes_per_pings.
     {
-        let mut tags = query_as!(
-            TagRow,
-            "SELECT ping, tag, timestamp, counter, node FROM tags WHERE document_id = $1",
-            claims.document_id,
-        )
-        .fetch(&mut *conn);
+        let mut query = QueryBuilder::new(
+            "SELECT ping, tag, observed, timestamp, counter, node FROM tags WHERE document_id = ",
+        );
+        query.push_bind(document_id);
+        push_cursor_filter(&mut query, vector);
+        query.push(" ORDER BY timestamp, counter, node");
+        push_limit(&mut query, limit);
+
+        let mut tags = query.build_query_as::<TagRow>().fetch(&mut *conn);
 
+        let mut returned: i64 = 0;
         while let Some(row) = tags.try_next().await? {
-            doc.merge_part(row.try_into()?);
+            let part: Part = row.try_into()?;
+            observe_part(&mut observed, &part);
+            parts.push(part);
+            returned += 1;
+        }
+        more |= limit.is_some_and(|limit| returned == limit);
+    }
+
+    Ok((parts, observed, more))
+}
+
+/// Append a `LIMIT` clause if `limit` is set.
+fn push_limit<'a>(query: &mut QueryBuilder<'a, sqlx::Postgres>, limit: Option<i64>) {
+    if let Some(limit) = limit {
+        query.push(" LIMIT ");
+        query.push_bind(limit);
+    }
+}
+
+/// Append a `WHERE`-continuing clause that keeps only rows not already
+/// covered by `vector`: one whose node is missing from the vector entirely,
+/// or whose `(timestamp, counter)` sorts above that node's watermark. This
+/// is the SQL mirror of `version_vector::covers`, pushed down to the
+/// database instead of fetching every row and filtering them out here.
+fn push_cursor_filter<'a>(
+    query: &mut QueryBuilder<'a, sqlx::Postgres>,
+    vector: &'a version_vector::VersionVector,
+) {
+    if vector.is_empty() {
+        return;
+    }
+
+    query.push(" AND (node NOT IN (");
+    {
+        let mut nodes = query.separated(", ");
+        for node in vector.keys() {
+            nodes.push_bind(i32::from(*node));
         }
     }
+    query.push(")");
+
+    for (node, (timestamp, counter)) in vector {
+        query.push(" OR (node = ");
+        query.push_bind(i32::from(*node));
+        query.push(" AND (timestamp, counter) > (");
+        query.push_bind(*timestamp);
+        query.push(", ");
+        query.push_bind(i32::from(*counter));
+        query.push("))");
+    }
 
-    Ok(Json(doc))
+    query.push(")");
+}
+
+/// Record the clock carried by a `Part` (if any) in `vector`.
+fn observe_part(vector: &mut version_vector::VersionVector, part: &Part) {
+    if let Some(clock) = part.clock() {
+        version_vector::observe(vector, clock);
+    }
 }
 
 #[derive(FromRow)]
@@ -77,12 +207,33 @@ impl TryFrom<MinutesPerPingRow> for Part {
 
 #[derive(FromRow)]
 struct PingRow {
-    ping: DateTime<Utc>,
+    ping: Option<DateTime<Utc>>,
+    observed: Option<sqlx::types::Json<BTreeSet<Hlc>>>,
+    timestamp: DateTime<Utc>,
+    counter: i32,
+    node: i32,
 }
 
-impl From<PingRow> for Part {
-    fn from(val: PingRow) -> Self {
-        Part::Ping(val.ping)
+impl TryFrom<PingRow> for Part {
+    type Error = Error;
+
+    fn try_from(row: PingRow) -> Result<Part, Self::Error> {
+        let id = Hlc::new_at(row.node.try_into()?, row.timestamp, row.counter.try_into()?);
+
+        // The `pings_add_xor_remove` check constraint guarantees exactly one
+        // of `ping`/`observed` is set; an add carries its value, a remove
+        // carries the instance ids it tombstoned.
+        let part = match (row.ping, row.observed) {
+            (Some(ping), None) => OrSetPart::Add(id, ping),
+            (None, Some(observed)) => OrSetPart::Remove(id, observed.0),
+            _ => {
+                return Err(Error::custom(
+                    "pings row had neither or both of ping/observed set",
+                ))
+            }
+        };
+
+        Ok(Self::Ping(part))
     }
 }
 
@@ -90,6 +241,7 @@ impl From<PingRow> for Part {
 struct TagRow {
     ping: DateTime<Utc>,
     tag: Option<String>,
+    observed: Option<sqlx::types::Json<BTreeSet<Hlc>>>,
     timestamp: DateTime<Utc>,
     counter: i32,
     node: i32,
@@ -99,23 +251,63 @@ impl TryFrom<TagRow> for Part {
     type Error = Error;
 
     fn try_from(row: TagRow) -> Result<Part, Self::Error> {
-        Ok(Self::Tag((
-            row.ping,
-            Lww::new(
-                row.tag,
-                Hlc::new_at(row.node.try_into()?, row.timestamp, row.counter.try_into()?),
-            ),
-        )))
+        let id = Hlc::new_at(row.node.try_into()?, row.timestamp, row.counter.try_into()?);
+
+        // The `tags_add_xor_remove` check constraint guarantees exactly one
+        // of `tag`/`observed` is set; an add carries its value, a remove
+        // carries the instance ids it tombstoned.
+        let part = match (row.tag, row.observed) {
+            (Some(tag), None) => OrSetPart::Add(id, tag),
+            (None, Some(observed)) => OrSetPart::Remove(id, observed.0),
+            _ => {
+                return Err(Error::custom(
+                    "tags row had neither or both of tag/observed set",
+                ))
+            }
+        };
+
+        Ok(Self::Tag((row.ping, part)))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::handlers::{push, test::TestDoc};
-    use beeps_core::NodeId;
+    use crate::{
+        handlers::{push, test::TestDoc},
+        state::Subscriptions,
+    };
+    use axum::extract::State;
+    use beeps_core::{split::Split, Document, NodeId};
     use sqlx::{Pool, Postgres};
 
+    fn metrics(pool: &Pool<Postgres>) -> Metrics {
+        Metrics::new(pool.clone(), None).unwrap()
+    }
+
+    fn authorizer() -> crate::authz::SharedAuthorizer {
+        std::sync::Arc::new(crate::authz::AllowAll)
+    }
+
+    /// Wrap a document up as a push request against the given document ID.
+    fn push_req(document_id: i64, document: Document) -> beeps_core::sync::push::Req {
+        beeps_core::sync::push::Req {
+            document_id,
+            parts: document.split().collect(),
+            vector: version_vector::VersionVector::new(),
+        }
+    }
+
+    /// Fold a pull response's parts back into a whole `Document`, for
+    /// assertions that want to compare against one.
+    fn merged(resp: &pull::Resp) -> Document {
+        let mut document = Document::default();
+        for part in resp.parts.clone() {
+            document.merge_part(part);
+        }
+        document
+    }
+
     #[test_log::test(sqlx::test)]
     async fn test_pulls_minutes_per_ping(pool: Pool<Postgres>) {
         let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
@@ -129,17 +321,25 @@ mod test {
         let _ = push::handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document.clone()),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document.clone())),
         )
         .await
         .unwrap();
 
-        let Json(pulled) = handler(Conn(pool.acquire().await.unwrap()), doc.claims())
-            .await
-            .unwrap();
+        let Json(pulled) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(metrics(&pool)),
+            Json(pull::Req::default()),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(
-            pulled.minutes_per_ping.value(),
+            merged(&pulled).minutes_per_ping.value(),
             document.minutes_per_ping.value()
         );
     }
@@ -149,21 +349,29 @@ mod test {
         let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
 
         let mut document = Document::default();
-        document.add_ping(Utc::now());
+        document.add_ping(Utc::now(), Hlc::new(NodeId::min()));
 
         let _ = push::handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document.clone()),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document.clone())),
         )
         .await
         .unwrap();
 
-        let Json(pulled) = handler(Conn(pool.acquire().await.unwrap()), doc.claims())
-            .await
-            .unwrap();
+        let Json(pulled) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(metrics(&pool)),
+            Json(pull::Req::default()),
+        )
+        .await
+        .unwrap();
 
-        assert_eq!(pulled.pings, document.pings);
+        assert_eq!(merged(&pulled).pings, document.pings);
     }
 
     #[test_log::test(sqlx::test)]
@@ -172,21 +380,152 @@ mod test {
 
         let mut document = Document::default();
         let now = Utc::now();
-        document.add_ping(now);
-        document.tag_ping(now, "tag".to_string(), Hlc::new(NodeId::min()));
+        let clock = Hlc::new(NodeId::min());
+        document.add_ping(now, clock);
+        document.add_tag(now, "tag".to_string(), clock.next());
 
         let _ = push::handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document.clone()),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document.clone())),
         )
         .await
         .unwrap();
 
-        let Json(pulled) = handler(Conn(pool.acquire().await.unwrap()), doc.claims())
-            .await
-            .unwrap();
+        let Json(pulled) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(metrics(&pool)),
+            Json(pull::Req::default()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(merged(&pulled).tags, document.tags);
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_skips_parts_already_covered_by_the_clients_vector(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut document = Document::default();
+        let clock = Hlc::new(NodeId::min());
+        document.set_minutes_per_ping(90, clock.clone());
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let mut vector = version_vector::VersionVector::new();
+        version_vector::observe(&mut vector, &clock);
+
+        let Json(pulled) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(metrics(&pool)),
+            Json(pull::Req { vector }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(*merged(&pulled).minutes_per_ping.value(), 45);
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_sends_parts_newer_than_the_clients_vector(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let stale_clock = Hlc::new(NodeId::min());
+        let latest_clock = stale_clock.next();
+
+        let mut document = Document::default();
+        document.set_minutes_per_ping(90, latest_clock);
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let mut vector = version_vector::VersionVector::new();
+        version_vector::observe(&mut vector, &stale_clock);
+
+        let Json(pulled) = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(metrics(&pool)),
+            Json(pull::Req { vector }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(*merged(&pulled).minutes_per_ping.value(), 90);
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_parts_since_pages_when_a_limit_is_given(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut document = Document::default();
+        let now = Utc::now();
+        let mut clock = Hlc::new(NodeId::min());
+        for n in 0..3 {
+            let when = now + chrono::Duration::seconds(n);
+            document.add_ping(when, clock);
+            clock = clock.next();
+            document.add_tag(when, format!("tag{n}"), clock);
+            clock = clock.next();
+        }
+
+        let _ = push::handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(push_req(doc.document_id, document)),
+        )
+        .await
+        .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let mut vector = version_vector::VersionVector::new();
+        let mut tags_seen = 0;
+        let mut pages = 0;
+
+        loop {
+            let (parts, page_vector, more) =
+                parts_since(&mut conn, doc.document_id, &vector, Some(2))
+                    .await
+                    .unwrap();
+            tags_seen += parts
+                .iter()
+                .filter(|part| matches!(part, Part::Tag(_)))
+                .count();
+            pages += 1;
+            version_vector::merge(&mut vector, &page_vector);
+
+            if !more {
+                break;
+            }
+        }
 
-        assert_eq!(pulled.tags, document.tags);
+        assert_eq!(tags_seen, 3);
+        assert_eq!(pages, 2);
     }
 }
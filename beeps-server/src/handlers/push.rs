@@ -1,39 +1,105 @@
+use crate::authz::SharedAuthorizer;
+use crate::bail_if;
 use crate::conn::Conn;
 use crate::error::Error;
+use crate::handlers::poll;
 use crate::jwt::Claims;
+use crate::merkle;
+use crate::metrics::Metrics;
+use crate::state::Subscriptions;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
 use axum::Json;
 use beeps_core::document::Part;
-use beeps_core::merge::Merge;
-use beeps_core::sync::push;
-use sqlx::{query, Acquire, QueryBuilder};
+use beeps_core::orset::OrSetPart;
+use beeps_core::split::Split;
+use beeps_core::sync::authz::PartCounts;
+use beeps_core::sync::{authz, push, version_vector};
+use beeps_core::{Document, Hlc};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::types::Json as SqlJson;
+use sqlx::{pool::PoolConnection, query, Acquire, Postgres, QueryBuilder};
+use std::collections::BTreeSet;
+use tokio_util::codec::{FramedRead, LinesCodec};
+use tokio_util::io::StreamReader;
 
 #[tracing::instrument]
 pub async fn handler(
     Conn(mut conn): Conn,
     claims: Claims,
+    State(subscriptions): State<Subscriptions>,
+    State(authorizer): State<SharedAuthorizer>,
+    State(metrics): State<Metrics>,
     Json(req): Json<push::Req>,
 ) -> Result<Json<push::Resp>, Error> {
+    let mut document_for_subscribers = Document::default();
     let mut minutes_per_pings = vec![];
     let mut pings = vec![];
     let mut tags = vec![];
+    let mut clocks = vec![];
 
-    req.split().for_each(|item| match item {
-        Part::MinutesPerPing(minutes) => {
-            minutes_per_pings.push(minutes);
-        }
-        Part::Ping(ping) => {
-            pings.push(ping);
+    for part in req.parts.clone() {
+        document_for_subscribers.merge_part(part.clone());
+
+        if let Some(clock) = part.clock() {
+            clocks.push(clock.clone());
         }
-        Part::Tag((ping, tag)) => {
-            tags.push((ping, tag));
+
+        match part {
+            Part::MinutesPerPing(minutes) => {
+                minutes_per_pings.push(minutes);
+            }
+            Part::Ping(ping) => {
+                pings.push(ping);
+            }
+            Part::Tag((ping, tag)) => {
+                tags.push((ping, tag));
+            }
         }
-    });
+    }
+
+    let ops_merged = minutes_per_pings.len() + pings.len() + tags.len();
+
+    // Validation: every clocked part must carry this replica's own
+    // server-assigned node ID, so a buggy or compromised client can't forge
+    // history under another replica's identity.
+    bail_if!(
+        minutes_per_pings
+            .iter()
+            .map(|value| value.clock())
+            .chain(pings.iter().map(OrSetPart::id))
+            .chain(tags.iter().map(|(_, part)| part.id()))
+            .any(|clock| i32::from(*clock.node()) != claims.node_id),
+        StatusCode::FORBIDDEN,
+        "push contained a clock for a different replica"
+    );
+
+    // Validation: let an external authorizer veto the push.
+    let decision = authorizer
+        .authorize(&authz::Req {
+            email: Some(claims.sub.clone()),
+            document_id: Some(claims.document_id),
+            parts: Some(authz::PartCounts {
+                minutes_per_ping: minutes_per_pings.len(),
+                pings: pings.len(),
+                tags: tags.len(),
+            }),
+        })
+        .await;
+    bail_if!(
+        !decision.allow,
+        StatusCode::FORBIDDEN,
+        &decision
+            .reason
+            .unwrap_or_else(|| "Push was not authorized".to_string())
+    );
 
     let mut tx = conn.begin().await?;
 
     if !minutes_per_pings.is_empty() {
         let mut query = QueryBuilder::new(
-            "INSERT INTO minutes_per_pings (document_id, minutes_per_ping, timestamp, counter, node)",
+            "INSERT INTO minutes_per_pings (document_id, minutes_per_ping, timestamp, counter, node, merkle_key)",
         );
         query.push_values(minutes_per_pings, |mut b, value| {
             let clock = value.clock();
@@ -42,36 +108,68 @@ pub async fn handler(
                 .push_bind(i32::from(*value.value()))
                 .push_bind(clock.timestamp())
                 .push_bind(i32::from(clock.counter()))
-                .push_bind(i32::from(*clock.node()));
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(clock));
         });
-        query.push("ON CONFLICT DO NOTHING");
+        // `minutes_per_ping` is an LWW-register: only overwrite the stored
+        // row if the incoming clock actually wins, matching `Lww::merge`.
+        query.push(
+            "ON CONFLICT (document_id) DO UPDATE SET \
+             minutes_per_ping = EXCLUDED.minutes_per_ping, \
+             timestamp = EXCLUDED.timestamp, \
+             counter = EXCLUDED.counter, \
+             node = EXCLUDED.node, \
+             merkle_key = EXCLUDED.merkle_key \
+             WHERE (EXCLUDED.timestamp, EXCLUDED.counter, EXCLUDED.node) > \
+             (minutes_per_pings.timestamp, minutes_per_pings.counter, minutes_per_pings.node)",
+        );
         query.build().execute(&mut *tx).await?;
     }
 
     if !pings.is_empty() {
-        let mut query = QueryBuilder::new("INSERT INTO pings (document_id, ping)");
-        query.push_values(pings, |mut b, value| {
-            b.push_bind(claims.document_id).push_bind(value);
+        let mut query = QueryBuilder::new(
+            "INSERT INTO pings (document_id, ping, observed, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(pings, |mut b, part| {
+            let clock = *part.id();
+            let (ping, observed) = ping_columns(&part);
+
+            b.push_bind(claims.document_id)
+                .push_bind(ping)
+                .push_bind(observed)
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(&clock));
         });
-        query.push("ON CONFLICT DO NOTHING");
+        // Each row is its own immutable OR-Set instance (an add or a remove
+        // operation), so unlike `minutes_per_pings` there's no "newer write"
+        // to lose by ignoring a duplicate.
+        query.push("ON CONFLICT (document_id, timestamp, counter, node) DO NOTHING");
         query.build().execute(&mut *tx).await?;
     }
 
     if !tags.is_empty() {
         let mut query = QueryBuilder::new(
-            "INSERT INTO tags (document_id, ping, tag, timestamp, counter, node)",
+            "INSERT INTO tags (document_id, ping, tag, observed, timestamp, counter, node, merkle_key)",
         );
-        query.push_values(tags, |mut b, (ping, tag)| {
-            let clock = tag.clock();
+        query.push_values(tags, |mut b, (ping, part)| {
+            let clock = *part.id();
+            let (tag, observed) = tag_columns(&part);
 
             b.push_bind(claims.document_id)
                 .push_bind(ping)
-                .push_bind(tag.value().clone())
+                .push_bind(tag)
+                .push_bind(observed)
                 .push_bind(clock.timestamp())
                 .push_bind(i32::from(clock.counter()))
-                .push_bind(i32::from(*clock.node()));
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(&clock));
         });
-        query.push("ON CONFLICT DO NOTHING");
+        // Each row is its own immutable OR-Set instance (an add or a remove
+        // operation), so unlike `minutes_per_pings` there's no "newer write"
+        // to lose by ignoring a duplicate.
+        query.push("ON CONFLICT (document_id, ping, timestamp, counter, node) DO NOTHING");
         query.build().execute(&mut *tx).await?;
     }
 
@@ -82,19 +180,338 @@ pub async fn handler(
     .execute(&mut *tx)
     .await?;
 
+    // Wake up anyone long-polling `/poll` for this document, in the same
+    // transaction as the update so a listener that subscribes right after
+    // seeing a stale `updated_at` can't miss the notification.
+    query(&format!("NOTIFY {}", poll::channel(claims.document_id)))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    // Keep the Merkle anti-entropy tree in sync with what we just wrote.
+    // This recomputes the bucket each incoming clock lands in; if an LWW
+    // overwrite moved a key to a different bucket than before, the old
+    // bucket's hash is left stale until another op happens to touch it.
+    // That's fine for anti-entropy: a stale bucket costs an extra, harmless
+    // round trip, never incorrect data.
+    for clock in &clocks {
+        merkle::update(&mut conn, claims.document_id, clock).await?;
+    }
+
+    let mut vector = version_vector::VersionVector::new();
+    for part in &req.parts {
+        if let Some(clock) = part.clock() {
+            version_vector::observe(&mut vector, clock);
+        }
+    }
+
+    subscriptions
+        .notify(claims.document_id, document_for_subscribers)
+        .await;
+
+    metrics.document_synced(ops_merged as u64);
+
+    Ok(Json(push::Resp {
+        accepted: ops_merged as u64,
+        vector,
+    }))
+}
+
+/// Split an `OrSetPart` into the `tag`/`observed` columns `tags` stores it
+/// under: an add carries its value in `tag` and leaves `observed` null,
+/// while a remove carries the instance ids it tombstoned in `observed` and
+/// leaves `tag` null. The `tags_add_xor_remove` check constraint enforces
+/// that exactly one of the two is ever set.
+fn tag_columns(part: &OrSetPart<String>) -> (Option<String>, Option<SqlJson<BTreeSet<Hlc>>>) {
+    match part {
+        OrSetPart::Add(_, value) => (Some(value.clone()), None),
+        OrSetPart::Remove(_, observed) => (None, Some(SqlJson(observed.clone()))),
+    }
+}
+
+/// Same idea as `tag_columns`, but for the `pings` table's `ping`/`observed`
+/// columns, and the `pings_add_xor_remove` check constraint that enforces
+/// exactly one of them is ever set.
+fn ping_columns(
+    part: &OrSetPart<DateTime<Utc>>,
+) -> (Option<DateTime<Utc>>, Option<SqlJson<BTreeSet<Hlc>>>) {
+    match part {
+        OrSetPart::Add(_, value) => (Some(*value), None),
+        OrSetPart::Remove(_, observed) => (None, Some(SqlJson(observed.clone()))),
+    }
+}
+
+/// How many decoded parts to buffer before flushing a batch to the
+/// database. Bounds memory for a very large initial sync, unlike `handler`,
+/// which merges the whole document into memory before writing any of it.
+const STREAM_BATCH_SIZE: usize = 500;
+
+/// Like `handler`, but for large initial syncs: the body is a stream of
+/// newline-delimited JSON `Part`s rather than a single JSON `push::Req`,
+/// decoded and merged into the database in bounded-size batches as they
+/// arrive instead of all at once. Meant to be reached over a multiplexed
+/// h2c connection (see `--h2c`), and kept under a separate body limit (see
+/// `--sync-body-limit`) since it's the one route expected to see much
+/// larger payloads than the rest of the server.
+#[tracing::instrument(skip(conn, subscriptions, authorizer, metrics, request))]
+pub async fn stream_handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    State(subscriptions): State<Subscriptions>,
+    State(authorizer): State<SharedAuthorizer>,
+    State(metrics): State<Metrics>,
+    request: Request,
+) -> Result<Json<push::Resp>, Error> {
+    let reader = StreamReader::new(
+        request
+            .into_body()
+            .into_data_stream()
+            .map_err(|err| std::io::Error::other(err.to_string())),
+    );
+    let mut lines = FramedRead::new(reader, LinesCodec::new());
+
+    let mut document_for_subscribers = Document::default();
+    let mut vector = version_vector::VersionVector::new();
+    let mut ops_merged: u64 = 0;
+    let mut batch = Vec::with_capacity(STREAM_BATCH_SIZE);
+
+    while let Some(line) = lines
+        .try_next()
+        .await
+        .map_err(|err| Error::custom(&err.to_string()))?
+    {
+        let part: Part =
+            serde_json::from_str(&line).map_err(|err| Error::custom(&err.to_string()))?;
+        batch.push(part);
+
+        if batch.len() >= STREAM_BATCH_SIZE {
+            ops_merged += flush_batch(
+                &mut conn,
+                &claims,
+                &authorizer,
+                &mut document_for_subscribers,
+                &mut vector,
+                std::mem::take(&mut batch),
+            )
+            .await?;
+        }
+    }
+
+    if !batch.is_empty() {
+        ops_merged += flush_batch(
+            &mut conn,
+            &claims,
+            &authorizer,
+            &mut document_for_subscribers,
+            &mut vector,
+            batch,
+        )
+        .await?;
+    }
+
+    query!(
+        "UPDATE documents SET updated_at = NOW() WHERE id = $1",
+        claims.document_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Same as `handler`: wake up anyone long-polling `/poll` for this
+    // document now that `updated_at` has moved.
+    query(&format!("NOTIFY {}", poll::channel(claims.document_id)))
+        .execute(&mut *conn)
+        .await?;
+
+    subscriptions
+        .notify(claims.document_id, document_for_subscribers)
+        .await;
+
+    metrics.document_synced(ops_merged);
+
+    Ok(Json(push::Resp {
+        accepted: ops_merged,
+        vector,
+    }))
+}
+
+/// Validate, merge, and insert one batch of a streamed push. Returns how
+/// many parts were merged, for the caller's running total.
+async fn flush_batch(
+    conn: &mut PoolConnection<Postgres>,
+    claims: &Claims,
+    authorizer: &SharedAuthorizer,
+    document_for_subscribers: &mut Document,
+    vector: &mut version_vector::VersionVector,
+    parts: Vec<Part>,
+) -> Result<u64, Error> {
+    // Validation: every clocked part must carry this replica's own
+    // server-assigned node ID, same as the whole-document push path.
+    bail_if!(
+        parts.iter().any(|part| part
+            .clock()
+            .is_some_and(|clock| i32::from(*clock.node()) != claims.node_id)),
+        StatusCode::FORBIDDEN,
+        "push contained a clock for a different replica"
+    );
+
+    let mut minutes_per_pings = vec![];
+    let mut pings = vec![];
+    let mut tags = vec![];
+    let mut clocks = vec![];
+
+    for part in &parts {
+        if let Some(clock) = part.clock() {
+            version_vector::observe(vector, clock);
+            clocks.push(clock.clone());
+        }
+        document_for_subscribers.merge_part(part.clone());
+    }
+
+    for part in parts {
+        match part {
+            Part::MinutesPerPing(minutes) => minutes_per_pings.push(minutes),
+            Part::Ping(ping) => pings.push(ping),
+            Part::Tag(tag) => tags.push(tag),
+        }
+    }
+
+    // Validation: let an external authorizer veto this batch, the same way
+    // it would a whole-document push.
+    let decision = authorizer
+        .authorize(&authz::Req {
+            email: Some(claims.sub.clone()),
+            document_id: Some(claims.document_id),
+            parts: Some(PartCounts {
+                minutes_per_ping: minutes_per_pings.len(),
+                pings: pings.len(),
+                tags: tags.len(),
+            }),
+        })
+        .await;
+    bail_if!(
+        !decision.allow,
+        StatusCode::FORBIDDEN,
+        &decision
+            .reason
+            .unwrap_or_else(|| "Push was not authorized".to_string())
+    );
+
+    let ops_merged = minutes_per_pings.len() + pings.len() + tags.len();
+
+    let mut tx = conn.begin().await?;
+
+    if !minutes_per_pings.is_empty() {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO minutes_per_pings (document_id, minutes_per_ping, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(minutes_per_pings, |mut b, value| {
+            let clock = value.clock();
+
+            b.push_bind(claims.document_id)
+                .push_bind(i32::from(*value.value()))
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(clock));
+        });
+        // See `handler`: `minutes_per_ping` is an LWW-register, so only
+        // overwrite the stored row if the incoming clock wins.
+        query.push(
+            "ON CONFLICT (document_id) DO UPDATE SET \
+             minutes_per_ping = EXCLUDED.minutes_per_ping, \
+             timestamp = EXCLUDED.timestamp, \
+             counter = EXCLUDED.counter, \
+             node = EXCLUDED.node, \
+             merkle_key = EXCLUDED.merkle_key \
+             WHERE (EXCLUDED.timestamp, EXCLUDED.counter, EXCLUDED.node) > \
+             (minutes_per_pings.timestamp, minutes_per_pings.counter, minutes_per_pings.node)",
+        );
+        query.build().execute(&mut *tx).await?;
+    }
+
+    if !pings.is_empty() {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO pings (document_id, ping, observed, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(pings, |mut b, part| {
+            let clock = *part.id();
+            let (ping, observed) = ping_columns(&part);
+
+            b.push_bind(claims.document_id)
+                .push_bind(ping)
+                .push_bind(observed)
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(&clock));
+        });
+        // See `handler`: each row is its own immutable OR-Set instance, so
+        // there's no "newer write" to lose by ignoring a duplicate.
+        query.push("ON CONFLICT (document_id, timestamp, counter, node) DO NOTHING");
+        query.build().execute(&mut *tx).await?;
+    }
+
+    if !tags.is_empty() {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO tags (document_id, ping, tag, observed, timestamp, counter, node, merkle_key)",
+        );
+        query.push_values(tags, |mut b, (ping, part)| {
+            let clock = *part.id();
+            let (tag, observed) = tag_columns(&part);
+
+            b.push_bind(claims.document_id)
+                .push_bind(ping)
+                .push_bind(tag)
+                .push_bind(observed)
+                .push_bind(clock.timestamp())
+                .push_bind(i32::from(clock.counter()))
+                .push_bind(i32::from(*clock.node()))
+                .push_bind(merkle::merkle_key(&clock));
+        });
+        // See `handler`: each row is its own immutable OR-Set instance, so
+        // there's no "newer write" to lose by ignoring a duplicate.
+        query.push("ON CONFLICT (document_id, ping, timestamp, counter, node) DO NOTHING");
+        query.build().execute(&mut *tx).await?;
+    }
+
     tx.commit().await?;
 
-    Ok(Json(push::Resp {}))
+    // Keep the Merkle anti-entropy tree in sync with what we just wrote; see
+    // `handler` for the staleness trade-off this accepts.
+    for clock in &clocks {
+        merkle::update(conn, claims.document_id, clock).await?;
+    }
+
+    Ok(ops_merged as u64)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{assert_eq_timestamps, handlers::test::TestDoc};
+    use crate::{assert_eq_timestamps, handlers::test::TestDoc, state::Subscriptions};
+    use axum::extract::State;
     use beeps_core::{Document, Hlc, NodeId};
     use chrono::Utc;
     use sqlx::{Pool, Postgres, Row};
 
+    /// Wrap a document up as a push request against the given document ID.
+    fn req(document_id: i64, document: Document) -> push::Req {
+        push::Req {
+            document_id,
+            parts: document.split().collect(),
+            vector: version_vector::VersionVector::new(),
+        }
+    }
+
+    fn authorizer() -> SharedAuthorizer {
+        std::sync::Arc::new(crate::authz::AllowAll)
+    }
+
+    fn metrics(pool: &Pool<Postgres>) -> Metrics {
+        Metrics::new(pool.clone(), None).unwrap()
+    }
+
     #[test_log::test(sqlx::test)]
     fn test_inserts_minutes_per_ping(pool: Pool<Postgres>) {
         let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
@@ -106,7 +523,10 @@ mod test {
         let _ = handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document)),
         )
         .await
         .unwrap();
@@ -131,25 +551,32 @@ mod test {
 
         let mut document = Document::default();
         let now = Utc::now();
-        document.add_ping(now);
+        let clock = Hlc::new(NodeId::min());
+        document.add_ping(now, clock);
 
         let _ = handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document)),
         )
         .await
         .unwrap();
 
         let inserted = query!(
-            "SELECT ping FROM pings WHERE document_id = $1",
+            "SELECT ping, timestamp, counter, node FROM pings WHERE document_id = $1",
             doc.document_id
         )
         .fetch_one(&mut *pool.acquire().await.unwrap())
         .await
         .unwrap();
 
-        assert_eq_timestamps!(inserted.ping, now);
+        assert_eq_timestamps!(inserted.ping, Some(now));
+        assert_eq_timestamps!(inserted.timestamp, clock.timestamp());
+        assert_eq!(inserted.counter, i32::from(clock.counter()));
+        assert_eq!(inserted.node, i32::from(*clock.node()));
     }
 
     #[test_log::test(sqlx::test)]
@@ -159,13 +586,16 @@ mod test {
         let mut document = Document::default();
         let now = Utc::now();
         let clock = Hlc::new(NodeId::min());
-        document.add_ping(now);
-        document.tag_ping(now, "test".to_string(), clock);
+        document.add_ping(now, clock.next());
+        document.add_tag(now, "test".to_string(), clock);
 
         let _ = handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document)),
         )
         .await
         .unwrap();
@@ -207,13 +637,16 @@ mod test {
         let now = Utc::now();
         let clock = Hlc::new(NodeId::min());
         document.set_minutes_per_ping(60, clock);
-        document.add_ping(now);
-        document.tag_ping(now, "test".to_string(), clock);
+        document.add_ping(now, clock.next());
+        document.add_tag(now, "test".to_string(), clock);
 
         let _ = handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document.clone()),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document.clone())),
         )
         .await
         .unwrap();
@@ -226,7 +659,10 @@ mod test {
         let _ = handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(document),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, document)),
         )
         .await
         .unwrap();
@@ -241,6 +677,95 @@ mod test {
         assert_eq!(num_tags_before, num_tags_after);
     }
 
+    #[test_log::test(sqlx::test)]
+    fn test_newer_clock_overwrites_minutes_per_ping(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let first_clock = Hlc::new(NodeId::min());
+        let mut first = Document::default();
+        first.set_minutes_per_ping(60, first_clock);
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, first)),
+        )
+        .await
+        .unwrap();
+
+        let mut second = Document::default();
+        second.set_minutes_per_ping(90, first_clock.next());
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, second)),
+        )
+        .await
+        .unwrap();
+
+        let inserted = query!(
+            "SELECT minutes_per_ping FROM minutes_per_pings WHERE document_id = $1",
+            doc.document_id
+        )
+        .fetch_one(&mut *pool.acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(inserted.minutes_per_ping, 90);
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_older_clock_does_not_overwrite_minutes_per_ping(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let earlier_clock = Hlc::new(NodeId::min());
+        let later_clock = earlier_clock.next();
+        let mut first = Document::default();
+        first.set_minutes_per_ping(60, later_clock);
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, first)),
+        )
+        .await
+        .unwrap();
+
+        let mut stale = Document::default();
+        stale.set_minutes_per_ping(90, earlier_clock);
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, stale)),
+        )
+        .await
+        .unwrap();
+
+        let inserted = query!(
+            "SELECT minutes_per_ping FROM minutes_per_pings WHERE document_id = $1",
+            doc.document_id
+        )
+        .fetch_one(&mut *pool.acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(inserted.minutes_per_ping, 60);
+    }
+
     #[test_log::test(sqlx::test)]
     fn test_updates_updated_at(pool: Pool<Postgres>) {
         let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
@@ -256,7 +781,10 @@ mod test {
         let _ = handler(
             Conn(pool.acquire().await.unwrap()),
             doc.claims(),
-            Json(Document::default()),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, Document::default())),
         )
         .await
         .unwrap();
@@ -276,4 +804,133 @@ mod test {
             after.updated_at
         );
     }
+
+    #[test_log::test(sqlx::test)]
+    fn test_authorizer_denies(pool: Pool<Postgres>) {
+        struct DenyAll;
+
+        #[async_trait::async_trait]
+        impl crate::authz::Authorizer for DenyAll {
+            async fn authorize(&self, _req: &authz::Req) -> authz::Resp {
+                authz::Resp {
+                    allow: false,
+                    reason: Some("push volume too high".to_string()),
+                }
+            }
+        }
+
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let res = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(std::sync::Arc::new(DenyAll)),
+            State(metrics(&pool)),
+            Json(req(doc.document_id, Document::default())),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(
+            res,
+            (
+                axum::http::StatusCode::FORBIDDEN,
+                "push volume too high".to_string()
+            )
+        );
+    }
+
+    /// Encode `parts` as the newline-delimited body `stream_handler` expects.
+    fn stream_body(parts: &[Part]) -> axum::body::Body {
+        let lines = parts
+            .iter()
+            .map(|part| serde_json::to_string(part).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        axum::body::Body::from(lines + "\n")
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_stream_inserts_minutes_per_ping(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let clock = Hlc::new(NodeId::min());
+        let part = Part::MinutesPerPing(beeps_core::Lww::new(60, clock.clone()));
+
+        let _ = stream_handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Request::builder().body(stream_body(&[part])).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let inserted = query!(
+            "SELECT minutes_per_ping, timestamp, counter, node FROM minutes_per_pings WHERE document_id = $1",
+            doc.document_id
+        )
+        .fetch_one(&mut *pool.acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(inserted.minutes_per_ping, 60);
+        assert_eq_timestamps!(inserted.timestamp, clock.timestamp());
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_stream_batches_across_multiple_flushes(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let mut clock = Hlc::new(NodeId::min());
+        let pings = (0..(STREAM_BATCH_SIZE + 10))
+            .map(|i| {
+                clock = clock.next();
+                Part::Ping(OrSetPart::Add(
+                    clock,
+                    Utc::now() + chrono::Duration::seconds(i as i64),
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let _ = stream_handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Request::builder().body(stream_body(&pings)).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let count: i64 = table_size!("pings", doc.document_id, pool);
+        assert_eq!(count, (STREAM_BATCH_SIZE + 10) as i64);
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_stream_rejects_foreign_node(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let foreign_clock = Hlc::new(NodeId::max());
+        let part = Part::MinutesPerPing(beeps_core::Lww::new(60, foreign_clock));
+
+        let res = stream_handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            State(Subscriptions::default()),
+            State(authorizer()),
+            State(metrics(&pool)),
+            Request::builder().body(stream_body(&[part])).unwrap(),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(res.0, axum::http::StatusCode::FORBIDDEN);
+    }
 }
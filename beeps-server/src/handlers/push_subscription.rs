@@ -0,0 +1,109 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::jwt::Claims;
+use axum::Json;
+use beeps_core::sync::push_subscription::{Req, Resp};
+use sqlx::query;
+
+/// Register (or refresh) a Web Push subscription for the caller's account,
+/// so the push notifier can deliver a ping reminder to it even when it
+/// isn't in the foreground.
+#[tracing::instrument]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    Json(req): Json<Req>,
+) -> Result<Json<Resp>, Error> {
+    query!(
+        "INSERT INTO push_subscriptions (account_id, endpoint, p256dh, auth) \
+         SELECT id, $2, $3, $4 FROM accounts WHERE email = $1 \
+         ON CONFLICT (account_id, endpoint) DO UPDATE SET \
+         p256dh = EXCLUDED.p256dh, auth = EXCLUDED.auth",
+        claims.sub,
+        req.subscription.endpoint,
+        req.subscription.p256dh,
+        req.subscription.auth,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Json(Resp {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use beeps_core::sync::push_subscription::Subscription;
+    use sqlx::{Pool, Postgres};
+
+    fn req(endpoint: &str) -> Req {
+        Req {
+            subscription: Subscription {
+                endpoint: endpoint.to_string(),
+                p256dh: "test-p256dh".to_string(),
+                auth: "test-auth".to_string(),
+            },
+        }
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_registers_subscription(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(req("https://push.example.com/subscription/abc123")),
+        )
+        .await
+        .unwrap();
+
+        let inserted = query!(
+            "SELECT p256dh, auth FROM push_subscriptions WHERE endpoint = $1",
+            "https://push.example.com/subscription/abc123"
+        )
+        .fetch_one(&mut *pool.acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(inserted.p256dh, "test-p256dh");
+        assert_eq!(inserted.auth, "test-auth");
+    }
+
+    #[test_log::test(sqlx::test)]
+    fn test_upserts_on_conflict(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+        let endpoint = "https://push.example.com/subscription/abc123";
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(req(endpoint)),
+        )
+        .await
+        .unwrap();
+
+        let mut updated = req(endpoint);
+        updated.subscription.p256dh = "updated-p256dh".to_string();
+
+        let _ = handler(
+            Conn(pool.acquire().await.unwrap()),
+            doc.claims(),
+            Json(updated),
+        )
+        .await
+        .unwrap();
+
+        let rows = query!(
+            "SELECT p256dh FROM push_subscriptions WHERE endpoint = $1",
+            endpoint
+        )
+        .fetch_all(&mut *pool.acquire().await.unwrap())
+        .await
+        .unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].p256dh, "updated-p256dh");
+    }
+}
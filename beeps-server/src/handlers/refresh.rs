@@ -0,0 +1,206 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::jwt;
+use crate::session;
+use axum::{extract::State, Json};
+use beeps_core::sync::refresh::{Req, Resp};
+use jsonwebtoken::EncodingKey;
+
+/// Redeem a refresh token for a fresh access token, rotating the session's
+/// refresh token forward a generation in the process. Presenting a token
+/// that's already been rotated away is treated as reuse of a stolen token
+/// and revokes the whole session family; either way the caller gets back
+/// the same "invalid token" error, since there's nothing it can do
+/// differently for one versus the other besides logging back in.
+#[tracing::instrument(skip(conn, encoding_key, req))]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    State(encoding_key): State<EncodingKey>,
+    Json(req): Json<Req>,
+) -> Result<Json<Resp>, Error> {
+    let redeemed = session::redeem(&mut conn, &req.refresh_token)
+        .await?
+        .ok_or_else(|| Error::custom("invalid token"))?;
+
+    // Password registration doesn't create a document either; see the
+    // `// TODO` in `register::handler`.
+    let document_id = sqlx::query!(
+        "SELECT id FROM documents WHERE owner_id = $1 LIMIT 1",
+        redeemed.account_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map_or(0, |document| document.id);
+
+    let (jwt, expires_at) = jwt::issue(
+        &encoding_key,
+        &redeemed.email,
+        document_id,
+        redeemed.node_id,
+        redeemed.session_id,
+    )?;
+
+    Ok(Json(Resp {
+        jwt,
+        expires_at,
+        refresh_token: redeemed.refresh_token,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use jsonwebtoken::DecodingKey;
+    use sqlx::{pool::PoolConnection, Pool, Postgres};
+
+    fn encoding_key() -> EncodingKey {
+        EncodingKey::from_secret(b"secret".as_ref())
+    }
+
+    fn decoding_key() -> DecodingKey {
+        DecodingKey::from_secret(b"secret".as_ref())
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_success(mut conn: PoolConnection<Postgres>) {
+        let doc = TestDoc::create(&mut conn).await;
+
+        let resp = handler(
+            Conn(conn),
+            State(encoding_key()),
+            Json(Req {
+                refresh_token: "test-refresh-token".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let decoded = jsonwebtoken::decode::<jwt::Claims>(
+            &resp.jwt,
+            &decoding_key(),
+            &jsonwebtoken::Validation::default(),
+        )
+        .unwrap();
+
+        assert_eq!(decoded.claims.sub, doc.email);
+        assert_eq!(decoded.claims.document_id, doc.document_id);
+        assert_eq!(decoded.claims.node_id, doc.node_id);
+        // Redeeming rotates the session forward a generation, so the
+        // access token is minted for a new session, not the one the
+        // refresh token was originally issued for.
+        assert_ne!(decoded.claims.session_id, doc.session_id);
+        // The refresh token is rotated too.
+        assert_ne!(resp.refresh_token, "test-refresh-token");
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_unknown_token_is_rejected(conn: PoolConnection<Postgres>) {
+        let res = handler(
+            Conn(conn),
+            State(encoding_key()),
+            Json(Req {
+                refresh_token: "not-a-real-token".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(
+            res,
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                "invalid token".to_string()
+            )
+        );
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_reused_token_is_rejected_and_revokes_family(pool: Pool<Postgres>) {
+        let _ = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let first = handler(
+            Conn(pool.acquire().await.unwrap()),
+            State(encoding_key()),
+            Json(Req {
+                refresh_token: "test-refresh-token".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        // The original token was already spent above, so presenting it
+        // again looks like reuse of a stolen token.
+        let reused = handler(
+            Conn(pool.acquire().await.unwrap()),
+            State(encoding_key()),
+            Json(Req {
+                refresh_token: "test-refresh-token".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(
+            reused,
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                "invalid token".to_string()
+            )
+        );
+
+        // The whole family is revoked, so even the token the first,
+        // legitimate refresh minted no longer works.
+        let locked_out = handler(
+            Conn(pool.acquire().await.unwrap()),
+            State(encoding_key()),
+            Json(Req {
+                refresh_token: first.refresh_token,
+            }),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(
+            locked_out,
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                "invalid token".to_string()
+            )
+        );
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_concurrent_redemption_of_the_same_token_only_succeeds_once(pool: Pool<Postgres>) {
+        let _ = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        // Two requests racing on the same refresh token must not both mint
+        // a fresh session: whichever loses the race has to see this as
+        // reuse, the same as if it'd arrived after the winner.
+        let (first, second) = tokio::join!(
+            handler(
+                Conn(pool.acquire().await.unwrap()),
+                State(encoding_key()),
+                Json(Req {
+                    refresh_token: "test-refresh-token".to_string(),
+                }),
+            ),
+            handler(
+                Conn(pool.acquire().await.unwrap()),
+                State(encoding_key()),
+                Json(Req {
+                    refresh_token: "test-refresh-token".to_string(),
+                }),
+            ),
+        );
+
+        assert_eq!(
+            usize::from(first.is_ok()) + usize::from(second.is_ok()),
+            1,
+            "exactly one racer should win the redemption"
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use crate::account_tokens::{self, Purpose};
+use crate::authz::SharedAuthorizer;
 use crate::bail_if;
 use crate::error::Error;
 use crate::jwt;
+use crate::mailer::SharedMailer;
+use crate::session;
 use crate::state::AllowRegistration;
 use crate::{bail, conn::Conn};
 use argon2::{
@@ -9,22 +13,42 @@ use argon2::{
 };
 use axum::http::StatusCode;
 use axum::{extract::State, Json};
+use beeps_core::sync::authz;
 use beeps_core::sync::register::{Req, Resp};
 use jsonwebtoken::EncodingKey;
 use sqlx::Acquire;
 
-#[tracing::instrument(skip(conn, encoding_key, req), fields(req.email = %req.email))]
+#[expect(clippy::too_many_arguments)]
+#[tracing::instrument(skip(conn, encoding_key, mailer, req), fields(req.email = %req.email))]
 pub async fn handler(
     Conn(mut conn): Conn,
     State(AllowRegistration(allow_registration)): State<AllowRegistration>,
     State(encoding_key): State<EncodingKey>,
+    State(authorizer): State<SharedAuthorizer>,
+    State(mailer): State<SharedMailer>,
     Json(req): Json<Req>,
 ) -> Result<Json<Resp>, Error> {
     // Validation: don't allow any calls to this endpoint if we don't allow registration.
     bail_if!(
         !allow_registration,
-        "Registration is closed",
-        StatusCode::FORBIDDEN
+        StatusCode::FORBIDDEN,
+        "Registration is closed"
+    );
+
+    // Validation: let an external authorizer veto the registration.
+    let decision = authorizer
+        .authorize(&authz::Req {
+            email: Some(req.email.clone()),
+            document_id: None,
+            parts: None,
+        })
+        .await;
+    bail_if!(
+        !decision.allow,
+        StatusCode::FORBIDDEN,
+        &decision
+            .reason
+            .unwrap_or_else(|| "Registration was not authorized".to_string())
     );
 
     // Validation: don't allow a duplicate account if one exists.
@@ -46,24 +70,56 @@ pub async fn handler(
     let argon2 = Argon2::default();
     let salt = SaltString::generate(&mut OsRng);
 
-    sqlx::query!(
-        "INSERT INTO accounts (email, password) VALUES ($1, $2)",
+    let account = sqlx::query!(
+        "INSERT INTO accounts (email, password) VALUES ($1, $2) RETURNING id",
         req.email,
         argon2
             .hash_password(req.password.as_bytes(), &salt)?
             .to_string(),
     )
-    .execute(&mut *tx)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Assign this first device its own server-issued replica node ID, so its
+    // HLC clock can never collide with another replica's.
+    let replica = sqlx::query!(
+        "INSERT INTO replicas (account_id) VALUES ($1) RETURNING id, node_id",
+        account.id
+    )
+    .fetch_one(&mut *tx)
     .await?;
 
+    let created =
+        session::create(&mut tx, account.id, replica.id, req.device_label.as_deref()).await?;
+
+    let verification = account_tokens::issue(&mut tx, account.id, Purpose::Verify).await?;
+
     tx.commit().await?;
 
-    Ok(Json(Resp {
-        jwt: jwt::issue(
-            &encoding_key,
+    mailer
+        .send(
             &req.email,
-            0, // TODO
-        )?,
+            "Verify your beeps email",
+            &format!(
+                "Use this code to verify your email and start logging in: {}",
+                verification.token
+            ),
+        )
+        .await;
+
+    let (jwt, expires_at) = jwt::issue(
+        &encoding_key,
+        &req.email,
+        0, // TODO
+        replica.node_id,
+        created.id,
+    )?;
+
+    Ok(Json(Resp {
+        jwt,
+        expires_at,
+        refresh_token: created.refresh_token,
+        document_id: 0, // TODO: registration doesn't create a document yet, see above
     }))
 }
 
@@ -78,10 +134,28 @@ mod test {
         EncodingKey::from_secret(b"secret".as_ref())
     }
 
+    /// An authorizer that always denies, for testing the rejection path.
+    #[derive(Debug, Clone)]
+    struct DenyAll;
+
+    #[async_trait::async_trait]
+    impl crate::authz::Authorizer for DenyAll {
+        async fn authorize(&self, _req: &authz::Req) -> authz::Resp {
+            authz::Resp {
+                allow: false,
+                reason: Some("not on the allow-list".to_string()),
+            }
+        }
+    }
+
     fn decoding_key() -> DecodingKey {
         DecodingKey::from_secret(b"secret".as_ref())
     }
 
+    fn mailer() -> crate::mailer::SharedMailer {
+        std::sync::Arc::new(crate::mailer::test::RecordingMailer::default())
+    }
+
     #[test_log::test(sqlx::test)]
     async fn test_success(conn: PoolConnection<Postgres>) {
         let email = "test@example.com".to_string();
@@ -89,12 +163,17 @@ mod test {
         let req = Req {
             email: email.clone(),
             password: "test".to_string(),
+            device_label: None,
         };
 
+        let mailer = std::sync::Arc::new(crate::mailer::test::RecordingMailer::default());
+
         let resp = handler(
             Conn(conn),
             State(AllowRegistration(true)),
             State(encoding_key()),
+            State(std::sync::Arc::new(crate::authz::AllowAll)),
+            State(mailer.clone()),
             Json(req),
         )
         .await
@@ -105,6 +184,10 @@ mod test {
                 .unwrap();
 
         assert_eq!(token.claims.sub, email);
+
+        let sent = mailer.sent.lock().await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, email);
     }
 
     #[test_log::test(sqlx::test)]
@@ -120,12 +203,15 @@ mod test {
         let req = Req {
             email,
             password: "test".to_string(),
+            device_label: None,
         };
 
         let res = handler(
             Conn(conn),
             State(AllowRegistration(true)),
             State(encoding_key()),
+            State(std::sync::Arc::new(crate::authz::AllowAll)),
+            State(mailer()),
             Json(req),
         )
         .await
@@ -146,12 +232,15 @@ mod test {
         let req = Req {
             email: "test@example.com".to_string(),
             password: "test".to_string(),
+            device_label: None,
         };
 
         let res = handler(
             Conn(conn),
             State(AllowRegistration(false)),
             State(encoding_key()),
+            State(std::sync::Arc::new(crate::authz::AllowAll)),
+            State(mailer()),
             Json(req),
         )
         .await
@@ -163,4 +252,30 @@ mod test {
             (StatusCode::FORBIDDEN, "Registration is closed".to_string())
         );
     }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_authorizer_denies(conn: PoolConnection<Postgres>) {
+        let req = Req {
+            email: "test@example.com".to_string(),
+            password: "test".to_string(),
+            device_label: None,
+        };
+
+        let res = handler(
+            Conn(conn),
+            State(AllowRegistration(true)),
+            State(encoding_key()),
+            State(std::sync::Arc::new(DenyAll)),
+            State(mailer()),
+            Json(req),
+        )
+        .await
+        .unwrap_err()
+        .unwrap_custom();
+
+        assert_eq!(
+            res,
+            (StatusCode::FORBIDDEN, "not on the allow-list".to_string())
+        );
+    }
 }
@@ -0,0 +1,86 @@
+use crate::account_tokens::{self, Purpose};
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::mailer::SharedMailer;
+use axum::{extract::State, Json};
+use beeps_core::sync::reset::{RequestReq, RequestResp};
+use sqlx::Acquire;
+
+#[tracing::instrument(skip(conn, mailer, req), fields(req.email = %req.email))]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    State(mailer): State<SharedMailer>,
+    Json(req): Json<RequestReq>,
+) -> Result<Json<RequestResp>, Error> {
+    let account = sqlx::query!(
+        "SELECT id FROM accounts WHERE email = $1 LIMIT 1",
+        req.email
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    // Issue and email the token only if the account actually exists, but
+    // respond the same way either way: nothing here should tell a caller
+    // whether `req.email` is registered.
+    if let Some(account) = account {
+        let mut tx = conn.begin().await?;
+        let issued = account_tokens::issue(&mut tx, account.id, Purpose::Reset).await?;
+        tx.commit().await?;
+
+        mailer
+            .send(
+                &req.email,
+                "Reset your beeps password",
+                &format!("Here's your password reset code: {}", issued.token),
+            )
+            .await;
+    }
+
+    Ok(Json(RequestResp {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use crate::mailer::test::RecordingMailer;
+    use sqlx::{pool::PoolConnection, Postgres};
+    use std::sync::Arc;
+
+    #[test_log::test(sqlx::test)]
+    async fn test_known_email_sends_mail(mut conn: PoolConnection<Postgres>) {
+        let doc = TestDoc::create(&mut conn).await;
+        let mailer = Arc::new(RecordingMailer::default());
+
+        handler(
+            Conn(conn),
+            State(mailer.clone()),
+            Json(RequestReq {
+                email: doc.email.clone(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let sent = mailer.sent.lock().await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, doc.email);
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_unknown_email_is_silent(conn: PoolConnection<Postgres>) {
+        let mailer = Arc::new(RecordingMailer::default());
+
+        handler(
+            Conn(conn),
+            State(mailer.clone()),
+            Json(RequestReq {
+                email: "nobody@example.com".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert!(mailer.sent.lock().await.is_empty());
+    }
+}
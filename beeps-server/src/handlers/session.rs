@@ -0,0 +1,82 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::jwt::Claims;
+use crate::session;
+use axum::Json;
+use beeps_core::sync::session::{ListResp, RevokeReq, RevokeResp, Session};
+
+/// List the caller's own non-revoked, unexpired sessions, most recently
+/// active first.
+#[tracing::instrument(skip(conn, claims), fields(claims.sub = %claims.sub))]
+pub async fn list(Conn(mut conn): Conn, claims: Claims) -> Result<Json<ListResp>, Error> {
+    let sessions = session::list(&mut conn, &claims.sub)
+        .await?
+        .into_iter()
+        .map(|listed| Session {
+            current: listed.id == claims.session_id,
+            id: listed.id,
+            device_label: listed.device_label,
+            created_at: listed.created_at,
+            last_seen_at: listed.last_seen_at,
+            expires_at: listed.expires_at,
+        })
+        .collect();
+
+    Ok(Json(ListResp { sessions }))
+}
+
+/// Revoke one of the caller's own sessions, logging that device out.
+#[tracing::instrument(skip(conn, claims, req), fields(claims.sub = %claims.sub))]
+pub async fn revoke(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    Json(req): Json<RevokeReq>,
+) -> Result<Json<RevokeResp>, Error> {
+    session::revoke(&mut conn, &claims.sub, req.id).await?;
+
+    Ok(Json(RevokeResp {}))
+}
+
+/// Revoke the session behind the caller's own access token, logging this
+/// device out.
+#[tracing::instrument(skip(conn, claims), fields(claims.sub = %claims.sub))]
+pub async fn logout(Conn(mut conn): Conn, claims: Claims) -> Result<Json<RevokeResp>, Error> {
+    session::revoke(&mut conn, &claims.sub, claims.session_id).await?;
+
+    Ok(Json(RevokeResp {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::test::TestDoc;
+    use sqlx::{Pool, Postgres};
+
+    #[test_log::test(sqlx::test)]
+    async fn test_list_includes_current_session(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+
+        let Json(resp) = list(Conn(pool.acquire().await.unwrap()), doc.claims())
+            .await
+            .unwrap();
+
+        assert_eq!(resp.sessions.len(), 1);
+        assert!(resp.sessions[0].current);
+        assert_eq!(resp.sessions[0].id, doc.session_id);
+    }
+
+    #[test_log::test(sqlx::test)]
+    async fn test_logout_revokes_the_callers_own_session(pool: Pool<Postgres>) {
+        let doc = TestDoc::create(&mut pool.acquire().await.unwrap()).await;
+        let claims = doc.claims();
+
+        let _ = logout(Conn(pool.acquire().await.unwrap()), claims)
+            .await
+            .unwrap();
+
+        let remaining = session::list(&mut pool.acquire().await.unwrap(), &doc.email)
+            .await
+            .unwrap();
+        assert!(remaining.is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::conn::Conn;
+use crate::error::Error;
+use crate::handlers::pull;
+use crate::jwt::Claims;
+use crate::state::Subscriptions;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use beeps_core::{split::Split, sync::version_vector::VersionVector, Document};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+/// How often to ping an otherwise-idle subscriber, so a connection that
+/// silently dropped (e.g. behind a NAT) gets noticed and cleaned up instead
+/// of leaking forever.
+const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// Upgrade to a WebSocket and stream document updates to the client as
+/// they're pushed by other replicas, instead of the client having to `pull`
+/// on a timer.
+///
+/// We don't bother checking that `claims.document_id` matches a document the
+/// caller actually has access to beyond what the JWT already grants; the
+/// token is scoped to a single document, so there's nothing else to subscribe
+/// to.
+#[tracing::instrument(skip(ws))]
+pub async fn handler(
+    Conn(mut conn): Conn,
+    claims: Claims,
+    State(subscriptions): State<Subscriptions>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, Error> {
+    // Snapshot the document as it stands right now, before subscribing, so a
+    // client that only just connected doesn't have to race a separate `pull`
+    // against this stream to find out what it missed.
+    let (parts, _, _) =
+        pull::parts_since(&mut conn, claims.document_id, &VersionVector::new(), None).await?;
+    let mut document = Document::default();
+    for part in parts {
+        document.merge_part(part);
+    }
+
+    let receiver = subscriptions.subscribe(claims.document_id).await;
+
+    Ok(ws.on_upgrade(move |socket| stream_updates(socket, document, receiver)))
+}
+
+/// Drive a single subscriber's socket: send the snapshot taken before we
+/// subscribed, then forward every update the document gets merged with,
+/// heartbeating in between so a dead connection gets noticed and closed.
+async fn stream_updates(
+    mut socket: WebSocket,
+    document: Document,
+    receiver: broadcast::Receiver<Document>,
+) {
+    let Some(snapshot) = document_message(&document) else {
+        return;
+    };
+    if socket.send(snapshot).await.is_err() {
+        return;
+    }
+
+    let mut updates = BroadcastStream::new(receiver);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT);
+    heartbeat.tick().await; // the first tick fires immediately; skip it
+
+    loop {
+        tokio::select! {
+            update = updates.next() => {
+                let Some(update) = update else {
+                    return;
+                };
+
+                // A lagged receiver just means we missed some updates; the
+                // next pull will catch us back up, so skip it rather than
+                // disconnecting.
+                let Ok(document) = update else {
+                    continue;
+                };
+
+                let Some(message) = document_message(&document) else {
+                    continue;
+                };
+                if socket.send(message).await.is_err() {
+                    return;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    return;
+                }
+            }
+            incoming = socket.recv() => {
+                // Axum answers protocol-level pings for us; anything we see
+                // here is either the client closing, or the connection
+                // failing outright, so either way we're done.
+                match incoming {
+                    None | Some(Err(_)) | Some(Ok(Message::Close(_))) => return,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
+
+/// Serialize a document update into a WebSocket text frame, logging (rather
+/// than failing the whole connection) if it can't be serialized.
+fn document_message(document: &Document) -> Option<Message> {
+    match serde_json::to_string(document) {
+        Ok(json) => Some(Message::Text(json)),
+        Err(problem) => {
+            tracing::error!(?problem, "couldn't serialize document update");
+            None
+        }
+    }
+}
@@ -6,12 +6,15 @@ use chrono::{Duration, Utc};
 use sqlx::{pool::PoolConnection, query, Acquire, Postgres, Row};
 
 use crate::jwt::Claims;
+use crate::session;
 
 /// A document for use in testing
 pub struct TestDoc {
     pub email: String,
     pub password: String,
     pub document_id: i64,
+    pub node_id: i32,
+    pub session_id: i64,
 }
 
 impl TestDoc {
@@ -28,14 +31,16 @@ impl TestDoc {
 
         let mut tx = pool.begin().await.unwrap();
 
-        let account_id: i64 =
-            query("INSERT INTO accounts (email, password) VALUES ($1, $2) RETURNING id::BIGINT")
-                .bind(&email)
-                .bind(&hash)
-                .fetch_one(&mut *tx)
-                .await
-                .expect("failed to insert account")
-                .get("id");
+        let account_id: i64 = query(
+            "INSERT INTO accounts (email, password, email_verified_at) \
+            VALUES ($1, $2, NOW()) RETURNING id::BIGINT",
+        )
+        .bind(&email)
+        .bind(&hash)
+        .fetch_one(&mut *tx)
+        .await
+        .expect("failed to insert account")
+        .get("id");
 
         let document_id =
             query("INSERT INTO documents (owner_id) VALUES ($1) RETURNING id::BIGINT")
@@ -45,12 +50,39 @@ impl TestDoc {
                 .expect("failed to insert document")
                 .get("id");
 
+        // Pinned to node 0 rather than drawn from `replica_node_ids` (which
+        // starts at 1), so tests that stamp clocks with `NodeId::min()` push
+        // them as this replica's own.
+        let replica =
+            query("INSERT INTO replicas (account_id, node_id) VALUES ($1, 0) RETURNING id::BIGINT, node_id")
+                .bind(&account_id)
+                .fetch_one(&mut *tx)
+                .await
+                .expect("failed to insert replica");
+        let replica_id: i64 = replica.get("id");
+        let node_id: i32 = replica.get("node_id");
+
+        let session_id: i64 = query(
+            "INSERT INTO sessions (account_id, replica_id, refresh_token_hash, expires_at) \
+            VALUES ($1, $2, $3, $4) RETURNING id::BIGINT",
+        )
+        .bind(&account_id)
+        .bind(&replica_id)
+        .bind(session::hash("test-refresh-token"))
+        .bind(Utc::now() + Duration::days(30))
+        .fetch_one(&mut *tx)
+        .await
+        .expect("failed to insert session")
+        .get("id");
+
         tx.commit().await.expect("failed to commit transaction");
 
         TestDoc {
             email,
             password,
             document_id,
+            node_id,
+            session_id,
         }
     }
 
@@ -60,6 +92,9 @@ impl TestDoc {
             sub: self.email.clone(),
             iat: Utc::now().timestamp(),
             exp: (Utc::now() + Duration::days(90)).timestamp(),
+            document_id: self.document_id,
+            node_id: self.node_id,
+            session_id: self.session_id,
         }
     }
 }
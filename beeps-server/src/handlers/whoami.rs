@@ -4,7 +4,10 @@ use beeps_core::sync::whoami;
 
 #[tracing::instrument]
 pub async fn handler(claims: Claims) -> Json<whoami::Resp> {
-    Json(whoami::Resp { email: claims.sub })
+    Json(whoami::Resp {
+        email: claims.sub,
+        node_id: claims.node_id,
+    })
 }
 
 #[cfg(test)]
@@ -18,10 +21,18 @@ mod test {
             iat: 0,
             exp: 1,
             document_id: 2,
+            node_id: 3,
+            session_id: 4,
         };
 
         let Json(resp) = handler(claims.clone()).await;
 
-        assert_eq!(resp, whoami::Resp { email: claims.sub });
+        assert_eq!(
+            resp,
+            whoami::Resp {
+                email: claims.sub,
+                node_id: claims.node_id,
+            }
+        );
     }
 }
@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::metrics::Metrics;
 use axum::extract::FromRef;
 use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
@@ -8,6 +9,13 @@ use axum_extra::TypedHeader;
 use jsonwebtoken::EncodingKey;
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/// How long an access token is good for. The token itself can't be revoked,
+/// but the session it was minted from is what `Claims::from_request_parts`
+/// checks on every request, so revoking that session cuts the token off
+/// before it expires.
+const ACCESS_TOKEN_TTL: chrono::Duration = chrono::Duration::minutes(15);
 
 /// Claims a JWT can make in our system
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -24,16 +32,28 @@ pub struct Claims {
 
     /// What document ID this token grants access to.
     pub document_id: i64,
+
+    /// The server-assigned node ID for this replica, so the client can seed
+    /// its HLC clock with an ID that's guaranteed unique across every other
+    /// replica in the deployment.
+    pub node_id: i32,
+
+    /// The session this access token was minted from. Checked against the
+    /// `sessions` table on every request, so revoking or expiring a session
+    /// takes effect well before this token's own `exp` would.
+    pub session_id: i64,
 }
 
 impl Claims {
     #[cfg(test)]
-    pub fn test(sub: &str, document_id: i64) -> Self {
+    pub fn test(sub: &str, document_id: i64, node_id: i32, session_id: i64) -> Self {
         Self {
             sub: sub.to_string(),
             iat: 0,
             exp: (chrono::Utc::now() + chrono::Duration::days(30)).timestamp(),
             document_id,
+            node_id,
+            session_id,
         }
     }
 
@@ -47,40 +67,123 @@ impl Claims {
     }
 }
 
-/// Issue a new JWT with the given subject and document ID
+/// Read the document ID out of a bearer token's claims without verifying
+/// its signature. Only meant for cluster routing, to decide which node
+/// should handle a request before paying for real verification; the token
+/// still goes through full, signature-checked `Claims` extraction wherever
+/// it's ultimately handled, whether that's here or after being proxied.
+pub fn peek_document_id(token: &str) -> Option<i64> {
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+
+    decode::<Claims>(token, &DecodingKey::from_secret(&[]), &validation)
+        .ok()
+        .map(|data| data.claims.document_id)
+}
+
+/// Issue a new access JWT for the given subject, document ID, replica node
+/// ID, and session. Returns the token alongside its `exp` as a
+/// `DateTime<Utc>`, so a caller can hand `expires_at` back to the client
+/// without having to decode the token it just minted.
 pub fn issue(
     encoding_key: &EncodingKey,
     sub: &str,
     document_id: i64,
-) -> jsonwebtoken::errors::Result<String> {
+    node_id: i32,
+    session_id: i64,
+) -> jsonwebtoken::errors::Result<(String, chrono::DateTime<chrono::Utc>)> {
+    let now = chrono::Utc::now();
+    let expires_at = now + ACCESS_TOKEN_TTL;
     let claims = Claims {
         sub: sub.to_string(),
-        iat: 0,
-        exp: (chrono::Utc::now() + chrono::Duration::days(90)).timestamp(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
         document_id,
+        node_id,
+        session_id,
     };
 
-    jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, encoding_key)
+    let token = jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, encoding_key)?;
+
+    Ok((token, expires_at))
 }
 
 impl<S> FromRequestParts<S> for Claims
 where
     DecodingKey: FromRef<S>,
+    Metrics: FromRef<S>,
+    PgPool: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = Error;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| Error::custom("missing or invalid authorization header"))?;
+        let metrics = Metrics::from_ref(state);
+
+        let Ok(TypedHeader(Authorization(bearer))) =
+            parts.extract::<TypedHeader<Authorization<Bearer>>>().await
+        else {
+            metrics.auth_failure();
+            return Err(Error::custom("missing or invalid authorization header"));
+        };
+
+        let claims = match Claims::from_str(bearer.token(), &DecodingKey::from_ref(state)) {
+            Ok(claims) => claims,
+            Err(_) => {
+                metrics.auth_failure();
+                return Err(Error::custom("invalid token"));
+            }
+        };
 
-        Claims::from_str(bearer.token(), &DecodingKey::from_ref(state))
-            .map_err(|_| Error::custom("invalid token"))
+        // The JWT signature checks out, but the session it was minted from
+        // might have been revoked (or simply expired) since; that's the
+        // part that actually lets us kick a device out before its access
+        // token's own `exp` would otherwise force it to.
+        let pool = PgPool::from_ref(state);
+        let touched = sqlx::query!(
+            "UPDATE sessions SET last_seen_at = NOW() \
+            WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW() \
+            RETURNING id",
+            claims.session_id,
+        )
+        .fetch_optional(&pool)
+        .await?;
+
+        if touched.is_none() {
+            metrics.auth_failure();
+            return Err(session_rejected(&pool, claims.session_id).await?);
+        }
+
+        metrics.auth_success();
+        Ok(claims)
     }
 }
 
+/// Figure out why a session no longer checks out, so a revoked session
+/// (which a retry or refresh can't fix) reads differently to the caller
+/// than an expired one (which a refresh can).
+async fn session_rejected(pool: &PgPool, session_id: i64) -> Result<Error, Error> {
+    let session = sqlx::query!(
+        "SELECT revoked_at, expires_at FROM sessions WHERE id = $1",
+        session_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(match session {
+        Some(session) if session.revoked_at.is_some() => Error::custom_with_status(
+            "this session has been revoked, log in again",
+            axum::http::StatusCode::UNAUTHORIZED,
+        ),
+        Some(session) if session.expires_at <= chrono::Utc::now() => Error::custom_with_status(
+            "this session has expired, log in again",
+            axum::http::StatusCode::UNAUTHORIZED,
+        ),
+        _ => Error::custom_with_status("invalid token", axum::http::StatusCode::UNAUTHORIZED),
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -88,7 +191,7 @@ mod test {
 
     #[test]
     fn valid_token() {
-        let claims = Claims::test("test@example.com", 1);
+        let claims = Claims::test("test@example.com", 1, 7, 1);
         let key = EncodingKey::from_secret(b"secret");
         let token = encode(&jsonwebtoken::Header::default(), &claims, &key).unwrap();
 
@@ -104,6 +207,8 @@ mod test {
             iat: 0,
             exp: 0,
             document_id: 1,
+            node_id: 1,
+            session_id: 1,
         };
         let key = EncodingKey::from_secret(b"secret");
         let token = encode(&jsonwebtoken::Header::default(), &claims, &key).unwrap();
@@ -0,0 +1,142 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::Arc;
+
+/// Something that can send a plain-text email, so the recovery/verification
+/// handlers don't have to care whether that means a real SMTP relay or a
+/// test double that just remembers what it was asked to send.
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    /// Send a plain-text email. Implementations should log and swallow
+    /// transport errors rather than let a flaky mail relay take the
+    /// request down with it; the caller treats "couldn't send" the same as
+    /// "sent but the user will never see it", i.e. there's nothing more to
+    /// do about it here.
+    async fn send(&self, to: &str, subject: &str, body: &str);
+}
+
+/// A shared handle to whichever `Mailer` the server is configured with.
+pub type SharedMailer = Arc<dyn Mailer>;
+
+/// The default mailer: doesn't send anything, just logs what would have
+/// been sent. This is what the server uses when no SMTP relay is
+/// configured, so recovery/verification links can still be read out of the
+/// logs in development instead of requiring a real mail server.
+#[derive(Debug, Clone, Default)]
+pub struct LoggingMailer;
+
+#[async_trait::async_trait]
+impl Mailer for LoggingMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) {
+        tracing::info!(to, subject, body, "mailer not configured, logging instead");
+    }
+}
+
+/// A mailer that relays through SMTP.
+#[derive(Clone)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// Build a mailer that relays through the SMTP server at `relay`,
+    /// authenticating with `username`/`password`, and sending as `from`.
+    ///
+    /// ## Errors
+    ///
+    /// If `relay` or `from` can't be parsed, or the transport can't be
+    /// built.
+    pub fn new(relay: &str, username: String, password: String, from: &str) -> Result<Self, Error> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::relay(relay)?
+            .credentials(Credentials::new(username, password))
+            .build();
+
+        Ok(Self {
+            transport,
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) {
+        let message = match Message::builder()
+            .from(self.from.clone())
+            .to(match to.parse() {
+                Ok(to) => to,
+                Err(problem) => {
+                    tracing::error!(?problem, to, "couldn't parse recipient address");
+                    return;
+                }
+            })
+            .subject(subject)
+            .body(body.to_string())
+        {
+            Ok(message) => message,
+            Err(problem) => {
+                tracing::error!(?problem, "couldn't build email");
+                return;
+            }
+        };
+
+        if let Err(problem) = self.transport.send(message).await {
+            tracing::error!(?problem, to, "couldn't send email");
+        }
+    }
+}
+
+/// Problems setting up an `SmtpMailer`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The SMTP relay address, or the `from` address, wasn't usable.
+    #[error("invalid mailer configuration: {0}")]
+    Config(String),
+}
+
+impl From<lettre::transport::smtp::Error> for Error {
+    fn from(err: lettre::transport::smtp::Error) -> Self {
+        Self::Config(err.to_string())
+    }
+}
+
+impl From<lettre::address::AddressError> for Error {
+    fn from(err: lettre::address::AddressError) -> Self {
+        Self::Config(err.to_string())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Mailer;
+    use tokio::sync::Mutex;
+
+    /// An email a `RecordingMailer` was asked to send.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Sent {
+        pub to: String,
+        pub subject: String,
+        pub body: String,
+    }
+
+    /// A mailer that remembers every email it was asked to send, instead of
+    /// sending anything, so tests can assert on a reset/verification link
+    /// without running a real mail server.
+    #[derive(Debug, Default)]
+    pub struct RecordingMailer {
+        pub sent: Mutex<Vec<Sent>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Mailer for RecordingMailer {
+        async fn send(&self, to: &str, subject: &str, body: &str) {
+            self.sent.lock().await.push(Sent {
+                to: to.to_string(),
+                subject: subject.to_string(),
+                body: body.to_string(),
+            });
+        }
+    }
+}
@@ -1,5 +1,16 @@
 //! A sync server for beeps.
 
+/// Single-use, expiring tokens backing password reset and email
+/// verification
+mod account_tokens;
+
+/// Pluggable external authorization for registration and push
+mod authz;
+
+/// Document-to-node ownership, for partitioning documents across several
+/// server nodes
+mod cluster;
+
 /// Get a database connection for a request
 mod conn;
 
@@ -12,9 +23,39 @@ mod handlers;
 /// JWT auth for requests
 mod jwt;
 
+/// Sends password reset and email verification links
+mod mailer;
+
+/// Per-document Merkle tree for anti-entropy sync, maintained alongside the
+/// tables it summarizes
+mod merkle;
+
+/// Prometheus/OTLP metrics, and the `/metrics` route
+mod metrics;
+
+/// Background sweep that pushes a Web Push reminder for each document with
+/// a ping due
+mod notifier;
+
+/// Discovery, PKCE, and ID token verification for single-sign-on via OIDC
+mod oidc;
+
+/// Caps how many attempts a key (e.g. an email or IP) gets per window
+mod rate_limit;
+
+/// Device sessions backing refresh tokens, so an access token can be
+/// revoked before its own expiry
+mod session;
+
 /// Shared state for requests
 mod state;
 
+/// RFC 6238 TOTP codes for two-factor authentication
+mod totp;
+
+/// RFC 8291/8292 message encryption and VAPID signing for Web Push
+mod webpush;
+
 use crate::state::State;
 use axum::{
     http::header::AUTHORIZATION,
@@ -23,6 +64,11 @@ use axum::{
 };
 use beeps_core::sync;
 use clap::Parser;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server,
+    service::TowerToHyperService,
+};
 use sqlx::{migrate, postgres::PgPoolOptions};
 use std::{iter::once, num::ParseIntError, time::Duration};
 use tokio::net::TcpListener;
@@ -65,6 +111,110 @@ struct Config {
     /// Whether or not to allow new registrations
     #[clap(long, env)]
     allow_registration: bool,
+
+    /// URL of an external authorization service to consult before accepting
+    /// a registration or push. When unset, everything is allowed.
+    #[clap(long, env)]
+    authz_url: Option<String>,
+
+    /// Issuer URL of an OIDC provider to support single-sign-on against, e.g.
+    /// `https://accounts.google.com`. When unset, only password login is
+    /// available.
+    #[clap(long, env)]
+    oidc_issuer: Option<String>,
+
+    /// Client ID registered with the OIDC provider. Required if
+    /// `--oidc-issuer` is set.
+    #[clap(long, env)]
+    oidc_client_id: Option<String>,
+
+    /// Client secret registered with the OIDC provider. Required if
+    /// `--oidc-issuer` is set.
+    #[clap(long, env)]
+    oidc_client_secret: Option<String>,
+
+    /// Where the OIDC provider should redirect back to after the user
+    /// authenticates. Required if `--oidc-issuer` is set.
+    #[clap(long, env)]
+    oidc_redirect_uri: Option<String>,
+
+    /// Comma-separated list of email domains allowed to log in or be
+    /// auto-provisioned via OIDC. When unset, any verified email is
+    /// accepted.
+    #[clap(long, env)]
+    oidc_allowed_email_domains: Option<String>,
+
+    /// URL of a Redis instance to relay document updates through, so
+    /// subscribers stay in sync across multiple server instances. When
+    /// unset, updates are only fanned out to subscribers on the same
+    /// instance that received the push.
+    #[clap(long, env)]
+    redis_url: Option<String>,
+
+    /// Address of an OTLP collector to also push metrics to. When unset,
+    /// metrics are only available locally via `/metrics`.
+    #[clap(long, env)]
+    otel_endpoint: Option<String>,
+
+    /// Accept HTTP/2 over cleartext (h2c) in addition to HTTP/1.1, so one
+    /// device can multiplex many concurrent sync requests over a single
+    /// connection instead of opening one per request. Off by default, since
+    /// it means accepting connections by hand instead of via `axum::serve`.
+    #[clap(long, env)]
+    h2c: bool,
+
+    /// Request body size limit for the sync ingest routes, in bytes. Kept
+    /// separate from `--body-limit` since an initial sync can legitimately
+    /// ship a much larger payload than anything else this server handles.
+    #[clap(long, env, default_value = "67108864")]
+    sync_body_limit: usize,
+
+    /// Base64url-encoded VAPID private key, used to sign Web Push requests.
+    /// When unset, push notifications are disabled.
+    #[clap(long, env)]
+    vapid_private_key: Option<String>,
+
+    /// Contact URI (`mailto:` or `https:`) to identify ourselves with in
+    /// the VAPID JWT. Required if `--vapid-private-key` is set.
+    #[clap(long, env)]
+    vapid_subject: Option<String>,
+
+    /// How often to sweep for documents with a ping due and push a
+    /// reminder, in seconds. Only relevant if `--vapid-private-key` is set.
+    #[clap(long, env, default_value = "60", value_parser = duration_parser)]
+    push_notification_interval: Duration,
+
+    /// This node's own address (e.g. `http://beeps-a:3000`), as it should
+    /// appear in `--cluster-nodes`. Required if `--cluster-nodes` is set.
+    #[clap(long, env)]
+    cluster_self_node: Option<String>,
+
+    /// Comma-separated addresses of every node in the deployment (including
+    /// this one), used to partition documents across them by consistent
+    /// hashing. When unset, this node treats every document as its own.
+    #[clap(long, env)]
+    cluster_nodes: Option<String>,
+
+    /// Address of the SMTP relay to send password reset and email
+    /// verification links through. When unset, those emails are only
+    /// logged, not sent, which is fine for development but not production.
+    #[clap(long, env)]
+    smtp_relay: Option<String>,
+
+    /// Username to authenticate to `--smtp-relay` with. Required if
+    /// `--smtp-relay` is set.
+    #[clap(long, env)]
+    smtp_username: Option<String>,
+
+    /// Password to authenticate to `--smtp-relay` with. Required if
+    /// `--smtp-relay` is set.
+    #[clap(long, env)]
+    smtp_password: Option<String>,
+
+    /// Address to send reset/verification emails from. Required if
+    /// `--smtp-relay` is set.
+    #[clap(long, env)]
+    smtp_from: Option<String>,
 }
 
 /// Parse a duration from a string
@@ -76,7 +226,6 @@ fn duration_parser(s: &str) -> Result<Duration, ParseIntError> {
 async fn main() {
     let options = Config::parse();
 
-    // TODO: opentelemetry
     tracing_subscriber::registry()
         .with(
             EnvFilter::builder()
@@ -107,30 +256,256 @@ async fn main() {
 
     tracing::info!(?options.allow_registration, "registration status");
 
-    let state = State::new(pool, &options.jwt_secret, options.allow_registration)
-        .expect("could not initialize state");
+    let authorizer: authz::SharedAuthorizer = match options.authz_url {
+        Some(url) => std::sync::Arc::new(authz::HttpAuthorizer::new(url)),
+        None => std::sync::Arc::new(authz::AllowAll),
+    };
+
+    tracing::info!(
+        redis_configured = options.redis_url.is_some(),
+        "fan-out mode"
+    );
+
+    let subscriptions = state::Subscriptions::new(options.redis_url.as_deref())
+        .expect("could not connect to redis");
+
+    tracing::info!(
+        otel_configured = options.otel_endpoint.is_some(),
+        "metrics push mode"
+    );
+    let metrics = metrics::Metrics::new(pool.clone(), options.otel_endpoint.as_deref())
+        .expect("could not initialize metrics");
+
+    tracing::info!(oidc_configured = options.oidc_issuer.is_some(), "SSO mode");
+
+    let http_client = reqwest::Client::new();
+
+    let oidc = match options.oidc_issuer {
+        Some(issuer) => {
+            let allowed_email_domains = options
+                .oidc_allowed_email_domains
+                .map(|domains| domains.split(',').map(str::to_string).collect())
+                .unwrap_or_default();
+
+            let config = oidc::OidcConfig::discover(
+                &http_client,
+                issuer,
+                options
+                    .oidc_client_id
+                    .expect("--oidc-client-id is required when --oidc-issuer is set"),
+                options
+                    .oidc_client_secret
+                    .expect("--oidc-client-secret is required when --oidc-issuer is set"),
+                options
+                    .oidc_redirect_uri
+                    .expect("--oidc-redirect-uri is required when --oidc-issuer is set"),
+                allowed_email_domains,
+            )
+            .await
+            .expect("could not discover OIDC provider");
+
+            Some(std::sync::Arc::new(config))
+        }
+        None => None,
+    };
+
+    tracing::info!(
+        push_notifications_configured = options.vapid_private_key.is_some(),
+        "push notification mode"
+    );
+
+    if let Some(vapid_private_key) = options.vapid_private_key {
+        let vapid_subject = options
+            .vapid_subject
+            .expect("--vapid-subject is required when --vapid-private-key is set");
+
+        let vapid = webpush::VapidConfig::from_base64(&vapid_private_key, vapid_subject)
+            .expect("invalid VAPID private key");
+        let sender = webpush::Sender::new(vapid, http_client.clone());
+
+        tokio::spawn(notifier::run(
+            pool.clone(),
+            sender,
+            options.push_notification_interval,
+        ));
+    }
+
+    tracing::info!(
+        cluster_configured = options.cluster_nodes.is_some(),
+        "cluster mode"
+    );
+
+    let cluster = options.cluster_nodes.map(|nodes| {
+        let self_node = options
+            .cluster_self_node
+            .expect("--cluster-self-node is required when --cluster-nodes is set");
+
+        std::sync::Arc::new(cluster::ClusterMetadata::new(
+            self_node,
+            nodes.split(',').map(str::to_string).collect(),
+        ))
+    });
+
+    tracing::info!(
+        smtp_configured = options.smtp_relay.is_some(),
+        "mailer mode"
+    );
+
+    let mailer: mailer::SharedMailer = match options.smtp_relay {
+        Some(relay) => std::sync::Arc::new(
+            mailer::SmtpMailer::new(
+                &relay,
+                options
+                    .smtp_username
+                    .expect("--smtp-username is required when --smtp-relay is set"),
+                options
+                    .smtp_password
+                    .expect("--smtp-password is required when --smtp-relay is set"),
+                &options
+                    .smtp_from
+                    .expect("--smtp-from is required when --smtp-relay is set"),
+            )
+            .expect("invalid mailer configuration"),
+        ),
+        None => std::sync::Arc::new(mailer::LoggingMailer),
+    };
+
+    let state = State::new(
+        pool,
+        &options.jwt_secret,
+        options.allow_registration,
+        authorizer,
+        subscriptions,
+        oidc,
+        http_client,
+        metrics.clone(),
+        cluster,
+        mailer,
+    )
+    .expect("could not initialize state");
+
+    // The sync ingest routes get their own, typically much larger, body
+    // limit, so a big initial sync doesn't force the limit on every other
+    // route up with it. They're also the only routes cluster-routed
+    // automatically, by the document ID in their bearer token; see
+    // `cluster::route_pushes_to_owner`.
+    let sync_ingest = Router::new()
+        .route(sync::push::PATH, post(handlers::push::handler))
+        .route(
+            sync::push::STREAM_PATH,
+            post(handlers::push::stream_handler),
+        )
+        .layer(limit::RequestBodyLimitLayer::new(options.sync_body_limit))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            cluster::route_pushes_to_owner,
+        ));
 
     let app = Router::new()
         // ROUTES
         .route("/health", get(handlers::health::handler))
+        .route("/metrics", get(metrics::handler))
         .route(sync::register::PATH, post(handlers::register::handler))
+        .route(sync::batch::PATH, post(handlers::batch::handler))
         .route(sync::login::PATH, post(handlers::login::handler))
+        .route(sync::refresh::PATH, post(handlers::refresh::handler))
+        .route(
+            sync::reset::REQUEST_PATH,
+            post(handlers::request_reset::handler),
+        )
+        .route(
+            sync::reset::CONFIRM_PATH,
+            post(handlers::confirm_reset::handler),
+        )
+        .route(
+            sync::reset::CONFIRM_EMAIL_PATH,
+            post(handlers::confirm_email::handler),
+        )
         .route(sync::whoami::PATH, get(handlers::whoami::handler))
+        .route(
+            sync::totp::ENROLL_PATH,
+            post(handlers::enroll_totp::handler),
+        )
+        .route(sync::oauth::START_PATH, get(handlers::oauth::start))
+        .route(sync::oauth::CALLBACK_PATH, get(handlers::oauth::callback))
+        .route(sync::session::LOGOUT_PATH, post(handlers::session::logout))
+        .route(sync::session::LIST_PATH, get(handlers::session::list))
+        .route(sync::session::REVOKE_PATH, post(handlers::session::revoke))
         .route(sync::documents::PATH, get(handlers::documents::handler))
+        .route(sync::subscribe::PATH, get(handlers::subscribe::handler))
+        .route(sync::poll::PATH, post(handlers::poll::handler))
+        .route(
+            sync::push_subscription::PATH,
+            post(handlers::push_subscription::handler),
+        )
+        .route(sync::merkle::PATH, post(handlers::merkle::handler))
+        .route(
+            sync::chunked_pull::PATH,
+            post(handlers::chunked_pull::handler),
+        )
+        .layer(limit::RequestBodyLimitLayer::new(options.body_limit))
+        .merge(sync_ingest)
         // STATE
         .with_state(state)
         // MIDDLEWARE
+        .layer(axum::middleware::from_fn_with_state(
+            metrics,
+            metrics::track_http_requests,
+        ))
         .layer(trace::TraceLayer::new_for_http())
         .layer(compression::CompressionLayer::new())
         .layer(decompression::DecompressionLayer::new())
-        .layer(limit::RequestBodyLimitLayer::new(options.body_limit))
         .layer(sensitive_headers::SetSensitiveHeadersLayer::new(once(
             AUTHORIZATION,
         )))
         .layer(timeout::TimeoutLayer::new(options.request_timeout));
 
     let listener = TcpListener::bind(options.address).await.unwrap();
-    tracing::info!(address = ?listener.local_addr(), "listening");
+    tracing::info!(address = ?listener.local_addr(), h2c = options.h2c, "listening");
+
+    if options.h2c {
+        serve_h2c(listener, app).await;
+    } else {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    }
+}
+
+/// Serve `app` over plain HTTP/2 (h2c), alongside HTTP/1.1, so a single
+/// device can multiplex many concurrent sync requests over one connection
+/// instead of paying a new connection's overhead per request. `axum::serve`
+/// only speaks HTTP/1.1, so this accepts connections by hand instead.
+async fn serve_h2c(listener: TcpListener, app: Router) {
+    let builder = server::conn::auto::Builder::new(TokioExecutor::new());
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                tracing::warn!(?err, "could not accept connection");
+                continue;
+            }
+        };
+
+        let builder = builder.clone();
+        // `axum::serve`'s `into_make_service_with_connect_info` isn't
+        // available here since we're driving hyper by hand; attach the
+        // same `ConnectInfo` extension it would have, so handlers that
+        // extract it (e.g. for rate limiting) work the same under h2c.
+        let service = TowerToHyperService::new(
+            app.clone()
+                .layer(axum::Extension(axum::extract::ConnectInfo(addr))),
+        );
 
-    axum::serve(listener, app).await.unwrap();
+        tokio::spawn(async move {
+            let io = TokioIo::new(stream);
+            if let Err(err) = builder.serve_connection_with_upgrades(io, service).await {
+                tracing::warn!(?err, "error serving h2c connection");
+            }
+        });
+    }
 }
@@ -0,0 +1,321 @@
+use crate::error::Error;
+use beeps_core::{Hlc, NodeId};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+/// How many hex nibbles deep the tree subdivides before a node lists the
+/// ops underneath it directly instead of pointing at more children. Four
+/// nibbles (the key's first two bytes) gives 65,536 buckets, small enough
+/// that a bucket's membership can be recomputed with one indexed query
+/// instead of needing a second pass to decide when to split it.
+const LEAF_DEPTH: usize = 4;
+
+/// The fixed-width identity of a clocked op: `(timestamp, counter, node)`,
+/// in that order, matching `Hlc`'s own tie-breaking so two replicas that
+/// agree on every op land on the same bytes for it.
+fn key(clock: &Hlc) -> [u8; 12] {
+    let mut bytes = [0; 12];
+    bytes[0..8].copy_from_slice(&clock.timestamp().timestamp_micros().to_be_bytes());
+    bytes[8..10].copy_from_slice(&clock.counter().to_be_bytes());
+    bytes[10..12].copy_from_slice(&(*clock.node()).to_be_bytes());
+    bytes
+}
+
+/// The byte key an op's row should store in its `merkle_key` column.
+pub fn merkle_key(clock: &Hlc) -> Vec<u8> {
+    key(clock).to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The two-byte prefix a leaf-depth path decodes back to, for filtering
+/// `merkle_key` columns down to just the ops in that bucket.
+fn leaf_prefix_bytes(path: &str) -> Vec<u8> {
+    (0..LEAF_DEPTH / 2)
+        .map(|byte| u8::from_str_radix(&path[byte * 2..byte * 2 + 2], 16).unwrap_or(0))
+        .collect()
+}
+
+/// Recompute every node on the path from `clock`'s leaf bucket up to the
+/// root, after a write that changed which op lives in it. Cheap: the leaf
+/// query is scoped to just that one bucket, and every level above it is at
+/// most 16 sibling lookups.
+///
+/// ## Errors
+///
+/// If any of the underlying queries fail.
+pub async fn update(conn: &mut PgConnection, document_id: i64, clock: &Hlc) -> Result<(), Error> {
+    let full_path = hex(&key(clock));
+    let leaf_path = &full_path[..LEAF_DEPTH];
+
+    let hash = leaf_hash(conn, document_id, leaf_path).await?;
+    upsert(conn, document_id, leaf_path, &hash).await?;
+
+    for depth in (0..LEAF_DEPTH).rev() {
+        let path = &leaf_path[..depth];
+        let hash = branch_hash(conn, document_id, path).await?;
+        upsert(conn, document_id, path, &hash).await?;
+    }
+
+    Ok(())
+}
+
+/// Hash together every op stored under leaf-depth path `prefix`, across
+/// every table that carries a clock.
+async fn leaf_hash(
+    conn: &mut PgConnection,
+    document_id: i64,
+    prefix: &str,
+) -> Result<Vec<u8>, Error> {
+    let prefix_bytes = leaf_prefix_bytes(prefix);
+
+    let mut op_hashes: Vec<Vec<u8>> = sqlx::query!(
+        "SELECT timestamp, counter, node FROM minutes_per_pings \
+        WHERE document_id = $1 AND substring(merkle_key from 1 for 2) = $2",
+        document_id,
+        prefix_bytes,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Sha256::digest(key(&Hlc::new_at(
+            row.node.try_into()?,
+            row.timestamp,
+            row.counter.try_into()?,
+        )))
+        .to_vec())
+    })
+    .collect::<Result<_, Error>>()?;
+
+    let ping_hashes: Vec<Vec<u8>> = sqlx::query!(
+        "SELECT timestamp, counter, node FROM pings \
+        WHERE document_id = $1 AND substring(merkle_key from 1 for 2) = $2",
+        document_id,
+        prefix_bytes,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Sha256::digest(key(&Hlc::new_at(
+            row.node.try_into()?,
+            row.timestamp,
+            row.counter.try_into()?,
+        )))
+        .to_vec())
+    })
+    .collect::<Result<_, Error>>()?;
+
+    let tag_hashes: Vec<Vec<u8>> = sqlx::query!(
+        "SELECT timestamp, counter, node FROM tags \
+        WHERE document_id = $1 AND substring(merkle_key from 1 for 2) = $2",
+        document_id,
+        prefix_bytes,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Sha256::digest(key(&Hlc::new_at(
+            row.node.try_into()?,
+            row.timestamp,
+            row.counter.try_into()?,
+        )))
+        .to_vec())
+    })
+    .collect::<Result<_, Error>>()?;
+
+    op_hashes.extend(ping_hashes);
+    op_hashes.extend(tag_hashes);
+    op_hashes.sort_unstable();
+
+    let mut hasher = Sha256::new();
+    for op_hash in op_hashes {
+        hasher.update(&op_hash);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Hash together the up-to-16 children stored under `path` one level down.
+/// A nibble with nothing underneath it just doesn't contribute a child, the
+/// same convention `children` uses when answering a walk.
+async fn branch_hash(
+    conn: &mut PgConnection,
+    document_id: i64,
+    path: &str,
+) -> Result<Vec<u8>, Error> {
+    let mut nodes = children(conn, document_id, path).await?;
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = Sha256::new();
+    for (nibble, hash) in nodes {
+        hasher.update(nibble.to_string().as_bytes());
+        hasher.update(&hash);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Every stored child of `path`, as `(nibble, hash)` pairs.
+///
+/// ## Errors
+///
+/// If the underlying query fails.
+pub async fn children(
+    conn: &mut PgConnection,
+    document_id: i64,
+    path: &str,
+) -> Result<Vec<(char, Vec<u8>)>, Error> {
+    let rows = sqlx::query!(
+        "SELECT path, hash FROM merkle_nodes WHERE document_id = $1 AND path LIKE $2 || '_'",
+        document_id,
+        path,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| row.path.chars().last().map(|nibble| (nibble, row.hash)))
+        .collect())
+}
+
+/// The clock of every op stored under leaf-depth path `prefix`.
+///
+/// ## Errors
+///
+/// If the underlying queries fail.
+pub async fn leaves(
+    conn: &mut PgConnection,
+    document_id: i64,
+    prefix: &str,
+) -> Result<Vec<Hlc>, Error> {
+    let prefix_bytes = leaf_prefix_bytes(prefix);
+
+    let mut clocks: Vec<Hlc> = sqlx::query!(
+        "SELECT timestamp, counter, node FROM minutes_per_pings \
+        WHERE document_id = $1 AND substring(merkle_key from 1 for 2) = $2",
+        document_id,
+        prefix_bytes,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Hlc::new_at(
+            row.node.try_into()?,
+            row.timestamp,
+            row.counter.try_into()?,
+        ))
+    })
+    .collect::<Result<_, Error>>()?;
+
+    let ping_clocks: Vec<Hlc> = sqlx::query!(
+        "SELECT timestamp, counter, node FROM pings \
+        WHERE document_id = $1 AND substring(merkle_key from 1 for 2) = $2",
+        document_id,
+        prefix_bytes,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Hlc::new_at(
+            row.node.try_into()?,
+            row.timestamp,
+            row.counter.try_into()?,
+        ))
+    })
+    .collect::<Result<_, Error>>()?;
+
+    let tag_clocks: Vec<Hlc> = sqlx::query!(
+        "SELECT timestamp, counter, node FROM tags \
+        WHERE document_id = $1 AND substring(merkle_key from 1 for 2) = $2",
+        document_id,
+        prefix_bytes,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Hlc::new_at(
+            row.node.try_into()?,
+            row.timestamp,
+            row.counter.try_into()?,
+        ))
+    })
+    .collect::<Result<_, Error>>()?;
+
+    clocks.extend(ping_clocks);
+    clocks.extend(tag_clocks);
+    Ok(clocks)
+}
+
+/// The stored hash at `path`, if this document's tree has ever recorded
+/// one. `None` for a subtree with nothing under it yet (for example, a
+/// brand-new document with no ops at all).
+///
+/// ## Errors
+///
+/// If the underlying query fails.
+pub async fn hash_at(
+    conn: &mut PgConnection,
+    document_id: i64,
+    path: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    let row = sqlx::query!(
+        "SELECT hash FROM merkle_nodes WHERE document_id = $1 AND path = $2",
+        document_id,
+        path,
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(row.map(|row| row.hash))
+}
+
+/// Whether `path` is a leaf (lists ops directly) rather than an internal
+/// node (lists children to recurse into).
+pub fn is_leaf(path: &str) -> bool {
+    path.len() >= LEAF_DEPTH
+}
+
+async fn upsert(
+    conn: &mut PgConnection,
+    document_id: i64,
+    path: &str,
+    hash: &[u8],
+) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO merkle_nodes (document_id, path, hash) VALUES ($1, $2, $3) \
+        ON CONFLICT (document_id, path) DO UPDATE SET hash = EXCLUDED.hash",
+        document_id,
+        path,
+        hash,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_leaf_at_leaf_depth() {
+        assert!(!is_leaf(""));
+        assert!(!is_leaf("a"));
+        assert!(is_leaf("abcd"));
+        assert!(is_leaf("abcde"));
+    }
+
+    #[test]
+    fn leaf_prefix_bytes_decodes_hex() {
+        assert_eq!(leaf_prefix_bytes("00ff"), vec![0x00, 0xff]);
+        assert_eq!(leaf_prefix_bytes("a1b2"), vec![0xa1, 0xb2]);
+    }
+}
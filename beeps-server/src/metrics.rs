@@ -0,0 +1,183 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use opentelemetry::metrics::{Counter, Histogram, ObservableGauge};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::runtime;
+use prometheus::{Encoder, Registry, TextEncoder};
+use sqlx::PgPool;
+use std::time::Instant;
+
+/// Problems setting up metrics collection.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// We couldn't wire the Prometheus registry into the meter provider.
+    #[error("could not set up the Prometheus exporter: {0}")]
+    Prometheus(#[from] opentelemetry_prometheus::ExporterBuildError),
+
+    /// We couldn't build an OTLP exporter for `--otel-endpoint`.
+    #[error("could not set up the OTLP exporter: {0}")]
+    Otlp(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+
+/// Metrics for the sync server: request volume and latency per route, auth
+/// outcomes, and sync throughput. Always scrapeable locally at `/metrics` in
+/// Prometheus text format; optionally also pushed to an OTLP collector if
+/// `otel_endpoint` is configured.
+#[derive(Clone)]
+pub struct Metrics {
+    /// Backs the `/metrics` scrape; kept separately from the meter provider
+    /// since that's what `TextEncoder` needs to read from.
+    registry: Registry,
+
+    /// HTTP requests handled, by route, method, and status.
+    http_requests_total: Counter<u64>,
+
+    /// HTTP request latency in seconds, by route.
+    http_request_duration_seconds: Histogram<f64>,
+
+    /// Auth attempts, by outcome (`success` or `failure`).
+    auth_total: Counter<u64>,
+
+    /// Documents synced via push or pull.
+    documents_synced_total: Counter<u64>,
+
+    /// Document parts merged across all syncs.
+    ops_merged_total: Counter<u64>,
+
+    /// Checked-out connections in the DB pool, sampled from `pool` each time
+    /// metrics are collected. Kept alive here so the callback isn't dropped.
+    db_pool_connections_active: ObservableGauge<u64>,
+}
+
+impl Metrics {
+    /// Set up metrics collection against `pool`. `otel_endpoint`, if given,
+    /// is where metrics are also periodically pushed over OTLP; either way,
+    /// they stay available locally for a Prometheus scrape of `/metrics`.
+    pub fn new(pool: PgPool, otel_endpoint: Option<&str>) -> Result<Self, Error> {
+        let registry = Registry::new();
+
+        let prometheus_reader = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()?;
+
+        let mut builder = SdkMeterProvider::builder().with_reader(prometheus_reader);
+
+        if let Some(endpoint) = otel_endpoint {
+            let exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+
+            builder =
+                builder.with_reader(PeriodicReader::builder(exporter, runtime::Tokio).build());
+        }
+
+        let meter_provider = builder.build();
+        let meter = meter_provider.meter("beeps-server");
+
+        Ok(Self {
+            registry,
+            http_requests_total: meter
+                .u64_counter("http_requests_total")
+                .with_description("Total HTTP requests handled")
+                .init(),
+            http_request_duration_seconds: meter
+                .f64_histogram("http_request_duration_seconds")
+                .with_description("HTTP request latency, in seconds")
+                .init(),
+            auth_total: meter
+                .u64_counter("auth_total")
+                .with_description("Authentication attempts, by outcome")
+                .init(),
+            documents_synced_total: meter
+                .u64_counter("documents_synced_total")
+                .with_description("Documents synced via push or pull")
+                .init(),
+            ops_merged_total: meter
+                .u64_counter("ops_merged_total")
+                .with_description("Document parts merged across all syncs")
+                .init(),
+            db_pool_connections_active: meter
+                .u64_observable_gauge("db_pool_connections_active")
+                .with_description("Connections currently checked out of the DB pool")
+                .with_callback(move |observer| {
+                    let active = u64::from(pool.size()) - pool.num_idle() as u64;
+                    observer.observe(active, &[]);
+                })
+                .init(),
+        })
+    }
+
+    /// Record a successful auth attempt.
+    pub fn auth_success(&self) {
+        self.auth_total
+            .add(1, &[KeyValue::new("outcome", "success")]);
+    }
+
+    /// Record a failed auth attempt.
+    pub fn auth_failure(&self) {
+        self.auth_total
+            .add(1, &[KeyValue::new("outcome", "failure")]);
+    }
+
+    /// Record that a document was synced (pushed or pulled), merging
+    /// `ops_merged` parts in the process.
+    pub fn document_synced(&self, ops_merged: u64) {
+        self.documents_synced_total.add(1, &[]);
+        self.ops_merged_total.add(ops_merged, &[]);
+    }
+}
+
+/// Axum middleware recording request count and latency for every route.
+#[tracing::instrument(skip_all)]
+pub async fn track_http_requests(
+    State(metrics): State<Metrics>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let method = req.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(req).await;
+    let latency = start.elapsed().as_secs_f64();
+
+    metrics
+        .http_request_duration_seconds
+        .record(latency, &[KeyValue::new("route", route.clone())]);
+    metrics.http_requests_total.add(
+        1,
+        &[
+            KeyValue::new("route", route),
+            KeyValue::new("method", method),
+            KeyValue::new("status", response.status().as_u16().to_string()),
+        ],
+    );
+
+    response
+}
+
+/// `GET /metrics`: a Prometheus-format scrape of everything in `Metrics`,
+/// including the DB pool gauge sampled as part of this collection.
+#[tracing::instrument(skip_all)]
+pub async fn handler(State(metrics): State<Metrics>) -> impl IntoResponse {
+    let encoder = TextEncoder::new();
+    let metric_families = metrics.registry.gather();
+    let mut buffer = Vec::new();
+
+    if let Err(problem) = encoder.encode(&metric_families, &mut buffer) {
+        tracing::error!(?problem, "could not encode metrics");
+        return (StatusCode::INTERNAL_SERVER_ERROR, String::new());
+    }
+
+    (
+        StatusCode::OK,
+        String::from_utf8_lossy(&buffer).into_owned(),
+    )
+}
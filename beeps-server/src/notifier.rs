@@ -0,0 +1,160 @@
+//! Sweeps for documents with a due ping and pushes a reminder to whichever
+//! devices have registered a Web Push subscription for them, so a user
+//! finds out a ping is due even if the app isn't in the foreground.
+
+use crate::webpush::{SendError, Sender};
+use beeps_core::Scheduler;
+use serde::Serialize;
+use sqlx::{query, PgPool};
+use std::time::Duration as StdDuration;
+
+/// Run the sweep on `interval`, forever. Errors are logged and swallowed
+/// rather than propagated, since one bad sweep shouldn't take the whole
+/// background task down.
+pub async fn run(pool: PgPool, sender: Sender, interval: StdDuration) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        if let Err(err) = sweep(&pool, &sender).await {
+            tracing::warn!(?err, "push notification sweep failed");
+        }
+    }
+}
+
+/// A document with a ping due, as found by `sweep`'s query.
+struct DueDocument {
+    document_id: i64,
+    minutes_per_ping: i32,
+    latest_ping: Option<chrono::DateTime<chrono::Utc>>,
+    last_notified_ping: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Look for documents with a ping due that we haven't already notified
+/// about, and push a reminder to each of their subscribers.
+async fn sweep(pool: &PgPool, sender: &Sender) -> Result<(), Error> {
+    let due = query!(
+        r#"
+        SELECT
+            d.id AS document_id,
+            mpp.minutes_per_ping,
+            (SELECT MAX(p.ping) FROM pings p WHERE p.document_id = d.id) AS latest_ping,
+            d.last_notified_ping
+        FROM documents d
+        JOIN minutes_per_pings mpp ON mpp.document_id = d.id
+        "#
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| DueDocument {
+        document_id: row.document_id,
+        minutes_per_ping: row.minutes_per_ping,
+        latest_ping: row.latest_ping,
+        last_notified_ping: row.last_notified_ping,
+    });
+
+    for document in due {
+        let Ok(minutes_per_ping) = u16::try_from(document.minutes_per_ping) else {
+            continue;
+        };
+
+        let latest_ping = document.latest_ping.unwrap_or_else(chrono::Utc::now);
+
+        let Some(next) = Scheduler::new(minutes_per_ping, latest_ping).next() else {
+            continue;
+        };
+
+        if next > chrono::Utc::now() {
+            continue;
+        }
+
+        if document
+            .last_notified_ping
+            .is_some_and(|notified| notified >= next)
+        {
+            continue;
+        }
+
+        notify(pool, sender, document.document_id, next).await?;
+    }
+
+    Ok(())
+}
+
+/// A reminder payload, pushed as-is (JSON-encoded) to every subscriber of
+/// `document_id`.
+#[derive(Serialize)]
+struct Reminder {
+    /// Which document has a ping due, so the client knows which one to
+    /// surface a notification for.
+    document_id: i64,
+}
+
+/// Push a reminder for `document_id`'s due `ping` to every subscriber,
+/// pruning any subscription the push service reports as gone, then record
+/// that this ping has been notified about so we don't push it again.
+async fn notify(
+    pool: &PgPool,
+    sender: &Sender,
+    document_id: i64,
+    ping: chrono::DateTime<chrono::Utc>,
+) -> Result<(), Error> {
+    let subscriptions = query!(
+        r#"
+        SELECT ps.id, ps.endpoint, ps.p256dh, ps.auth
+        FROM push_subscriptions ps
+        JOIN accounts a ON a.id = ps.account_id
+        JOIN documents d ON d.owner_id = a.id
+        WHERE d.id = $1
+        "#,
+        document_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let payload = serde_json::to_vec(&Reminder { document_id })?;
+
+    for row in subscriptions {
+        let subscription = beeps_core::sync::push_subscription::Subscription {
+            endpoint: row.endpoint,
+            p256dh: row.p256dh,
+            auth: row.auth,
+        };
+
+        match sender.send(&subscription, &payload).await {
+            Ok(()) => {}
+            Err(SendError::Gone) => {
+                query!("DELETE FROM push_subscriptions WHERE id = $1", row.id)
+                    .execute(pool)
+                    .await?;
+            }
+            Err(err) => {
+                tracing::warn!(?err, document_id, "failed to push reminder");
+            }
+        }
+    }
+
+    query!(
+        "UPDATE documents SET last_notified_ping = $1 WHERE id = $2",
+        ping,
+        document_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Things that can go wrong sweeping for and sending due-ping reminders.
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    /// A query against the database failed.
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    /// The reminder payload couldn't be serialized.
+    #[error("couldn't serialize reminder: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
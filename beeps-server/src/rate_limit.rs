@@ -0,0 +1,50 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// A fixed-window rate limiter, keyed by caller-supplied string (e.g. an
+/// email or an IP). Used to slow down brute-forcing or enumerating accounts
+/// through `login::handler`'s deliberately-uniform error message, which
+/// otherwise gives an attacker nothing to rate-limit on except volume.
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// How many attempts a key gets per window.
+    limit: u32,
+
+    /// How long a window lasts before a key's count resets.
+    window: Duration,
+
+    /// Each key's current window: when it started, and how many attempts
+    /// have been recorded in it so far.
+    windows: Arc<Mutex<HashMap<String, (DateTime<Utc>, u32)>>>,
+}
+
+impl RateLimiter {
+    /// Allow at most `limit` attempts per `window` for each key.
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record an attempt for `key`, returning whether it's still within the
+    /// limit. A key that's never been seen, or whose window has expired,
+    /// starts a fresh window with this attempt as its first.
+    pub async fn check(&self, key: &str) -> bool {
+        let now = Utc::now();
+        let mut windows = self.windows.lock().await;
+
+        let (started_at, count) = windows.entry(key.to_string()).or_insert((now, 0));
+
+        if now - *started_at > self.window {
+            *started_at = now;
+            *count = 0;
+        }
+
+        *count += 1;
+        *count <= self.limit
+    }
+}
@@ -0,0 +1,274 @@
+use crate::error::Error;
+use chrono::{DateTime, Duration, Utc};
+use rand::{distr::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+/// How long a session (and the refresh token backing it) stays valid before
+/// the device has to log back in from scratch.
+pub const TTL: Duration = Duration::days(30);
+
+/// How many random characters to generate for a refresh token. Comparable
+/// in entropy to the PKCE verifiers in `oidc`, which this otherwise mirrors.
+const REFRESH_TOKEN_LEN: usize = 48;
+
+/// A freshly-created session: what login, registration, and the OIDC
+/// callback each need to mint an access token and hand a refresh token back
+/// to the client.
+pub struct Created {
+    /// The session's row ID. Goes in the access JWT's claims, so later
+    /// requests can be checked against this specific session.
+    pub id: i64,
+
+    /// The opaque refresh token for this session. Shown to the client
+    /// exactly once; only its hash is kept.
+    pub refresh_token: String,
+}
+
+/// Start tracking a new device session for a replica that was just logged
+/// into, so its access tokens can later be revoked without waiting out
+/// their own expiry.
+///
+/// ## Errors
+///
+/// If the insert fails.
+pub async fn create(
+    conn: &mut PgConnection,
+    account_id: i64,
+    replica_id: i64,
+    device_label: Option<&str>,
+) -> Result<Created, Error> {
+    let refresh_token = generate_refresh_token();
+
+    let session = sqlx::query!(
+        "INSERT INTO sessions (account_id, replica_id, refresh_token_hash, device_label, expires_at) \
+        VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        account_id,
+        replica_id,
+        hash(&refresh_token),
+        device_label,
+        Utc::now() + TTL,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(Created {
+        id: session.id,
+        refresh_token,
+    })
+}
+
+/// Generate a fresh opaque refresh token.
+fn generate_refresh_token() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(REFRESH_TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// Hash a refresh token the same way on creation and redemption, so the
+/// plaintext token is never stored. A refresh token is already
+/// high-entropy and single-use-until-revoked, so a fast, unsalted hash is
+/// fine here, unlike a user-chosen password.
+pub fn hash(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// What redeeming a refresh token hands back: enough to mint a fresh
+/// access token for the account and replica behind it, plus the new
+/// refresh token the caller should hand back in place of the one they
+/// just spent.
+pub struct Redeemed {
+    /// The new session generation, so the new access token can be minted
+    /// with it as `session_id`.
+    pub session_id: i64,
+
+    /// The account the session belongs to.
+    pub account_id: i64,
+
+    /// The email to put in the access token's `sub`.
+    pub email: String,
+
+    /// The replica's server-assigned node ID.
+    pub node_id: i32,
+
+    /// The refresh token for the new session generation. The one the
+    /// caller redeemed is now retired and will trip reuse detection if
+    /// it's ever presented again.
+    pub refresh_token: String,
+}
+
+/// Redeem a refresh token for a fresh one, rotating the session forward a
+/// generation. Returns `None` if the token is unknown, already rotated
+/// away, revoked, or expired.
+///
+/// Presenting a token that's already been rotated away means either a
+/// client retried a request whose response it never saw, or a leaked
+/// token is being replayed after the legitimate device already moved on.
+/// We can't tell those apart, so we treat it as a compromise signal and
+/// revoke every session in the token's family: the legitimate device will
+/// simply have to log back in, which is a better outcome than leaving a
+/// potentially-stolen token family usable.
+///
+/// ## Errors
+///
+/// If any of the underlying queries fail.
+pub async fn redeem(
+    conn: &mut PgConnection,
+    refresh_token: &str,
+) -> Result<Option<Redeemed>, Error> {
+    // Claim the row atomically: only one caller can ever flip `rotated_at`
+    // from NULL, so two requests racing on the same token can't both read
+    // it as not-yet-rotated and both mint a fresh session.
+    let claimed = sqlx::query!(
+        "UPDATE sessions SET rotated_at = NOW() \
+        WHERE refresh_token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW() \
+        AND rotated_at IS NULL \
+        RETURNING id, account_id, replica_id, device_label, family_id",
+        hash(refresh_token),
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let found = match claimed {
+        Some(found) => found,
+        None => {
+            // We didn't claim anything, but that's either because the
+            // token is unknown/revoked/expired, or because it's a reuse of
+            // one we already rotated away. Only the latter is a sign of
+            // compromise.
+            let Some(reused) = sqlx::query!(
+                "SELECT id, family_id FROM sessions \
+                WHERE refresh_token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW() \
+                AND rotated_at IS NOT NULL",
+                hash(refresh_token),
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+            else {
+                return Ok(None);
+            };
+
+            let family_id = reused.family_id.unwrap_or(reused.id);
+
+            tracing::warn!(
+                session_id = reused.id,
+                family_id,
+                "refresh token reuse detected, revoking family"
+            );
+
+            sqlx::query!(
+                "UPDATE sessions SET revoked_at = NOW() \
+                WHERE COALESCE(family_id, id) = $1 AND revoked_at IS NULL",
+                family_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+
+            return Ok(None);
+        }
+    };
+
+    let family_id = found.family_id.unwrap_or(found.id);
+
+    let refresh_token = generate_refresh_token();
+
+    let next = sqlx::query!(
+        "INSERT INTO sessions (account_id, replica_id, refresh_token_hash, device_label, family_id, expires_at) \
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        found.account_id,
+        found.replica_id,
+        hash(&refresh_token),
+        found.device_label,
+        family_id,
+        Utc::now() + TTL,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let account = sqlx::query!("SELECT email FROM accounts WHERE id = $1", found.account_id,)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let replica = sqlx::query!(
+        "SELECT node_id FROM replicas WHERE id = $1",
+        found.replica_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Some(Redeemed {
+        session_id: next.id,
+        account_id: found.account_id,
+        email: account.email,
+        node_id: replica.node_id,
+        refresh_token,
+    }))
+}
+
+/// One of an account's own sessions, as seen by that account.
+pub struct Listed {
+    /// The session's ID. Pass this to [`revoke`] to log that device out.
+    pub id: i64,
+
+    /// Whatever label the device gave itself at login, if any.
+    pub device_label: Option<String>,
+
+    /// When this session was created.
+    pub created_at: DateTime<Utc>,
+
+    /// When this session last refreshed or otherwise touched the server.
+    pub last_seen_at: DateTime<Utc>,
+
+    /// When this session expires if it's never refreshed again.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// List an account's own non-revoked, unexpired sessions, most recently
+/// active first.
+///
+/// ## Errors
+///
+/// If the query fails.
+pub async fn list(conn: &mut PgConnection, email: &str) -> Result<Vec<Listed>, Error> {
+    let sessions = sqlx::query_as!(
+        Listed,
+        "SELECT sessions.id, sessions.device_label, sessions.created_at, \
+        sessions.last_seen_at, sessions.expires_at \
+        FROM sessions \
+        JOIN accounts ON accounts.id = sessions.account_id \
+        WHERE accounts.email = $1 AND sessions.revoked_at IS NULL AND sessions.expires_at > NOW() \
+        ORDER BY sessions.last_seen_at DESC",
+        email,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Revoke one of an account's own sessions, scoped to that account so one
+/// caller can't revoke another's. Returns whether a session was actually
+/// revoked.
+///
+/// ## Errors
+///
+/// If the update fails.
+pub async fn revoke(conn: &mut PgConnection, email: &str, session_id: i64) -> Result<bool, Error> {
+    let touched = sqlx::query!(
+        "UPDATE sessions SET revoked_at = NOW() \
+        FROM accounts \
+        WHERE sessions.account_id = accounts.id \
+        AND sessions.id = $1 \
+        AND accounts.email = $2 \
+        AND sessions.revoked_at IS NULL",
+        session_id,
+        email,
+    )
+    .execute(conn)
+    .await?
+    .rows_affected();
+
+    Ok(touched > 0)
+}
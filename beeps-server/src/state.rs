@@ -1,6 +1,35 @@
+use crate::authz::SharedAuthorizer;
+use crate::cluster::ClusterMetadata;
+use crate::mailer::SharedMailer;
+use crate::metrics::Metrics;
+use crate::oidc::OidcConfig;
+use crate::rate_limit::RateLimiter;
 use axum::extract::FromRef;
+use beeps_core::Document;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use jsonwebtoken::{errors::Error, DecodingKey, EncodingKey};
 use sqlx::{Pool, Postgres};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast, Mutex};
+
+/// How long to wait before reconnecting a document's Redis relay after it
+/// drops (e.g. the connection was reset). A fixed delay rather than
+/// backoff: this is one long-lived background task per document, not a
+/// request a caller is waiting on, so there's no need to be clever about
+/// it.
+const RELAY_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How many login attempts a single email or IP gets per
+/// `LOGIN_RATE_LIMIT_WINDOW`.
+const LOGIN_ATTEMPTS_PER_WINDOW: u32 = 10;
+
+/// The window `LOGIN_ATTEMPTS_PER_WINDOW` is measured over.
+const LOGIN_RATE_LIMIT_WINDOW: chrono::Duration = chrono::Duration::minutes(5);
 
 /// Shared state needed by requests.
 #[derive(Clone, FromRef)]
@@ -16,24 +45,310 @@ pub struct State {
 
     /// Whether or not to allow new registrations.
     allow_registration: AllowRegistration,
+
+    /// Live subscribers, keyed by document ID.
+    subscriptions: Subscriptions,
+
+    /// External authorization hook consulted before registration and push.
+    authorizer: SharedAuthorizer,
+
+    /// Outstanding OIDC login attempts, keyed by the `state` we handed the
+    /// provider.
+    oauth_flows: OAuthFlows,
+
+    /// The OIDC provider to support single-sign-on against, if one is
+    /// configured.
+    oidc: Option<Arc<OidcConfig>>,
+
+    /// Client used to talk to the OIDC provider during the callback.
+    http_client: reqwest::Client,
+
+    /// Request, auth, and sync metrics, scraped at `/metrics`.
+    metrics: Metrics,
+
+    /// Which node owns which document, if this deployment is partitioned
+    /// across more than one. When unset, every document is treated as
+    /// owned locally.
+    cluster: Option<Arc<ClusterMetadata>>,
+
+    /// Sends password reset and email verification links.
+    mailer: SharedMailer,
+
+    /// Caps how many login attempts a single email can make per window,
+    /// independent of how many different IPs they come from.
+    login_rate_limiter_by_email: LoginRateLimiterByEmail,
+
+    /// Caps how many login attempts a single IP can make per window,
+    /// independent of how many different accounts it's trying.
+    login_rate_limiter_by_ip: LoginRateLimiterByIp,
 }
 
+/// Wraps a `RateLimiter` so `State`'s `FromRef` derive (which generates one
+/// impl per field type) can tell this one apart from
+/// `LoginRateLimiterByIp`, which would otherwise be the same type.
+#[derive(Clone)]
+pub struct LoginRateLimiterByEmail(pub RateLimiter);
+
+/// See `LoginRateLimiterByEmail`.
+#[derive(Clone)]
+pub struct LoginRateLimiterByIp(pub RateLimiter);
+
 /// Whether or not the server should allow new registrations.
 #[derive(Debug, Clone)]
 pub struct AllowRegistration(pub bool);
 
+/// Per-document broadcast channels, so pushes can be fanned out to anyone
+/// subscribed to that document. When Redis is configured, document updates
+/// are relayed through it instead of being delivered locally, so a push
+/// landing on one instance still reaches subscribers connected to another.
+///
+/// Deliberately not Postgres `LISTEN`/`NOTIFY`: since documents are
+/// partitioned across instances by consistent hashing (see `cluster`), a
+/// notification would still need relaying to whichever instance actually
+/// holds the subscriber's WebSocket, which is exactly what Redis pub/sub is
+/// already doing here.
+#[derive(Clone)]
+pub struct Subscriptions {
+    /// Local fan-out, keyed by document ID. Each sender's bounded channel
+    /// buffer is the "per-subscriber queue": a slow subscriber just lags and
+    /// misses some updates (caught up by the next pull) rather than blocking
+    /// anyone else.
+    channels: Arc<Mutex<HashMap<i64, broadcast::Sender<Document>>>>,
+
+    /// The Redis client to relay updates through, if configured.
+    redis: Option<redis::Client>,
+
+    /// Documents we've already started a Redis relay task for, so we don't
+    /// spawn a duplicate subscriber every time a new client connects.
+    relayed: Arc<Mutex<HashSet<i64>>>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            redis: None,
+            relayed: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+}
+
+impl Subscriptions {
+    /// Set up fan-out, optionally relaying through Redis so it works across
+    /// multiple instances. With no URL, updates are fanned out in-memory
+    /// only, which is fine for a single instance.
+    pub fn new(redis_url: Option<&str>) -> redis::RedisResult<Self> {
+        let redis = redis_url.map(redis::Client::open).transpose()?;
+        Ok(Self {
+            redis,
+            ..Self::default()
+        })
+    }
+
+    /// The Redis channel a document's updates are relayed over.
+    fn redis_channel(document_id: i64) -> String {
+        format!("beeps:documents:{document_id}")
+    }
+
+    /// Start (or join) a subscription for a document, getting a receiver for
+    /// every future update.
+    pub async fn subscribe(&self, document_id: i64) -> broadcast::Receiver<Document> {
+        let mut channels = self.channels.lock().await;
+        let sender = channels
+            .entry(document_id)
+            .or_insert_with(|| broadcast::channel(16).0)
+            .clone();
+        drop(channels);
+
+        self.ensure_relay(document_id, sender.clone()).await;
+
+        sender.subscribe()
+    }
+
+    /// Make sure there's a task relaying this document's Redis channel into
+    /// its local broadcast sender. A no-op if Redis isn't configured, or a
+    /// relay for this document is already running.
+    async fn ensure_relay(&self, document_id: i64, sender: broadcast::Sender<Document>) {
+        let Some(client) = self.redis.clone() else {
+            return;
+        };
+
+        let mut relayed = self.relayed.lock().await;
+        if !relayed.insert(document_id) {
+            return;
+        }
+        drop(relayed);
+
+        // `relayed` only ever gains an entry for this document, so if the
+        // relay dies here without this loop it's gone for good: later
+        // subscribers' `ensure_relay` calls see the entry already present
+        // and skip straight past. Keep reconnecting instead, so a blip in
+        // the Redis connection doesn't silently strand this instance's
+        // subscribers until it's restarted.
+        tokio::spawn(async move {
+            loop {
+                if let Err(problem) = relay(client.clone(), document_id, sender.clone()).await {
+                    tracing::error!(
+                        ?problem,
+                        document_id,
+                        "redis relay for document disconnected"
+                    );
+                }
+                tokio::time::sleep(RELAY_RECONNECT_DELAY).await;
+            }
+        });
+    }
+
+    /// Let subscribers of a document know it changed. If Redis is
+    /// configured, it's the single path to local subscribers (even on the
+    /// instance that received the push), so every instance delivers the
+    /// update the same way; otherwise we fan out locally, directly.
+    pub async fn notify(&self, document_id: i64, document: Document) {
+        let Some(client) = self.redis.clone() else {
+            let channels = self.channels.lock().await;
+            if let Some(sender) = channels.get(&document_id) {
+                // An error here just means there are no receivers left;
+                // that's fine, there's nobody to tell.
+                let _ = sender.send(document);
+            }
+            return;
+        };
+
+        match serde_json::to_string(&document) {
+            Ok(payload) => {
+                if let Err(problem) = publish(client, document_id, payload).await {
+                    tracing::error!(
+                        ?problem,
+                        document_id,
+                        "couldn't publish document update to redis"
+                    );
+                }
+            }
+            Err(problem) => {
+                tracing::error!(?problem, document_id, "couldn't serialize document update");
+            }
+        }
+    }
+}
+
+/// Publish a document update to its Redis channel.
+async fn publish(
+    client: redis::Client,
+    document_id: i64,
+    payload: String,
+) -> redis::RedisResult<()> {
+    use redis::AsyncCommands;
+
+    let mut conn = client.get_multiplexed_async_connection().await?;
+    conn.publish(Subscriptions::redis_channel(document_id), payload)
+        .await
+}
+
+/// Forward every message on a document's Redis channel to its local
+/// broadcast sender, for as long as the connection holds up.
+async fn relay(
+    client: redis::Client,
+    document_id: i64,
+    sender: broadcast::Sender<Document>,
+) -> redis::RedisResult<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub
+        .subscribe(Subscriptions::redis_channel(document_id))
+        .await?;
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        match serde_json::from_str::<Document>(&payload) {
+            Ok(document) => {
+                let _ = sender.send(document);
+            }
+            Err(problem) => {
+                tracing::error!(
+                    ?problem,
+                    document_id,
+                    "couldn't deserialize relayed document update"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl State {
     /// Create a new state.
     pub fn new(
         pool: Pool<Postgres>,
         jwt_base64_secret: &str,
         allow_registration: bool,
+        authorizer: SharedAuthorizer,
+        subscriptions: Subscriptions,
+        oidc: Option<Arc<OidcConfig>>,
+        http_client: reqwest::Client,
+        metrics: Metrics,
+        cluster: Option<Arc<ClusterMetadata>>,
+        mailer: SharedMailer,
     ) -> Result<Self, Error> {
         Ok(Self {
             pool,
             encoding_key: EncodingKey::from_base64_secret(jwt_base64_secret)?,
             decoding_key: DecodingKey::from_base64_secret(jwt_base64_secret)?,
             allow_registration: AllowRegistration(allow_registration),
+            subscriptions,
+            authorizer,
+            oauth_flows: OAuthFlows::default(),
+            oidc,
+            http_client,
+            metrics,
+            cluster,
+            mailer,
+            login_rate_limiter_by_email: LoginRateLimiterByEmail(RateLimiter::new(
+                LOGIN_ATTEMPTS_PER_WINDOW,
+                LOGIN_RATE_LIMIT_WINDOW,
+            )),
+            login_rate_limiter_by_ip: LoginRateLimiterByIp(RateLimiter::new(
+                LOGIN_ATTEMPTS_PER_WINDOW,
+                LOGIN_RATE_LIMIT_WINDOW,
+            )),
         })
     }
 }
+
+/// Outstanding OIDC login attempts, keyed by the `state` value we handed
+/// the provider in the authorize URL. Holds the PKCE verifier generated for
+/// that attempt (and the device label it was started with, if any), so the
+/// callback can prove it's the same client that started the flow.
+#[derive(Clone, Default)]
+pub struct OAuthFlows(Arc<Mutex<HashMap<String, (String, Option<String>, DateTime<Utc>)>>>);
+
+impl OAuthFlows {
+    /// How long a login attempt is valid for before it must be restarted.
+    const TTL: chrono::Duration = chrono::Duration::minutes(10);
+
+    /// Record a fresh login attempt, keyed by its `state` value.
+    pub async fn issue(&self, state: &str, code_verifier: &str, device_label: Option<&str>) {
+        let mut flows = self.0.lock().await;
+        flows.insert(
+            state.to_string(),
+            (
+                code_verifier.to_string(),
+                device_label.map(str::to_string),
+                Utc::now() + Self::TTL,
+            ),
+        );
+    }
+
+    /// Redeem the PKCE verifier (and device label) for a login attempt.
+    /// Consumes it either way, so a `state` can't be replayed whether it
+    /// matched or not.
+    pub async fn redeem(&self, state: &str) -> Option<(String, Option<String>)> {
+        let mut flows = self.0.lock().await;
+        match flows.remove(state) {
+            Some((code_verifier, device_label, expires_at)) if Utc::now() < expires_at => {
+                Some((code_verifier, device_label))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many seconds each code is valid for, per RFC 6238's default time step.
+const STEP_SECONDS: u64 = 30;
+
+/// How many digits a generated code has.
+const DIGITS: u32 = 6;
+
+/// How many steps on either side of the current one we'll also accept, to
+/// tolerate a little clock skew between us and whatever generated the code.
+const SKEW_STEPS: i64 = 1;
+
+/// How many random bytes to generate for a fresh secret. 20 bytes (160 bits)
+/// matches SHA-1's own output size, which most authenticator apps expect.
+const SECRET_BYTES: usize = 20;
+
+/// A decoded TOTP secret, ready to generate or verify codes against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    /// Generate a fresh random secret.
+    pub fn generate() -> Self {
+        let mut bytes = vec![0; SECRET_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    /// Decode a base32-encoded secret, e.g. as stored in the database.
+    pub fn from_base32(encoded: &str) -> Option<Self> {
+        decode_base32(encoded).map(Self)
+    }
+
+    /// Encode this secret as base32, e.g. for storage or display in an
+    /// `otpauth://` URI.
+    pub fn to_base32(&self) -> String {
+        encode_base32(&self.0)
+    }
+
+    /// Does `code` match the step `at` is in, or one step to either side,
+    /// and is that step newer than `last_accepted_step`? Returns the step
+    /// `code` was valid for if so, which the caller should persist as the
+    /// account's new last-accepted step — otherwise the same code could be
+    /// replayed any number of times within its ~90-second validity window.
+    pub fn verify(&self, code: &str, at: SystemTime, last_accepted_step: Option<u64>) -> Option<u64> {
+        let counter = step(at);
+
+        ((-SKEW_STEPS)..=SKEW_STEPS)
+            .filter_map(|offset| counter.checked_add_signed(offset))
+            .filter(|&counter| last_accepted_step.is_none_or(|last| counter > last))
+            .find(|&counter| generate_at(&self.0, counter) == code)
+    }
+
+    /// An `otpauth://` URI an authenticator app can scan to enroll this
+    /// secret, per the (now de-facto standard) Key URI Format.
+    pub fn otpauth_uri(&self, issuer: &str, account: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{account}?secret={}&issuer={issuer}",
+            self.to_base32()
+        )
+    }
+
+    /// The code valid for the current step, for exercising a real login
+    /// round-trip in tests without reimplementing RFC 6238 there too.
+    #[cfg(test)]
+    pub fn current_code(&self) -> String {
+        generate_at(&self.0, step(SystemTime::now()))
+    }
+}
+
+/// Which time step `at` falls in.
+fn step(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .expect("system clock is before the Unix epoch")
+        .as_secs()
+        / STEP_SECONDS
+}
+
+/// RFC 6238's `TOTP(K) = HOTP(K, floor(unix_time / step))`, with RFC 4226's
+/// dynamic truncation: HMAC-SHA1 the big-endian counter, take the offset
+/// from the low nibble of the last byte, read 4 bytes there, mask off the
+/// high bit (so the result is never negative once read as a signed `i32`),
+/// and reduce modulo `10^DIGITS`.
+fn generate_at(secret: &[u8], counter: u64) -> String {
+    type HmacSha1 = Hmac<Sha1>;
+
+    let mut mac = HmacSha1::new_from_slice(secret).expect("HMAC accepts a key of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        truncated % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// The alphabet RFC 4648 base32 uses.
+const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Encode `data` as unpadded base32.
+fn encode_base32(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+
+    for &byte in data {
+        bits = (bits << 8) | u32::from(byte);
+        bit_count += 8;
+
+        while bit_count >= 5 {
+            bit_count -= 5;
+            out.push(ALPHABET[((bits >> bit_count) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bit_count > 0 {
+        out.push(ALPHABET[((bits << (5 - bit_count)) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+/// Decode unpadded (or padded; `=` is just ignored) base32 back to bytes.
+/// Returns `None` on any character outside the base32 alphabet.
+fn decode_base32(encoded: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+
+    for ch in encoded.chars().filter(|&c| c != '=') {
+        let value = ALPHABET
+            .iter()
+            .position(|&a| a == ch.to_ascii_uppercase() as u8)?;
+
+        bits = (bits << 5) | value as u32;
+        bit_count += 5;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push(((bits >> bit_count) & 0xff) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod base32 {
+        use super::*;
+
+        #[test]
+        fn round_trips_arbitrary_bytes() {
+            let secret = Secret::generate();
+
+            let encoded = secret.to_base32();
+            let decoded = Secret::from_base32(&encoded).unwrap();
+
+            assert_eq!(decoded, secret);
+        }
+
+        #[test]
+        fn matches_the_rfc_4648_test_vector() {
+            // "foobar" -> "MZXW6YTBOI" is one of RFC 4648's base32 test vectors.
+            assert_eq!(encode_base32(b"foobar"), "MZXW6YTBOI");
+            assert_eq!(decode_base32("MZXW6YTBOI"), Some(b"foobar".to_vec()));
+        }
+
+        #[test]
+        fn rejects_invalid_characters() {
+            assert_eq!(decode_base32("not valid base32!"), None);
+        }
+    }
+
+    mod verify {
+        use super::*;
+        use std::time::Duration;
+
+        #[test]
+        fn accepts_the_code_for_the_current_step() {
+            let secret = Secret::generate();
+            let now = SystemTime::now();
+
+            let code = generate_at(&secret.0, step(now));
+
+            assert!(secret.verify(&code, now, None).is_some());
+        }
+
+        #[test]
+        fn accepts_a_code_one_step_away_to_tolerate_skew() {
+            let secret = Secret::generate();
+            let now = SystemTime::now();
+            let next_step = now + Duration::from_secs(STEP_SECONDS);
+
+            let code = generate_at(&secret.0, step(next_step));
+
+            assert!(secret.verify(&code, now, None).is_some());
+        }
+
+        #[test]
+        fn rejects_a_code_outside_the_skew_window() {
+            let secret = Secret::generate();
+            let now = SystemTime::now();
+            let far_future = now + Duration::from_secs(STEP_SECONDS * 10);
+
+            let code = generate_at(&secret.0, step(far_future));
+
+            assert!(secret.verify(&code, now, None).is_none());
+        }
+
+        #[test]
+        fn rejects_a_code_from_a_different_secret() {
+            let secret = Secret::generate();
+            let other = Secret::generate();
+            let now = SystemTime::now();
+
+            let code = generate_at(&other.0, step(now));
+
+            assert!(secret.verify(&code, now, None).is_none());
+        }
+
+        #[test]
+        fn rejects_a_code_for_a_step_already_accepted() {
+            let secret = Secret::generate();
+            let now = SystemTime::now();
+
+            let code = generate_at(&secret.0, step(now));
+            let accepted_step = secret.verify(&code, now, None).unwrap();
+
+            // Presenting the same code again, e.g. replayed by an
+            // eavesdropper, must not verify a second time even though it's
+            // still within the skew window.
+            assert!(secret.verify(&code, now, Some(accepted_step)).is_none());
+        }
+
+        #[test]
+        fn accepts_a_later_code_after_one_step_is_already_accepted() {
+            let secret = Secret::generate();
+            let now = SystemTime::now();
+            let next_step = now + Duration::from_secs(STEP_SECONDS);
+
+            let first_code = generate_at(&secret.0, step(now));
+            let accepted_step = secret.verify(&first_code, now, None).unwrap();
+
+            let second_code = generate_at(&secret.0, step(next_step));
+            assert!(secret
+                .verify(&second_code, next_step, Some(accepted_step))
+                .is_some());
+        }
+    }
+}
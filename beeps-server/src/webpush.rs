@@ -0,0 +1,513 @@
+//! RFC 8291 message encryption and RFC 8292 VAPID signing for Web Push, so
+//! `notifier` can deliver a ping reminder to a subscribed browser without
+//! the push service (or anyone relaying it) being able to read the payload.
+
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes128Gcm, KeyInit, Nonce};
+use beeps_core::sync::push_subscription::Subscription;
+use chrono::{TimeDelta, Utc};
+use hkdf::Hkdf;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use p256::ecdh::diffie_hellman;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::pkcs8::EncodePrivateKey;
+use p256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use reqwest::{StatusCode, Url};
+use serde::Serialize;
+use sha2::Sha256;
+
+/// How long a VAPID JWT is valid for. RFC 8292 caps this at 24 hours; we
+/// mint a fresh one per push anyway, so a much shorter window is plenty.
+const VAPID_TTL: TimeDelta = TimeDelta::hours(1);
+
+/// The `Content-Encoding: aes128gcm` record size (RFC 8188) we declare in
+/// the coded body. A reminder is always small enough to fit in the one
+/// record we send, so this just needs to be bigger than any payload we'll
+/// ever push.
+const RECORD_SIZE: u32 = 4096;
+
+/// Our VAPID identity: the EC keypair push services use to recognize us
+/// across requests, and the contact we give them if something's wrong with
+/// it.
+#[derive(Clone)]
+pub struct VapidConfig {
+    /// Signs the JWT in every push's `Authorization` header.
+    private_key: SecretKey,
+
+    /// Sent alongside the JWT as the `k` parameter, so the push service can
+    /// check the JWT was signed by the key it expects without a prior
+    /// handshake.
+    public_key: PublicKey,
+
+    /// `mailto:` or `https:` contact URI, sent as the JWT's `sub` claim.
+    subject: String,
+}
+
+impl VapidConfig {
+    /// Load a VAPID keypair from its base64url-encoded private scalar (the
+    /// format most Web Push tooling exports), deriving the public key from
+    /// it.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`Error::InvalidKey`] if `private_key_b64` isn't a valid
+    /// base64url-encoded P-256 scalar.
+    pub fn from_base64(private_key_b64: &str, subject: String) -> Result<Self, Error> {
+        let bytes = base64url_decode(private_key_b64).ok_or(Error::InvalidKey)?;
+        let private_key = SecretKey::from_slice(&bytes).map_err(|_| Error::InvalidKey)?;
+        let public_key = private_key.public_key();
+
+        Ok(Self {
+            private_key,
+            public_key,
+            subject,
+        })
+    }
+
+    /// Sign a fresh VAPID JWT authorizing a push to `endpoint`, good for
+    /// [`VAPID_TTL`].
+    fn sign(&self, endpoint: &str) -> Result<String, Error> {
+        let aud = Url::parse(endpoint)
+            .map_err(|_| Error::InvalidEndpoint)?
+            .origin()
+            .ascii_serialization();
+
+        let claims = VapidClaims {
+            aud,
+            exp: (Utc::now() + VAPID_TTL).timestamp(),
+            sub: self.subject.clone(),
+        };
+
+        let der = self
+            .private_key
+            .to_pkcs8_der()
+            .map_err(|_| Error::InvalidKey)?;
+
+        Ok(jsonwebtoken::encode(
+            &Header::new(Algorithm::ES256),
+            &claims,
+            &EncodingKey::from_ec_der(der.as_bytes()),
+        )?)
+    }
+
+    /// The `Authorization` header value for a push to `endpoint`: a signed
+    /// JWT plus our public key, per RFC 8292.
+    fn authorization(&self, endpoint: &str) -> Result<String, Error> {
+        let jwt = self.sign(endpoint)?;
+        let public_key = base64url_encode(self.public_key.to_encoded_point(false).as_bytes());
+
+        Ok(format!("vapid t={jwt}, k={public_key}"))
+    }
+}
+
+/// The claims a VAPID JWT makes, per RFC 8292.
+#[derive(Debug, Serialize)]
+struct VapidClaims {
+    /// The push service's origin, so it can check the JWT was minted for
+    /// this request rather than replayed from another one.
+    aud: String,
+
+    /// When this JWT stops being accepted.
+    exp: i64,
+
+    /// Our contact, in case the push service needs to reach us.
+    sub: String,
+}
+
+/// Pushes encrypted reminders to subscribers, signing each request with our
+/// VAPID identity.
+#[derive(Clone)]
+pub struct Sender {
+    /// Our VAPID identity, used to sign every outgoing push.
+    vapid: VapidConfig,
+
+    /// Reused across pushes, same as every other outbound HTTP call this
+    /// server makes.
+    client: reqwest::Client,
+}
+
+impl Sender {
+    /// Build a sender around an already-loaded VAPID identity and HTTP
+    /// client.
+    pub fn new(vapid: VapidConfig, client: reqwest::Client) -> Self {
+        Self { vapid, client }
+    }
+
+    /// Encrypt `payload` for `subscription` (RFC 8291) and push it, signed
+    /// with our VAPID identity (RFC 8292).
+    ///
+    /// ## Errors
+    ///
+    /// - [`SendError::Gone`] if the push service says this subscription no
+    ///   longer exists (HTTP 404 or 410), so the caller knows to forget it.
+    /// - [`SendError::Message`] if we couldn't even build the request.
+    /// - [`SendError::Http`] if the push service rejected the request for
+    ///   any other reason.
+    /// - [`SendError::Request`] if we couldn't reach the push service at
+    ///   all.
+    pub async fn send(&self, subscription: &Subscription, payload: &[u8]) -> Result<(), SendError> {
+        let body = encrypt(subscription, payload).map_err(SendError::Message)?;
+        let authorization = self
+            .vapid
+            .authorization(&subscription.endpoint)
+            .map_err(SendError::Message)?;
+
+        let resp = self
+            .client
+            .post(&subscription.endpoint)
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header("TTL", "60")
+            .header("Authorization", authorization)
+            .body(body)
+            .send()
+            .await?;
+
+        let status = resp.status();
+
+        if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+            return Err(SendError::Gone);
+        }
+
+        if !status.is_success() {
+            return Err(SendError::Http(status));
+        }
+
+        Ok(())
+    }
+}
+
+/// Ways sending a push can fail, distinguishing "this subscription is dead,
+/// prune it" from everything else.
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    /// The push service returned 404 or 410: this subscription no longer
+    /// exists and should be deleted.
+    #[error("push subscription no longer exists")]
+    Gone,
+
+    /// The push service rejected the request for some other reason.
+    #[error("push service returned {0}")]
+    Http(StatusCode),
+
+    /// We couldn't reach the push service at all.
+    #[error("couldn't reach push service: {0}")]
+    Request(#[from] reqwest::Error),
+
+    /// We couldn't build the request in the first place: a bad VAPID key, a
+    /// bad subscription, or an encryption failure.
+    #[error("couldn't build push request: {0}")]
+    Message(#[source] Error),
+}
+
+/// Encrypt `plaintext` per RFC 8291's "aes128gcm" content coding for
+/// `subscription`, returning the full coded body ready to send as-is.
+fn encrypt(subscription: &Subscription, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let ua_public_bytes =
+        base64url_decode(&subscription.p256dh).ok_or(Error::InvalidSubscription)?;
+    let ua_public =
+        PublicKey::from_sec1_bytes(&ua_public_bytes).map_err(|_| Error::InvalidSubscription)?;
+    let auth_secret = base64url_decode(&subscription.auth).ok_or(Error::InvalidSubscription)?;
+
+    // A fresh ECDH keypair per message, so a push service watching traffic
+    // can't correlate two messages to the same subscriber by their key.
+    let as_secret = SecretKey::random(&mut OsRng);
+    let as_public = as_secret.public_key();
+
+    let shared_secret = diffie_hellman(as_secret.to_nonzero_scalar(), ua_public.as_affine());
+
+    let ua_public_point = ua_public.to_encoded_point(false);
+    let as_public_point = as_public.to_encoded_point(false);
+
+    // RFC 8291 section 3.3: the "key_info" that binds the derived key to
+    // both parties' public keys, so replaying it against a different
+    // ECDH exchange won't produce the same key.
+    let mut key_info =
+        Vec::with_capacity("WebPush: info\0".len() + ua_public_point.len() + as_public_point.len());
+    key_info.extend_from_slice(b"WebPush: info\0");
+    key_info.extend_from_slice(ua_public_point.as_bytes());
+    key_info.extend_from_slice(as_public_point.as_bytes());
+
+    let ikm_extractor = Hkdf::<Sha256>::new(Some(&auth_secret), shared_secret.raw_secret_bytes());
+    let mut ikm = [0u8; 32];
+    ikm_extractor
+        .expand(&key_info, &mut ikm)
+        .map_err(|_| Error::Encryption)?;
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    let prk = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+
+    let mut cek = [0u8; 16];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .map_err(|_| Error::Encryption)?;
+
+    let mut nonce_bytes = [0u8; 12];
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce_bytes)
+        .map_err(|_| Error::Encryption)?;
+
+    // RFC 8188's padding delimiter: 0x02 marks this as the final (and only)
+    // record, since our payload always fits in one.
+    let mut padded = Vec::with_capacity(plaintext.len() + 1);
+    padded.extend_from_slice(plaintext);
+    padded.push(0x02);
+
+    let cipher = Aes128Gcm::new_from_slice(&cek).map_err(|_| Error::Encryption)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), padded.as_ref())
+        .map_err(|_| Error::Encryption)?;
+
+    // RFC 8188's aes128gcm header: salt (16) || record size (4, BE) || key
+    // ID length (1) || key ID (our ephemeral public key) || ciphertext.
+    let as_public_raw = as_public_point.as_bytes();
+    let mut body = Vec::with_capacity(16 + 4 + 1 + as_public_raw.len() + ciphertext.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(u8::try_from(as_public_raw.len()).map_err(|_| Error::Encryption)?);
+    body.extend_from_slice(as_public_raw);
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+/// Things that can go wrong signing or encrypting a Web Push message.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The configured VAPID private key isn't a valid base64url-encoded
+    /// P-256 scalar.
+    #[error("invalid VAPID private key")]
+    InvalidKey,
+
+    /// A subscription's `p256dh` or `auth` isn't valid base64url, or
+    /// `p256dh` isn't a valid P-256 public key.
+    #[error("invalid push subscription")]
+    InvalidSubscription,
+
+    /// A subscription's `endpoint` isn't a valid URL.
+    #[error("invalid push endpoint")]
+    InvalidEndpoint,
+
+    /// HKDF or AES-128-GCM failed. Both only fail on a programmer error
+    /// (wrong-length output, wrong-length key), never on untrusted input.
+    #[error("payload encryption failed")]
+    Encryption,
+
+    /// We couldn't sign the VAPID JWT.
+    #[error("couldn't sign VAPID JWT: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+}
+
+/// RFC 4648 base64url, without padding: the encoding a subscription's
+/// `p256dh`/`auth` arrive in, and the one the VAPID `k` parameter expects.
+fn base64url_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut out = String::with_capacity((data.len() * 4).div_ceil(3));
+
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = chunk.get(1).copied();
+        let b2 = chunk.get(2).copied();
+
+        out.push(ALPHABET[(b0 >> 2) as usize] as char);
+        out.push(ALPHABET[(((b0 & 0x03) << 4) | (b1.unwrap_or(0) >> 4)) as usize] as char);
+
+        if let Some(b1) = b1 {
+            out.push(ALPHABET[(((b1 & 0x0f) << 2) | (b2.unwrap_or(0) >> 6)) as usize] as char);
+        }
+
+        if let Some(b2) = b2 {
+            out.push(ALPHABET[(b2 & 0x3f) as usize] as char);
+        }
+    }
+
+    out
+}
+
+/// Decode RFC 4648 base64url, tolerating missing padding. Returns `None` on
+/// any character outside the base64url alphabet.
+fn base64url_decode(encoded: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut out = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+
+    for ch in encoded.bytes().filter(|&b| b != b'=') {
+        let value = u32::try_from(ALPHABET.iter().position(|&b| b == ch)?).ok()?;
+        bits = (bits << 6) | value;
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod base64url {
+        use super::*;
+
+        #[test]
+        fn round_trips_arbitrary_bytes() {
+            let bytes = b"a push subscription's keys are opaque to us";
+            assert_eq!(
+                base64url_decode(&base64url_encode(bytes)),
+                Some(bytes.to_vec())
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_characters() {
+            assert_eq!(base64url_decode("not valid base64url!"), None);
+        }
+    }
+
+    /// A subscriber's keypair and auth secret, generated the same way a
+    /// browser's Push API would, for exercising `encrypt` end to end.
+    struct Subscriber {
+        secret: SecretKey,
+        subscription: Subscription,
+    }
+
+    impl Subscriber {
+        fn generate() -> Self {
+            let secret = SecretKey::random(&mut OsRng);
+            let mut auth = [0u8; 16];
+            OsRng.fill_bytes(&mut auth);
+
+            let subscription = Subscription {
+                endpoint: "https://push.example.com/subscription/abc123".to_string(),
+                p256dh: base64url_encode(secret.public_key().to_encoded_point(false).as_bytes()),
+                auth: base64url_encode(&auth),
+            };
+
+            Self {
+                secret,
+                subscription,
+            }
+        }
+
+        /// Reverse `encrypt`, the way a subscriber's push service worker
+        /// would, to check the message round-trips.
+        fn decrypt(&self, body: &[u8]) -> Vec<u8> {
+            let salt = &body[0..16];
+            let record_size = u32::from_be_bytes(body[16..20].try_into().unwrap());
+            assert_eq!(record_size, RECORD_SIZE);
+            let id_len = body[20] as usize;
+            let as_public_bytes = &body[21..21 + id_len];
+            let ciphertext = &body[21 + id_len..];
+
+            let as_public = PublicKey::from_sec1_bytes(as_public_bytes).unwrap();
+            let shared_secret =
+                diffie_hellman(self.secret.to_nonzero_scalar(), as_public.as_affine());
+
+            let ua_public_point = self.secret.public_key().to_encoded_point(false);
+            let auth_secret = base64url_decode(&self.subscription.auth).unwrap();
+
+            let mut key_info = Vec::new();
+            key_info.extend_from_slice(b"WebPush: info\0");
+            key_info.extend_from_slice(ua_public_point.as_bytes());
+            key_info.extend_from_slice(as_public_bytes);
+
+            let ikm_extractor =
+                Hkdf::<Sha256>::new(Some(&auth_secret), shared_secret.raw_secret_bytes());
+            let mut ikm = [0u8; 32];
+            ikm_extractor.expand(&key_info, &mut ikm).unwrap();
+
+            let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+            let mut cek = [0u8; 16];
+            prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+                .unwrap();
+            let mut nonce = [0u8; 12];
+            prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+                .unwrap();
+
+            let cipher = Aes128Gcm::new_from_slice(&cek).unwrap();
+            let mut padded = cipher
+                .decrypt(Nonce::from_slice(&nonce), ciphertext)
+                .unwrap();
+
+            assert_eq!(padded.pop(), Some(0x02));
+            padded
+        }
+    }
+
+    mod encrypt {
+        use super::*;
+
+        #[test]
+        fn round_trips_the_plaintext() {
+            let subscriber = Subscriber::generate();
+            let body = encrypt(&subscriber.subscription, b"time to ping!").unwrap();
+
+            assert_eq!(subscriber.decrypt(&body), b"time to ping!");
+        }
+
+        #[test]
+        fn rejects_an_invalid_subscription_key() {
+            let subscription = Subscription {
+                endpoint: "https://push.example.com/x".to_string(),
+                p256dh: "not valid base64url!".to_string(),
+                auth: base64url_encode(&[0u8; 16]),
+            };
+
+            assert!(matches!(
+                encrypt(&subscription, b"hi"),
+                Err(Error::InvalidSubscription)
+            ));
+        }
+    }
+
+    mod vapid {
+        use super::*;
+
+        fn config() -> VapidConfig {
+            let private_key = SecretKey::random(&mut OsRng);
+            VapidConfig {
+                private_key: private_key.clone(),
+                public_key: private_key.public_key(),
+                subject: "mailto:ops@example.com".to_string(),
+            }
+        }
+
+        #[test]
+        fn signs_a_jwt_verifiable_with_our_own_public_key() {
+            let vapid = config();
+            let jwt = vapid
+                .sign("https://push.example.com/subscription/abc123")
+                .unwrap();
+
+            let public_key_bytes = vapid.public_key.to_encoded_point(false);
+            let decoding_key = jsonwebtoken::DecodingKey::from_ec_der(public_key_bytes.as_bytes());
+            let mut validation = jsonwebtoken::Validation::new(Algorithm::ES256);
+            validation.set_audience(&["https://push.example.com"]);
+
+            let claims = jsonwebtoken::decode::<VapidClaims>(&jwt, &decoding_key, &validation)
+                .unwrap()
+                .claims;
+
+            assert_eq!(claims.sub, "mailto:ops@example.com");
+        }
+
+        #[test]
+        fn authorization_header_carries_the_jwt_and_public_key() {
+            let vapid = config();
+            let header = vapid
+                .authorization("https://push.example.com/subscription/abc123")
+                .unwrap();
+
+            assert!(header.starts_with("vapid t="));
+            assert!(header.contains(", k="));
+        }
+    }
+}
@@ -7,14 +7,19 @@ mod auth_form;
 
 /// Side effects the app can do
 pub mod effect;
-pub use effect::{Effect, Problem};
+pub use effect::{Effect, EffectContext, Problem};
 
 /// Information displayed above the main layout
 mod popover;
 use popover::{AuthIntent, Popover};
 
-use crate::config::Config;
+/// Durable, crash-safe persistence of the replica to disk
+pub mod store;
+use store::{Op, Store};
+
 use beeps_core::{
+    scheduler::Timer,
+    split::Split,
     sync::{login, register, Client},
     Document, NodeId, Replica,
 };
@@ -28,12 +33,17 @@ use ratatui::{
     },
     Frame,
 };
-use std::process::ExitCode;
-use tokio::fs;
+use std::{process::ExitCode, sync::Arc};
 use tui_input::{backend::crossterm::EventHandler, Input};
 
 /// The "functional core" of the app.
 pub struct App {
+    /// Where the replica and sync client auth are persisted. Held rather
+    /// than re-derived from a `Config` on every load/save, so swapping in a
+    /// different backend (in-memory for tests, SQLite, encrypted-at-rest)
+    /// never touches `App` itself.
+    store: Arc<dyn Store>,
+
     /// Status to display (visible at the bottom of the screen)
     status_line: Option<String>,
 
@@ -66,59 +76,73 @@ pub struct App {
 
     /// When did we last sync?
     last_sync: Option<DateTime<Utc>>,
+
+    /// Holds the next scheduled ping so we can wake up and notify about it
+    /// exactly when it's due, rather than polling `Replica::next_ping` on
+    /// `TimePassed`'s fixed interval. See `arm_timer`.
+    timer: Timer<()>,
+
+    /// The time `timer` is currently armed for, so `arm_timer` can tell
+    /// whether `Replica::next_ping` actually moved since the last call
+    /// instead of scheduling a redundant wakeup for the same moment.
+    armed_for: Option<DateTime<Utc>>,
 }
 
 impl App {
-    /// Create a new instance of the app
-    #[tracing::instrument]
-    pub async fn init(config: &Config) -> Result<Self, Problem> {
+    /// Create a new instance of the app, loading whatever `store` already
+    /// has persisted. Along with the app itself, returns whatever initial
+    /// effects it needs run right away — at minimum, arming the timer for
+    /// the first scheduled ping (if any) so we don't wait for the first
+    /// `TimePassed` tick to start listening for it.
+    #[tracing::instrument(skip(store))]
+    pub async fn init(store: Arc<dyn Store>) -> Result<(Self, Vec<Effect>), Problem> {
         tracing::info!("initializing");
 
-        let auth_path = config.data_dir().join("auth.json");
-        let auth: Option<Client> = if fs::try_exists(&auth_path).await? {
-            let data = fs::read(&auth_path).await?;
-            Some(serde_json::from_slice(&data)?)
-        } else {
-            None
+        let auth = store.load_auth().await?;
+        tracing::debug!(found = auth.is_some(), "tried to load client auth");
+
+        let replica = match store.load_replica().await? {
+            Some(replica) => replica,
+            None => Replica::new(NodeId::random()),
         };
 
-        tracing::debug!(found = auth.is_some(), "tried to load client auth");
+        let mut app = Self {
+            store,
+            status_line: None,
+            replica,
+            client: auth,
+            in_first_sync: false,
+            first_sync_document: None,
+            last_sync: None,
+            table_state: TableState::new().with_selected(0),
+            popover: None,
+            copied: None,
+            exiting: None,
+            timer: Timer::new(64, chrono::Duration::seconds(1), Utc::now()),
+            armed_for: None,
+        };
 
-        let store_path = config.data_dir().join("store.json");
-        if fs::try_exists(&store_path).await? {
-            tracing::debug!(found = true, "tried to load store");
-
-            let data = fs::read(&store_path).await?;
-            let replica: Replica = serde_json::from_slice(&data)?;
-
-            Ok(Self {
-                status_line: None,
-                replica,
-                client: auth,
-                in_first_sync: false,
-                first_sync_document: None,
-                last_sync: None,
-                table_state: TableState::new().with_selected(0),
-                popover: None,
-                copied: None,
-                exiting: None,
-            })
-        } else {
-            tracing::debug!(found = false, "tried to load store");
-
-            Ok(Self {
-                status_line: None,
-                replica: Replica::new(NodeId::random()),
-                client: auth,
-                in_first_sync: false,
-                first_sync_document: None,
-                last_sync: None,
-                table_state: TableState::new().with_selected(0),
-                popover: None,
-                copied: None,
-                exiting: None,
-            })
+        let effects = app.arm_timer().into_iter().collect();
+
+        Ok((app, effects))
+    }
+
+    /// Re-arm `timer` for whatever `Replica::next_ping` currently says is
+    /// next, returning the effect to wait for it — unless we're already
+    /// armed for that exact moment, in which case there's nothing new to
+    /// schedule. Returns nothing if `schedule_pings` hasn't run yet (so
+    /// there's no "next ping" to speak of).
+    fn arm_timer(&mut self) -> Option<Effect> {
+        let next = self.replica.next_ping()?;
+
+        if self.armed_for == Some(next) {
+            return None;
         }
+
+        self.timer.add(next, ());
+        self.armed_for = Some(next);
+
+        Some(Effect::ScheduleNext(next))
     }
 
     /// Render the app's UI to the screen
@@ -150,9 +174,14 @@ impl App {
                             .format("%a, %b %-d, %-I:%M %p")
                             .to_string(),
                     ),
-                    match self.replica.get_tag(ping) {
-                        Some(tag) => Cell::new(tag.clone()),
-                        _ => Cell::new("<unknown>".to_string()).fg(Color::DarkGray),
+                    {
+                        let tags: Vec<&str> =
+                            self.replica.get_tags(ping).map(String::as_str).collect();
+                        if tags.is_empty() {
+                            Cell::new("<unknown>".to_string()).fg(Color::DarkGray)
+                        } else {
+                            Cell::new(tags.join(", "))
+                        }
                     },
                 ])
             })
@@ -162,7 +191,7 @@ impl App {
 
         let table = Table::new(rows, [Constraint::Min(21), Constraint::Min(9)])
             .header(
-                Row::new(["Ping", "Tag"])
+                Row::new(["Ping", "Tags"])
                     .bg(Color::DarkGray)
                     .fg(Color::White),
             )
@@ -235,12 +264,14 @@ impl App {
             Action::TimePassed => {
                 let mut effects = Vec::new();
 
-                if self.replica.schedule_pings() {
+                if !self.replica.schedule_pings().is_empty() {
                     tracing::debug!("handling new ping(s)");
                     effects.push(Effect::NotifyAboutNewPing);
                     effects.push(Effect::SaveReplica(self.replica.clone()));
                 }
 
+                effects.extend(self.arm_timer());
+
                 if let Some(client) = &self.client {
                     if self
                         .last_sync
@@ -258,37 +289,154 @@ impl App {
 
                 effects
             }
+            Action::PingDue(when) => {
+                tracing::debug!(%when, "scheduled ping came due");
+
+                let mut effects = Vec::new();
+
+                self.timer.take_next(Utc::now());
+
+                // Safe to call regardless of whether the schedule moved
+                // out from under us since we were armed (e.g. a sync pulled
+                // in a new `minutes_per_ping`): a stale due time just means
+                // `schedule_pings` finds we're already caught up and
+                // returns nothing new.
+                if !self.replica.schedule_pings().is_empty() {
+                    tracing::debug!("handling new ping(s)");
+                    effects.push(Effect::NotifyAboutNewPing);
+                    effects.push(Effect::SaveReplica(self.replica.clone()));
+                }
+
+                effects.extend(self.arm_timer());
+
+                effects
+            }
             Action::LoggedIn(client) => {
                 self.client = Some(client.clone());
                 self.in_first_sync = true;
 
                 vec![
                     Effect::SaveSyncClientAuth(client.clone()),
-                    Effect::Pull(client),
+                    Effect::Pull(client.clone()),
+                    Effect::Subscribe(client.clone()),
+                    Effect::PollForChanges(client),
                 ]
             }
+            Action::TotpRequired(client, email, password) => {
+                self.status_line = Some("Two-factor code required".to_string());
+                self.popover = Some(Popover::Authenticating(
+                    auth_form::AuthForm::awaiting_totp(client.server, email, password),
+                    AuthIntent::LogIn,
+                ));
+
+                vec![]
+            }
             Action::GotWhoAmI(resp) => {
                 self.status_line = Some(format!("Logged in as \"{}\"", resp.email));
 
                 vec![]
             }
-            Action::Pushed => {
+            Action::Pushed(Ok(resp)) => {
+                if let Some(client) = &mut self.client {
+                    client.advance(&resp.vector);
+                }
+
                 self.status_line = Some("Pushed to the server".to_string());
 
                 vec![]
             }
-            Action::Pulled(resp) => {
+            Action::Pushed(Err(problem)) => {
+                self.status_line = Some(format!("Couldn't push: {problem}"));
+
+                vec![]
+            }
+            Action::Pulled(Ok(resp)) => {
+                if let Some(client) = &mut self.client {
+                    client.advance(&resp.vector);
+                }
+
+                let mut document = Document::default();
+                for part in resp.parts {
+                    document.merge_part(part);
+                }
+
                 self.status_line = Some("Got a new doc from the server".to_string());
 
                 if self.in_first_sync {
-                    self.first_sync_document = Some(resp.document);
+                    self.first_sync_document = Some(document);
                     self.popover = Some(Popover::ConfirmReplaceOrMerge);
                 } else {
-                    self.replica.merge(resp.document);
+                    self.replica.merge(document);
                 };
 
                 vec![]
             }
+            Action::Pulled(Err(problem)) => {
+                self.status_line = Some(format!("Couldn't pull: {problem}"));
+
+                vec![]
+            }
+            Action::LoggedOut => {
+                self.client = None;
+                self.status_line = Some("Logged out; please log back in".to_string());
+                self.start_logging_in();
+
+                vec![]
+            }
+            Action::Subscribed(subscription, Ok(update)) => {
+                self.status_line = Some("Got a live update from the server".to_string());
+
+                if self.in_first_sync {
+                    self.first_sync_document = Some(update.document);
+                    self.popover = Some(Popover::ConfirmReplaceOrMerge);
+                } else {
+                    self.replica.merge(update.document);
+                }
+
+                vec![Effect::AwaitSubscriptionUpdate(subscription)]
+            }
+            Action::Subscribed(_subscription, Err(problem)) => {
+                self.status_line = Some(format!("Subscription dropped, reconnecting: {problem}"));
+
+                match &self.client {
+                    Some(client) => vec![Effect::Subscribe(client.clone())],
+                    None => vec![],
+                }
+            }
+            Action::Polled(Ok(resp)) => {
+                if let Some(client) = &mut self.client {
+                    client.advance(&resp.vector);
+                }
+
+                if !resp.parts.is_empty() {
+                    self.status_line = Some("Got a live update from a long-poll".to_string());
+
+                    let mut document = Document::default();
+                    for part in resp.parts {
+                        document.merge_part(part);
+                    }
+
+                    if self.in_first_sync {
+                        self.first_sync_document = Some(document);
+                        self.popover = Some(Popover::ConfirmReplaceOrMerge);
+                    } else {
+                        self.replica.merge(document);
+                    }
+                }
+
+                match &self.client {
+                    Some(client) => vec![Effect::PollForChanges(client.clone())],
+                    None => vec![],
+                }
+            }
+            Action::Polled(Err(problem)) => {
+                self.status_line = Some(format!("Long-poll failed, retrying: {problem}"));
+
+                match &self.client {
+                    Some(client) => vec![Effect::PollForChanges(client.clone())],
+                    None => vec![],
+                }
+            }
         }
     }
 
@@ -319,12 +467,13 @@ impl App {
                 KeyCode::Char('q') | KeyCode::Esc => self.dismiss_popover(),
                 _ => (),
             },
-            Some(Popover::Editing(ping, tag_input)) => match key.code {
+            Some(Popover::Editing(ping, _existing_tags, tag_input)) => match key.code {
                 KeyCode::Enter => {
-                    self.replica.tag_ping(*ping, tag_input.value().to_string());
+                    let tag = tag_input.value().to_string();
+                    self.replica.add_tag(*ping, tag.clone());
 
                     self.dismiss_popover();
-                    effects.push(Effect::SaveReplica(self.replica.clone()));
+                    effects.push(Effect::AppendOp(Op::TagPing(*ping, tag)));
                 }
                 KeyCode::Esc => self.dismiss_popover(),
                 _ => {
@@ -344,6 +493,8 @@ impl App {
                                 login::Req {
                                     email: finished.email,
                                     password: finished.password,
+                                    totp: finished.totp,
+                                    device_label: None,
                                 },
                             ));
                         }
@@ -353,6 +504,7 @@ impl App {
                                 register::Req {
                                     email: finished.email,
                                     password: finished.password,
+                                    device_label: None,
                                 },
                             ));
                         }
@@ -424,24 +576,32 @@ impl App {
         self.popover = Some(Popover::Help);
     }
 
-    /// Clear the tag from the selected ping
+    /// Clear every tag from the selected ping
     fn clear_selected(&mut self) -> Vec<Effect> {
         if let Some(idx) = self.table_state.selected() {
-            let ping = self.current_pings().nth(idx).unwrap();
-            self.replica.untag_ping(*ping);
+            let ping = *self.current_pings().nth(idx).unwrap();
+
+            let tags: Vec<String> = self.replica.get_tags(&ping).cloned().collect();
 
-            vec![Effect::SaveReplica(self.replica.clone())]
+            tags.into_iter()
+                .map(|tag| {
+                    self.replica.remove_tag(ping, &tag);
+                    Effect::AppendOp(Op::UntagPing(ping, tag))
+                })
+                .collect()
         } else {
             vec![]
         }
     }
 
-    /// Show a new popover editing the selected ping.
+    /// Show a new popover for adding a tag to the selected ping, alongside
+    /// whatever tags are already set on it.
     fn edit_selected(&mut self) {
         self.popover = self.selected_ping().map(|ping| {
             Popover::Editing(
                 *ping,
-                Input::new(self.replica.get_tag(ping).cloned().unwrap_or_default()),
+                self.replica.get_tags(ping).cloned().collect(),
+                Input::default(),
             )
         });
     }
@@ -450,15 +610,15 @@ impl App {
     fn copy_selected(&mut self) {
         self.copied = self
             .selected_ping()
-            .and_then(|ping| self.replica.get_tag(ping).cloned());
+            .and_then(|ping| self.replica.get_tags(ping).next().cloned());
     }
 
     /// Paste the copied tag (if any) into the selected ping.
     fn paste_copied(&mut self) -> Vec<Effect> {
-        if let Some((ping, tag)) = self.selected_ping().zip(self.copied.as_ref()) {
-            self.replica.tag_ping(*ping, tag.clone());
+        if let Some((ping, tag)) = self.selected_ping().copied().zip(self.copied.clone()) {
+            self.replica.add_tag(ping, tag.clone());
 
-            vec![Effect::SaveReplica(self.replica.clone())]
+            vec![Effect::AppendOp(Op::TagPing(ping, tag))]
         } else {
             vec![]
         }
@@ -1,4 +1,5 @@
-use beeps_core::sync::{error, pull, push, whoami, Client};
+use beeps_core::sync::{error, poll, pull, push, subscribe, whoami, Client};
+use chrono::{DateTime, Utc};
 use crossterm::event::KeyEvent;
 
 /// Things that can happen to this app
@@ -13,6 +14,11 @@ pub enum Action {
     /// We logged in successfully and got a new JWT
     LoggedIn(Client),
 
+    /// The password was right, but the account needs a TOTP code to finish
+    /// logging in. Carries the client and the email/password that were
+    /// already verified, so the user only has to type the code.
+    TotpRequired(Client, String, String),
+
     /// We got information about who is logged in
     GotWhoAmI(whoami::Resp),
 
@@ -30,4 +36,24 @@ pub enum Action {
 
     /// We got an update from the server
     Pulled(error::Result<pull::Resp>),
+
+    /// Our refresh token no longer works (expired, revoked, or caught by
+    /// reuse detection), so the app needs to log back in from scratch.
+    LoggedOut,
+
+    /// Our live document subscription produced an update, or an error
+    /// reading the next one. Carries the subscription itself back either
+    /// way, so the app can keep listening on it (or, on an error, reconnect
+    /// a fresh one).
+    Subscribed(subscribe::Subscription, error::Result<subscribe::Update>),
+
+    /// A long-poll request we had outstanding came back, either with new
+    /// parts or (if it just timed out) empty.
+    Polled(error::Result<poll::Resp>),
+
+    /// The `Timer` we armed for the next scheduled ping says it's due.
+    /// Carries the time it was armed for, mostly for logging — handling it
+    /// is just another call to `Replica::schedule_pings`, which is safe to
+    /// call even if the schedule moved out from under us in the meantime.
+    PingDue(DateTime<Utc>),
 }
@@ -22,15 +22,24 @@ pub struct AuthForm {
 
     /// What's your password? (Will be masked)
     password: Input,
+
+    /// Current code from an authenticator app, if the account has two-factor
+    /// enabled. Only shown once the server has told us it's needed.
+    totp: Input,
+
+    /// Whether the server has told us this login needs a TOTP code, so we
+    /// should show and cycle through the TOTP field.
+    totp_required: bool,
 }
 
-form_fields!(Field, Server, Email, Password);
+form_fields!(Field, Server, Email, Password, Totp);
 
 impl AuthForm {
     /// Render this form to the screen
     #[expect(clippy::cast_possible_truncation)]
     pub fn render(&mut self, body_area: Rect, frame: &mut Frame<'_>) {
-        let popup_vert = Layout::vertical([Constraint::Length(9)]).flex(Flex::Center);
+        let height = if self.totp_required { 12 } else { 9 };
+        let popup_vert = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
         let popup_horiz = Layout::horizontal([Constraint::Percentage(50)]).flex(Flex::Center);
 
         let [popup_area] = popup_vert.areas(body_area);
@@ -39,8 +48,16 @@ impl AuthForm {
 
         let width = popup_area.width - 2 - 1; // -2 for the border, -1 for the cursor
 
-        let fields = Layout::vertical(Constraint::from_lengths([3, 3, 3]));
-        let [server_area, email_area, password_area] = fields.areas(popup_area);
+        let lengths = if self.totp_required {
+            &[3, 3, 3, 3][..]
+        } else {
+            &[3, 3, 3][..]
+        };
+        let fields = Layout::vertical(Constraint::from_lengths(lengths.iter().copied()));
+        let areas = fields.split(popup_area);
+        let server_area = areas[0];
+        let email_area = areas[1];
+        let password_area = areas[2];
 
         let border_style = Style::default().fg(Color::Blue);
 
@@ -118,6 +135,32 @@ impl AuthForm {
                 ));
             }
         }
+
+        // TOTP
+        if self.totp_required {
+            let totp_area = areas[3];
+            let totp_input_scroll = self.totp.visual_scroll(width as usize);
+
+            let totp_field = Paragraph::new(self.totp.value())
+                .scroll((0, totp_input_scroll as u16))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("Authenticator Code")
+                        .border_style(border_style),
+                );
+
+            frame.render_widget(totp_field, totp_area);
+
+            if matches!(self.active, Field::Totp) {
+                frame.set_cursor_position((
+                    popup_area.x
+                        + (self.totp.visual_cursor().max(totp_input_scroll) - totp_input_scroll) as u16 // current end of text
+                        + 1, // just past the end of the text
+                    totp_area.y + 1, // +1 row for the border/title
+                ));
+            }
+        }
     }
 
     /// Handle a key event, updating the internal state of the form. This
@@ -126,10 +169,10 @@ impl AuthForm {
     pub fn handle_event(&mut self, key: KeyEvent) {
         match key.code {
             KeyCode::Tab => {
-                self.active = self.active.next();
+                self.active = self.skip_totp_if_not_required(self.active.next(), Field::next);
             }
             KeyCode::BackTab => {
-                self.active = self.active.prev();
+                self.active = self.skip_totp_if_not_required(self.active.prev(), Field::prev);
             }
             _ => {
                 let event = Event::Key(key);
@@ -138,11 +181,23 @@ impl AuthForm {
                     Field::Server => self.server.handle_event(&event),
                     Field::Email => self.email.handle_event(&event),
                     Field::Password => self.password.handle_event(&event),
+                    Field::Totp => self.totp.handle_event(&event),
                 };
             }
         }
     }
 
+    /// `Field` has no idea whether TOTP is in play, so cycling through
+    /// fields will happily land on it even when we're not showing it. Step
+    /// past it (in the same direction) in that case.
+    fn skip_totp_if_not_required(&self, field: Field, step: impl Fn(&Field) -> Field) -> Field {
+        if !self.totp_required && matches!(field, Field::Totp) {
+            step(&field)
+        } else {
+            field
+        }
+    }
+
     /// Once you're done filling out the form, call `finish` to unwrap the
     /// inputs into something you can use to make an HTTP request.
     pub fn finish(&self) -> AuthInfo {
@@ -150,6 +205,20 @@ impl AuthForm {
             server: self.server.to_string(),
             email: self.email.to_string(),
             password: self.password.to_string(),
+            totp: self.totp_required.then(|| self.totp.to_string()),
+        }
+    }
+
+    /// Re-open the form already filled in, asking only for a TOTP code,
+    /// after the server has told us a login attempt needs one.
+    pub fn awaiting_totp(server: String, email: String, password: String) -> Self {
+        Self {
+            active: Field::Totp,
+            server: Input::new(server),
+            email: Input::new(email),
+            password: Input::new(password),
+            totp: Input::new(String::new()),
+            totp_required: true,
         }
     }
 }
@@ -161,6 +230,8 @@ impl Default for AuthForm {
             server: Input::new("https://beeps.bytes.zone".into()),
             email: Input::new(String::new()),
             password: Input::new(String::new()),
+            totp: Input::new(String::new()),
+            totp_required: false,
         }
     }
 }
@@ -176,4 +247,8 @@ pub struct AuthInfo {
 
     /// What password to use
     pub password: String,
+
+    /// The current TOTP code, if the form was shown with two-factor
+    /// required.
+    pub totp: Option<String>,
 }
@@ -1,24 +1,31 @@
+use super::store::{self, Op, Store};
 use super::Action;
-use crate::config::Config;
 use beeps_core::{
-    sync::{self, login, register, Client},
+    sync::{self, client::Backoff, login, register, subscribe, Client},
     Document, Replica,
 };
+use chrono::{DateTime, Utc};
 use notify_rust::Notification;
-use tokio::{fs, io};
+use std::sync::Arc;
+use tokio::io;
 
-/// Connections to external services that effect use. We keep these around to
+/// Connections to external services that effects use. We keep these around to
 /// have some level of connection sharing for the app as a whole.
 pub struct EffectContext {
     /// an HTTP client with reqwest
     http: reqwest::Client,
+
+    /// Where to persist the replica and sync client auth. The same instance
+    /// `App::init` loaded from, so a save here is visible to the next load.
+    store: Arc<dyn Store>,
 }
 
 impl EffectContext {
-    /// Get a new `EffectConnections`
-    pub fn new() -> Self {
+    /// Get a new `EffectConnections`, persisting through `store`.
+    pub fn new(store: Arc<dyn Store>) -> Self {
         Self {
             http: reqwest::Client::new(),
+            store,
         }
     }
 }
@@ -26,9 +33,15 @@ impl EffectContext {
 /// Things that can happen as a result of user input. Side effects!
 #[derive(Debug)]
 pub enum Effect {
-    /// Save replica to disk
+    /// Save a full, fresh snapshot of the replica to disk, compacting away
+    /// any operations appended to the log since the last snapshot.
     SaveReplica(Replica),
 
+    /// Append a single operation to the on-disk log, without rewriting the
+    /// whole replica. Used for the frequent, small mutations (tagging or
+    /// untagging a single ping) so they don't pay the cost of a full save.
+    AppendOp(Op),
+
     /// Save sync client auth to disk
     SaveSyncClientAuth(Client),
 
@@ -44,15 +57,42 @@ pub enum Effect {
     /// Check login status
     WhoAmI(Client),
 
-    /// Push our replica to the server
+    /// Push our replica to the server. Only the parts the server hasn't
+    /// already acked (per the client's own state) are actually sent.
     Push(Client, Document),
+
+    /// Pull whatever's changed since the last sync.
+    Pull(Client),
+
+    /// Long-poll the server for changes, for near-real-time sync with a
+    /// single outstanding request instead of polling `Pull` on a timer.
+    /// `App` keeps exactly one of these outstanding per logged-in client,
+    /// re-issuing it from both `Action::Polled` arms (success or failure)
+    /// so there's always another one in flight — the `handle` loop itself
+    /// never changes shape to do this, it just returns the next
+    /// `PollForChanges` alongside whatever else a poll result produced.
+    PollForChanges(Client),
+
+    /// Open a live WebSocket subscription to the server, so we learn about
+    /// another replica's push the moment it happens instead of waiting on
+    /// `Pull`'s timer.
+    Subscribe(Client),
+
+    /// Wait for the next update on a subscription we already have open.
+    AwaitSubscriptionUpdate(subscribe::Subscription),
+
+    /// Sleep until `when`, then report that the ping scheduled for that
+    /// moment is due. Replaces polling `Replica::next_ping` on a fixed
+    /// interval with a single wakeup timed to exactly when it's needed, the
+    /// same way `PollForChanges` replaces polling `Pull` on a timer.
+    ScheduleNext(DateTime<Utc>),
 }
 
 impl Effect {
     /// Perform the side-effectful portions of this effect, returning the next
     /// `Action` the application needs to handle
-    pub async fn run(self, conn: &EffectContext, config: &Config) -> Option<Action> {
-        match self.run_inner(conn, config).await {
+    pub async fn run(self, conn: &EffectContext) -> Option<Action> {
+        match self.run_inner(conn).await {
             Ok(action) => action,
             Err(problem) => {
                 tracing::error!(?problem, "problem running effect");
@@ -63,36 +103,28 @@ impl Effect {
 
     /// The actual implementation of `run`, but with a `Result` wrapper to make
     /// it more ergonomic to write.
-    async fn run_inner(
-        self,
-        conn: &EffectContext,
-        config: &Config,
-    ) -> Result<Option<Action>, Problem> {
+    async fn run_inner(self, conn: &EffectContext) -> Result<Option<Action>, Problem> {
         match self {
             Self::SaveReplica(replica) => {
                 tracing::debug!("saving replica");
 
-                let base = config.data_dir();
-                fs::create_dir_all(&base).await?;
+                conn.store.save_replica(&replica).await?;
 
-                let store = base.join("store.json");
+                Ok(Some(Action::SavedReplica))
+            }
 
-                let data = serde_json::to_vec(&replica)?;
-                fs::write(&store, &data).await?;
+            Self::AppendOp(op) => {
+                tracing::debug!(?op, "appending operation");
 
-                Ok(Some(Action::SavedReplica))
+                conn.store.append_op(&op).await?;
+
+                Ok(None)
             }
 
             Self::SaveSyncClientAuth(client) => {
                 tracing::info!("saving client auth");
 
-                let base = config.data_dir();
-                fs::create_dir_all(&base).await?;
-
-                let store = base.join("auth.json");
-
-                let data = serde_json::to_vec(&client)?;
-                fs::write(&store, &data).await?;
+                conn.store.save_auth(&client).await?;
 
                 Ok(Some(Action::SavedSyncClientAuth))
             }
@@ -115,6 +147,8 @@ impl Effect {
                 let resp = client.register(&conn.http, &req).await?;
 
                 client.auth = Some(resp.jwt);
+                client.refresh_token = Some(resp.refresh_token);
+                client.document_id = Some(resp.document_id);
 
                 Ok(Some(Action::LoggedIn(client)))
             }
@@ -122,11 +156,28 @@ impl Effect {
             Self::LogIn(mut client, req) => {
                 tracing::info!("logging in");
 
-                let resp = client.login(&conn.http, &req).await?;
+                let email = req.email.clone();
+                let password = req.password.clone();
 
-                client.auth = Some(resp.jwt);
+                let resp = client.login(&conn.http, &req).await?;
 
-                Ok(Some(Action::LoggedIn(client)))
+                match resp {
+                    login::Resp::Ok {
+                        jwt,
+                        refresh_token,
+                        document_id,
+                        ..
+                    } => {
+                        client.auth = Some(jwt);
+                        client.refresh_token = Some(refresh_token);
+                        client.document_id = Some(document_id);
+
+                        Ok(Some(Action::LoggedIn(client)))
+                    }
+                    login::Resp::TotpRequired => {
+                        Ok(Some(Action::TotpRequired(client, email, password)))
+                    }
+                }
             }
 
             Self::WhoAmI(client) => {
@@ -137,12 +188,90 @@ impl Effect {
                 Ok(Some(Action::GotWhoAmI(resp)))
             }
 
-            Self::Push(client, document) => {
+            Self::Push(mut client, document) => {
                 tracing::info!("pushing document");
 
-                let _ = client.push(&conn.http, &document).await?;
+                let req = match client.delta(document) {
+                    Ok(req) => req,
+                    Err(err) => return Ok(Some(Action::Pushed(Err(err)))),
+                };
+
+                let resp = client
+                    .with_retry(&conn.http, &Backoff::default(), |c| {
+                        c.push(&conn.http, &req)
+                    })
+                    .await;
+
+                // A refresh attempt inside `with_retry` may have rotated
+                // our tokens; persist them so a later run doesn't try the
+                // one that's now retired.
+                conn.store.save_auth(&client).await?;
+
+                match resp {
+                    Err(err) if err.is_auth_failure() => Ok(Some(Action::LoggedOut)),
+                    resp => Ok(Some(Action::Pushed(resp))),
+                }
+            }
+
+            Self::Pull(mut client) => {
+                tracing::info!("pulling document");
+
+                let req = client.pull_req();
+                let resp = client
+                    .with_retry(&conn.http, &Backoff::default(), |c| {
+                        c.pull(&conn.http, &req)
+                    })
+                    .await;
+
+                conn.store.save_auth(&client).await?;
 
-                Ok(Some(Action::Pushed))
+                match resp {
+                    Err(err) if err.is_auth_failure() => Ok(Some(Action::LoggedOut)),
+                    resp => Ok(Some(Action::Pulled(resp))),
+                }
+            }
+
+            Self::PollForChanges(mut client) => {
+                tracing::info!("long-polling for changes");
+
+                let req = client.poll_req();
+                let resp = client
+                    .with_retry(&conn.http, &Backoff::default(), |c| {
+                        c.poll(&conn.http, &req)
+                    })
+                    .await;
+
+                conn.store.save_auth(&client).await?;
+
+                match resp {
+                    Err(err) if err.is_auth_failure() => Ok(Some(Action::LoggedOut)),
+                    resp => Ok(Some(Action::Polled(resp))),
+                }
+            }
+
+            Self::Subscribe(client) => {
+                tracing::info!("opening document subscription");
+
+                let mut subscription = client.subscribe().await?;
+                let update = subscription.next().await;
+
+                Ok(Some(Action::Subscribed(subscription, update)))
+            }
+
+            Self::AwaitSubscriptionUpdate(mut subscription) => {
+                let update = subscription.next().await;
+
+                Ok(Some(Action::Subscribed(subscription, update)))
+            }
+
+            Self::ScheduleNext(when) => {
+                let remaining = (when - Utc::now())
+                    .to_std()
+                    .unwrap_or(std::time::Duration::ZERO);
+
+                tokio::time::sleep(remaining).await;
+
+                Ok(Some(Action::PingDue(when)))
             }
         }
     }
@@ -164,4 +293,8 @@ pub enum Problem {
     /// URL or expired credentials.
     #[error("Problem communicating with the server: {0}")]
     Server(#[from] sync::Error),
+
+    /// We had a problem sealing or unsealing a `JsonFileStore` container.
+    #[error("Encryption error: {0}")]
+    Crypto(#[from] store::crypto::Error),
 }
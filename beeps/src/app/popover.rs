@@ -14,8 +14,10 @@ pub enum Popover {
     /// Show a table of keyboard shortcuts
     Help,
 
-    /// Editing the tag for a ping
-    Editing(DateTime<Utc>, Input),
+    /// Adding a tag to a ping. Carries the tags already set on it, so the
+    /// popover can show what's there alongside the input for the new one —
+    /// a ping can carry more than one tag, so this never replaces them.
+    Editing(DateTime<Utc>, Vec<String>, Input),
 
     /// Register with the server
     Authenticating(auth_form::AuthForm, AuthIntent),
@@ -53,12 +55,13 @@ impl Popover {
                         Row::new(vec!["? / F1", "Display this help"]),
                         Row::new(vec!["j / down", "Select ping below"]),
                         Row::new(vec!["k / up", "Select ping above"]),
-                        Row::new(vec!["e / enter", "Edit tag for selected ping"]),
-                        Row::new(vec!["c", "Copy tag for selected ping"]),
+                        Row::new(vec!["e / enter", "Add a tag to selected ping"]),
+                        Row::new(vec!["backspace / delete", "Clear every tag from selected ping"]),
+                        Row::new(vec!["c", "Copy a tag from selected ping"]),
                         Row::new(vec!["v", "Paste copied tag to selected ping"]),
                         Row::new(vec!["q", "Quit / Close help"]),
                         Row::new(vec!["r", "Register a new account with the server"]),
-                        Row::new(vec!["enter (editing)", "Save"]),
+                        Row::new(vec!["enter (editing)", "Add the tag"]),
                         Row::new(vec!["escape (editing)", "Cancel"]),
                     ],
                     [Constraint::Max(16), Constraint::Fill(1)],
@@ -74,7 +77,7 @@ impl Popover {
                 frame.render_widget(Clear, popup_area);
                 frame.render_widget(popup, popup_area);
             }
-            Popover::Editing(ping, tag_input) => {
+            Popover::Editing(ping, existing_tags, tag_input) => {
                 let popup_vert = Layout::vertical([Constraint::Length(3)]).flex(Flex::Center);
                 let popup_horiz =
                     Layout::horizontal([Constraint::Percentage(50)]).flex(Flex::Center);
@@ -86,13 +89,19 @@ impl Popover {
 
                 let input_scroll = tag_input.visual_scroll(width as usize);
 
+                let title = if existing_tags.is_empty() {
+                    format!("Add a tag for {}", ping.to_rfc2822())
+                } else {
+                    format!(
+                        "Add a tag for {} (already: {})",
+                        ping.to_rfc2822(),
+                        existing_tags.join(", ")
+                    )
+                };
+
                 let popup = Paragraph::new(tag_input.value())
                     .scroll((0, input_scroll as u16))
-                    .block(
-                        Block::default()
-                            .borders(Borders::ALL)
-                            .title(format!("Edit tag for {}", ping.to_rfc2822())),
-                    )
+                    .block(Block::default().borders(Borders::ALL).title(title))
                     .style(Style::default().blue());
 
                 frame.render_widget(Clear, popup_area);
@@ -0,0 +1,439 @@
+/// Optional encryption-at-rest for `JsonFileStore`'s containers.
+pub(crate) mod crypto;
+
+use super::effect::Problem;
+use beeps_core::{sync::Client, Replica};
+use chrono::{DateTime, Utc};
+use std::{path::PathBuf, sync::Mutex};
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, ErrorKind},
+};
+
+/// A single mutation to a `Replica`, as recorded in the append-only operation
+/// log between snapshots. Mirrors `Replica`'s own mutating methods.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Op {
+    /// See `Replica::set_minutes_per_ping`.
+    SetMinutesPerPing(u16),
+
+    /// See `Replica::add_ping`.
+    AddPing(DateTime<Utc>),
+
+    /// See `Replica::add_tag`.
+    TagPing(DateTime<Utc>, String),
+
+    /// See `Replica::remove_tag`.
+    UntagPing(DateTime<Utc>, String),
+}
+
+impl Op {
+    /// Apply this operation to a replica, e.g. when replaying the log on load.
+    fn apply(&self, replica: &mut Replica) {
+        match self.clone() {
+            Self::SetMinutesPerPing(new) => replica.set_minutes_per_ping(new),
+            Self::AddPing(when) => replica.add_ping(when),
+            Self::TagPing(when, tag) => {
+                replica.add_tag(when, tag);
+            }
+            Self::UntagPing(when, tag) => {
+                replica.remove_tag(when, &tag);
+            }
+        }
+    }
+}
+
+/// Durable storage for a replica's persisted state and the sync client's
+/// auth, abstracted away from any particular backend. `App` holds one of
+/// these instead of deriving file paths from `Config` itself, so swapping in
+/// a different backend (SQLite, encrypted-at-rest, ...) is a matter of
+/// writing a new impl rather than touching `App`/`Effect`.
+#[async_trait::async_trait]
+pub trait Store: Send + Sync {
+    /// Load the replica as last persisted, replaying any operations recorded
+    /// since the last snapshot. `None` if nothing's ever been saved.
+    async fn load_replica(&self) -> Result<Option<Replica>, Problem>;
+
+    /// Append a single operation on top of whatever's already persisted,
+    /// without rewriting the whole replica. Cheap enough to call on every
+    /// small mutation (tagging or untagging a single ping).
+    async fn append_op(&self, op: &Op) -> Result<(), Problem>;
+
+    /// Persist `replica` as a fresh, complete snapshot, superseding any
+    /// operations appended since the last one.
+    async fn save_replica(&self, replica: &Replica) -> Result<(), Problem>;
+
+    /// Load the sync client's saved auth, if we've ever logged in.
+    async fn load_auth(&self) -> Result<Option<Client>, Problem>;
+
+    /// Persist the sync client's current auth.
+    async fn save_auth(&self, client: &Client) -> Result<(), Problem>;
+}
+
+/// The default `Store`: a JSON snapshot plus an append-only JSON-lines
+/// operation log, both under a directory on disk.
+///
+/// If `passphrase` is set, every container is sealed with it before it
+/// touches disk: the replica snapshot and auth file via `crypto::seal`,
+/// and the append-only operation log one line at a time via
+/// `crypto::seal_line` (it's appended to rather than rewritten, so there's
+/// no single buffer to seal, and a ping's tag is just as sensitive as
+/// anything in the snapshot). A file written before encryption was turned
+/// on (or by a build without a passphrase configured) is read back as
+/// plaintext automatically, since `crypto::is_sealed`/`is_sealed_line`
+/// check for our magic header rather than assuming either format.
+pub struct JsonFileStore {
+    dir: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl JsonFileStore {
+    /// Store everything under `dir`, creating it on first write if it
+    /// doesn't already exist. Pass `passphrase` to seal the replica
+    /// snapshot and auth file at rest; `None` keeps writing them as plain
+    /// JSON, same as before encryption-at-rest existed.
+    pub fn new(dir: PathBuf, passphrase: Option<String>) -> Self {
+        Self { dir, passphrase }
+    }
+
+    /// Seal `data` if we have a passphrase configured, otherwise pass it
+    /// through untouched.
+    fn maybe_seal(&self, data: Vec<u8>) -> Result<Vec<u8>, Problem> {
+        match &self.passphrase {
+            Some(passphrase) => Ok(crypto::seal(passphrase, &data)?),
+            None => Ok(data),
+        }
+    }
+
+    /// Unseal `data` if it's one of our sealed containers. Plaintext data
+    /// is passed through untouched, whether or not we have a passphrase
+    /// configured — that's what lets an unencrypted file keep working. A
+    /// sealed file with no passphrase configured, or the wrong one, fails
+    /// with `Problem::Crypto`.
+    fn maybe_unseal(&self, data: Vec<u8>) -> Result<Vec<u8>, Problem> {
+        if !crypto::is_sealed(&data) {
+            return Ok(data);
+        }
+
+        let passphrase = self
+            .passphrase
+            .as_deref()
+            .ok_or(Problem::Crypto(crypto::Error::MissingPassphrase))?;
+
+        Ok(crypto::unseal(passphrase, &data)?)
+    }
+
+    /// Seal a single operation-log line if we have a passphrase configured,
+    /// otherwise pass it through untouched. See `maybe_seal`, the
+    /// whole-buffer equivalent used for the snapshot and auth file.
+    fn maybe_seal_line(&self, line: &[u8]) -> Result<Vec<u8>, Problem> {
+        match &self.passphrase {
+            Some(passphrase) => Ok(crypto::seal_line(passphrase, line)?.into_bytes()),
+            None => Ok(line.to_vec()),
+        }
+    }
+
+    /// Unseal a single operation-log line if it's sealed. Plaintext lines
+    /// are passed through untouched, same as `maybe_unseal`.
+    fn maybe_unseal_line(&self, line: &str) -> Result<Vec<u8>, Problem> {
+        if !crypto::is_sealed_line(line) {
+            return Ok(line.as_bytes().to_vec());
+        }
+
+        let passphrase = self
+            .passphrase
+            .as_deref()
+            .ok_or(Problem::Crypto(crypto::Error::MissingPassphrase))?;
+
+        Ok(crypto::unseal_line(passphrase, line)?)
+    }
+
+    /// Where we write the last full snapshot of the replica.
+    fn snapshot_path(&self) -> PathBuf {
+        self.dir.join("store.json")
+    }
+
+    /// Where we write a temporary snapshot before atomically renaming it
+    /// into place.
+    fn snapshot_tmp_path(&self) -> PathBuf {
+        self.dir.join("store.json.tmp")
+    }
+
+    /// Where we append operations that have happened since the last
+    /// snapshot.
+    fn log_path(&self) -> PathBuf {
+        self.dir.join("store.ops.jsonl")
+    }
+
+    /// Where we write the sync client's auth.
+    fn auth_path(&self) -> PathBuf {
+        self.dir.join("auth.json")
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for JsonFileStore {
+    async fn load_replica(&self) -> Result<Option<Replica>, Problem> {
+        let mut replica = match fs::read(self.snapshot_path()).await {
+            Ok(data) => serde_json::from_slice::<Replica>(&self.maybe_unseal(data)?)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        match fs::read_to_string(self.log_path()).await {
+            Ok(log) => {
+                for line in log.lines().filter(|line| !line.is_empty()) {
+                    let op: Op = serde_json::from_slice(&self.maybe_unseal_line(line)?)?;
+                    op.apply(&mut replica);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        // Re-seed the clock from what we just loaded rather than trust it as
+        // replayed, so a wall-clock rewind since the last time we wrote to
+        // disk can't make us hand out a timestamp at or below one already
+        // persisted.
+        Ok(Some(Replica::recovered(
+            replica.node_id(),
+            replica.state().clone(),
+        )))
+    }
+
+    async fn append_op(&self, op: &Op) -> Result<(), Problem> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let mut line = self.maybe_seal_line(&serde_json::to_vec(op)?)?;
+        line.push(b'\n');
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())
+            .await?;
+        file.write_all(&line).await?;
+
+        Ok(())
+    }
+
+    async fn save_replica(&self, replica: &Replica) -> Result<(), Problem> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let data = self.maybe_seal(serde_json::to_vec(replica)?)?;
+
+        let tmp = self.snapshot_tmp_path();
+        fs::write(&tmp, &data).await?;
+        fs::rename(&tmp, self.snapshot_path()).await?;
+
+        // The log only records operations on top of the snapshot we just
+        // wrote, so it's safe to drop everything that was in it.
+        fs::write(self.log_path(), b"").await?;
+
+        Ok(())
+    }
+
+    async fn load_auth(&self) -> Result<Option<Client>, Problem> {
+        match fs::read(self.auth_path()).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&self.maybe_unseal(data)?)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn save_auth(&self, client: &Client) -> Result<(), Problem> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let data = self.maybe_seal(serde_json::to_vec(client)?)?;
+        fs::write(self.auth_path(), &data).await?;
+
+        Ok(())
+    }
+}
+
+/// An in-memory `Store`, for exercising `App::init`/`Effect::run` in tests
+/// without touching the filesystem.
+#[derive(Default)]
+pub struct InMemoryStore {
+    replica: Mutex<Option<Replica>>,
+    ops: Mutex<Vec<Op>>,
+    auth: Mutex<Option<Client>>,
+}
+
+#[async_trait::async_trait]
+impl Store for InMemoryStore {
+    async fn load_replica(&self) -> Result<Option<Replica>, Problem> {
+        let mut replica = self.replica.lock().expect("store mutex poisoned").clone();
+
+        if let Some(replica) = &mut replica {
+            for op in self.ops.lock().expect("store mutex poisoned").iter() {
+                op.apply(replica);
+            }
+        }
+
+        Ok(replica)
+    }
+
+    async fn append_op(&self, op: &Op) -> Result<(), Problem> {
+        self.ops.lock().expect("store mutex poisoned").push(op.clone());
+
+        Ok(())
+    }
+
+    async fn save_replica(&self, replica: &Replica) -> Result<(), Problem> {
+        *self.replica.lock().expect("store mutex poisoned") = Some(replica.clone());
+        self.ops.lock().expect("store mutex poisoned").clear();
+
+        Ok(())
+    }
+
+    async fn load_auth(&self) -> Result<Option<Client>, Problem> {
+        Ok(self.auth.lock().expect("store mutex poisoned").clone())
+    }
+
+    async fn save_auth(&self, client: &Client) -> Result<(), Problem> {
+        *self.auth.lock().expect("store mutex poisoned") = Some(client.clone());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod json_file_store {
+        use super::*;
+        use beeps_core::NodeId;
+        use tempdir::TempDir;
+
+        #[tokio::test]
+        async fn round_trips_an_unencrypted_replica() {
+            let temp = TempDir::new("beeps").expect("could not create temp dir");
+            let store = JsonFileStore::new(temp.path().to_owned(), None);
+
+            let replica = Replica::new(NodeId::random());
+            store.save_replica(&replica).await.unwrap();
+
+            assert!(store.load_replica().await.unwrap().is_some());
+        }
+
+        #[tokio::test]
+        async fn round_trips_a_sealed_replica() {
+            let temp = TempDir::new("beeps").expect("could not create temp dir");
+            let store =
+                JsonFileStore::new(temp.path().to_owned(), Some("correct horse".to_string()));
+
+            let replica = Replica::new(NodeId::random());
+            store.save_replica(&replica).await.unwrap();
+
+            let on_disk = fs::read(temp.path().join("store.json")).await.unwrap();
+            assert!(crypto::is_sealed(&on_disk));
+
+            assert!(store.load_replica().await.unwrap().is_some());
+        }
+
+        #[tokio::test]
+        async fn appended_ops_are_sealed_on_disk_and_replay_under_a_passphrase() {
+            let temp = TempDir::new("beeps").expect("could not create temp dir");
+            let store =
+                JsonFileStore::new(temp.path().to_owned(), Some("correct horse".to_string()));
+
+            let replica = Replica::new(NodeId::random());
+            store.save_replica(&replica).await.unwrap();
+
+            let when = Utc::now();
+            store.append_op(&Op::AddPing(when)).await.unwrap();
+
+            let on_disk = fs::read_to_string(temp.path().join("store.ops.jsonl"))
+                .await
+                .unwrap();
+            let line = on_disk.lines().next().expect("expected an appended line");
+            assert!(crypto::is_sealed_line(line));
+
+            let reloaded = store.load_replica().await.unwrap().unwrap();
+            assert!(reloaded.state().pings.contains(&when));
+        }
+
+        #[tokio::test]
+        async fn refuses_to_replay_a_sealed_op_log_without_a_passphrase() {
+            let temp = TempDir::new("beeps").expect("could not create temp dir");
+            let sealing_store =
+                JsonFileStore::new(temp.path().to_owned(), Some("correct horse".to_string()));
+            sealing_store
+                .save_replica(&Replica::new(NodeId::random()))
+                .await
+                .unwrap();
+            sealing_store
+                .append_op(&Op::AddPing(Utc::now()))
+                .await
+                .unwrap();
+
+            let store = JsonFileStore::new(temp.path().to_owned(), None);
+            assert!(matches!(
+                store.load_replica().await,
+                Err(Problem::Crypto(crypto::Error::MissingPassphrase))
+            ));
+        }
+
+        #[tokio::test]
+        async fn refuses_to_open_a_sealed_replica_without_a_passphrase() {
+            let temp = TempDir::new("beeps").expect("could not create temp dir");
+            let sealing_store =
+                JsonFileStore::new(temp.path().to_owned(), Some("correct horse".to_string()));
+            sealing_store
+                .save_replica(&Replica::new(NodeId::random()))
+                .await
+                .unwrap();
+
+            let store = JsonFileStore::new(temp.path().to_owned(), None);
+            assert!(matches!(
+                store.load_replica().await,
+                Err(Problem::Crypto(crypto::Error::MissingPassphrase))
+            ));
+        }
+    }
+
+    mod in_memory_store {
+        use super::*;
+        use beeps_core::NodeId;
+
+        #[tokio::test]
+        async fn round_trips_a_saved_replica() {
+            let store = InMemoryStore::default();
+            assert!(store.load_replica().await.unwrap().is_none());
+
+            let replica = Replica::new(NodeId::random());
+            store.save_replica(&replica).await.unwrap();
+
+            assert!(store.load_replica().await.unwrap().is_some());
+        }
+
+        #[tokio::test]
+        async fn replays_appended_ops_on_top_of_the_snapshot() {
+            let store = InMemoryStore::default();
+            let replica = Replica::new(NodeId::random());
+            store.save_replica(&replica).await.unwrap();
+
+            let when = Utc::now();
+            store.append_op(&Op::AddPing(when)).await.unwrap();
+
+            let reloaded = store.load_replica().await.unwrap().unwrap();
+            assert!(reloaded.state().pings.contains(&when));
+        }
+
+        #[tokio::test]
+        async fn round_trips_saved_auth() {
+            let store = InMemoryStore::default();
+            assert!(store.load_auth().await.unwrap().is_none());
+
+            let client = Client::new("https://example.com".to_string());
+            store.save_auth(&client).await.unwrap();
+
+            assert_eq!(
+                store.load_auth().await.unwrap().map(|c| c.server),
+                Some(client.server)
+            );
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+
+/// Prefixed to a sealed container so `is_sealed`/`unseal` can tell one from
+/// plain JSON written before encryption-at-rest existed (or with it turned
+/// off) without guessing from content.
+const MAGIC: &[u8; 8] = b"BEEPSE01";
+
+/// Random per-container salt the key is derived from, long enough that two
+/// containers never collide even across every replica/auth file we ever
+/// write.
+const SALT_LEN: usize = 16;
+
+/// `XChaCha20Poly1305`'s extended nonce, fresh per seal so the same
+/// passphrase can seal many containers without ever reusing one.
+const NONCE_LEN: usize = 24;
+
+/// Whether `data` looks like one of our sealed containers, as opposed to
+/// plain JSON. Cheap enough to call before every load so unencrypted files
+/// keep working untouched.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Derive a key from `passphrase` via Argon2id and a fresh random salt, then
+/// seal `plaintext` with `XChaCha20Poly1305` under a fresh random nonce.
+/// The salt and nonce are written alongside the ciphertext, behind the magic
+/// header, so `unseal` has everything it needs to reverse this.
+///
+/// ## Errors
+///
+/// `Error::Seal` if the underlying AEAD seal fails. This shouldn't happen
+/// in practice; the cipher can't fail on well-formed input.
+pub fn seal(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher.encrypt(&nonce, plaintext).map_err(|_| Error::Seal)?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+
+    Ok(out)
+}
+
+/// Reverse of `seal`: re-derive the key from `passphrase` and the salt
+/// stored in `data`, then open the container.
+///
+/// ## Errors
+///
+/// - `Error::NotSealed` if `data` doesn't start with our magic header.
+///   Callers should fall back to reading it as plaintext in that case.
+/// - `Error::Truncated` if it has the header but not enough bytes for a
+///   salt and nonce, which means it was cut short somehow.
+/// - `Error::WrongPassphrase` if the passphrase was wrong, or the
+///   ciphertext was tampered with (the AEAD tag can't tell these apart).
+pub fn unseal(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_sealed(data) {
+        return Err(Error::NotSealed);
+    }
+
+    let rest = &data[MAGIC.len()..];
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        return Err(Error::Truncated);
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+
+    cipher
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::WrongPassphrase)
+}
+
+/// Derive a 256-bit key from `passphrase` and `salt` via Argon2id, using the
+/// library's recommended parameters.
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], Error> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| Error::Kdf)?;
+
+    Ok(key)
+}
+
+/// Whether `line` looks like a sealed line from [`seal_line`], as opposed to
+/// a plain JSON op. A sealed line is base64, which never contains `{`, so
+/// this can't mistake one for the other.
+pub fn is_sealed_line(line: &str) -> bool {
+    base64_decode(line).is_some_and(|data| is_sealed(&data))
+}
+
+/// Seal `plaintext` like [`seal`], then base64-encode the result so it can
+/// be written as a single line in the line-oriented operation log — the
+/// raw output of `seal` is binary and may contain newlines of its own,
+/// which would corrupt a line-at-a-time log.
+///
+/// ## Errors
+///
+/// Same as [`seal`].
+pub fn seal_line(passphrase: &str, plaintext: &[u8]) -> Result<String, Error> {
+    Ok(base64_encode(&seal(passphrase, plaintext)?))
+}
+
+/// Reverse of [`seal_line`].
+///
+/// ## Errors
+///
+/// `Error::NotSealed` if `line` isn't valid base64; otherwise the same as
+/// [`unseal`].
+pub fn unseal_line(passphrase: &str, line: &str) -> Result<Vec<u8>, Error> {
+    let data = base64_decode(line).ok_or(Error::NotSealed)?;
+    unseal(passphrase, &data)
+}
+
+/// RFC 4648 base64, without padding. Only used to keep a sealed line's
+/// ciphertext free of embedded newlines; no need to depend on a crate for
+/// something this small.
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity((data.len() * 4).div_ceil(3));
+
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = chunk.get(1).copied();
+        let b2 = chunk.get(2).copied();
+
+        out.push(ALPHABET[(b0 >> 2) as usize] as char);
+        out.push(ALPHABET[(((b0 & 0x03) << 4) | (b1.unwrap_or(0) >> 4)) as usize] as char);
+
+        if let Some(b1) = b1 {
+            out.push(ALPHABET[(((b1 & 0x0f) << 2) | (b2.unwrap_or(0) >> 6)) as usize] as char);
+        }
+
+        if let Some(b2) = b2 {
+            out.push(ALPHABET[(b2 & 0x3f) as usize] as char);
+        }
+    }
+
+    out
+}
+
+/// Decode RFC 4648 base64, tolerating missing padding. Returns `None` on any
+/// character outside the base64 alphabet.
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+
+    for ch in encoded.bytes().filter(|&b| b != b'=') {
+        let value = u32::try_from(ALPHABET.iter().position(|&b| b == ch)?).ok()?;
+        bits = (bits << 6) | value;
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+/// Things that can go wrong sealing or unsealing a container.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// `data` doesn't start with our magic header, so it isn't one of our
+    /// sealed containers.
+    #[error("not a sealed container")]
+    NotSealed,
+
+    /// `data` is a sealed container, but we have no passphrase configured
+    /// to open it with.
+    #[error("data is sealed, but no passphrase is configured")]
+    MissingPassphrase,
+
+    /// `data` has our magic header but is too short to hold a salt and
+    /// nonce.
+    #[error("sealed container is truncated")]
+    Truncated,
+
+    /// Key derivation itself failed, e.g. Argon2 rejected its parameters.
+    #[error("key derivation failed")]
+    Kdf,
+
+    /// The AEAD seal failed.
+    #[error("couldn't seal data")]
+    Seal,
+
+    /// The AEAD open failed: either the passphrase was wrong, or the
+    /// ciphertext was tampered with.
+    #[error("wrong passphrase, or data was corrupted")]
+    WrongPassphrase,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_the_right_passphrase() {
+        let sealed = seal("correct horse battery staple", b"hello").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(unseal("correct horse battery staple", &sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn rejects_the_wrong_passphrase() {
+        let sealed = seal("correct horse battery staple", b"hello").unwrap();
+        assert!(matches!(
+            unseal("wrong passphrase", &sealed),
+            Err(Error::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn treats_plaintext_as_not_sealed() {
+        assert!(!is_sealed(b"{\"hello\":\"world\"}"));
+        assert!(matches!(
+            unseal("whatever", b"{\"hello\":\"world\"}"),
+            Err(Error::NotSealed)
+        ));
+    }
+
+    mod base64 {
+        use super::*;
+
+        #[test]
+        fn round_trips_arbitrary_bytes() {
+            let bytes = b"a sealed line's ciphertext is opaque to us";
+            assert_eq!(base64_decode(&base64_encode(bytes)), Some(bytes.to_vec()));
+        }
+
+        #[test]
+        fn rejects_invalid_characters() {
+            assert_eq!(base64_decode("not valid base64!"), None);
+        }
+    }
+
+    mod line {
+        use super::*;
+
+        #[test]
+        fn round_trips_with_the_right_passphrase() {
+            let sealed = seal_line("correct horse battery staple", b"hello").unwrap();
+            assert!(is_sealed_line(&sealed));
+            assert_eq!(
+                unseal_line("correct horse battery staple", &sealed).unwrap(),
+                b"hello"
+            );
+        }
+
+        #[test]
+        fn rejects_the_wrong_passphrase() {
+            let sealed = seal_line("correct horse battery staple", b"hello").unwrap();
+            assert!(matches!(
+                unseal_line("wrong passphrase", &sealed),
+                Err(Error::WrongPassphrase)
+            ));
+        }
+
+        #[test]
+        fn treats_a_json_line_as_not_sealed() {
+            assert!(!is_sealed_line("{\"hello\":\"world\"}"));
+        }
+    }
+}
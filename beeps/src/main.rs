@@ -6,7 +6,7 @@ mod app;
 /// Configuration and argument parsing
 mod config;
 
-use app::App;
+use app::{store::JsonFileStore, App, Effect, EffectContext};
 use clap::Parser;
 use crossterm::event::{Event, EventStream};
 use futures::StreamExt;
@@ -22,17 +22,31 @@ use tokio::{
 async fn main() -> io::Result<ExitCode> {
     let config = config::Config::parse();
 
+    let store: Arc<dyn app::store::Store> = Arc::new(JsonFileStore::new(config.data_dir(), None));
+    let (app, init_effects) = App::init(Arc::clone(&store))
+        .await
+        .map_err(|problem| io::Error::other(problem.to_string()))?;
+
     let mut terminal = ratatui::init();
     terminal.clear()?;
-    let res = run(terminal, Arc::new(config)).await;
+    let res = run(
+        terminal,
+        Arc::new(EffectContext::new(store)),
+        app,
+        init_effects,
+    )
+    .await;
     ratatui::restore();
     res
 }
 
 /// Manage the lifecycle of the app
-async fn run(mut terminal: DefaultTerminal, config: Arc<config::Config>) -> io::Result<ExitCode> {
-    let mut app = App::new();
-
+async fn run(
+    mut terminal: DefaultTerminal,
+    conn: Arc<EffectContext>,
+    mut app: App,
+    init_effects: Vec<Effect>,
+) -> io::Result<ExitCode> {
     // We expect side-effectful behaviors (that is, things like FS or network
     // access) to take place via async tasks. Once those tasks are done, we read
     // their results off of a channel. We keep track of outstanding effects so
@@ -40,15 +54,14 @@ async fn run(mut terminal: DefaultTerminal, config: Arc<config::Config>) -> io::
     let (effect_tx, mut effect_rx) = unbounded_channel();
     let mut outstanding_effects = Vec::with_capacity(1);
 
-    // Initialize the app, spawn a task to handle side effects, and render the
-    // first frame. We could render before spawning for a slightly faster draw,
-    // but defer it so that anything taken care of in `app.init` will reflect in
-    // the first draw.
-    outstanding_effects.push(spawn_effect_task(
-        effect_tx.clone(),
-        Arc::clone(&config),
-        app.init(),
-    ));
+    // Spawn the app's initial side effects (e.g. arming the ping timer) and
+    // render the first frame. The effects run in their own tasks, so what
+    // they produce reaches the app through `effect_rx` and shows up on a
+    // later draw, not this first one.
+    for effect in init_effects {
+        outstanding_effects.push(spawn_effect_task(effect_tx.clone(), Arc::clone(&conn), effect));
+    }
+
     terminal.draw(|frame| app.render(frame))?;
 
     let mut event_stream = EventStream::new();
@@ -93,7 +106,7 @@ async fn run(mut terminal: DefaultTerminal, config: Arc<config::Config>) -> io::
                 // in a list.
                 outstanding_effects.push(spawn_effect_task(
                     effect_tx.clone(),
-                    Arc::clone(&config),
+                    Arc::clone(&conn),
                     effect,
                 ));
             }
@@ -127,15 +140,15 @@ async fn run(mut terminal: DefaultTerminal, config: Arc<config::Config>) -> io::
 /// Spawn a task to run an effect and send the next action to the app.
 fn spawn_effect_task(
     effect_tx: UnboundedSender<app::Action>,
-    config: Arc<config::Config>,
-    effect: app::Effect,
+    conn: Arc<EffectContext>,
+    effect: Effect,
 ) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let next_action = effect.run(config).await;
-
-        // TODO: what do we do if the channel is closed? It probably means
-        // we're shutting down and it's OK to drop messages, but we still
-        // get the error.
-        let _ = effect_tx.send(next_action);
+        if let Some(next_action) = effect.run(&conn).await {
+            // TODO: what do we do if the channel is closed? It probably means
+            // we're shutting down and it's OK to drop messages, but we still
+            // get the error.
+            let _ = effect_tx.send(next_action);
+        }
     })
 }
@@ -0,0 +1,218 @@
+use crate::split::Split;
+use sha2::{Digest, Sha256};
+
+/// How many low bits of the rolling hash must be zero to declare a
+/// boundary. Once a chunk is past `MIN_CHUNK_SIZE`, a boundary turns up on
+/// average every `2^GEAR_SHIFT` bytes, up to the `MAX_CHUNK_SIZE` clamp.
+const GEAR_SHIFT: u32 = 13;
+
+/// Boundaries are only considered once a chunk has grown to at least this
+/// many bytes, so a run of unlucky hash values can't produce pathologically
+/// tiny chunks.
+const MIN_CHUNK_SIZE: usize = 2 * 1024;
+
+/// A chunk is cut here regardless of the rolling hash, so one long run of
+/// hash values that never hits a boundary can't produce an unbounded chunk.
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Per-byte multipliers for the Gear rolling hash, generated with a fixed
+/// splitmix64 seed so the table (and therefore every chunk boundary it
+/// produces) is the same on every replica without shipping 2KB of literal
+/// constants.
+static GEAR_TABLE: [u64; 256] = gear_table();
+
+/// A piece of a byte stream cut at a content-defined boundary, identified by
+/// a strong hash of its own bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The SHA-256 hash of `bytes`. Two chunks with the same content always
+    /// have the same hash, regardless of which replica produced them or
+    /// where in the stream they fell.
+    pub hash: [u8; 32],
+
+    /// The chunk's own bytes.
+    pub bytes: Vec<u8>,
+}
+
+/// Split `data` into content-defined chunks: a Gear rolling hash runs over a
+/// sliding window of the byte stream, and a chunk boundary falls wherever
+/// the low `GEAR_SHIFT` bits of the hash are zero, clamped to
+/// `MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE`. Because boundaries are determined by
+/// local content rather than a fixed offset, inserting or deleting bytes in
+/// the middle of `data` only changes the chunks from the edit up to where
+/// the boundaries fall back in step, usually within a few chunks; every
+/// chunk before the edit and after that point comes out byte-identical (and
+/// so hashes the same) to a chunking of the unedited stream. This is the
+/// content-defined chunking scheme Garage uses for its block store, applied
+/// here to deduplicate repeated sync payloads across a replica's lifetime.
+///
+/// The server's chunked pull uses this: the client reports the chunk hashes
+/// it already has, and only bytes for the hashes it doesn't are sent back,
+/// rather than a whole document's worth of parts every time. Local storage
+/// does not chunk.
+pub fn chunk(data: &[u8]) -> Vec<Chunk> {
+    let mask = (1u64 << GEAR_SHIFT) - 1;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash: u64 = 0;
+
+    for (i, &byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(GEAR_TABLE[byte as usize]);
+        let len = i + 1 - start;
+
+        if len >= MAX_CHUNK_SIZE || (len >= MIN_CHUNK_SIZE && hash & mask == 0) {
+            chunks.push(make_chunk(&data[start..=i]));
+            start = i + 1;
+            hash = 0;
+        }
+    }
+
+    if start < data.len() {
+        chunks.push(make_chunk(&data[start..]));
+    }
+
+    chunks
+}
+
+/// Serialize `value`'s parts (as produced by `Split::split`, newline-
+/// delimited the same way `push::stream_handler` decodes a streamed push)
+/// and run [`chunk`] over the resulting byte stream.
+pub fn chunk_split<T: Split>(value: T) -> Vec<Chunk>
+where
+    T::Part: serde::Serialize,
+{
+    let mut bytes = Vec::new();
+    for part in value.split() {
+        serde_json::to_writer(&mut bytes, &part).expect("a Part always serializes");
+        bytes.push(b'\n');
+    }
+
+    chunk(&bytes)
+}
+
+/// Wrap `bytes` up as a `Chunk`, hashing it along the way.
+fn make_chunk(bytes: &[u8]) -> Chunk {
+    Chunk {
+        hash: Sha256::digest(bytes).into(),
+        bytes: bytes.to_vec(),
+    }
+}
+
+/// Build `GEAR_TABLE` at compile time from a fixed seed, so the table never
+/// has to be checked in as a 256-entry literal.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut i = 0;
+
+    while i < 256 {
+        state = splitmix64(state);
+        table[i] = state;
+        i += 1;
+    }
+
+    table
+}
+
+/// One step of the splitmix64 generator, used only to seed [`GEAR_TABLE`]
+/// deterministically.
+const fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Reassembling every chunk's bytes in order always reproduces the
+    /// original input.
+    fn reassemble(chunks: &[Chunk]) -> Vec<u8> {
+        chunks.iter().flat_map(|c| c.bytes.clone()).collect()
+    }
+
+    #[test]
+    fn empty_input_produces_no_chunks() {
+        assert_eq!(chunk(&[]), vec![]);
+    }
+
+    #[test]
+    fn every_chunk_is_within_bounds_except_possibly_the_last() {
+        let data = vec![0u8; MAX_CHUNK_SIZE * 4];
+        let chunks = chunk(&data);
+
+        assert!(chunks.len() > 1, "expected more than one chunk");
+        for (i, c) in chunks.iter().enumerate() {
+            assert!(c.bytes.len() <= MAX_CHUNK_SIZE, "chunk {i} was too big");
+            if i != chunks.len() - 1 {
+                assert!(c.bytes.len() >= MIN_CHUNK_SIZE, "chunk {i} was too small");
+            }
+        }
+    }
+
+    #[test]
+    fn identical_bytes_chunk_to_the_same_hash() {
+        let data = (0..10_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        let a = chunk(&data);
+        let b = chunk(&data);
+
+        assert_eq!(a, b);
+    }
+
+    /// A deterministic stand-in for random bytes, so the insertion-stability
+    /// test below doesn't depend on a `rand` dev-dependency just to build a
+    /// large buffer.
+    fn pseudo_random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = splitmix64(state);
+                (state & 0xff) as u8
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn reassembled_chunks_equal_the_input(data: Vec<u8>) {
+            let chunks = chunk(&data);
+            prop_assert_eq!(reassemble(&chunks), data);
+        }
+    }
+
+    /// The defining property of content-defined chunking: inserting bytes in
+    /// the middle of a stream only perturbs the chunk(s) near the
+    /// insertion. The chunk at the very end of a long, otherwise-identical
+    /// tail should come out byte-for-byte (and so hash-for-hash) the same
+    /// whether or not something was inserted far upstream of it, because
+    /// content-defined boundaries resync within a few chunks of an edit
+    /// instead of shifting every chunk after it the way fixed-size chunking
+    /// would.
+    #[test]
+    fn an_insertion_does_not_change_a_distant_trailing_chunk() {
+        let prefix = pseudo_random_bytes(1, MAX_CHUNK_SIZE * 3);
+        let suffix = pseudo_random_bytes(2, MAX_CHUNK_SIZE * 3);
+        let insertion = pseudo_random_bytes(3, 500);
+
+        let mut before = prefix.clone();
+        before.extend(&suffix);
+
+        let mut after = prefix;
+        after.extend(&insertion);
+        after.extend(&suffix);
+
+        let last_before = chunk(&before).last().unwrap().hash;
+        let last_after = chunk(&after).last().unwrap().hash;
+
+        assert_eq!(
+            last_before, last_after,
+            "an insertion upstream changed the final chunk of an otherwise-identical tail"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::merge::Merge;
+use crate::split::Split;
+use crate::NodeId;
+use std::collections::HashMap;
+
+/// A grow-only counter (G-Counter.) Each node tracks its own running total,
+/// so nodes can increment concurrently without coordination; merging takes
+/// the element-wise max of each node's total, and the counter's value is the
+/// sum across all nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct GCounter(HashMap<NodeId, u64>);
+
+impl GCounter {
+    /// Create an empty `GCounter`.
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// Increment this node's own total by `amount`. A node should only ever
+    /// increment its own entry; incrementing another node's would let two
+    /// replicas disagree about that node's true total.
+    pub fn increment(&mut self, node: NodeId, amount: u64) {
+        *self.0.entry(node).or_insert(0) += amount;
+    }
+
+    /// The counter's current value: the sum of every node's total.
+    pub fn value(&self) -> u64 {
+        self.0.values().sum()
+    }
+}
+
+impl Merge for GCounter {
+    fn merge(mut self, other: Self) -> Self {
+        for (node, count) in other.0 {
+            let entry = self.0.entry(node).or_insert(0);
+            *entry = (*entry).max(count);
+        }
+
+        self
+    }
+}
+
+impl Split for GCounter {
+    type Part = (NodeId, u64);
+
+    fn split(self) -> impl Iterator<Item = Self::Part> {
+        self.0.into_iter()
+    }
+
+    fn merge_part(&mut self, (node, count): Self::Part) {
+        let entry = self.0.entry(node).or_insert(0);
+        *entry = (*entry).max(count);
+    }
+}
+
+/// A part of a [`PnCounter`]: an update to one node's total in either the
+/// positive or the negative `GCounter` underneath it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub enum PnCounterPart {
+    /// An update to the positive counter.
+    Increment(NodeId, u64),
+
+    /// An update to the negative counter.
+    Decrement(NodeId, u64),
+}
+
+/// A positive-negative counter (PN-Counter), built from two [`GCounter`]s: one
+/// tracking increments, one tracking decrements. The counter's value is the
+/// positive total minus the negative total, which lets it go down as well as
+/// up while still merging as a CRDT.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct PnCounter {
+    /// Running total of increments, per node.
+    positive: GCounter,
+
+    /// Running total of decrements, per node.
+    negative: GCounter,
+}
+
+impl PnCounter {
+    /// Create an empty `PnCounter`.
+    pub fn new() -> Self {
+        Self {
+            positive: GCounter::new(),
+            negative: GCounter::new(),
+        }
+    }
+
+    /// Increment this node's own total by `amount`.
+    pub fn increment(&mut self, node: NodeId, amount: u64) {
+        self.positive.increment(node, amount);
+    }
+
+    /// Decrement this node's own total by `amount`.
+    pub fn decrement(&mut self, node: NodeId, amount: u64) {
+        self.negative.increment(node, amount);
+    }
+
+    /// The counter's current value: the positive total minus the negative
+    /// total. Signed because decrements can outrun increments.
+    #[expect(clippy::cast_possible_wrap)]
+    pub fn value(&self) -> i64 {
+        self.positive.value() as i64 - self.negative.value() as i64
+    }
+}
+
+impl Merge for PnCounter {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            positive: self.positive.merge(other.positive),
+            negative: self.negative.merge(other.negative),
+        }
+    }
+}
+
+impl Split for PnCounter {
+    type Part = PnCounterPart;
+
+    fn split(self) -> impl Iterator<Item = Self::Part> {
+        let increments = self
+            .positive
+            .split()
+            .map(|(node, count)| PnCounterPart::Increment(node, count));
+        let decrements = self
+            .negative
+            .split()
+            .map(|(node, count)| PnCounterPart::Decrement(node, count));
+
+        increments.chain(decrements)
+    }
+
+    fn merge_part(&mut self, part: Self::Part) {
+        match part {
+            PnCounterPart::Increment(node, count) => self.positive.merge_part((node, count)),
+            PnCounterPart::Decrement(node, count) => self.negative.merge_part((node, count)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::proptest;
+
+    mod gcounter {
+        use super::*;
+
+        #[test]
+        fn value_sums_every_node() {
+            let mut counter = GCounter::new();
+            counter.increment(NodeId(0), 1);
+            counter.increment(NodeId(1), 2);
+
+            assert_eq!(counter.value(), 3);
+        }
+
+        #[test]
+        fn increment_accumulates_on_the_same_node() {
+            let mut counter = GCounter::new();
+            counter.increment(NodeId(0), 1);
+            counter.increment(NodeId(0), 2);
+
+            assert_eq!(counter.value(), 3);
+        }
+
+        #[test]
+        fn merge_takes_the_max_per_node() {
+            let mut a = GCounter::new();
+            a.increment(NodeId(0), 5);
+
+            let mut b = GCounter::new();
+            b.increment(NodeId(0), 3);
+
+            assert_eq!(a.merge(b).value(), 5);
+        }
+
+        proptest! {
+            #[test]
+            fn merge_commutative(a: GCounter, b: GCounter) {
+                crate::merge::test_commutative(a, b);
+            }
+
+            #[test]
+            fn merge_associative(a: GCounter, b: GCounter, c: GCounter) {
+                crate::merge::test_associative(a, b, c);
+            }
+
+            #[test]
+            fn merge_idempotent(a: GCounter) {
+                crate::merge::test_idempotent(a);
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn merge_or_merge_parts(a: GCounter, b: GCounter) {
+                crate::split::test_merge_or_merge_parts(a, b);
+            }
+        }
+    }
+
+    mod pn_counter {
+        use super::*;
+
+        #[test]
+        fn value_is_increments_minus_decrements() {
+            let mut counter = PnCounter::new();
+            counter.increment(NodeId(0), 5);
+            counter.decrement(NodeId(0), 2);
+
+            assert_eq!(counter.value(), 3);
+        }
+
+        #[test]
+        fn value_can_go_negative() {
+            let mut counter = PnCounter::new();
+            counter.decrement(NodeId(0), 2);
+
+            assert_eq!(counter.value(), -2);
+        }
+
+        proptest! {
+            #[test]
+            fn merge_commutative(a: PnCounter, b: PnCounter) {
+                crate::merge::test_commutative(a, b);
+            }
+
+            #[test]
+            fn merge_associative(a: PnCounter, b: PnCounter, c: PnCounter) {
+                crate::merge::test_associative(a, b, c);
+            }
+
+            #[test]
+            fn merge_idempotent(a: PnCounter) {
+                crate::merge::test_idempotent(a);
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn merge_or_merge_parts(a: PnCounter, b: PnCounter) {
+                crate::split::test_merge_or_merge_parts(a, b);
+            }
+        }
+    }
+}
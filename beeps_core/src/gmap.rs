@@ -1,5 +1,6 @@
 use crate::merge::Merge;
 use crate::split::Split;
+use crate::NodeId;
 use std::collections::{
     hash_map::{Drain, Entry, Iter},
     HashMap,
@@ -75,6 +76,30 @@ where
     }
 }
 
+impl<K, V> GMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Merge + Clone,
+{
+    /// Insert a value exactly like [`GMap::upsert`], but also return the
+    /// minimal delta: a `GMap` holding just this key's new merged value.
+    /// Because a delta is itself a `GMap`, it join-merges into a peer's
+    /// state with the exact same `GMap::merge`/`GMap::merge_part` a full
+    /// map uses — nothing about merge semantics changes. Buffer what this
+    /// returns in a [`DeltaBuffer`] to replicate only what changed instead
+    /// of resending the whole map every time.
+    pub fn upsert_delta(&mut self, key: K, value: V) -> Self {
+        self.upsert(key.clone(), value);
+
+        let mut delta = HashMap::new();
+        if let Some(merged) = self.0.get(&key) {
+            delta.insert(key, merged.clone());
+        }
+
+        Self(delta)
+    }
+}
+
 impl<K, V> Merge for GMap<K, V>
 where
     K: Eq + Hash,
@@ -89,7 +114,7 @@ where
     }
 }
 
-impl<K, V> Split<(K, V)> for GMap<K, V>
+impl<K, V> Split for GMap<K, V>
 where
     K: Eq + Hash,
     V: Merge,
@@ -148,6 +173,100 @@ where
     }
 }
 
+/// Buffers the deltas produced by [`GMap::upsert_delta`] so a replica can
+/// ship a peer only what it's missing instead of the whole map, and
+/// garbage-collect whatever every currently-tracked peer has already
+/// acknowledged.
+///
+/// Deltas are kept in the order they were produced, each tagged with a
+/// sequence number; a peer's acknowledgment is just the highest sequence
+/// number it's confirmed merging in, the same `(peer, watermark)` shape
+/// `sync::version_vector::VersionVector` already uses for `Document`-level
+/// anti-entropy, just keyed by sequence instead of `(timestamp, counter)`
+/// since an arbitrary `GMap` key has no clock of its own to watermark
+/// against.
+pub struct DeltaBuffer<K: Eq + Hash, V: Merge> {
+    /// Buffered deltas not yet acknowledged by every tracked peer, oldest
+    /// first.
+    deltas: Vec<(u64, GMap<K, V>)>,
+
+    /// The sequence number the next buffered delta will get.
+    next_seq: u64,
+
+    /// The highest sequence number each peer has acknowledged.
+    acked: HashMap<NodeId, u64>,
+}
+
+impl<K: Eq + Hash, V: Merge> DeltaBuffer<K, V> {
+    /// Create an empty buffer.
+    pub fn new() -> Self {
+        Self {
+            deltas: Vec::new(),
+            next_seq: 1,
+            acked: HashMap::new(),
+        }
+    }
+
+    /// Buffer a delta, as returned by `GMap::upsert_delta`, for later
+    /// replication.
+    pub fn push(&mut self, delta: GMap<K, V>) {
+        self.deltas.push((self.next_seq, delta));
+        self.next_seq += 1;
+    }
+
+    /// Record that `peer` has merged in everything through sequence
+    /// `through` (the highest sequence number from the batch it just
+    /// confirmed, as returned by `DeltaBuffer::deltas_for`), then drop any
+    /// buffered delta every tracked peer has now acknowledged. A peer we've
+    /// never heard an acknowledgment from doesn't block garbage collection
+    /// — there's nothing to wait for until it shows up.
+    pub fn ack(&mut self, peer: NodeId, through: u64) {
+        self.acked
+            .entry(peer)
+            .and_modify(|existing| *existing = (*existing).max(through))
+            .or_insert(through);
+
+        let min_acked = self.acked.values().copied().min().unwrap_or(0);
+        self.deltas.retain(|(seq, _)| *seq > min_acked);
+    }
+}
+
+impl<K: Eq + Hash, V: Merge> Default for DeltaBuffer<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> DeltaBuffer<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Merge + Clone,
+{
+    /// Join every delta `peer` hasn't acknowledged yet into one `GMap` to
+    /// send in its next anti-entropy round, along with the highest
+    /// sequence number included. Pass that sequence number back to
+    /// `DeltaBuffer::ack` once the peer confirms it merged the batch in.
+    /// `None` if there's nothing new to send.
+    pub fn deltas_for(&self, peer: NodeId) -> Option<(u64, GMap<K, V>)> {
+        let watermark = self.acked.get(&peer).copied().unwrap_or(0);
+        let mut pending = self
+            .deltas
+            .iter()
+            .filter(|(seq, _)| *seq > watermark)
+            .peekable();
+        pending.peek()?;
+
+        let mut highest = watermark;
+        let mut out = GMap::new();
+        for (seq, delta) in pending {
+            highest = highest.max(*seq);
+            out = out.merge(delta.clone());
+        }
+
+        Some((highest, out))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -268,4 +387,111 @@ mod test {
             }
         }
     }
+
+    mod upsert_delta {
+        use super::*;
+
+        #[test]
+        fn delta_contains_only_the_changed_key() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            map.upsert("untouched", Lww::new(1, Hlc::zero()));
+
+            let delta = map.upsert_delta("test", Lww::new(2, Hlc::zero()));
+
+            assert_eq!(delta.len(), 1);
+            assert_eq!(delta.get(&"test").unwrap().value(), &2);
+        }
+
+        #[test]
+        fn merging_the_delta_into_an_empty_map_matches_the_key() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            let delta = map.upsert_delta("test", Lww::new(1, Hlc::zero()));
+
+            let rebuilt = GMap::<&str, Lww<u8>>::new().merge(delta);
+
+            assert_eq!(rebuilt.get(&"test"), map.get(&"test"));
+        }
+    }
+
+    mod delta_buffer {
+        use super::*;
+
+        fn node(id: u16) -> NodeId {
+            NodeId(id)
+        }
+
+        #[test]
+        fn a_peer_with_no_acknowledgment_gets_every_buffered_delta() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            let mut buffer = DeltaBuffer::new();
+
+            buffer.push(map.upsert_delta("a", Lww::new(1, Hlc::zero())));
+            buffer.push(map.upsert_delta("b", Lww::new(2, Hlc::zero())));
+
+            let (through, deltas) = buffer.deltas_for(node(1)).unwrap();
+
+            assert_eq!(through, 2);
+            assert_eq!(deltas.get(&"a").unwrap().value(), &1);
+            assert_eq!(deltas.get(&"b").unwrap().value(), &2);
+        }
+
+        #[test]
+        fn acking_through_a_sequence_hides_earlier_deltas() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            let mut buffer = DeltaBuffer::new();
+
+            buffer.push(map.upsert_delta("a", Lww::new(1, Hlc::zero())));
+            buffer.push(map.upsert_delta("b", Lww::new(2, Hlc::zero())));
+
+            buffer.ack(node(1), 1);
+            let (through, deltas) = buffer.deltas_for(node(1)).unwrap();
+
+            assert_eq!(through, 2);
+            assert!(deltas.get(&"a").is_none());
+            assert_eq!(deltas.get(&"b").unwrap().value(), &2);
+        }
+
+        #[test]
+        fn fully_acknowledged_peer_has_nothing_pending() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            let mut buffer = DeltaBuffer::new();
+
+            buffer.push(map.upsert_delta("a", Lww::new(1, Hlc::zero())));
+            buffer.ack(node(1), 1);
+
+            assert!(buffer.deltas_for(node(1)).is_none());
+        }
+
+        #[test]
+        fn garbage_collects_deltas_every_tracked_peer_has_acked() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            let mut buffer: DeltaBuffer<&str, Lww<u8>> = DeltaBuffer::new();
+
+            buffer.push(map.upsert_delta("a", Lww::new(1, Hlc::zero())));
+            buffer.push(map.upsert_delta("b", Lww::new(2, Hlc::zero())));
+
+            // Track node(2) without it having acknowledged anything yet.
+            buffer.ack(node(2), 0);
+
+            buffer.ack(node(1), 2);
+            assert_eq!(buffer.deltas.len(), 2);
+
+            buffer.ack(node(2), 2);
+            assert!(buffer.deltas.is_empty());
+        }
+
+        #[test]
+        fn an_unacknowledged_peer_blocks_garbage_collection() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            let mut buffer: DeltaBuffer<&str, Lww<u8>> = DeltaBuffer::new();
+
+            buffer.push(map.upsert_delta("a", Lww::new(1, Hlc::zero())));
+            buffer.ack(node(2), 0);
+            buffer.ack(node(1), 1);
+            // node(2) is tracked but hasn't acknowledged anything, so `a`
+            // stays buffered.
+
+            assert_eq!(buffer.deltas.len(), 1);
+        }
+    }
 }
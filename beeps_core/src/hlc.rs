@@ -1,5 +1,5 @@
 use crate::node_id::NodeId;
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use std::cmp::Ordering;
 use std::fmt::{self, Display};
 
@@ -43,6 +43,25 @@ impl Hlc {
         }
     }
 
+    /// Recover a clock for `node` after a restart, guaranteed to issue a
+    /// timestamp greater than `stored_max` — the highest HLC found anywhere
+    /// in local storage (a snapshot, an op log, or both). Protects against a
+    /// wall-clock rewind (NTP correction, a crash-and-restart within the
+    /// same second) handing out a timestamp at or below one we already
+    /// persisted, which plain `Hlc::new` can't guard against since it only
+    /// ever looks at the current time. Pass `Hlc::zero()` as `stored_max`
+    /// when there's nothing to recover.
+    ///
+    /// This is the persisted-frontier technique used by dataflow timestamp
+    /// sources: remember the high-water mark, and never hand out a
+    /// timestamp at or below it.
+    #[must_use]
+    pub fn recovered(node: NodeId, stored_max: Self) -> Self {
+        let floor = Self::new(node).max(stored_max);
+
+        Self { node, ..floor }.next()
+    }
+
     /// An HCL less than any other HLC. Useful as a base or default value in
     /// something like an LWW-Register.
     pub fn zero() -> Self {
@@ -90,12 +109,37 @@ impl Hlc {
     /// replica. This is helpful for being able to continue to issue timestamps
     /// across all replicas, even if some physical clocks are rushing.
     ///
-    /// This variant allows you to specify what time "now" is.
+    /// This variant allows you to specify what time "now" is, and never
+    /// rejects `other`: a single replica with a rushing (or malicious) clock
+    /// can drag every other node's HLC arbitrarily far into the future.
+    /// Prefer `mut_receive_at_bounded` when that's a concern.
     pub fn mut_receive_at(&mut self, other: &Self, now: DateTime<Utc>) {
+        self.mut_receive_at_bounded(other, now, Duration::MAX)
+            .expect("an unbounded max_drift can never be exceeded");
+    }
+
+    /// Like `mut_receive_at`, but rejects `other` if its timestamp is more
+    /// than `max_drift` ahead of `now`, per the maximum-drift bound (ε) from
+    /// the HLC paper. Keeps a single node's fast or malicious clock from
+    /// permanently dragging every other replica's HLC into the future: once
+    /// rejected, the clock stays exactly as it would have without `other`
+    /// having been received at all, so monotonicity and the total order from
+    /// `Ord` are unaffected.
+    pub fn mut_receive_at_bounded(
+        &mut self,
+        other: &Self,
+        now: DateTime<Utc>,
+        max_drift: Duration,
+    ) -> Result<(), ClockDrift> {
+        let drift = other.timestamp - now;
+        if drift > max_drift {
+            return Err(ClockDrift { drift, max_drift });
+        }
+
         if now > self.timestamp && now > other.timestamp {
             self.timestamp = now;
             self.counter = 0;
-            return;
+            return Ok(());
         }
 
         match self.timestamp.cmp(&other.timestamp) {
@@ -106,6 +150,8 @@ impl Hlc {
                 self.counter = other.counter + 1;
             }
         }
+
+        Ok(())
     }
 
     /// Update this HCL to be higher than a HLC we're receiving from another
@@ -147,6 +193,19 @@ impl Hlc {
     }
 }
 
+/// An incoming HLC's timestamp was further ahead of physical "now" than the
+/// caller's configured maximum drift allows, so `mut_receive_at_bounded`
+/// rejected it rather than adopt it.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("incoming clock is {drift} ahead of now, which exceeds the maximum drift of {max_drift}")]
+pub struct ClockDrift {
+    /// How far ahead of "now" the incoming timestamp was.
+    drift: Duration,
+
+    /// The maximum drift that was configured.
+    max_drift: Duration,
+}
+
 impl Ord for Hlc {
     fn cmp(&self, other: &Self) -> Ordering {
         self.timestamp
@@ -181,7 +240,6 @@ impl Display for Hlc {
 #[cfg(test)]
 mod test {
     use super::*;
-    use chrono::Duration;
     use proptest::{prop_assert, prop_assume, proptest};
 
     proptest! {
@@ -397,4 +455,169 @@ mod test {
             assert_eq!(next.node, hlc.node);
         }
     }
+
+    mod recovered {
+        use super::*;
+
+        #[test]
+        fn uses_now_when_nothing_was_stored() {
+            let now = Utc::now();
+            let node = NodeId::random();
+
+            let recovered = Hlc::recovered(node, Hlc::zero());
+
+            assert!(recovered.timestamp >= now);
+            assert_eq!(recovered.node, node);
+        }
+
+        #[test]
+        fn stays_above_a_stored_max_that_is_ahead_of_now() {
+            let node = NodeId::random();
+            let stored_max = Hlc {
+                timestamp: Utc::now() + Duration::days(1),
+                counter: 7,
+                node: NodeId::random(),
+            };
+
+            let recovered = Hlc::recovered(node, stored_max);
+
+            assert!(recovered > stored_max);
+            assert_eq!(recovered.node, node, "always writes as the current node");
+        }
+
+        #[test]
+        fn stays_above_a_stored_max_that_is_behind_now() {
+            let node = NodeId::random();
+            let stored_max = Hlc {
+                timestamp: Utc::now() - Duration::days(1),
+                counter: 7,
+                node: NodeId::random(),
+            };
+
+            let recovered = Hlc::recovered(node, stored_max);
+
+            assert!(recovered > stored_max);
+            assert_eq!(recovered.node, node);
+        }
+    }
+
+    mod mut_receive_at_bounded {
+        use super::*;
+
+        #[test]
+        fn accepts_other_within_the_drift_bound() {
+            let now = Utc::now();
+
+            let mut hlc = Hlc {
+                timestamp: now,
+                counter: 0,
+                node: NodeId::random(),
+            };
+            let other = Hlc {
+                timestamp: now + Duration::seconds(1),
+                counter: 0,
+                node: NodeId::random(),
+            };
+
+            hlc.mut_receive_at_bounded(&other, now, Duration::seconds(5))
+                .expect("drift is within bound");
+
+            assert_eq!(hlc.timestamp, now + Duration::seconds(1));
+        }
+
+        #[test]
+        fn rejects_other_beyond_the_drift_bound() {
+            let now = Utc::now();
+
+            let mut hlc = Hlc {
+                timestamp: now,
+                counter: 0,
+                node: NodeId::random(),
+            };
+            let other = Hlc {
+                timestamp: now + Duration::hours(1),
+                counter: 0,
+                node: NodeId::random(),
+            };
+
+            let err = hlc
+                .mut_receive_at_bounded(&other, now, Duration::seconds(5))
+                .unwrap_err();
+            assert_eq!(err.max_drift, Duration::seconds(5));
+
+            // rejected: self is left exactly as it was
+            assert_eq!(hlc.timestamp, now);
+            assert_eq!(hlc.counter, 0);
+        }
+
+        #[test]
+        fn unbounded_delegate_never_rejects() {
+            let now = Utc::now();
+
+            let mut hlc = Hlc {
+                timestamp: now,
+                counter: 0,
+                node: NodeId::random(),
+            };
+            let other = Hlc {
+                timestamp: now + Duration::days(365),
+                counter: 0,
+                node: NodeId::random(),
+            };
+
+            hlc.mut_receive_at(&other, now);
+
+            assert_eq!(hlc.timestamp, now + Duration::days(365));
+        }
+
+        proptest! {
+            #[test]
+            fn repeated_receives_from_a_far_future_peer_never_exceed_the_drift_bound(
+                drift_hours in 1..24 * 365i64,
+            ) {
+                let now = Utc::now();
+                let max_drift = Duration::minutes(5);
+
+                let mut hlc = Hlc::new_at(NodeId::random(), now, 0);
+                let attacker = Hlc::new_at(NodeId::random(), now + Duration::hours(drift_hours), 0);
+
+                // The attacker keeps presenting the same far-future clock;
+                // none of those receives should ever drag `hlc` past the
+                // drift bound.
+                for _ in 0..20 {
+                    let _ = hlc.mut_receive_at_bounded(&attacker, now, max_drift);
+                    prop_assert!(hlc.timestamp() <= now + max_drift);
+                }
+            }
+
+            #[test]
+            fn accepted_receives_preserve_total_order(
+                self_offset_secs in -60..60i64,
+                other_offset_secs in -60..60i64,
+                self_counter in 0..10u16,
+                other_counter in 0..10u16,
+            ) {
+                let now = Utc::now();
+                let max_drift = Duration::minutes(5);
+
+                let self_node = NodeId::random();
+                let other_node = NodeId::random();
+                prop_assume!(self_node != other_node);
+
+                let before = Hlc::new_at(self_node, now + Duration::seconds(self_offset_secs), self_counter);
+                let other = Hlc::new_at(other_node, now + Duration::seconds(other_offset_secs), other_counter);
+
+                let mut received = before;
+                received
+                    .mut_receive_at_bounded(&other, now, max_drift)
+                    .expect("offsets are well within the drift bound");
+
+                // A receive that's accepted must move `self` strictly ahead
+                // of both the clock it started from and the one it just
+                // received, under the same `Ord` used everywhere else.
+                prop_assert!(received > before);
+                prop_assert!(received > other);
+            }
+        }
+    }
 }
@@ -1,5 +1,13 @@
 //! Common code across all beeps clients (TUI, WASM in the browser)
 
+/// Content-defined chunking over a serialized part stream, for deduplicating
+/// repeated sync payloads.
+pub mod chunking;
+
+/// Grow-only and positive-negative counter CRDTs.
+pub mod counter;
+pub use counter::{GCounter, PnCounter};
+
 /// The state that gets synced between nodes.
 pub mod document;
 pub use document::Document;
@@ -23,10 +31,22 @@ pub use lww::Lww;
 /// The interface all CRDTs must implement to merge.
 pub mod merge;
 
+/// The interface all CRDTs must implement to split into parts (and merge
+/// those parts back in), for delta-state sync and per-part storage.
+pub mod split;
+
 /// A node ID.
 pub mod node_id;
 pub use node_id::NodeId;
 
+/// An Observed-Remove Map (OR-Map) CRDT.
+pub mod ormap;
+pub use ormap::ORMap;
+
+/// An Observed-Remove Set (OR-Set) CRDT.
+pub mod orset;
+pub use orset::OrSet;
+
 /// A replica (that is, state + node ID)
 pub mod replica;
 pub use replica::Replica;
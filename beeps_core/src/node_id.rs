@@ -12,6 +12,7 @@ use std::ops::Deref;
     Eq,
     PartialOrd,
     Ord,
+    Hash,
     Clone,
     serde::Serialize,
     serde::Deserialize,
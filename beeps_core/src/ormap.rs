@@ -0,0 +1,311 @@
+use crate::hlc::Hlc;
+use crate::merge::Merge;
+use crate::split::Split;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+/// An Observed-Remove Map (OR-Map) CRDT: like `GMap`, but keys can be
+/// removed. Each insert is a distinct instance keyed by the `Hlc` it was
+/// created with, and a remove is its own operation, keyed by the remover's
+/// own fresh clock, that tombstones whichever instance ids for a key it can
+/// currently see. An insert this replica hasn't merged in yet (and so
+/// couldn't have observed) isn't among those ids, so a concurrent insert
+/// under the same key survives the removal once it arrives — add-wins, the
+/// same rule `OrSet` uses for values.
+///
+/// A key can end up with more than one live instance (concurrent inserts
+/// under the same key that neither replica removed), so `get` merges them
+/// together with `V`'s own `Merge` impl rather than picking one arbitrarily.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct ORMap<K: Eq + Hash, V: Merge> {
+    adds: HashMap<K, BTreeMap<Hlc, V>>,
+    removes: BTreeMap<Hlc, BTreeSet<Hlc>>,
+}
+
+impl<K, V> ORMap<K, V>
+where
+    K: Eq + Hash,
+    V: Merge,
+{
+    /// Create an empty `ORMap`.
+    pub fn new() -> Self {
+        Self {
+            adds: HashMap::new(),
+            removes: BTreeMap::new(),
+        }
+    }
+
+    /// Insert a new instance of `value` under `key`, identified by `id`.
+    /// `id` should be a freshly issued clock unique to this insert, so a
+    /// later `remove` of the key can tombstone it without also catching
+    /// some other replica's concurrent insert.
+    pub fn insert(&mut self, key: K, id: Hlc, value: V) {
+        self.adds.entry(key).or_default().insert(id, value);
+    }
+
+    /// Whether `id` (an insert instance) has been tombstoned by any remove.
+    fn is_removed(&self, id: &Hlc) -> bool {
+        self.removes.values().any(|ids| ids.contains(id))
+    }
+
+    /// Tombstone every instance currently visible under `key`, as one remove
+    /// operation identified by `id` (a freshly issued clock, distinct from
+    /// any insert's id).
+    pub fn remove(&mut self, key: &K, id: Hlc) {
+        let observed: BTreeSet<Hlc> = self
+            .adds
+            .get(key)
+            .into_iter()
+            .flat_map(BTreeMap::keys)
+            .filter(|add_id| !self.is_removed(add_id))
+            .copied()
+            .collect();
+
+        self.removes.insert(id, observed);
+    }
+
+    /// Whether `key` currently has at least one live instance.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.adds
+            .get(key)
+            .is_some_and(|tags| tags.keys().any(|id| !self.is_removed(id)))
+    }
+
+    /// Check if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.adds.keys().all(|key| !self.contains_key(key))
+    }
+}
+
+impl<K, V> ORMap<K, V>
+where
+    K: Eq + Hash,
+    V: Merge + Clone,
+{
+    /// The merged value currently visible under `key`, folding together
+    /// every live concurrent instance with `V::merge`. `None` if `key` was
+    /// never inserted, or every instance has been removed.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.adds
+            .get(key)?
+            .iter()
+            .filter(|(id, _)| !self.is_removed(id))
+            .map(|(_, value)| value.clone())
+            .reduce(V::merge)
+    }
+}
+
+impl<K, V> Default for ORMap<K, V>
+where
+    K: Eq + Hash,
+    V: Merge,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Merge for ORMap<K, V>
+where
+    K: Eq + Hash,
+    V: Merge,
+{
+    fn merge(mut self, other: Self) -> Self {
+        for (key, tags) in other.adds {
+            let entry = self.adds.entry(key).or_default();
+
+            for (id, value) in tags {
+                entry.entry(id).or_insert(value);
+            }
+        }
+
+        for (id, observed) in other.removes {
+            self.removes.entry(id).or_insert(observed);
+        }
+
+        self
+    }
+}
+
+/// The smallest unit an `ORMap` splits into: either a new instance under a
+/// key, or a remove operation tombstoning the instance ids it observed.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum ORMapPart<K, V> {
+    /// A new instance of a value under a key, identified by its `Hlc`.
+    Add(K, Hlc, V),
+
+    /// A remove operation, identified by the remover's own `Hlc`, carrying
+    /// every instance id it tombstoned.
+    Remove(Hlc, BTreeSet<Hlc>),
+}
+
+impl<K, V> Split for ORMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Merge,
+{
+    type Part = ORMapPart<K, V>;
+
+    fn split(self) -> impl Iterator<Item = Self::Part> {
+        let Self { adds, removes } = self;
+
+        adds.into_iter()
+            .flat_map(|(key, tags)| {
+                tags.into_iter()
+                    .map(move |(id, value)| ORMapPart::Add(key.clone(), id, value))
+            })
+            .chain(
+                removes
+                    .into_iter()
+                    .map(|(id, observed)| ORMapPart::Remove(id, observed)),
+            )
+    }
+
+    fn merge_part(&mut self, part: Self::Part) {
+        match part {
+            ORMapPart::Add(key, id, value) => {
+                self.adds.entry(key).or_default().entry(id).or_insert(value);
+            }
+            ORMapPart::Remove(id, observed) => {
+                self.removes.entry(id).or_insert(observed);
+            }
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for ORMap<K, V>
+where
+    K: Eq + Hash + fmt::Debug,
+    V: Merge + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ORMap")
+            .field("adds", &self.adds)
+            .field("removes", &self.removes)
+            .finish()
+    }
+}
+
+impl<K, V> PartialEq for ORMap<K, V>
+where
+    K: Eq + Hash,
+    V: Merge + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.adds == other.adds && self.removes == other.removes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lww::Lww;
+    use crate::NodeId;
+
+    mod insert {
+        use super::*;
+
+        #[test]
+        fn makes_the_key_visible() {
+            let mut map = ORMap::new();
+            map.insert("key", Hlc::zero(), Lww::new(1, Hlc::zero()));
+
+            assert!(map.contains_key(&"key"));
+            assert_eq!(map.get(&"key").unwrap().value(), &1);
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test]
+        fn hides_the_key() {
+            let mut map = ORMap::new();
+            map.insert("key", Hlc::zero(), Lww::new(1, Hlc::zero()));
+            map.remove(&"key", Hlc::zero().next());
+
+            assert!(!map.contains_key(&"key"));
+            assert_eq!(map.get(&"key"), None);
+        }
+
+        #[test]
+        fn does_not_affect_an_unmerged_concurrent_insert() {
+            // Replica A inserts and then removes "key" without ever seeing
+            // replica B's concurrent insert under the same key.
+            let mut a = ORMap::new();
+            let a_clock = Hlc::new(NodeId::min());
+            a.insert("key", a_clock, Lww::new(1, a_clock));
+            a.remove(&"key", a_clock.next());
+
+            let mut b = ORMap::new();
+            let b_clock = Hlc::new(NodeId::max());
+            b.insert("key", b_clock, Lww::new(2, b_clock));
+
+            let merged = a.merge(b);
+
+            assert!(
+                merged.contains_key(&"key"),
+                "B's concurrent insert should survive A's remove, since A never observed it"
+            );
+            assert_eq!(merged.get(&"key").unwrap().value(), &2);
+        }
+    }
+
+    mod get {
+        use super::*;
+
+        #[test]
+        fn merges_concurrent_instances_under_the_same_key() {
+            let mut a = ORMap::new();
+            a.insert(
+                "key",
+                Hlc::new(NodeId::min()),
+                Lww::new(1, Hlc::new(NodeId::min())),
+            );
+
+            let mut b = ORMap::new();
+            let later = Hlc::new(NodeId::max());
+            b.insert("key", later, Lww::new(2, later));
+
+            let merged = a.merge(b);
+
+            assert_eq!(merged.get(&"key").unwrap().value(), &2);
+        }
+    }
+
+    mod merge {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_idempotent(a: ORMap<u8, Lww<u8>>) {
+                crate::merge::test_idempotent(a);
+            }
+
+            #[test]
+            fn test_commutative(a: ORMap<u8, Lww<u8>>, b: ORMap<u8, Lww<u8>>) {
+                crate::merge::test_commutative(a, b);
+            }
+
+            #[test]
+            fn test_associative(a: ORMap<u8, Lww<u8>>, b: ORMap<u8, Lww<u8>>, c: ORMap<u8, Lww<u8>>) {
+                crate::merge::test_associative(a, b, c);
+            }
+        }
+    }
+
+    mod split {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn merge_or_merge_parts(a: ORMap<u8, Lww<u8>>, b: ORMap<u8, Lww<u8>>) {
+                crate::split::test_merge_or_merge_parts(a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::hlc::Hlc;
+use crate::merge::Merge;
+use crate::split::Split;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// An Observed-Remove Set (OR-Set) CRDT. Unlike a `GSet`, elements can be
+/// removed: each add is a distinct instance keyed by the `Hlc` it was
+/// created with, and a remove is its own operation, keyed by the remover's
+/// own fresh clock, that tombstones whichever instance ids of a value it
+/// can currently see. An add this replica hasn't merged in yet (and so
+/// couldn't have observed) isn't among those ids, so a concurrent add of
+/// the same value on another replica survives the removal once it arrives.
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct OrSet<T> {
+    adds: BTreeMap<Hlc, T>,
+    removes: BTreeMap<Hlc, BTreeSet<Hlc>>,
+}
+
+impl<T> OrSet<T> {
+    /// Create an empty `OrSet`.
+    pub fn new() -> Self {
+        Self {
+            adds: BTreeMap::new(),
+            removes: BTreeMap::new(),
+        }
+    }
+
+    /// Add a new instance of `value`, identified by `id`. `id` should be a
+    /// freshly issued clock unique to this add, so a later `remove` of the
+    /// same value can tombstone it without also catching some other
+    /// replica's concurrent add of equal content.
+    pub fn insert(&mut self, id: Hlc, value: T) {
+        self.adds.insert(id, value);
+    }
+
+    /// Whether `id` (an add instance) has been tombstoned by any remove.
+    fn is_removed(&self, id: &Hlc) -> bool {
+        self.removes.values().any(|ids| ids.contains(id))
+    }
+
+    /// Iterate over the currently-visible values. A value added
+    /// concurrently by two replicas with equal content appears twice;
+    /// dedup at the call site if that's not what you want.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.adds
+            .iter()
+            .filter(|(id, _)| !self.is_removed(id))
+            .map(|(_, value)| value)
+    }
+
+    /// Whether there are no currently-visible values.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// How many values are currently visible.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Every clock contained in this set, whether a live add or a remove
+    /// operation, tombstoned adds included. Used to recover a replica's own
+    /// clock after a restart; see `Hlc::recovered`.
+    pub fn clocks(&self) -> impl Iterator<Item = &Hlc> {
+        self.adds.keys().chain(self.removes.keys())
+    }
+
+    /// Tombstone every instance currently visible to this replica,
+    /// regardless of value, as one remove operation identified by `id`.
+    /// Unlike `remove`, this doesn't need `T: PartialEq`, since it isn't
+    /// filtering by value; used to clear a whole set under one clock, e.g.
+    /// when the thing the set is attached to (a ping, say) is itself
+    /// removed.
+    pub fn clear(&mut self, id: Hlc) {
+        let observed: BTreeSet<Hlc> = self
+            .adds
+            .keys()
+            .filter(|add_id| !self.is_removed(add_id))
+            .copied()
+            .collect();
+
+        self.removes.insert(id, observed);
+    }
+}
+
+impl<T: PartialEq> OrSet<T> {
+    /// Tombstone every instance of `value` currently visible to this
+    /// replica, as one remove operation identified by `id` (a freshly
+    /// issued clock, distinct from any add's id). Recorded as its own
+    /// entry rather than merged into the tombstoned adds, so the set of
+    /// ids it observed stays attributed to the replica that removed them
+    /// when this is split into a `Part` for syncing.
+    pub fn remove(&mut self, id: Hlc, value: &T) {
+        let observed: BTreeSet<Hlc> = self
+            .adds
+            .iter()
+            .filter(|(add_id, v)| *v == value && !self.is_removed(add_id))
+            .map(|(add_id, _)| *add_id)
+            .collect();
+
+        self.removes.insert(id, observed);
+    }
+
+    /// Whether any instance of `value` is currently visible.
+    pub fn contains(&self, value: &T) -> bool {
+        self.adds
+            .iter()
+            .any(|(id, v)| v == value && !self.is_removed(id))
+    }
+}
+
+impl<T> Default for OrSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Merge for OrSet<T> {
+    fn merge(mut self, other: Self) -> Self {
+        for (id, value) in other.adds {
+            self.adds.entry(id).or_insert(value);
+        }
+
+        for (id, observed) in other.removes {
+            self.removes.entry(id).or_insert(observed);
+        }
+
+        self
+    }
+}
+
+/// The smallest unit an `OrSet` splits into: either a new instance, or a
+/// remove operation tombstoning the instance ids it observed.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum OrSetPart<T> {
+    /// A new instance of a value, identified by its `Hlc`.
+    Add(Hlc, T),
+
+    /// A remove operation, identified by the remover's own `Hlc`, carrying
+    /// every instance id it tombstoned.
+    Remove(Hlc, BTreeSet<Hlc>),
+}
+
+impl<T> OrSetPart<T> {
+    /// The clock identifying this part: the add's own id, or the
+    /// remover's own id (not the ids it tombstoned). This is what should
+    /// be used to attribute the part to a node and advance a version
+    /// vector, since it's the clock whoever issued this part actually
+    /// owns.
+    pub fn id(&self) -> &Hlc {
+        match self {
+            Self::Add(id, _) | Self::Remove(id, _) => id,
+        }
+    }
+}
+
+impl<T> Split for OrSet<T> {
+    type Part = OrSetPart<T>;
+
+    fn split(self) -> impl Iterator<Item = Self::Part> {
+        let Self { adds, removes } = self;
+
+        adds.into_iter()
+            .map(|(id, value)| OrSetPart::Add(id, value))
+            .chain(
+                removes
+                    .into_iter()
+                    .map(|(id, observed)| OrSetPart::Remove(id, observed)),
+            )
+    }
+
+    fn merge_part(&mut self, part: Self::Part) {
+        match part {
+            OrSetPart::Add(id, value) => {
+                self.adds.entry(id).or_insert(value);
+            }
+            OrSetPart::Remove(id, observed) => {
+                self.removes.entry(id).or_insert(observed);
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OrSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OrSet")
+            .field("adds", &self.adds)
+            .field("removes", &self.removes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::NodeId;
+
+    mod insert {
+        use super::*;
+
+        #[test]
+        fn makes_the_value_visible() {
+            let mut set = OrSet::new();
+            set.insert(Hlc::zero(), "tag");
+
+            assert!(set.contains(&"tag"));
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test]
+        fn hides_the_value() {
+            let mut set = OrSet::new();
+            set.insert(Hlc::zero(), "tag");
+            set.remove(Hlc::zero().next(), &"tag");
+
+            assert!(!set.contains(&"tag"));
+        }
+
+        #[test]
+        fn does_not_affect_an_unmerged_concurrent_add() {
+            // Replica A adds and then removes "tag" without ever seeing
+            // replica B's concurrent add of the same value.
+            let mut a = OrSet::new();
+            let a_clock = Hlc::new(NodeId::min());
+            a.insert(a_clock, "tag");
+            a.remove(a_clock.next(), &"tag");
+
+            let mut b = OrSet::new();
+            b.insert(Hlc::new(NodeId::max()), "tag");
+
+            let merged = a.merge(b);
+
+            assert!(
+                merged.contains(&"tag"),
+                "B's concurrent add should survive A's remove, since A never observed it"
+            );
+        }
+    }
+
+    mod clear {
+        use super::*;
+
+        #[test]
+        fn hides_every_value() {
+            let mut set = OrSet::new();
+            set.insert(Hlc::zero(), "one");
+            set.insert(Hlc::zero().next(), "two");
+            set.clear(Hlc::zero().next().next());
+
+            assert!(!set.contains(&"one"));
+            assert!(!set.contains(&"two"));
+        }
+
+        #[test]
+        fn does_not_affect_an_unmerged_concurrent_add() {
+            let mut a = OrSet::new();
+            let a_clock = Hlc::new(NodeId::min());
+            a.insert(a_clock, "tag");
+            a.clear(a_clock.next());
+
+            let mut b = OrSet::new();
+            b.insert(Hlc::new(NodeId::max()), "tag");
+
+            let merged = a.merge(b);
+
+            assert!(
+                merged.contains(&"tag"),
+                "B's concurrent add should survive A's clear, since A never observed it"
+            );
+        }
+    }
+
+    mod merge {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn test_idempotent(a: OrSet<u8>) {
+                crate::merge::test_idempotent(a);
+            }
+
+            #[test]
+            fn test_commutative(a: OrSet<u8>, b: OrSet<u8>) {
+                crate::merge::test_commutative(a, b);
+            }
+
+            #[test]
+            fn test_associative(a: OrSet<u8>, b: OrSet<u8>, c: OrSet<u8>) {
+                crate::merge::test_associative(a, b, c);
+            }
+        }
+    }
+
+    mod split {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn merge_or_merge_parts(a: OrSet<u8>, b: OrSet<u8>) {
+                crate::split::test_merge_or_merge_parts(a, b);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::hlc::Hlc;
 use crate::node_id::NodeId;
 use crate::scheduler::Scheduler;
-use crate::{document::Document, merge::Merge};
+use crate::{document::Document, split::Split};
 use chrono::{DateTime, Utc};
 
 /// The local state of a replica ("who am I" and "what do I know"). Reading the
@@ -33,6 +33,24 @@ impl Replica {
         self.clock
     }
 
+    /// Recover a replica after a restart, with its clock guaranteed to issue
+    /// a timestamp greater than every one already observed in `document` —
+    /// the state as loaded from local storage. Protects against a
+    /// wall-clock rewind between restarts; see `Hlc::recovered`. Prefer this
+    /// over `Replica::new` whenever you're loading an existing document
+    /// rather than starting from scratch.
+    pub fn recovered(node_id: NodeId, document: Document) -> Self {
+        Self {
+            clock: Hlc::recovered(node_id, document.max_clock()),
+            document,
+        }
+    }
+
+    /// The node ID this replica writes its clock as.
+    pub fn node_id(&self) -> NodeId {
+        self.clock.node()
+    }
+
     /// Read the current state.
     pub fn state(&self) -> &Document {
         &self.document
@@ -46,21 +64,30 @@ impl Replica {
 
     /// Add a ping, likely in coordination with a `Scheduler`.
     pub fn add_ping(&mut self, when: DateTime<Utc>) {
-        self.document.add_ping(when);
+        let clock = self.next_clock();
+        self.document.add_ping(when, clock);
+    }
+
+    /// Remove a ping (returns false if it cannot be removed because it does
+    /// not exist.) Also untags it, so a removed ping is never left tagged.
+    pub fn remove_ping(&mut self, when: DateTime<Utc>) -> bool {
+        let ping_clock = self.next_clock();
+        let tags_clock = self.next_clock();
+        self.document.remove_ping(when, ping_clock, tags_clock)
     }
 
-    /// Tag an existing ping (returns false if the ping cannot be tagged because
-    /// it does not exist.)
-    pub fn tag_ping(&mut self, when: DateTime<Utc>, tag: String) -> bool {
+    /// Add a tag to an existing ping (returns false if the ping cannot be
+    /// tagged because it does not exist.) A ping can carry more than one tag.
+    pub fn add_tag(&mut self, when: DateTime<Utc>, tag: String) -> bool {
         let clock = self.next_clock();
-        self.document.tag_ping(when, tag, clock)
+        self.document.add_tag(when, tag, clock)
     }
 
-    /// Untag an existing ping (returns false if the ping cannot be tagged
-    /// because it does not exist.)
-    pub fn untag_ping(&mut self, when: DateTime<Utc>) -> bool {
+    /// Remove a tag from an existing ping (returns false if the ping cannot
+    /// be untagged because it does not exist.)
+    pub fn remove_tag(&mut self, when: DateTime<Utc>, tag: &str) -> bool {
         let clock = self.next_clock();
-        self.document.untag_ping(when, clock)
+        self.document.remove_tag(when, tag, clock)
     }
 
     /// Does the same as `schedule_ping` but allows you to specify the cutoff.
@@ -72,7 +99,8 @@ impl Replica {
             ping
         } else {
             let now = Utc::now();
-            self.document.pings.insert(now);
+            let clock = self.next_clock();
+            self.document.pings.insert(clock, now);
             new_pings.push(now);
 
             now
@@ -87,7 +115,8 @@ impl Replica {
         let scheduler = Scheduler::new(*self.document.minutes_per_ping.value(), latest_ping);
 
         for next in scheduler {
-            self.document.pings.insert(next);
+            let clock = self.next_clock();
+            self.document.pings.insert(clock, next);
             new_pings.push(next);
 
             // accepting one past the cutoff gets us into the future
@@ -107,9 +136,17 @@ impl Replica {
         self.schedule_pings_with_cutoff(Utc::now())
     }
 
-    /// Get the current value of the given ping.
-    pub fn get_tag(&self, ping: &DateTime<Utc>) -> Option<&String> {
-        self.document.get_tag(ping)
+    /// The latest scheduled ping, i.e. (per `schedule_pings`'s doc comment)
+    /// the time we should next notify at, assuming `schedule_pings` has
+    /// already been called at least once. Lets a caller arm a `Timer` for
+    /// exactly that moment instead of polling on a fixed interval.
+    pub fn next_ping(&self) -> Option<DateTime<Utc>> {
+        self.document.latest_ping().copied()
+    }
+
+    /// Get every tag currently set for the given ping.
+    pub fn get_tags(&self, ping: &DateTime<Utc>) -> impl Iterator<Item = &String> {
+        self.document.get_tags(ping)
     }
 
     /// Get all the pings that have been scheduled.
@@ -122,15 +159,21 @@ impl Replica {
         &self.document
     }
 
-    /// Merge another document into ours (for syncing)
+    /// Merge another document into ours (for syncing). Advances our clock
+    /// past everything we observe in `other` first, so every write we make
+    /// afterwards causally dominates anything we just learned about, even
+    /// if `other` came from a replica whose physical clock is running
+    /// ahead of ours.
     pub fn merge(&mut self, other: Document) {
-        // TODO: make sure that our clock is higher than any clock in this document.
-        self.document.merge_mut(other);
+        self.clock.mut_receive(&other.max_clock());
+        self.document.merge(other);
     }
 
-    /// Replace our document with another (for initial syncs)
+    /// Replace our document with another (for initial syncs). Same clock
+    /// catch-up as `merge`, since adopting `other` wholesale still means
+    /// every clock in it is now something we've "observed".
     pub fn replace_doc(&mut self, other: Document) {
-        // TODO: make sure that our clock is higher than any clock in this document.
+        self.clock.mut_receive(&other.max_clock());
         self.document = other;
     }
 }
@@ -139,6 +182,72 @@ impl Replica {
 mod test {
     use super::*;
 
+    mod recovered {
+        use super::*;
+
+        #[test]
+        fn picks_up_after_the_highest_clock_in_the_document() {
+            let node = NodeId::random();
+
+            let mut document = Document::default();
+            let stored_max = Hlc::new(node).next();
+            document.set_minutes_per_ping(60, stored_max);
+
+            let replica = Replica::recovered(node, document);
+
+            assert!(replica.clock > stored_max);
+            assert_eq!(replica.node_id(), node);
+        }
+    }
+
+    mod merge {
+        use super::*;
+
+        #[test]
+        fn advances_our_clock_past_the_merged_documents_max_clock() {
+            let mut replica = Replica::new(NodeId::random());
+
+            let mut remote = Document::default();
+            let remote_clock = Hlc::new(NodeId::random()).next().next().next();
+            remote.set_minutes_per_ping(90, remote_clock);
+
+            replica.merge(remote);
+
+            assert!(replica.clock > remote_clock);
+        }
+
+        #[test]
+        fn a_write_after_merging_causally_dominates_the_remote_clock() {
+            let mut replica = Replica::new(NodeId::random());
+
+            let mut remote = Document::default();
+            let remote_clock = Hlc::new(NodeId::random()).next().next().next();
+            remote.set_minutes_per_ping(90, remote_clock);
+
+            replica.merge(remote);
+            replica.set_minutes_per_ping(45);
+
+            assert!(*replica.state().minutes_per_ping.clock() > remote_clock);
+        }
+    }
+
+    mod replace_doc {
+        use super::*;
+
+        #[test]
+        fn advances_our_clock_past_the_replacement_documents_max_clock() {
+            let mut replica = Replica::new(NodeId::random());
+
+            let mut remote = Document::default();
+            let remote_clock = Hlc::new(NodeId::random()).next().next().next();
+            remote.set_minutes_per_ping(90, remote_clock);
+
+            replica.replace_doc(remote);
+
+            assert!(replica.clock > remote_clock);
+        }
+    }
+
     mod schedule_pings {
         use super::*;
 
@@ -219,8 +328,9 @@ mod test {
         enum Transition {
             SetMinutesPerPing(u16),
             AddPing(chrono::DateTime<Utc>),
+            RemovePing(chrono::DateTime<Utc>),
             TagPing(chrono::DateTime<Utc>, String),
-            UntagPing(chrono::DateTime<Utc>),
+            UntagPing(chrono::DateTime<Utc>, String),
         }
 
         #[derive(Debug, Clone)]
@@ -239,12 +349,13 @@ mod test {
                 prop_oneof![
                     1 => (1..=4u16).prop_map(|i| Transition::SetMinutesPerPing(i * 15)),
                     10 => crate::test::timestamp_range(0..=2i64).prop_map(Transition::AddPing),
+                    3 => crate::test::timestamp_range(0..=2i64).prop_map(Transition::RemovePing),
                     10 =>
                         (crate::test::timestamp_range(0..=2i64), "(a|b|c)")
                             .prop_map(|(ts, tag)| Transition::TagPing(ts, tag)),
                     5 =>
-                        crate::test::timestamp_range(0..=2i64)
-                            .prop_map(Transition::UntagPing),
+                        (crate::test::timestamp_range(0..=2i64), "(a|b|c)")
+                            .prop_map(|(ts, tag)| Transition::UntagPing(ts, tag)),
                 ]
                 .boxed()
             }
@@ -279,11 +390,14 @@ mod test {
                     Transition::AddPing(when) => {
                         state.add_ping(when);
                     }
+                    Transition::RemovePing(when) => {
+                        state.remove_ping(when);
+                    }
                     Transition::TagPing(when, tag) => {
-                        state.tag_ping(when, tag.clone());
+                        state.add_tag(when, tag.clone());
                     }
-                    Transition::UntagPing(when) => {
-                        state.untag_ping(when);
+                    Transition::UntagPing(when, tag) => {
+                        state.remove_tag(when, &tag);
                     }
                 }
 
@@ -303,13 +417,18 @@ mod test {
                     state.clock,
                     state.document.minutes_per_ping.clock()
                 );
-                for (_, lww) in &state.document.tags {
-                    debug_assert!(
-                        &state.clock >= lww.clock(),
-                        "{} < {}",
-                        state.clock,
-                        state.document.minutes_per_ping.clock()
-                    );
+                for clock in state.document.pings.clocks() {
+                    debug_assert!(&state.clock >= clock, "{} < {}", state.clock, clock);
+                }
+                for (_, tags) in &state.document.tags {
+                    for clock in tags.clocks() {
+                        debug_assert!(
+                            &state.clock >= clock,
+                            "{} < {}",
+                            state.clock,
+                            clock
+                        );
+                    }
                 }
             }
         }
@@ -319,4 +438,134 @@ mod test {
             fn state_machine(sequential 1..20 => ReplicaStateMachine);
         }
     }
+
+    /// Unlike `state_machine` above, which checks invariants on a single
+    /// replica, this exercises several replicas exchanging `Split::Part`s the
+    /// way the sync endpoints actually do: deltas sit in a shared buffer
+    /// until something chooses to deliver them, so a part can be delivered
+    /// late, out of order, to the wrong replica first, or more than once.
+    /// After a final quiesce (every emitted part delivered to every replica,
+    /// each one twice) every replica should hold bit-for-bit identical
+    /// state — the CRDT literature calls this strong eventual consistency.
+    mod convergence {
+        use super::*;
+        use crate::document::Part;
+        use crate::split::Split;
+        use proptest::prelude::*;
+
+        const NODE_COUNT: usize = 3;
+
+        #[derive(Debug, Clone)]
+        enum Edit {
+            SetMinutesPerPing(u16),
+            AddPing(DateTime<Utc>),
+            RemovePing(DateTime<Utc>),
+            TagPing(DateTime<Utc>, String),
+            UntagPing(DateTime<Utc>, String),
+        }
+
+        #[derive(Debug, Clone)]
+        enum Command {
+            /// Make a local edit on the replica at this index.
+            LocalEdit(usize, Edit),
+
+            /// Split the replica at this index's document and push the
+            /// resulting parts into the shared in-flight buffer.
+            EmitDelta(usize),
+
+            /// Merge one part from the buffer into the replica at this
+            /// index. The part index is taken modulo the buffer's length at
+            /// delivery time, so it's always in range but still picks
+            /// whichever part the buffer happens to hold then — including
+            /// one already delivered earlier.
+            Deliver(usize, usize),
+        }
+
+        fn edit() -> impl Strategy<Value = Edit> {
+            prop_oneof![
+                (1..=4u16).prop_map(|i| Edit::SetMinutesPerPing(i * 15)),
+                crate::test::timestamp_range(0..=2i64).prop_map(Edit::AddPing),
+                crate::test::timestamp_range(0..=2i64).prop_map(Edit::RemovePing),
+                (crate::test::timestamp_range(0..=2i64), "(a|b|c)")
+                    .prop_map(|(ts, tag)| Edit::TagPing(ts, tag)),
+                (crate::test::timestamp_range(0..=2i64), "(a|b|c)")
+                    .prop_map(|(ts, tag)| Edit::UntagPing(ts, tag)),
+            ]
+        }
+
+        fn command() -> impl Strategy<Value = Command> {
+            prop_oneof![
+                3 => (0..NODE_COUNT, edit()).prop_map(|(node, edit)| Command::LocalEdit(node, edit)),
+                2 => (0..NODE_COUNT).prop_map(Command::EmitDelta),
+                3 => (any::<usize>(), 0..NODE_COUNT)
+                    .prop_map(|(part, node)| Command::Deliver(part, node)),
+            ]
+        }
+
+        fn apply_edit(replica: &mut Replica, edit: &Edit) {
+            match edit {
+                Edit::SetMinutesPerPing(minutes) => replica.set_minutes_per_ping(*minutes),
+                Edit::AddPing(when) => {
+                    replica.add_ping(*when);
+                }
+                Edit::RemovePing(when) => {
+                    replica.remove_ping(*when);
+                }
+                Edit::TagPing(when, tag) => {
+                    replica.add_tag(*when, tag.clone());
+                }
+                Edit::UntagPing(when, tag) => {
+                    replica.remove_tag(*when, tag);
+                }
+            }
+        }
+
+        fn deliver(replica: &mut Replica, part: &Part) {
+            let mut document = Document::default();
+            document.merge_part(part.clone());
+            replica.merge(document);
+        }
+
+        proptest! {
+            #[test]
+            fn replicas_converge_under_partial_reordered_duplicated_delivery(
+                commands in proptest::collection::vec(command(), 0..60),
+            ) {
+                let mut replicas: Vec<Replica> =
+                    (0..NODE_COUNT).map(|_| Replica::new(NodeId::random())).collect();
+                let mut buffer: Vec<Part> = Vec::new();
+
+                for command in commands {
+                    match command {
+                        Command::LocalEdit(node, edit) => apply_edit(&mut replicas[node], &edit),
+                        Command::EmitDelta(node) => {
+                            buffer.extend(replicas[node].document().clone().split());
+                        }
+                        Command::Deliver(part, node) => {
+                            if !buffer.is_empty() {
+                                let part = buffer[part % buffer.len()].clone();
+                                deliver(&mut replicas[node], &part);
+                            }
+                        }
+                    }
+                }
+
+                // Quiesce: every emitted part, delivered to every replica,
+                // twice — so a part that never made it to some replica
+                // during the random phase still gets there, and
+                // re-delivering one that already landed has to be a no-op.
+                for part in &buffer {
+                    for replica in &mut replicas {
+                        deliver(replica, part);
+                        deliver(replica, part);
+                    }
+                }
+
+                let first = replicas[0].document().clone();
+                for replica in &replicas[1..] {
+                    prop_assert_eq!(replica.document(), &first);
+                }
+            }
+        }
+    }
 }
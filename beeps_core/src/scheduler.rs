@@ -0,0 +1,355 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+/// Shared by every replica, so that replaying the same `(seed, anchor)` pair
+/// always produces the same universal ping sequence, regardless of which
+/// node generates it or when.
+const SEED: u64 = 0x5d47_c2b1_9e3a_11af;
+
+/// Deterministically generates the (infinite) sequence of future ping times
+/// for a given average gap, anchored at the last ping we actually know
+/// about.
+#[derive(Clone)]
+pub struct Scheduler {
+    average_pings_per_minute: f64,
+    ping: DateTime<Utc>,
+}
+
+impl Scheduler {
+    /// Build a scheduler that picks up right after `anchor`, averaging one
+    /// ping every `average_minutes_between_pings` minutes.
+    pub fn new(average_minutes_between_pings: u16, anchor: DateTime<Utc>) -> Self {
+        // We want to eventually find out how many minutes we should wait for the
+        // next ping. To do that, we need to know the rate of pings per minute.
+        let average_pings_per_minute = 1.0 / f64::from(average_minutes_between_pings);
+
+        Self {
+            average_pings_per_minute,
+            ping: anchor,
+        }
+    }
+
+    /// Same as `new`, named for callers that replay the sequence from a
+    /// known anchor (e.g. with `pings_in_range`) rather than scheduling one
+    /// ping forward.
+    pub fn from_anchor(average_minutes_between_pings: u16, anchor: DateTime<Utc>) -> Self {
+        Self::new(average_minutes_between_pings, anchor)
+    }
+
+    /// Replay the deterministic ping sequence forward from this scheduler's
+    /// anchor, discarding anything before `start` and collecting everything
+    /// in `[start, end]`—stopping as soon as a ping passes `end`. Because
+    /// the sequence only depends on `(seed, anchor, average rate)`, every
+    /// device that reconstructs a `Scheduler` from the same last-known ping
+    /// computes the exact same missed pings, so a client that was offline
+    /// can backfill reminders for everything it missed instead of just
+    /// picking up from whenever it next starts.
+    pub fn pings_in_range(self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        self.skip_while(|ping| *ping < start)
+            .take_while(|ping| *ping <= end)
+            .collect()
+    }
+}
+
+impl Iterator for Scheduler {
+    type Item = DateTime<Utc>;
+
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn next(&mut self) -> Option<Self::Item> {
+        // Next, we'll generate a random number seeded with the time of the last
+        // ping, mixed with the shared seed above. This is memoryless (each gap
+        // only depends on where the last ping landed, not on how we got there),
+        // so it allows us to generate the exact same sequence no matter which
+        // node computes it, or which ping in that sequence it starts from.
+        let mut rng = Pcg32::new(
+            // A Chrono timestamp is an i64. If that's a negative number (e.g.
+            // before 1970) that will underflow to a very high u64 value. This seems
+            // like it could cause a problem, but is actually fine—we're just using
+            // this as a seed, so we can accept whatever behavior we like *as long
+            // as it's consistent*.
+            (self.ping.timestamp() as u64) ^ SEED,
+            0xa02_bdbf_7bb3_c0a7, // Default stream
+        );
+
+        // We want an exponential distribution of values (many small values with a
+        // few much longer ones.) To get there, we'll start with a uniform
+        // distribution and use inverse transform sampling to transform it into what
+        // we want.
+        let uniform: f64 = rng.gen(); // 0.0f64..1.0f64
+        let exponential = uniform.ln() / -self.average_pings_per_minute;
+
+        // The exponential distribution above gives us fractional minutes. We'll
+        // accept that fraction down to the second level.
+        let adjustment_seconds = (exponential * 60.0).ceil() as i64;
+
+        // and we're done! Our next value in the sequence is simply the last ping
+        // plus the amount of seconds we just calculated.
+        self.ping += Duration::seconds(adjustment_seconds);
+
+        Some(self.ping)
+    }
+}
+
+/// A hashed timing wheel: `Scheduler` tells us *which* times pings are due,
+/// but nothing about actually firing a notification when one arrives. Rather
+/// than keeping one list sorted by time (which gets expensive to scan once
+/// there are many outstanding items across reminders, snoozes, and multiple
+/// documents), items are hashed into a fixed ring of buckets by how many
+/// `granularity`-sized ticks away they are, so adding and draining are both
+/// cheap regardless of how many items are in flight.
+pub struct Timer<T> {
+    /// `buckets[cursor]` is the bucket due right now; stepping the cursor
+    /// forward by one bucket advances the wheel by one `granularity`.
+    buckets: Vec<Vec<(DateTime<Utc>, T)>>,
+
+    /// Index of the bucket representing `anchor`.
+    cursor: usize,
+
+    /// The moment the bucket at `cursor` represents. Advances by one
+    /// `granularity` every time `take_next` steps the cursor past it.
+    anchor: DateTime<Utc>,
+
+    /// How much time each bucket covers.
+    granularity: Duration,
+
+    /// Items scheduled further out than the wheel's span
+    /// (`buckets.len() * granularity`), kept by absolute time until
+    /// `take_next` brings them back into range and gives them a real slot.
+    overflow: Vec<(DateTime<Utc>, T)>,
+}
+
+impl<T> Timer<T> {
+    /// Build an empty wheel of `bucket_count` buckets, each covering
+    /// `granularity`, with the cursor's bucket representing `now`.
+    pub fn new(bucket_count: usize, granularity: Duration, now: DateTime<Utc>) -> Self {
+        Self {
+            buckets: (0..bucket_count).map(|_| Vec::new()).collect(),
+            cursor: 0,
+            anchor: now,
+            granularity,
+            overflow: Vec::new(),
+        }
+    }
+
+    /// How many whole `granularity`-sized ticks separate `self.anchor` from
+    /// `time`. Negative (i.e. already past due) collapses to zero, so a
+    /// stale add still lands in the cursor's own bucket instead of being
+    /// lost.
+    fn ticks_until(&self, time: DateTime<Utc>) -> usize {
+        let elapsed = (time - self.anchor).num_milliseconds();
+        let granularity_ms = self.granularity.num_milliseconds().max(1);
+
+        (elapsed / granularity_ms).max(0) as usize
+    }
+
+    /// Schedule `item` to become due at `time`.
+    pub fn add(&mut self, time: DateTime<Utc>, item: T) {
+        let ticks = self.ticks_until(time);
+
+        if ticks < self.buckets.len() {
+            let index = (self.cursor + ticks) % self.buckets.len();
+            self.buckets[index].push((time, item));
+        } else {
+            self.overflow.push((time, item));
+        }
+    }
+
+    /// The earliest time something in the wheel is due, if anything is
+    /// scheduled at all. Scans buckets forward from the cursor for the
+    /// first one holding anything, then compares that against the overflow
+    /// list's minimum.
+    pub fn next_time(&self) -> Option<DateTime<Utc>> {
+        let from_buckets = (0..self.buckets.len()).find_map(|offset| {
+            let bucket = &self.buckets[(self.cursor + offset) % self.buckets.len()];
+
+            bucket.iter().map(|(time, _)| *time).min()
+        });
+
+        let from_overflow = self.overflow.iter().map(|(time, _)| *time).min();
+
+        match (from_buckets, from_overflow) {
+            (Some(bucket_time), Some(overflow_time)) => Some(bucket_time.min(overflow_time)),
+            (bucket_time, overflow_time) => bucket_time.or(overflow_time),
+        }
+    }
+
+    /// Advance the wheel up to `now`, draining and returning every item due
+    /// by then. Anything still in the future (including overflow entries
+    /// that have come back into the wheel's span but aren't due yet) stays
+    /// put for a later call.
+    pub fn take_next(&mut self, now: DateTime<Utc>) -> Vec<T> {
+        let mut due = Vec::new();
+
+        while self.anchor <= now {
+            let bucket = std::mem::take(&mut self.buckets[self.cursor]);
+            for (time, item) in bucket {
+                if time <= now {
+                    due.push(item);
+                } else {
+                    // Shares this bucket with something due on a later lap
+                    // of the wheel; leave it for next time.
+                    self.buckets[self.cursor].push((time, item));
+                }
+            }
+
+            self.cursor = (self.cursor + 1) % self.buckets.len();
+            self.anchor += self.granularity;
+        }
+
+        // Anything still in overflow might have come back within the
+        // wheel's span now that the cursor's moved; re-add lets `add` sort
+        // that out the same way it would for a brand new item, rather than
+        // duplicating its range check here.
+        for (time, item) in std::mem::take(&mut self.overflow) {
+            if time <= now {
+                due.push(item);
+            } else {
+                self.add(time, item);
+            }
+        }
+
+        due
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+
+    mod scheduler {
+        use super::*;
+
+        // The scheduler needs to be random, but consistent over time. We don't
+        // really care about the values here, just that we have a heads-up if the
+        // generation changes in some way.
+        #[test]
+        fn well_known_values() {
+            let scheduler = Scheduler::new(45, Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+
+            let dates = scheduler.take(5).collect::<Vec<_>>();
+            let expected = vec![
+                Utc.with_ymd_and_hms(2024, 1, 1, 1, 33, 44).unwrap(),
+                Utc.with_ymd_and_hms(2024, 1, 1, 2, 7, 37).unwrap(),
+                Utc.with_ymd_and_hms(2024, 1, 1, 3, 21, 57).unwrap(),
+                Utc.with_ymd_and_hms(2024, 1, 1, 3, 59, 34).unwrap(),
+                Utc.with_ymd_and_hms(2024, 1, 1, 4, 37, 52).unwrap(),
+            ];
+
+            assert_eq!(dates, expected);
+        }
+
+        #[test]
+        fn same_anchor_produces_the_same_universal_sequence() {
+            let anchor = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+
+            let a = Scheduler::new(30, anchor).take(20).collect::<Vec<_>>();
+            let b = Scheduler::new(30, anchor).take(20).collect::<Vec<_>>();
+
+            assert_eq!(
+                a, b,
+                "two schedulers anchored at the same point should agree on every ping"
+            );
+        }
+
+        proptest! {
+            #[test]
+            fn next_is_later_than_last_ping(
+                minutes_per_ping in 1..=60u16,
+                last_timestamp in 0i64..2_000_000_000_000i64,
+            ) {
+                let last_ping = Utc.timestamp_opt(last_timestamp, 0).unwrap();
+                let mut scheduler = Scheduler::new(minutes_per_ping, last_ping);
+
+                prop_assert!(scheduler.next().unwrap() > last_ping);
+            }
+        }
+
+        #[test]
+        fn pings_in_range_matches_manually_filtering_the_sequence() {
+            let anchor = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+            let start = anchor + Duration::hours(2);
+            let end = anchor + Duration::hours(5);
+
+            let backfilled = Scheduler::from_anchor(45, anchor).pings_in_range(start, end);
+            let expected: Vec<_> = Scheduler::new(45, anchor)
+                .take_while(|ping| *ping <= end)
+                .filter(|ping| *ping >= start)
+                .collect();
+
+            assert_eq!(backfilled, expected);
+            assert!(backfilled.iter().all(|ping| *ping >= start && *ping <= end));
+        }
+
+        #[test]
+        fn pings_in_range_is_empty_when_nothing_falls_in_the_window() {
+            let anchor = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+            // A window entirely before the anchor: nothing the scheduler
+            // generates (which is always after the anchor) can land in it.
+            let start = anchor - Duration::hours(2);
+            let end = anchor - Duration::hours(1);
+
+            let backfilled = Scheduler::from_anchor(45, anchor).pings_in_range(start, end);
+
+            assert!(backfilled.is_empty());
+        }
+    }
+
+    mod timer {
+        use super::*;
+
+        fn wheel(now: DateTime<Utc>) -> Timer<&'static str> {
+            Timer::new(8, Duration::seconds(1), now)
+        }
+
+        #[test]
+        fn fires_an_item_once_its_time_arrives() {
+            let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+            let mut timer = wheel(now);
+
+            timer.add(now + Duration::seconds(3), "due soon");
+
+            assert!(timer.take_next(now + Duration::seconds(2)).is_empty());
+            assert_eq!(timer.take_next(now + Duration::seconds(3)), vec!["due soon"]);
+        }
+
+        #[test]
+        fn past_due_items_fire_immediately() {
+            let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+            let mut timer = wheel(now);
+
+            timer.add(now - Duration::seconds(10), "already due");
+
+            assert_eq!(timer.take_next(now), vec!["already due"]);
+        }
+
+        #[test]
+        fn overflow_entries_eventually_come_back_into_range() {
+            let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+            let mut timer = wheel(now);
+
+            // The wheel only spans 8 seconds; this lands in overflow.
+            timer.add(now + Duration::seconds(100), "far out");
+
+            assert!(timer.take_next(now + Duration::seconds(50)).is_empty());
+            assert_eq!(
+                timer.take_next(now + Duration::seconds(100)),
+                vec!["far out"]
+            );
+        }
+
+        #[test]
+        fn next_time_reports_the_earliest_pending_item() {
+            let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+            let mut timer = wheel(now);
+
+            timer.add(now + Duration::seconds(5), "later");
+            timer.add(now + Duration::seconds(2), "sooner");
+            timer.add(now + Duration::seconds(100), "overflow");
+
+            assert_eq!(timer.next_time(), Some(now + Duration::seconds(2)));
+        }
+    }
+}
@@ -2,12 +2,33 @@
 pub mod client;
 pub use client::Client;
 
+/// A pluggable request pipeline for `Client`, for cross-cutting behavior
+/// like retry, logging, or re-authentication
+pub mod middleware;
+pub use middleware::Middleware;
+
+/// Background connectivity tracking and an offline push queue for `Client`
+pub mod connectivity;
+pub use connectivity::Connectivity;
+
 /// Push an update to a document
 pub mod push;
 
 /// Pull the latest version of a document
 pub mod pull;
 
+/// Push and pull parts in a single round trip, for a client catching up
+/// after being offline
+pub mod batch;
+
+/// Find where two replicas' documents diverge via a Merkle tree, in
+/// logarithmic exchanges instead of a full version vector
+pub mod merkle;
+
+/// Content-defined-chunked variant of `pull`, for cheaply re-syncing a
+/// document whose serialized history is mostly unchanged since last time
+pub mod chunked_pull;
+
 /// Things that can go wrong in the API
 pub mod error;
 pub use error::Error;
@@ -18,5 +39,41 @@ pub mod login;
 /// Register with the server
 pub mod register;
 
+/// Redeem a refresh token for a fresh access token
+pub mod refresh;
+
+/// List and revoke the caller's own device sessions
+pub mod session;
+
 /// Check auth
 pub mod whoami;
+
+/// Subscribe to live updates for a document
+pub mod subscribe;
+
+/// Long-poll for the next change to a document
+pub mod poll;
+
+/// Version vectors for delta (rather than whole-document) sync
+pub mod version_vector;
+pub use version_vector::VersionVector;
+
+/// Types for an external authorization callout
+pub mod authz;
+
+/// Enroll in and check two-factor authentication via TOTP
+pub mod totp;
+
+/// Log in via an external OIDC provider instead of a password
+pub mod oauth;
+
+/// Register a Web Push subscription, so the server can notify a device of a
+/// due ping even when it isn't in the foreground
+pub mod push_subscription;
+
+/// An alternative sync transport against a causal key-value store, for
+/// syncing without a stateful application server
+pub mod k2v;
+
+/// Recover a lost password, and confirm a newly-registered account's email
+pub mod reset;
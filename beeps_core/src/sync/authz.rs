@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// A count of each kind of `Part` present in a push, without shipping the
+/// parts themselves, so an authorizer can reason about volume without
+/// seeing the actual data.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PartCounts {
+    /// How many `minutes_per_ping` parts are in this push.
+    pub minutes_per_ping: usize,
+
+    /// How many `ping` parts are in this push.
+    pub pings: usize,
+
+    /// How many `tag` parts are in this push.
+    pub tags: usize,
+}
+
+/// What we ask an external authorizer before letting a request through.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Req {
+    /// The email associated with the request, if any. Present for
+    /// registration; absent is not expected for push, since push requires a
+    /// JWT.
+    pub email: Option<String>,
+
+    /// Which document the request is about, if any (absent for
+    /// registration).
+    pub document_id: Option<i64>,
+
+    /// A summary of the data being pushed, if this is a push request.
+    pub parts: Option<PartCounts>,
+}
+
+/// Whether the authorizer will let the request through.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resp {
+    /// Whether to allow the request.
+    pub allow: bool,
+
+    /// Why the request was denied, if it was. Surfaced to the caller.
+    pub reason: Option<String>,
+}
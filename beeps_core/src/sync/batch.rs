@@ -0,0 +1,69 @@
+use super::VersionVector;
+use crate::document::Part;
+use serde::{Deserialize, Serialize};
+
+/// The most parts a single batch request may push. Modeled on Garage's K2V
+/// batch API, which bounds the number of items per call the same way, so one
+/// request can't force an unbounded amount of validation and database work.
+pub const MAX_PARTS: usize = 1_000;
+
+/// A batch of parts to push, plus what the caller has already seen, so one
+/// round trip can both land an offline client's changes and catch it up on
+/// everyone else's, instead of a separate push and pull.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Req {
+    /// Which document we're syncing.
+    pub document_id: i64,
+
+    /// The parts to push, as produced by `Split::split`. Capped at
+    /// `MAX_PARTS`.
+    pub parts: Vec<Part>,
+
+    /// The highest clock we've seen from each node, so the server only
+    /// sends back what this vector doesn't already cover.
+    pub vector: VersionVector,
+}
+
+/// What happened to one pushed part. Kept separate from the failure
+/// machinery `Error` gives a whole request, so one bad `Part` (say, a `Tag`
+/// stamped with a clock from a replica other than the caller's own) doesn't
+/// throw away every other part in the same batch.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PartResult {
+    /// The part merged in cleanly.
+    Applied,
+
+    /// The part was skipped, and never merged, for the given reason.
+    Rejected(String),
+}
+
+/// The result of a batch push/pull round trip.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resp {
+    /// One result per part in the request's `parts`, in the same order, so
+    /// a caller can tell exactly which of its pushed parts landed.
+    pub pushed: Vec<PartResult>,
+
+    /// The parts the caller is missing, symmetric to `pull::Resp`: not
+    /// already covered by the request's `vector`, after folding in whatever
+    /// this batch just pushed.
+    pub parts: Vec<Part>,
+
+    /// The server's own version vector, covering everything accepted from
+    /// this push plus everything returned in `parts`, so the caller can
+    /// advance its watermarks without a separate pull.
+    pub vector: VersionVector,
+
+    /// Whether the server held back more parts than fit in this response.
+    /// A caller that sees `true` should call again with `vector` to page
+    /// through the rest, same as `pull::Resp::more`.
+    pub more: bool,
+}
+
+/// Where the batch sync endpoint lives.
+pub static PATH: &str = "/api/v1/batch/:id";
+
+/// Construct a path given a document ID.
+pub fn path(id: i64) -> String {
+    PATH.replace(":id", &id.to_string())
+}
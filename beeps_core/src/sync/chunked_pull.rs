@@ -0,0 +1,85 @@
+use super::error::{self, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// Ask for a document's current chunk list, skipping the bytes of any chunk
+/// whose hash is already in `known_hashes`. A hash learned from a previous
+/// chunked pull can be reused here even across documents, since
+/// content-defined chunking (see `chunking::chunk_split`) hashes purely on a
+/// chunk's own bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Req {
+    /// Which document to pull.
+    pub document_id: i64,
+
+    /// Chunk hashes already cached locally.
+    pub known_hashes: BTreeSet<[u8; 32]>,
+}
+
+/// One chunk of the document's serialized part stream, in the order it
+/// appears in that stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The chunk's content hash.
+    pub hash: [u8; 32],
+
+    /// The chunk's own bytes, or `None` if the caller already reported
+    /// having this hash in `Req::known_hashes`.
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// The document's current chunk list, in stream order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resp {
+    /// The chunks that make up the document's serialized part stream.
+    pub chunks: Vec<Chunk>,
+}
+
+impl Resp {
+    /// Reassemble the original newline-delimited `Part` stream (the same
+    /// bytes `chunking::chunk_split` consumed) by concatenating every
+    /// chunk's bytes in order, filling in from `cache` wherever the server
+    /// omitted a chunk's bytes. Decode the result line by line into `Part`s
+    /// and feed each one through `Document::merge_part` to apply it; chunk
+    /// order only matters for this reassembly step; merging the decoded
+    /// parts back in is commutative and idempotent regardless of what order
+    /// they end up in.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Client` if a chunk came back with no bytes and isn't in
+    /// `cache` either, meaning we claimed to have it but didn't.
+    pub fn reassemble(self, cache: &HashMap<[u8; 32], Vec<u8>>) -> error::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        for chunk in self.chunks {
+            match chunk.bytes {
+                Some(owned) => bytes.extend(owned),
+                None => {
+                    let cached = cache.get(&chunk.hash).ok_or_else(|| {
+                        Error::Client(format!(
+                            "server omitted chunk {}, but we don't have it cached",
+                            hex(&chunk.hash)
+                        ))
+                    })?;
+                    bytes.extend(cached);
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Render a chunk hash for the error message above.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Where the chunked pull endpoint lives.
+pub static PATH: &str = "/api/v1/chunked_pull/:id";
+
+/// Construct a path given a document ID.
+pub fn path(id: i64) -> String {
+    PATH.replace(":id", &id.to_string())
+}
@@ -1,11 +1,26 @@
 use super::error::{self, Error};
-use super::{login, register, whoami};
+use super::middleware::{self, Middleware};
+use super::version_vector::{self, VersionVector};
+use super::{
+    chunked_pull, login, merkle, oauth, poll, pull, push, push_subscription, refresh, register,
+    reset, subscribe, totp, whoami,
+};
+use crate::split::Split;
+use crate::Document;
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use url::Url;
 
 /// Client for the sync API
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Client {
     /// The server to connect to. Should only be the protocol and domain, e.g.
     /// `https://beeps.your-domain.com`.
@@ -13,12 +28,133 @@ pub struct Client {
 
     /// Auth. Set this by logging in or registering.
     pub auth: Option<String>,
+
+    /// Opaque refresh token, set alongside `auth` by logging in or
+    /// registering, and redeemed by [`Client::refresh`] for a fresh `auth`
+    /// once it expires.
+    pub refresh_token: Option<String>,
+
+    /// Which document we sync, set alongside `auth` by logging in or
+    /// registering. `None` until then.
+    #[serde(default)]
+    pub document_id: Option<i64>,
+
+    /// The highest clock we've had the server confirm for each node, so
+    /// [`Client::delta`] only needs to send what it doesn't have yet.
+    #[serde(default)]
+    pub acked: VersionVector,
+
+    /// The request pipeline every outgoing request runs through before
+    /// (and whose responses run back through after) it's actually sent; see
+    /// `Client::use_middleware`. Not persisted: a reloaded `Client` starts
+    /// with an empty chain, same as a freshly constructed one.
+    #[serde(skip)]
+    pub middlewares: Vec<std::sync::Arc<dyn Middleware>>,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("server", &self.server)
+            .field("auth", &self.auth)
+            .field("refresh_token", &self.refresh_token)
+            .field("document_id", &self.document_id)
+            .field("acked", &self.acked)
+            .field("middlewares", &self.middlewares.len())
+            .finish()
+    }
 }
 
 impl Client {
     /// Construct a new client
     pub fn new(server: String) -> Self {
-        Self { server, auth: None }
+        Self {
+            server,
+            auth: None,
+            refresh_token: None,
+            document_id: None,
+            acked: VersionVector::new(),
+            middlewares: Vec::new(),
+        }
+    }
+
+    /// Register a middleware at the end of the request pipeline: it runs
+    /// after every middleware already registered, and closest to the actual
+    /// send. Middlewares run in registration order on the way out, and the
+    /// reverse order on the way back, the same as any other nested-call
+    /// pipeline.
+    pub fn use_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middlewares.push(std::sync::Arc::new(middleware));
+    }
+
+    /// Build a push request for `document`, keeping only the parts whose
+    /// clock isn't already covered by `self.acked` (a part with no clock
+    /// would always be included, but every `Part` variant carries one).
+    /// Because every part merge is
+    /// commutative and idempotent, sending one the server already has is
+    /// wasteful but harmless.
+    ///
+    /// This is already the "delta buffer" a caller would otherwise have to
+    /// maintain by hand: rather than accumulating the `(key, value)` parts
+    /// produced by each local mutation since the last ack, we just re-split
+    /// the whole document on every push and filter against `self.acked`.
+    /// The two are equivalent — `split` is cheap and the filter throws away
+    /// exactly what a hand-rolled buffer would never have recorded in the
+    /// first place — but re-deriving it avoids a second piece of state that
+    /// could drift out of sync with `self.acked` if a mutation path ever
+    /// forgot to record itself. `self.acked` itself only moves forward via
+    /// [`Client::advance`], which callers run from a push/pull/poll
+    /// response's vector rather than on a failure, so a request that never
+    /// got an ack is simply resent (and, by the same commutative/idempotent
+    /// argument, harmlessly re-merged) next time around.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Client` if we don't know our document ID yet, i.e. we
+    /// haven't logged in or registered.
+    pub fn delta(&self, document: Document) -> error::Result<push::Req> {
+        let document_id = self
+            .document_id
+            .ok_or_else(|| Error::Client("not logged in".to_string()))?;
+
+        let parts = document
+            .split()
+            .filter(|part| {
+                part.clock()
+                    .is_none_or(|clock| !version_vector::covers(&self.acked, clock))
+            })
+            .collect();
+
+        Ok(push::Req {
+            document_id,
+            parts,
+            vector: self.acked.clone(),
+        })
+    }
+
+    /// Build a pull request asking for everything newer than what we've
+    /// already acked.
+    #[must_use]
+    pub fn pull_req(&self) -> pull::Req {
+        pull::Req {
+            vector: self.acked.clone(),
+        }
+    }
+
+    /// Build a poll request asking to be held until something newer than
+    /// what we've already acked shows up.
+    #[must_use]
+    pub fn poll_req(&self) -> poll::Req {
+        poll::Req {
+            vector: self.acked.clone(),
+        }
+    }
+
+    /// Advance our acknowledgment state with a vector the server just
+    /// returned from a push, pull, or poll, so future requests don't resend
+    /// or re-fetch what it's already confirmed.
+    pub fn advance(&mut self, vector: &VersionVector) {
+        version_vector::merge(&mut self.acked, vector);
     }
 
     /// Register with the server.
@@ -33,7 +169,56 @@ impl Client {
     ) -> error::Result<register::Resp> {
         let url = Url::parse(&self.server)?.join(register::PATH)?;
 
-        Self::handle_response(client.post(url).json(req)).await
+        self.handle_response(client, client.post(url).json(req)).await
+    }
+
+    /// Ask the server to email a password reset link, if `req.email`
+    /// matches an account. Answered the same way either way, so the
+    /// response can't be used to tell whether an account exists.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn request_reset(
+        &self,
+        client: &reqwest::Client,
+        req: &reset::RequestReq,
+    ) -> error::Result<reset::RequestResp> {
+        let url = Url::parse(&self.server)?.join(reset::REQUEST_PATH)?;
+
+        self.handle_response(client, client.post(url).json(req)).await
+    }
+
+    /// Redeem a password reset token (from the emailed link) for a new
+    /// password.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn confirm_reset(
+        &self,
+        client: &reqwest::Client,
+        req: &reset::ConfirmReq,
+    ) -> error::Result<reset::ConfirmResp> {
+        let url = Url::parse(&self.server)?.join(reset::CONFIRM_PATH)?;
+
+        self.handle_response(client, client.post(url).json(req)).await
+    }
+
+    /// Redeem an email verification token (from the emailed link) to
+    /// confirm an account's address.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn confirm_email(
+        &self,
+        client: &reqwest::Client,
+        req: &reset::ConfirmEmailReq,
+    ) -> error::Result<reset::ConfirmEmailResp> {
+        let url = Url::parse(&self.server)?.join(reset::CONFIRM_EMAIL_PATH)?;
+
+        self.handle_response(client, client.post(url).json(req)).await
     }
 
     /// Log into the server.
@@ -48,7 +233,7 @@ impl Client {
     ) -> error::Result<login::Resp> {
         let url = Url::parse(&self.server)?.join(login::PATH)?;
 
-        Self::handle_response(client.post(url).json(req)).await
+        self.handle_response(client, client.post(url).json(req)).await
     }
 
     /// Check that your auth works.
@@ -59,23 +244,326 @@ impl Client {
     pub async fn whoami(&self, client: &reqwest::Client) -> error::Result<whoami::Resp> {
         let url = Url::parse(&self.server)?.join(whoami::PATH)?;
 
-        self.authenticated(|jwt| client.get(url).bearer_auth(jwt))
+        self.authenticated(client, |jwt| client.get(url).bearer_auth(jwt))
+            .await
+    }
+
+    /// Enroll in two-factor authentication, generating a fresh TOTP secret
+    /// for this account.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn enroll_totp(&self, client: &reqwest::Client) -> error::Result<totp::EnrollResp> {
+        let url = Url::parse(&self.server)?.join(totp::ENROLL_PATH)?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt))
+            .await
+    }
+
+    /// Register a Web Push subscription against our account, so the server
+    /// can notify this device of a due ping even when it isn't in the
+    /// foreground.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn register_push_subscription(
+        &self,
+        client: &reqwest::Client,
+        req: &push_subscription::Req,
+    ) -> error::Result<push_subscription::Resp> {
+        let url = Url::parse(&self.server)?.join(push_subscription::PATH)?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt).json(req))
+            .await
+    }
+
+    /// Start a single-sign-on login against whatever OIDC provider the
+    /// server is configured with, getting back a URL to send the user's
+    /// browser to.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn oauth_start(
+        &self,
+        client: &reqwest::Client,
+        req: &oauth::StartReq,
+    ) -> error::Result<oauth::StartResp> {
+        let url = Url::parse(&self.server)?.join(oauth::START_PATH)?;
+
+        self.handle_response(client, client.get(url).query(req)).await
+    }
+
+    /// Finish a single-sign-on login, exchanging the code and state the
+    /// provider redirected back with for a JWT.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn oauth_callback(
+        &self,
+        client: &reqwest::Client,
+        req: &oauth::CallbackReq,
+    ) -> error::Result<oauth::CallbackResp> {
+        let url = Url::parse(&self.server)?.join(oauth::CALLBACK_PATH)?;
+
+        self.handle_response(client, client.get(url).query(req)).await
+    }
+
+    /// Push local changes to a document.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn push(
+        &self,
+        client: &reqwest::Client,
+        req: &push::Req,
+    ) -> error::Result<push::Resp> {
+        let url = Url::parse(&self.server)?.join(&push::path(req.document_id))?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt).json(req))
             .await
     }
 
-    async fn authenticated<CB, T>(&self, cb: CB) -> Result<T, Error>
+    /// Pull the latest version of a document. The server bounds how many
+    /// parts it sends back in one response; if `Resp::more` comes back
+    /// `true`, call this again with `req.vector` advanced to the returned
+    /// vector (e.g. via `Client::advance`) to fetch the rest.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn pull(
+        &self,
+        client: &reqwest::Client,
+        req: &pull::Req,
+    ) -> error::Result<pull::Resp> {
+        let url = Url::parse(&self.server)?.join(pull::PATH)?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt).json(req))
+            .await
+    }
+
+    /// Long-poll for changes to a document: the server holds the request
+    /// open until it has parts we haven't acked yet, or a server-side
+    /// timeout elapses, whichever comes first. An empty response means the
+    /// timeout won; poll again to keep waiting. Otherwise returns the same
+    /// parts `Self::pull` would, but all in one response rather than paged.
+    ///
+    /// Meant for callers that want near-real-time sync with a single
+    /// outstanding request instead of `Self::pull` on a timer, without the
+    /// WebSocket `Self::subscribe` needs.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Client` if we don't know our document ID yet, i.e. we haven't
+    /// logged in or registered. Otherwise the same as `handle_response`.
+    pub async fn poll(
+        &self,
+        client: &reqwest::Client,
+        req: &poll::Req,
+    ) -> error::Result<poll::Resp> {
+        let document_id = self
+            .document_id
+            .ok_or_else(|| Error::Client("not logged in".to_string()))?;
+        let url = Url::parse(&self.server)?.join(&poll::path(document_id))?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt).json(req))
+            .await
+    }
+
+    /// Take one step of a Merkle anti-entropy walk against a document:
+    /// check whether the subtree at `req.path` still matches
+    /// `req.expected_hash`, and if not, get back either its children's
+    /// hashes to recurse into or, at a leaf, the clocks of the ops stored
+    /// there.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn merkle_step(
+        &self,
+        client: &reqwest::Client,
+        req: &merkle::Req,
+    ) -> error::Result<merkle::Resp> {
+        let url = Url::parse(&self.server)?.join(&merkle::path(req.document_id))?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt).json(req))
+            .await
+    }
+
+    /// Pull a document's current chunk list, omitting the bytes of any
+    /// chunk whose hash is already in `req.known_hashes`. Reassemble the
+    /// response with `chunked_pull::Resp::reassemble` before decoding it
+    /// into `Part`s.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`.
+    pub async fn chunked_pull(
+        &self,
+        client: &reqwest::Client,
+        req: &chunked_pull::Req,
+    ) -> error::Result<chunked_pull::Resp> {
+        let url = Url::parse(&self.server)?.join(&chunked_pull::path(req.document_id))?;
+
+        self.authenticated(client, |jwt| client.post(url).bearer_auth(jwt).json(req))
+            .await
+    }
+
+    /// Open a live subscription to our document over a WebSocket, so we
+    /// learn about another replica's push the moment it happens instead of
+    /// waiting for the next scheduled `pull`. The server sends the document
+    /// as it stands right now as the first update, so there's no need for a
+    /// separate `pull` to catch up before relying on the stream.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Client` if we don't know our document ID yet, i.e. we
+    /// haven't logged in or registered. Otherwise `Error::WebSocket` if the
+    /// handshake fails, or `Error::UrlParse` if `self.server` can't be
+    /// turned into a WebSocket URL.
+    pub async fn subscribe(&self) -> error::Result<Subscription> {
+        let document_id = self
+            .document_id
+            .ok_or_else(|| Error::Client("not logged in".to_string()))?;
+        let jwt = self
+            .auth
+            .clone()
+            .ok_or_else(|| Error::Client("not logged in".to_string()))?;
+
+        let mut url = Url::parse(&self.server)?.join(&subscribe::path(document_id))?;
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|()| Error::Client(format!("couldn't build a {scheme} URL")))?;
+
+        let mut request = url.as_str().into_client_request()?;
+        request.headers_mut().insert(
+            http::header::AUTHORIZATION,
+            http::HeaderValue::from_str(&format!("Bearer {jwt}"))
+                .map_err(|err| Error::Client(err.to_string()))?,
+        );
+
+        let (socket, _) = connect_async(request).await?;
+
+        Ok(Subscription { socket })
+    }
+
+    /// Probe the server's health check. Doesn't require auth; used to track
+    /// whether we're online without needing a valid token.
+    ///
+    /// ## Errors
+    ///
+    /// Errors are the same as `handle_response`, except there's no
+    /// `Error::Client` case since there's no auth to reject.
+    pub async fn health(&self, client: &reqwest::Client) -> error::Result<()> {
+        let url = Url::parse(&self.server)?.join("/health")?;
+        let resp = client.get(url).send().await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Unexpected(resp.status()))
+        }
+    }
+
+    /// Redeem our refresh token for a fresh access token, updating
+    /// `self.auth` and `self.refresh_token` in place on success. The
+    /// refresh token just spent is retired server-side; presenting it
+    /// again (e.g. from a stale copy of `self`) is treated as reuse of a
+    /// stolen token and revokes the whole session.
+    ///
+    /// ## Errors
+    ///
+    /// - `Error::Client("Unauthorized")` if we have no refresh token yet.
+    /// - Otherwise, the same as `handle_response`.
+    pub async fn refresh(&mut self, client: &reqwest::Client) -> error::Result<()> {
+        let Some(refresh_token) = self.refresh_token.clone() else {
+            return Err(Error::Client("Unauthorized".to_string()));
+        };
+
+        let url = Url::parse(&self.server)?.join(refresh::PATH)?;
+        let resp: refresh::Resp =
+            self.handle_response(client, client.post(url).json(&refresh::Req { refresh_token })).await?;
+
+        self.auth = Some(resp.jwt);
+        self.refresh_token = Some(resp.refresh_token);
+
+        Ok(())
+    }
+
+    /// Run `attempt` against the server, retrying transient failures
+    /// (`Error::Http` and `Error::Server`) with exponential backoff and
+    /// jitter, up to `backoff.max_retries` times.
+    ///
+    /// A `Error::Client` that looks like an expired or rejected token
+    /// triggers a single [`Client::refresh`] attempt before retrying once
+    /// more; any other `Error::Client`, or a repeat failure after
+    /// refreshing, is returned immediately rather than retried, since
+    /// retrying a request the server has already rejected on its merits
+    /// wouldn't help.
+    ///
+    /// ## Errors
+    ///
+    /// Whatever `attempt` or `refresh` last failed with, once retries are
+    /// exhausted.
+    pub async fn with_retry<T, F>(
+        &mut self,
+        client: &reqwest::Client,
+        backoff: &Backoff,
+        mut attempt: impl FnMut(&Self) -> F,
+    ) -> error::Result<T>
+    where
+        F: std::future::Future<Output = error::Result<T>>,
+    {
+        let mut delay = backoff.initial;
+        let mut refreshed = false;
+        let mut retries = 0;
+
+        loop {
+            match attempt(self).await {
+                Ok(value) => return Ok(value),
+
+                Err(Error::Client(message)) if !refreshed && looks_like_expired_token(&message) => {
+                    refreshed = true;
+                    self.refresh(client).await?;
+                }
+
+                Err(err @ (Error::Http(_) | Error::Server)) if retries < backoff.max_retries => {
+                    retries += 1;
+                    tokio::time::sleep(jittered(delay)).await;
+                    delay = (delay * 2).min(backoff.max);
+                    tracing::debug!(?err, retries, "retrying after transient sync failure");
+                }
+
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn authenticated<CB, T>(
+        &self,
+        client: &reqwest::Client,
+        cb: CB,
+    ) -> Result<T, Error>
     where
         CB: FnOnce(&str) -> reqwest::RequestBuilder,
         T: DeserializeOwned,
     {
         match &self.auth {
-            Some(auth) => Self::handle_response(cb(auth)).await,
+            Some(auth) => self.handle_response(client, cb(auth)).await,
             None => Err(Error::Client("Unauthorized".to_string())),
         }
     }
 
     /// Convert an HTTP response into a result, interpreting errors in a
-    /// standard way.
+    /// standard way. `builder` is run through `self.middlewares` (see
+    /// `Client::use_middleware`) before it's actually sent, so a registered
+    /// middleware gets a chance to rewrite the request, retry, or
+    /// short-circuit around it.
     ///
     /// ## Errors
     ///
@@ -84,11 +572,18 @@ impl Client {
     /// - `Error::Server` if the server returned a server error (5xx)
     /// - `Error::Unexpected` if the server returned something else (the server is
     ///   not supposed to issue redirects or informational responses.)
-    async fn handle_response<T>(resp: reqwest::RequestBuilder) -> error::Result<T>
+    async fn handle_response<T>(
+        &self,
+        client: &reqwest::Client,
+        builder: reqwest::RequestBuilder,
+    ) -> error::Result<T>
     where
         T: DeserializeOwned,
     {
-        let resp = resp.send().await?;
+        let request = builder.build()?;
+        let resp = middleware::Next::new(client, &self.middlewares)
+            .run(request)
+            .await?;
 
         let status = resp.status();
 
@@ -104,3 +599,99 @@ impl Client {
         }
     }
 }
+
+/// A live, already-authenticated subscription to a document's updates, as
+/// opened by [`Client::subscribe`]. Keep calling [`Subscription::next`] to
+/// wait for each update the server pushes down; a dropped or erroring
+/// connection is surfaced there rather than panicking, so the caller can
+/// decide whether to reconnect with a fresh `Client::subscribe`.
+pub struct Subscription {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Subscription {
+    /// Wait for the next update pushed to this subscription. Ping frames
+    /// from the server are answered with a pong transparently; only text
+    /// frames (document updates) are ever returned.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Client` if the server closed the connection or sent
+    /// something that didn't parse as an `Update`. Otherwise
+    /// `Error::WebSocket` if the connection itself failed.
+    pub async fn next(&mut self) -> error::Result<subscribe::Update> {
+        loop {
+            let message = self
+                .socket
+                .next()
+                .await
+                .ok_or_else(|| Error::Client("subscription closed".to_string()))??;
+
+            match message {
+                Message::Text(text) => {
+                    return serde_json::from_str(text.as_str())
+                        .map_err(|err| Error::Client(err.to_string()));
+                }
+                Message::Ping(payload) => {
+                    self.socket.send(Message::Pong(payload)).await?;
+                }
+                Message::Close(_) => {
+                    return Err(Error::Client("subscription closed".to_string()));
+                }
+                Message::Binary(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription").finish_non_exhaustive()
+    }
+}
+
+/// Exponential backoff configuration for [`Client::with_retry`], with
+/// jitter applied at each step.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial: Duration,
+
+    /// Ceiling the delay is capped at, no matter how many retries.
+    pub max: Duration,
+
+    /// How many times to retry a transient failure before giving up.
+    pub max_retries: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(250),
+            max: Duration::from_secs(30),
+            max_retries: 5,
+        }
+    }
+}
+
+/// Add up to 20% random jitter to `delay`, so a fleet of clients retrying
+/// after the same outage don't all land on the server in the same instant.
+fn jittered(delay: Duration) -> Duration {
+    delay + delay.mul_f64(rand::rng().random_range(0.0..0.2))
+}
+
+/// Whether a `Error::Client` message looks like it was caused by an
+/// expired or otherwise rejected token, based on the messages `jwt.rs`
+/// actually sends. Best-effort: if the server's wording changes, we just
+/// fall back to surfacing the error without attempting a refresh.
+///
+/// Deliberately doesn't match a revoked session: unlike a merely-expired
+/// access token, a revoked session's refresh token was revoked right
+/// alongside it, so a refresh attempt would just fail too. Skipping it
+/// means the caller gets the "log in again" error immediately instead of
+/// after a wasted round trip.
+fn looks_like_expired_token(message: &str) -> bool {
+    message.contains("invalid token")
+        || message.contains("missing or invalid authorization")
+        || message.contains("session has expired")
+}
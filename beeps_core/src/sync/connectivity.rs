@@ -0,0 +1,105 @@
+use super::client::{Backoff, Client};
+use super::{error, push};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Tracks whether `client`'s server is currently reachable, based on
+/// periodic `/health` probes, so a background syncer knows when it's worth
+/// trying again instead of hammering a connection that's definitely down.
+pub struct Connectivity {
+    /// The last probe's result. `watch` lets `wait_for_online` park until
+    /// it actually changes, rather than polling.
+    online: watch::Receiver<bool>,
+}
+
+impl Connectivity {
+    /// Start probing `client`'s server every `interval`, in the background.
+    /// Keep the returned `JoinHandle` around to abort the prober at once
+    /// (for example, when the user logs out); dropping `Connectivity` only
+    /// stops it after its next probe, when it finds nobody listening.
+    pub fn spawn(
+        client: Client,
+        http: reqwest::Client,
+        interval: std::time::Duration,
+    ) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = watch::channel(true);
+
+        let handle = tokio::spawn(async move {
+            loop {
+                let reachable = client.health(&http).await.is_ok();
+
+                // `send` only fails once every receiver has been dropped,
+                // meaning nobody's listening anymore - fine to just stop.
+                if tx.send(reachable).is_err() {
+                    break;
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+
+        (Self { online: rx }, handle)
+    }
+
+    /// Whether the most recent probe succeeded.
+    pub fn is_online(&self) -> bool {
+        *self.online.borrow()
+    }
+
+    /// Park until a probe reports the server reachable again. Returns
+    /// immediately if it already is.
+    pub async fn wait_for_online(&mut self) {
+        while !*self.online.borrow() {
+            if self.online.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Document pushes produced while we might be offline, queued up to flush
+/// once connectivity returns. Safe to flush repeatedly or out of order with
+/// respect to a concurrent pull, since merging a `Document` is idempotent.
+#[derive(Default)]
+pub struct PendingPushes {
+    queue: Vec<push::Req>,
+}
+
+impl PendingPushes {
+    /// Queue a push produced by a local edit (e.g. `Document::add_ping` or
+    /// `Document::add_tag`) made while we might be offline.
+    pub fn enqueue(&mut self, req: push::Req) {
+        self.queue.push(req);
+    }
+
+    /// Whether there's anything waiting to be flushed.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Flush every queued push, in order, retrying each with `backoff` and
+    /// refreshing `client`'s token if it's rejected as expired. Stops at
+    /// (and leaves queued) the first push that still fails once retries
+    /// are exhausted, so a persistent failure doesn't reorder later ones
+    /// ahead of it on the next attempt.
+    ///
+    /// ## Errors
+    ///
+    /// Whatever the first unrecoverable push failed with.
+    pub async fn flush(
+        &mut self,
+        client: &mut Client,
+        http: &reqwest::Client,
+        backoff: &Backoff,
+    ) -> error::Result<()> {
+        while let Some(req) = self.queue.first().cloned() {
+            client
+                .with_retry(http, backoff, |client| client.push(http, &req))
+                .await?;
+
+            self.queue.remove(0);
+        }
+
+        Ok(())
+    }
+}
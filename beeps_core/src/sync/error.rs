@@ -30,6 +30,24 @@ pub enum Error {
     /// The server sent us something unexpected
     #[error("The server sent an unexpected response")]
     Unexpected(StatusCode),
+
+    /// We couldn't establish or maintain a WebSocket connection, for example
+    /// during `Client::subscribe`.
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+}
+
+impl Error {
+    /// Whether this means our credentials no longer work and we should
+    /// prompt to log back in from scratch, rather than something worth
+    /// retrying or just surfacing as a one-off problem. A `with_retry` call
+    /// already tries a single refresh before giving up, so a `Client`
+    /// error that survives it is either a refresh failure or a client
+    /// error the refresh couldn't have helped with anyway.
+    #[must_use]
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self, Self::Client(_))
+    }
 }
 
 #[expect(clippy::module_name_repetitions)]
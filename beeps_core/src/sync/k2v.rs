@@ -0,0 +1,195 @@
+//! An alternative to [`super::Client`] that syncs against a causal
+//! key-value store (in the style of Garage's K2V API) instead of the
+//! bespoke JWT server. There's no application server in this model: any
+//! store that speaks causal contexts is a valid meeting point for replicas.
+//!
+//! Each replica owns a namespace of keys, one per `(NodeId, counter)` pair
+//! in its op stream. A partition index key tracks the highest counter each
+//! replica has written, so a reader can tell who has new data without
+//! fetching every key. Writes present the causal context they read under;
+//! the store uses that to decide whether a write supersedes what's there or
+//! should be kept as a sibling, and reads can come back with more than one
+//! sibling when two replicas raced to the same slot. Because every `Part`'s
+//! `Merge`/`Split` impl is already commutative and idempotent, reconciling
+//! siblings is just merging them all in, the same as any other delta.
+
+use super::error::{self, Error};
+use crate::document::Part;
+use crate::split::Split;
+use crate::{Document, NodeId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use url::Url;
+
+/// The set of `(NodeId, counter)` version tokens a read was derived from.
+/// Presented back on write so the store knows exactly which versions that
+/// write supersedes; a write made without ever having observed a
+/// concurrent counter is kept as a sibling rather than silently dropped.
+pub type CausalContext = HashMap<NodeId, u64>;
+
+/// A value read back from a single `(node, counter)` slot. Ordinarily one
+/// sibling, but a slot two replicas wrote to concurrently comes back with
+/// however many are still unresolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Value {
+    /// Which replica's op stream this slot belongs to.
+    pub node: NodeId,
+
+    /// This slot's position in that replica's op stream.
+    pub counter: u64,
+
+    /// The concurrent writes still unresolved at this slot.
+    pub siblings: Vec<Part>,
+
+    /// The causal context to present if writing back a resolution.
+    pub context: CausalContext,
+}
+
+/// A client for the K2V-style sync backend.
+#[derive(Debug, Clone)]
+pub struct K2vClient {
+    /// Base URL of the causal KV store, e.g. `https://k2v.your-domain.com`.
+    pub endpoint: String,
+
+    /// Which bucket (namespace) this document's keys live under.
+    pub bucket: String,
+}
+
+impl K2vClient {
+    /// Construct a new client for `bucket` on the store at `endpoint`.
+    #[must_use]
+    pub fn new(endpoint: String, bucket: String) -> Self {
+        Self { endpoint, bucket }
+    }
+
+    /// Read the partition index: the highest counter each replica has
+    /// written so far. Diff this against what we already have to find out
+    /// who has new data, without fetching every key up front.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Http`/`Error::Server`/`Error::Unexpected` if the request
+    /// fails; see `Client::handle_response` for how those are decided.
+    pub async fn index(&self, client: &reqwest::Client) -> error::Result<HashMap<NodeId, u64>> {
+        let url = self.key_url("_index")?;
+        let resp = client.get(url).send().await?;
+
+        Self::parse(resp).await
+    }
+
+    /// Fetch everything `node` has written after `since`, up to and
+    /// including `latest`, one slot at a time.
+    ///
+    /// ## Errors
+    ///
+    /// Same as [`K2vClient::get`].
+    pub async fn fetch(
+        &self,
+        client: &reqwest::Client,
+        node: NodeId,
+        since: u64,
+        latest: u64,
+    ) -> error::Result<Vec<Value>> {
+        let mut values = Vec::new();
+
+        for counter in (since + 1)..=latest {
+            values.push(self.get(client, node, counter).await?);
+        }
+
+        Ok(values)
+    }
+
+    /// Read a single `(node, counter)` slot.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Http`/`Error::Server`/`Error::Unexpected` as above.
+    pub async fn get(
+        &self,
+        client: &reqwest::Client,
+        node: NodeId,
+        counter: u64,
+    ) -> error::Result<Value> {
+        let url = self.key_url(&slot_key(node, counter))?;
+        let resp = client.get(url).send().await?;
+
+        Self::parse(resp).await
+    }
+
+    /// Write `part` to our own slot `(node, counter)`, presenting
+    /// `context` so the store can tell which versions it supersedes.
+    /// Returns the context to present next, which folds in whatever
+    /// sibling (if any) the store still had to keep around.
+    ///
+    /// ## Errors
+    ///
+    /// `Error::Http`/`Error::Server`/`Error::Unexpected` as above.
+    pub async fn put(
+        &self,
+        client: &reqwest::Client,
+        node: NodeId,
+        counter: u64,
+        context: &CausalContext,
+        part: &Part,
+    ) -> error::Result<CausalContext> {
+        let url = self.key_url(&slot_key(node, counter))?;
+        let resp = client
+            .put(url)
+            .json(&Value {
+                node,
+                counter,
+                siblings: vec![part.clone()],
+                context: context.clone(),
+            })
+            .send()
+            .await?;
+
+        let value: Value = Self::parse(resp).await?;
+
+        Ok(value.context)
+    }
+
+    /// Build the URL for a single key under our bucket.
+    fn key_url(&self, key: &str) -> error::Result<Url> {
+        Ok(Url::parse(&self.endpoint)?.join(&format!("{}/{key}", self.bucket))?)
+    }
+
+    /// Shared response handling, matching `Client::handle_response`'s
+    /// rules for what counts as a client, server, or unexpected error.
+    async fn parse<T: serde::de::DeserializeOwned>(resp: reqwest::Response) -> error::Result<T> {
+        let status = resp.status();
+
+        if status.is_success() {
+            Ok(resp.json().await?)
+        } else if status.is_client_error() {
+            let err: error::ErrorResp = resp.json().await?;
+            Err(Error::Client(err.error))
+        } else if status.is_server_error() {
+            Err(Error::Server)
+        } else {
+            Err(Error::Unexpected(status))
+        }
+    }
+}
+
+/// The key a replica's `(node, counter)` slot lives under.
+fn slot_key(node: NodeId, counter: u64) -> String {
+    format!("{node}/{counter}")
+}
+
+/// Fold every sibling across `values` into a single `Document`. Each
+/// `Part`'s own `Merge`/`Split` impl resolves concurrent siblings the same
+/// principled way whole-document merges already do, so there's no
+/// "replace or merge" guesswork left for the caller.
+#[must_use]
+pub fn reconcile(values: Vec<Value>) -> Document {
+    let mut document = Document::default();
+
+    for value in values {
+        for part in value.siblings {
+            document.merge_part(part);
+        }
+    }
+
+    document
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// The request to log into the server.
@@ -8,13 +9,45 @@ pub struct Req {
 
     /// Plaintext password to use for login.
     pub password: String,
+
+    /// Current TOTP code, if the account has two-factor enabled. Only
+    /// checked once the password has already verified; omit it on the
+    /// first attempt and resubmit with it set if the server comes back
+    /// with `Resp::TotpRequired`.
+    pub totp: Option<String>,
+
+    /// A human-readable label for this device (e.g. "Jo's iPhone"), shown
+    /// back when listing sessions. Optional.
+    pub device_label: Option<String>,
 }
 
 /// Result of logging in.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Resp {
-    /// JWT to use for future requests.
-    pub jwt: String,
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Resp {
+    /// Login succeeded.
+    Ok {
+        /// JWT to use for future requests.
+        jwt: String,
+
+        /// When `jwt` expires, so a long-running client knows to refresh
+        /// before the server starts rejecting it rather than finding out
+        /// from a failed request.
+        expires_at: DateTime<Utc>,
+
+        /// Opaque token to redeem at `refresh::PATH` once `jwt` expires,
+        /// without logging in again.
+        refresh_token: String,
+
+        /// The document this account syncs, so the client knows what to
+        /// pass `push`/`pull` without having to guess.
+        document_id: i64,
+    },
+
+    /// The password was correct, but the account has two-factor enabled and
+    /// no (or an incorrect) TOTP code was provided. Resubmit the same
+    /// request with `Req::totp` set.
+    TotpRequired,
 }
 
 /// Where the login endpoint lives.
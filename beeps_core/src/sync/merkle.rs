@@ -0,0 +1,45 @@
+use crate::Hlc;
+use serde::{Deserialize, Serialize};
+
+/// One step of a Merkle anti-entropy walk: a subtree the caller already has
+/// a hash for, so the server can say whether it's still in sync without
+/// shipping a whole version vector up front. Start with `path` empty (the
+/// root) and `expected_hash` empty (which never matches anything), and
+/// recurse into whichever children the response says differ.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Req {
+    /// Which document's tree to walk.
+    pub document_id: i64,
+
+    /// The subtree to check, as a string of hex nibbles ("" for the root).
+    pub path: String,
+
+    /// The hash the caller already has on file for `path`.
+    pub expected_hash: Vec<u8>,
+}
+
+/// What the server found at `path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Resp {
+    /// `expected_hash` already matches; there's nothing under this subtree
+    /// worth recursing into.
+    Identical,
+
+    /// An internal node: the hash of each present child, keyed by the hex
+    /// nibble that extends `path` to reach it. A nibble absent from this
+    /// list has no op anywhere underneath it.
+    Children(Vec<(char, Vec<u8>)>),
+
+    /// A leaf: the clock of every op stored under this subtree. The caller
+    /// diffs these against what it already has and fetches whichever ones
+    /// it's missing.
+    Leaves(Vec<Hlc>),
+}
+
+/// Where the Merkle anti-entropy endpoint lives.
+pub static PATH: &str = "/api/v1/merkle/:id";
+
+/// Construct a path given a document ID.
+pub fn path(id: i64) -> String {
+    PATH.replace(":id", &id.to_string())
+}
@@ -0,0 +1,188 @@
+use super::client::Backoff;
+use super::error;
+use rand::Rng;
+use reqwest::{header, Request, Response, StatusCode};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A single link in a [`super::Client`]'s request pipeline: can inspect or
+/// rewrite a request before it goes out, inspect a response (or the lack of
+/// one) once the rest of the chain has run, or short-circuit entirely,
+/// depending on whether (and how) it calls `next`. Register one on a
+/// `Client` to add cross-cutting behavior, like retry-with-backoff on a 5xx
+/// ([`RetryMiddleware`]), request/response logging ([`LoggingMiddleware`]),
+/// or a transparent re-login on a 401 ([`ReloginMiddleware`]), without
+/// touching every method that builds a request.
+#[async_trait::async_trait]
+pub trait Middleware: Send + Sync {
+    /// Handle `req`, calling `next.run(req)` to continue down the chain (or
+    /// not, to short-circuit without sending anything).
+    async fn handle(&self, req: Request, next: Next<'_>) -> error::Result<Response>;
+}
+
+/// The remainder of a `Client`'s middleware chain still left to run, plus
+/// the underlying `reqwest::Client` that actually sends the request once
+/// every middleware has had its turn.
+///
+/// Holds `Arc<dyn Middleware>` rather than the plain `Box<dyn Middleware>`
+/// a chain walk would otherwise need, so `sync::Client` (which stores the
+/// registered chain) can stay `Clone` the same way every other field on it
+/// already is.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    client: &'a reqwest::Client,
+    middlewares: &'a [Arc<dyn Middleware>],
+}
+
+impl<'a> Next<'a> {
+    /// Start (or continue) a chain walk: `middlewares` is whatever's left to
+    /// run before `client` sends the request itself.
+    pub(crate) fn new(client: &'a reqwest::Client, middlewares: &'a [Arc<dyn Middleware>]) -> Self {
+        Self { client, middlewares }
+    }
+
+    /// Run `req` through whatever's left of the chain, terminating in an
+    /// actual send once it's exhausted.
+    ///
+    /// ## Errors
+    ///
+    /// Whatever the chain, or the underlying send, fails with.
+    pub async fn run(self, req: Request) -> error::Result<Response> {
+        match self.middlewares {
+            [] => Ok(self.client.execute(req).await?),
+            [head, tail @ ..] => head.handle(req, Next::new(self.client, tail)).await,
+        }
+    }
+}
+
+/// Add up to 20% random jitter to `delay`, so a fleet of clients retrying
+/// after the same outage don't all land on the server in the same instant.
+/// Mirrors `client::jittered`, which `Client::with_retry` uses for the same
+/// reason at a higher level.
+fn jittered(delay: Duration) -> Duration {
+    delay + delay.mul_f64(rand::rng().random_range(0.0..0.2))
+}
+
+/// Retries a request with exponential backoff and jitter whenever the rest
+/// of the chain comes back with a 5xx, up to `backoff.max_retries` times.
+/// Transport failures (a dropped connection, a timeout) are surfaced
+/// immediately instead of retried here, since those come back as `Err`
+/// rather than a response to inspect; `Client::with_retry` already retries
+/// those one level up, around a whole request-building attempt rather than
+/// a single already-built one.
+///
+/// A request with a streaming body can't be retried (there's nothing to
+/// re-read the body from), so one is just passed through to the rest of the
+/// chain once and whatever it returns is the final answer.
+pub struct RetryMiddleware {
+    backoff: Backoff,
+}
+
+impl RetryMiddleware {
+    /// Retry on the given `backoff`'s schedule.
+    #[must_use]
+    pub fn new(backoff: Backoff) -> Self {
+        Self { backoff }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RetryMiddleware {
+    async fn handle(&self, req: Request, next: Next<'_>) -> error::Result<Response> {
+        if req.try_clone().is_none() {
+            return next.run(req).await;
+        }
+
+        let mut delay = self.backoff.initial;
+        let mut retries = 0;
+
+        loop {
+            let attempt = req.try_clone().expect("checked above");
+            let resp = next.run(attempt).await?;
+
+            if !resp.status().is_server_error() || retries >= self.backoff.max_retries {
+                return Ok(resp);
+            }
+
+            retries += 1;
+            tracing::debug!(retries, status = %resp.status(), "retrying sync request");
+            tokio::time::sleep(jittered(delay)).await;
+            delay = (delay * 2).min(self.backoff.max);
+        }
+    }
+}
+
+/// Logs every request's method and URL, and either the response status or
+/// the error it failed with, at debug level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for LoggingMiddleware {
+    async fn handle(&self, req: Request, next: Next<'_>) -> error::Result<Response> {
+        let method = req.method().clone();
+        let url = req.url().clone();
+
+        match next.run(req).await {
+            Ok(resp) => {
+                tracing::debug!(%method, %url, status = %resp.status(), "sync request");
+                Ok(resp)
+            }
+            Err(problem) => {
+                tracing::debug!(%method, %url, %problem, "sync request failed");
+                Err(problem)
+            }
+        }
+    }
+}
+
+/// Transparently retries a request once, with a freshly-fetched bearer
+/// token, if the rest of the chain comes back with a 401. `refresh` is
+/// whatever the caller needs to get a new token — typically a closure that
+/// calls `Client::refresh` against a shared handle to the `Client` itself,
+/// since that's the piece that actually knows how to redeem a refresh
+/// token and needs `&mut self` to record the result.
+///
+/// Like [`RetryMiddleware`], a request with a streaming body can't be
+/// retried, so one is passed through once and whatever comes back is final.
+pub struct ReloginMiddleware<F> {
+    refresh: F,
+}
+
+impl<F, Fut> ReloginMiddleware<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = error::Result<String>> + Send,
+{
+    /// Re-login by calling `refresh` to get a fresh bearer token.
+    pub fn new(refresh: F) -> Self {
+        Self { refresh }
+    }
+}
+
+#[async_trait::async_trait]
+impl<F, Fut> Middleware for ReloginMiddleware<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = error::Result<String>> + Send,
+{
+    async fn handle(&self, req: Request, next: Next<'_>) -> error::Result<Response> {
+        let Some(mut retry) = req.try_clone() else {
+            return next.run(req).await;
+        };
+
+        let resp = next.run(req).await?;
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+
+        let token = (self.refresh)().await?;
+        let value = header::HeaderValue::try_from(format!("Bearer {token}")).map_err(|_| {
+            error::Error::Client("refreshed token wasn't a valid header value".to_string())
+        })?;
+        retry.headers_mut().insert(header::AUTHORIZATION, value);
+
+        next.run(retry).await
+    }
+}
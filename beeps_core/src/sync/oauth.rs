@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Start an OIDC login attempt.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct StartReq {
+    /// A human-readable label for this device (e.g. "Jo's iPhone"), shown
+    /// back when listing sessions. Optional.
+    pub device_label: Option<String>,
+}
+
+/// Where to send the user's browser to start the authorization-code-with-PKCE
+/// flow against whatever OIDC provider the server is configured with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartResp {
+    /// The provider's authorize URL, including `state` and `code_challenge`.
+    /// Open this in a browser to continue.
+    pub authorize_url: String,
+}
+
+/// What the provider redirects back to `CALLBACK_PATH` with, once the user
+/// has authenticated.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CallbackReq {
+    /// The authorization code to exchange for tokens.
+    pub code: String,
+
+    /// The `state` value we handed the provider in `StartResp`, echoed back
+    /// so we can look up the matching PKCE verifier.
+    pub state: String,
+}
+
+/// Result of completing the OIDC login flow.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CallbackResp {
+    /// JWT to use for future requests.
+    pub jwt: String,
+
+    /// When `jwt` expires, so the client knows to refresh before the
+    /// server starts rejecting it rather than finding out from a failed
+    /// request.
+    pub expires_at: DateTime<Utc>,
+
+    /// Opaque token to redeem at `refresh::PATH` once `jwt` expires,
+    /// without going through the provider again.
+    pub refresh_token: String,
+}
+
+/// Where to start an OIDC login.
+pub const START_PATH: &str = "/api/v1/oauth/start";
+
+/// Where the OIDC provider redirects back to with an authorization code.
+pub const CALLBACK_PATH: &str = "/api/v1/oauth/callback";
@@ -0,0 +1,42 @@
+use super::VersionVector;
+use crate::document::Part;
+use serde::{Deserialize, Serialize};
+
+/// What the client has already seen, so the server knows whether to return
+/// right away or hold the request open waiting for something newer.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Req {
+    /// The highest clock the client has seen from each node. A node missing
+    /// from the vector means "send everything for that node."
+    pub vector: VersionVector,
+}
+
+/// The parts of the document the client doesn't have yet, as seen by the
+/// server once something changed (or the timeout ran out, whichever came
+/// first).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resp {
+    /// The parts not already covered by the client's vector. Empty means
+    /// nothing changed before the timeout, and it's safe to poll again with
+    /// the same request.
+    pub parts: Vec<Part>,
+
+    /// The server's own version vector, covering everything in `parts`, so
+    /// the client can advance its watermarks to match what it was just
+    /// sent, the same as a `pull` response.
+    pub vector: VersionVector,
+
+    /// Whether the server held back more parts than fit in this response.
+    /// The server's poll handler sends every missing part at once, so this
+    /// comes back `false`; a client that does see `true` should `pull` (not
+    /// poll again) to page through the rest.
+    pub more: bool,
+}
+
+/// Where the long-poll endpoint lives.
+pub static PATH: &str = "/api/v1/poll/:id";
+
+/// Construct a path given a document ID.
+pub fn path(id: i64) -> String {
+    PATH.replace(":id", &id.to_string())
+}
@@ -1,11 +1,40 @@
-use crate::Document;
+use super::VersionVector;
+use crate::document::Part;
 use serde::{Deserialize, Serialize};
 
-/// The current document, as seen by the server
+/// What the client has already seen, so the server can send only the `Part`s
+/// it's missing instead of rebuilding and sending the whole document. This
+/// is already the per-part high-water-mark cursor an incremental pull
+/// needs: `beeps-server`'s `parts_since` turns each node's watermark into a
+/// `node = ... AND (timestamp, counter) > (...)` predicate at the database, so
+/// a long-lived replica only ever pays for rows that changed since its last
+/// pull.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Req {
+    /// The highest clock the client has seen from each node. A node missing
+    /// from the vector means "send everything for that node."
+    pub vector: VersionVector,
+}
+
+/// The parts of the document the client doesn't have yet, as seen by the
+/// server.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Resp {
-    /// The constructed document
-    pub document: Document,
+    /// The parts not already covered by the client's vector. Fold these in
+    /// with `Document::merge_part` (or build a fresh `Document` from them)
+    /// to get the caught-up state.
+    pub parts: Vec<Part>,
+
+    /// The server's own version vector, covering everything in `parts`, so
+    /// the client can advance its watermarks to match what it was just
+    /// sent.
+    pub vector: VersionVector,
+
+    /// Whether the server held back more parts than fit in this response.
+    /// A client that sees `true` should pull again with `vector` (which
+    /// already covers everything returned so far) to page through the
+    /// rest, rather than assuming it's caught up.
+    pub more: bool,
 }
 
 /// Where the document push endpoint lives.
@@ -1,19 +1,42 @@
-use crate::Document;
+use super::VersionVector;
+use crate::document::Part;
 use serde::{Deserialize, Serialize};
 
 /// The replica data we send to the server.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Req {
     /// Which document we're pushing.
     pub document_id: i64,
 
-    /// The document contents to push.
-    pub document: Document,
+    /// The parts to push, as produced by `Document::split`. Callers that
+    /// maintain per-peer acknowledgment state (see `Client::delta`) should
+    /// filter this down to only what the peer hasn't already acked, rather
+    /// than sending the whole document every time.
+    ///
+    /// Each part already carries its own natural key (document, clock), so
+    /// the server's insert is idempotent on re-send without us needing a
+    /// separate "diff against the server's last-known clock per device"
+    /// step first — see the `ON CONFLICT ... DO NOTHING`/`DO UPDATE`
+    /// handling in `beeps-server`'s push handler.
+    pub parts: Vec<Part>,
+
+    /// The highest clock we've seen from each node, so the server knows what
+    /// it can skip sending back in the response.
+    pub vector: VersionVector,
 }
 
 /// Confirmation that the server accepted the document.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Resp {}
+pub struct Resp {
+    /// How many parts were merged. Mostly useful against `STREAM_PATH`,
+    /// where a caller uploading a large backlog has no other way to tell
+    /// how much of it actually landed.
+    pub accepted: u64,
+
+    /// The server's own version vector after applying the push, so the
+    /// client can advance its watermarks without a separate pull.
+    pub vector: VersionVector,
+}
 
 /// Where the document push endpoint lives.
 pub static PATH: &str = "/api/v1/push/:id";
@@ -22,3 +45,14 @@ pub static PATH: &str = "/api/v1/push/:id";
 pub fn path(id: i64) -> String {
     PATH.replace(":id", &id.to_string())
 }
+
+/// Where the streaming variant of the push endpoint lives. Accepts the same
+/// document, but as a body of newline-delimited `Part`s instead of a single
+/// JSON `Req`, so a large initial sync can be decoded and merged
+/// incrementally instead of buffered whole.
+pub static STREAM_PATH: &str = "/api/v1/push/:id/stream";
+
+/// Construct a stream path given a document ID.
+pub fn stream_path(id: i64) -> String {
+    STREAM_PATH.replace(":id", &id.to_string())
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// A client's Web Push subscription, exactly as handed back by the browser's
+/// Push API. Opaque to us beyond what RFC 8291 needs to encrypt a payload
+/// for it and RFC 8292 needs to sign the request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Subscription {
+    /// Where to `POST` the encrypted payload.
+    pub endpoint: String,
+
+    /// The subscriber's P-256 Diffie-Hellman public key, base64url-encoded.
+    pub p256dh: String,
+
+    /// The subscriber's auth secret, base64url-encoded.
+    pub auth: String,
+}
+
+/// Register a Web Push subscription against the caller's account, so the
+/// server can notify it when a ping is due even if it isn't in the
+/// foreground.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Req {
+    /// The subscription to register.
+    pub subscription: Subscription,
+}
+
+/// Acknowledges that a subscription was registered.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Resp {}
+
+/// Where to register a Web Push subscription.
+pub const PATH: &str = "/api/v1/push-subscriptions";
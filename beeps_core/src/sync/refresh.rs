@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Redeem a refresh token for a fresh access token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Req {
+    /// The refresh token issued at login, registration, or OIDC callback.
+    pub refresh_token: String,
+}
+
+/// A freshly-issued access token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resp {
+    /// JWT to use for future requests.
+    pub jwt: String,
+
+    /// When `jwt` expires, so the client can refresh again ahead of time
+    /// instead of waiting for a request to fail.
+    pub expires_at: DateTime<Utc>,
+
+    /// The refresh token to use next time, replacing the one just spent.
+    /// The old one is retired and presenting it again is treated as a
+    /// compromise signal.
+    pub refresh_token: String,
+}
+
+/// Where to redeem a refresh token for a fresh access token.
+pub const PATH: &str = "/api/v1/refresh";
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// The request to register a new account.
@@ -8,6 +9,10 @@ pub struct Req {
 
     /// Plaintext password to use for login.
     pub password: String,
+
+    /// A human-readable label for this device (e.g. "Jo's iPhone"), shown
+    /// back when listing sessions. Optional.
+    pub device_label: Option<String>,
 }
 
 /// Result of registering a new account.
@@ -15,6 +20,19 @@ pub struct Req {
 pub struct Resp {
     /// JWT to use for future requests.
     pub jwt: String,
+
+    /// When `jwt` expires, so a long-running client knows to refresh
+    /// before the server starts rejecting it rather than finding out from
+    /// a failed request.
+    pub expires_at: DateTime<Utc>,
+
+    /// Opaque token to redeem at `refresh::PATH` once `jwt` expires,
+    /// without logging in again.
+    pub refresh_token: String,
+
+    /// The document this account syncs, so the client knows what to pass
+    /// `push`/`pull` without having to guess.
+    pub document_id: i64,
 }
 
 /// Where the register endpoint lives.
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+/// Ask the server to email a password reset link to an account. Always
+/// answered the same way whether or not the email matches an account, so
+/// this can't be used to enumerate who's registered.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestReq {
+    /// The account's email.
+    pub email: String,
+}
+
+/// Redeem a reset token (from the emailed link) for a new password.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfirmReq {
+    /// The token embedded in the emailed link.
+    pub token: String,
+
+    /// The password to set, in place of whatever the account's forgotten.
+    pub new_password: String,
+}
+
+/// Redeem an email verification token (from the emailed link) to confirm
+/// an account's address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfirmEmailReq {
+    /// The token embedded in the emailed link.
+    pub token: String,
+}
+
+/// Acknowledges that a reset email was sent, if the account exists.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RequestResp {}
+
+/// Acknowledges that the password was changed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConfirmResp {}
+
+/// Acknowledges that the email was verified.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConfirmEmailResp {}
+
+/// Where to ask for a password reset email.
+pub const REQUEST_PATH: &str = "/api/v1/reset/request";
+
+/// Where to redeem a password reset token for a new password.
+pub const CONFIRM_PATH: &str = "/api/v1/reset/confirm";
+
+/// Where to redeem an email verification token.
+pub const CONFIRM_EMAIL_PATH: &str = "/api/v1/verify-email/confirm";
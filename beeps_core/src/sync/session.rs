@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// One of the caller's own device sessions.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Session {
+    /// The session's ID. Pass this to `REVOKE_PATH` to log that device out.
+    pub id: i64,
+
+    /// Whatever label the device gave itself at login, if any.
+    pub device_label: Option<String>,
+
+    /// When this session was created.
+    pub created_at: DateTime<Utc>,
+
+    /// When this session last refreshed or otherwise touched the server.
+    pub last_seen_at: DateTime<Utc>,
+
+    /// When this session expires if it's never refreshed again.
+    pub expires_at: DateTime<Utc>,
+
+    /// Whether this is the session the caller is currently authenticated as.
+    pub current: bool,
+}
+
+/// The caller's own live sessions.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ListResp {
+    /// Every non-revoked, unexpired session on the account, including the
+    /// one the caller is using right now.
+    pub sessions: Vec<Session>,
+}
+
+/// Revoke one of the caller's own sessions, logging that device out on its
+/// next request: the server checks the session behind every access token,
+/// so a revoked session's token stops working before it expires.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeReq {
+    /// The session to revoke. Must belong to the caller's own account.
+    pub id: i64,
+}
+
+/// Acknowledges that a session was revoked or logged out.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RevokeResp {}
+
+/// Where to list the caller's own sessions.
+pub const LIST_PATH: &str = "/api/v1/sessions";
+
+/// Where to revoke one of the caller's own sessions.
+pub const REVOKE_PATH: &str = "/api/v1/sessions/revoke";
+
+/// Where to revoke the session behind the caller's current access token,
+/// logging this device out.
+pub const LOGOUT_PATH: &str = "/api/v1/logout";
@@ -0,0 +1,20 @@
+use crate::Document;
+use serde::{Deserialize, Serialize};
+
+/// A document update pushed to a subscriber over the WebSocket opened at
+/// [`PATH`], sent as a JSON text frame. The server sends one of these
+/// immediately on connecting (the document as it stands right now), then
+/// one more each time another replica's push changes it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Update {
+    /// The document as it stood after the triggering push.
+    pub document: Document,
+}
+
+/// Where the document subscription WebSocket lives.
+pub static PATH: &str = "/api/v1/subscribe/:id";
+
+/// Construct a path given a document ID.
+pub fn path(id: i64) -> String {
+    PATH.replace(":id", &id.to_string())
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Result of enrolling in two-factor authentication.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnrollResp {
+    /// The secret, base32-encoded, for manual entry.
+    pub secret: String,
+
+    /// An `otpauth://` URI an authenticator app can scan to enroll the same
+    /// secret without typing it in.
+    pub uri: String,
+}
+
+/// Where to enroll in two-factor authentication. Requires an existing JWT,
+/// since you must already be logged in to turn it on.
+pub const ENROLL_PATH: &str = "/api/v1/totp/enroll";
@@ -0,0 +1,52 @@
+use crate::{Hlc, NodeId};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// The highest `(timestamp, counter)` we've observed so far from each node.
+/// Lets a peer ask for only the `Part`s it hasn't seen yet instead of an
+/// entire `Document`.
+pub type VersionVector = HashMap<NodeId, (DateTime<Utc>, u16)>;
+
+/// Record that we've observed `clock`, advancing the vector's entry for its
+/// node if `clock` is newer than what's already there.
+pub fn observe(vector: &mut VersionVector, clock: &Hlc) {
+    let watermark = (clock.timestamp(), clock.counter());
+
+    vector
+        .entry(clock.node())
+        .and_modify(|existing| {
+            if watermark > *existing {
+                *existing = watermark;
+            }
+        })
+        .or_insert(watermark);
+}
+
+/// Whether `vector` already covers `clock`, i.e. its node has a recorded
+/// watermark at or beyond `clock`'s own `(timestamp, counter)`.
+///
+/// A clock from a node absent from the vector is never covered. That's the
+/// safe default: because merges are commutative and idempotent, sending a
+/// `Part` the peer already has is wasteful but harmless, while skipping one
+/// they need would lose data.
+pub fn covers(vector: &VersionVector, clock: &Hlc) -> bool {
+    vector
+        .get(&clock.node())
+        .is_some_and(|&watermark| (clock.timestamp(), clock.counter()) <= watermark)
+}
+
+/// Fold `other` into `vector`, keeping the higher watermark per node. Used
+/// to advance a client's acknowledgment state with the vector a peer just
+/// returned, so later pushes and pulls don't resend what it already has.
+pub fn merge(vector: &mut VersionVector, other: &VersionVector) {
+    for (&node, &watermark) in other {
+        vector
+            .entry(node)
+            .and_modify(|existing| {
+                if watermark > *existing {
+                    *existing = watermark;
+                }
+            })
+            .or_insert(watermark);
+    }
+}
@@ -5,6 +5,9 @@ use serde::{Deserialize, Serialize};
 pub struct Resp {
     /// The email address of the currently logged-in user.
     pub email: String,
+
+    /// This replica's server-assigned node ID, for seeding its HLC clock.
+    pub node_id: i32,
 }
 
 /// Where the whoami endpoint lives.
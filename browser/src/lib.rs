@@ -31,8 +31,8 @@ pub fn main() {
 
     let now = Utc::now();
     replica.add_ping(now);
-    replica.tag_ping(now, Some("HI!".to_string()));
+    replica.add_tag(now, "HI!".to_string());
 
     alert(&replica.state().pings.contains(&now).to_string());
-    alert(replica.state().get_tag(&now).unwrap());
+    alert(replica.get_tags(&now).next().unwrap());
 }
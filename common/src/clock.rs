@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+
+/// A source of the current time. Scheduling and clock logic should read time
+/// through this instead of calling `Utc::now()` directly, so both can be
+/// driven deterministically in tests (see `MockClock`).
+pub trait Clock: Send + Sync {
+    /// The current time, according to this source.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real system clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
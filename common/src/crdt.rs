@@ -0,0 +1,44 @@
+use crate::merge::Merge;
+use std::cmp::Ordering;
+
+/// A `Merge` that's actually a join-semilattice: it has a bottom element (a
+/// value every other value dominates, so merging with it is a no-op) and
+/// its states can be compared by how much each has already incorporated of
+/// the other, not just folded together via `merge`. A caller that already
+/// knows `self.compare(other) == Some(Ordering::Greater)` can skip the
+/// merge entirely—`self` already dominates `other`.
+pub trait Crdt: Merge {
+    /// The lattice's least element. Merging anything with `bottom` returns
+    /// that thing unchanged.
+    #[must_use]
+    fn bottom() -> Self;
+
+    /// Compare two states in the lattice's partial order. `Some(Less)`
+    /// means `other` has (weakly) incorporated everything `self` has and
+    /// then some; `Some(Greater)` is the reverse; `Some(Equal)` means
+    /// they've converged. `None` means the two have diverged—concurrent
+    /// updates neither of which dominates the other—which is exactly the
+    /// case `merge` exists to resolve.
+    #[must_use]
+    fn compare(&self, other: &Self) -> Option<Ordering>;
+}
+
+/// Test that `compare` agrees with `merge`: whichever side `compare` says
+/// dominates is exactly what `merge` produces. When the two are concurrent
+/// (`compare` returns `None`), `merge` is still well-defined—it just isn't
+/// simply "return the dominating side", so there's nothing to check here.
+#[cfg(test)]
+pub fn test_compare_consistent_with_merge<T>(a: T, b: T)
+where
+    T: Crdt + Clone + PartialEq + std::fmt::Debug,
+{
+    let expected = match a.compare(&b) {
+        Some(Ordering::Less) => Some(b.clone()),
+        Some(Ordering::Equal | Ordering::Greater) => Some(a.clone()),
+        None => None,
+    };
+
+    if let Some(expected) = expected {
+        assert_eq!(a.merge(b), expected, "compare/merge disagreement");
+    }
+}
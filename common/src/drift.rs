@@ -0,0 +1,273 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+
+/// How many past filter states we keep around for introspection.
+const DIAGNOSTIC_STATES: usize = 5;
+
+/// How many past frequency estimates we keep around for introspection.
+const DIAGNOSTIC_FREQUENCIES: usize = 3;
+
+/// How many past applied corrections we keep around for introspection.
+const DIAGNOSTIC_CORRECTIONS: usize = 3;
+
+/// A snapshot of the filter's internal state at some point in time, kept
+/// around for diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterState {
+    /// The estimated offset of the peer's clock relative to ours, in
+    /// seconds (positive means the peer is ahead).
+    pub offset_seconds: f64,
+
+    /// The estimated fractional frequency error between the peer's clock and
+    /// ours (e.g. `1e-5` means the peer's clock runs about 10 microseconds
+    /// fast per second).
+    pub frequency_error: f64,
+}
+
+/// Rolling history the estimator keeps around so its behavior can be
+/// inspected (e.g. from a diagnostics page), rather than being a total
+/// black box.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    /// The last [`DIAGNOSTIC_STATES`] filter states, most recent last.
+    pub states: VecDeque<FilterState>,
+
+    /// The last [`DIAGNOSTIC_FREQUENCIES`] frequency estimates, most recent
+    /// last.
+    pub frequency_estimates: VecDeque<f64>,
+
+    /// The last [`DIAGNOSTIC_CORRECTIONS`] corrections applied to a raw
+    /// timestamp via [`DriftEstimator::correct`], most recent last.
+    pub applied_corrections: VecDeque<Duration>,
+}
+
+impl Diagnostics {
+    /// Record a new filter state, dropping the oldest if we're at capacity.
+    fn push_state(&mut self, state: FilterState) {
+        if self.states.len() == DIAGNOSTIC_STATES {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+
+        if self.frequency_estimates.len() == DIAGNOSTIC_FREQUENCIES {
+            self.frequency_estimates.pop_front();
+        }
+        self.frequency_estimates.push_back(state.frequency_error);
+    }
+
+    /// Record a correction applied to a raw timestamp, dropping the oldest if
+    /// we're at capacity.
+    fn push_correction(&mut self, correction: Duration) {
+        if self.applied_corrections.len() == DIAGNOSTIC_CORRECTIONS {
+            self.applied_corrections.pop_front();
+        }
+        self.applied_corrections.push_back(correction);
+    }
+}
+
+/// A one-dimensional Kalman filter over clock offset and frequency error,
+/// used to correct physical time stamped on new `Hlc`s against a fleet of
+/// peers whose system clocks may be skewed or drifting relative to ours.
+///
+/// Every merge with a peer yields a `(peer_timestamp, local_receive_timestamp)`
+/// sample; feed it in with [`DriftEstimator::observe`]. The filter predicts
+/// the offset forward from the last sample using the current frequency
+/// estimate, compares that prediction against what was actually observed,
+/// and nudges offset and frequency toward the observation by a Kalman gain
+/// derived from the process and measurement noise. Samples whose innovation
+/// (the gap between predicted and observed offset) exceeds
+/// [`DriftEstimator::innovation_gate_seconds`] are rejected outright, on the
+/// assumption that they're outliers caused by asymmetric network latency
+/// rather than real drift.
+pub struct DriftEstimator {
+    offset_seconds: f64,
+    frequency_error: f64,
+    covariance: [[f64; 2]; 2],
+    process_noise: [[f64; 2]; 2],
+    measurement_noise: f64,
+    innovation_gate_seconds: f64,
+    last_sample_at: Option<DateTime<Utc>>,
+    diagnostics: Diagnostics,
+}
+
+impl Default for DriftEstimator {
+    /// A filter tuned for machine clocks: a frequency error that barely
+    /// moves sample to sample, offset noise of about a millisecond, and a
+    /// 5-second innovation gate wide enough to tolerate ordinary network
+    /// jitter while still rejecting bad clocks.
+    fn default() -> Self {
+        Self::new(1e-6, 1e-12, 1e-3, 5.0)
+    }
+}
+
+impl DriftEstimator {
+    /// Create a new estimator.
+    ///
+    /// - `offset_process_noise` / `frequency_process_noise` describe how much
+    ///   we expect offset and frequency to wander between samples.
+    /// - `measurement_noise` is our uncertainty in a single observed offset
+    ///   (e.g. from network jitter).
+    /// - `innovation_gate_seconds` is the largest innovation we'll accept
+    ///   before treating a sample as an outlier and discarding it.
+    #[must_use]
+    pub fn new(
+        offset_process_noise: f64,
+        frequency_process_noise: f64,
+        measurement_noise: f64,
+        innovation_gate_seconds: f64,
+    ) -> Self {
+        Self {
+            offset_seconds: 0.0,
+            frequency_error: 0.0,
+            covariance: [[1.0, 0.0], [0.0, 1.0]],
+            process_noise: [[offset_process_noise, 0.0], [0.0, frequency_process_noise]],
+            measurement_noise,
+            innovation_gate_seconds,
+            last_sample_at: None,
+            diagnostics: Diagnostics::default(),
+        }
+    }
+
+    /// Feed in a sample gathered while merging with a peer: the time they
+    /// stamped on the message, and our own clock's reading at the moment we
+    /// received it. Returns `true` if the sample was accepted and used to
+    /// update the filter, `false` if its innovation exceeded the gate and it
+    /// was discarded as an outlier.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn observe(
+        &mut self,
+        peer_timestamp: DateTime<Utc>,
+        local_receive_timestamp: DateTime<Utc>,
+    ) -> bool {
+        let elapsed = self
+            .last_sample_at
+            .map_or(0.0, |last| {
+                (local_receive_timestamp - last).num_milliseconds() as f64 / 1000.0
+            })
+            .max(0.0);
+
+        // Predict: offset drifts forward at the current frequency estimate.
+        let predicted_offset = self.offset_seconds + self.frequency_error * elapsed;
+        let observed_offset =
+            (peer_timestamp - local_receive_timestamp).num_milliseconds() as f64 / 1000.0;
+        let innovation = observed_offset - predicted_offset;
+
+        if innovation.abs() > self.innovation_gate_seconds {
+            return false;
+        }
+
+        // Predicted covariance: P' = F P F^T + Q, with F = [[1, elapsed], [0, 1]].
+        let p = self.covariance;
+        let p00 = p[0][0] + elapsed * (p[1][0] + p[0][1]) + elapsed * elapsed * p[1][1]
+            + self.process_noise[0][0];
+        let p01 = p[0][1] + elapsed * p[1][1];
+        let p10 = p[1][0] + elapsed * p[1][1];
+        let p11 = p[1][1] + self.process_noise[1][1];
+
+        // Kalman gain for H = [1, 0]: K = P' H^T / (H P' H^T + R).
+        let innovation_covariance = p00 + self.measurement_noise;
+        let gain_offset = p00 / innovation_covariance;
+        let gain_frequency = p10 / innovation_covariance;
+
+        self.offset_seconds = predicted_offset + gain_offset * innovation;
+        self.frequency_error += gain_frequency * innovation;
+
+        // Updated covariance: P = (I - K H) P'.
+        self.covariance = [
+            [p00 * (1.0 - gain_offset), p01 * (1.0 - gain_offset)],
+            [p10 - gain_frequency * p00, p11 - gain_frequency * p01],
+        ];
+
+        self.last_sample_at = Some(local_receive_timestamp);
+        self.diagnostics.push_state(FilterState {
+            offset_seconds: self.offset_seconds,
+            frequency_error: self.frequency_error,
+        });
+
+        true
+    }
+
+    /// Apply the current offset estimate to a raw timestamp (e.g. fresh off
+    /// the system clock), and record the correction in the diagnostics.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn correct(&mut self, raw: DateTime<Utc>) -> DateTime<Utc> {
+        let correction = Duration::milliseconds((self.offset_seconds * 1000.0).round() as i64);
+        self.diagnostics.push_correction(correction);
+
+        raw + correction
+    }
+
+    /// The rolling history of filter states, frequency estimates, and
+    /// applied corrections, for introspection.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn converges_toward_a_consistent_offset() {
+        let mut estimator = DriftEstimator::default();
+
+        for i in 0..20 {
+            let local = at(i * 10);
+            let peer = local + Duration::milliseconds(250);
+            assert!(estimator.observe(peer, local));
+        }
+
+        assert!(
+            (estimator.offset_seconds - 0.25).abs() < 0.05,
+            "offset {} did not converge near 0.25s",
+            estimator.offset_seconds
+        );
+    }
+
+    #[test]
+    fn rejects_an_outlier_sample() {
+        let mut estimator = DriftEstimator::default();
+
+        for i in 0..10 {
+            let local = at(i * 10);
+            let peer = local + Duration::milliseconds(250);
+            assert!(estimator.observe(peer, local));
+        }
+
+        let local = at(100);
+        let outlier = local + Duration::seconds(30);
+        assert!(!estimator.observe(outlier, local));
+    }
+
+    #[test]
+    fn correct_applies_the_current_offset() {
+        let mut estimator = DriftEstimator::default();
+        estimator.observe(at(10) + Duration::milliseconds(500), at(10));
+
+        let raw = at(20);
+        let corrected = estimator.correct(raw);
+
+        assert!(corrected > raw);
+    }
+
+    #[test]
+    fn diagnostics_are_capped() {
+        let mut estimator = DriftEstimator::default();
+
+        for i in 0..(DIAGNOSTIC_STATES as i64 + 5) {
+            estimator.observe(at(i * 10) + Duration::milliseconds(100), at(i * 10));
+        }
+
+        assert_eq!(estimator.diagnostics().states.len(), DIAGNOSTIC_STATES);
+        assert_eq!(
+            estimator.diagnostics().frequency_estimates.len(),
+            DIAGNOSTIC_FREQUENCIES
+        );
+    }
+}
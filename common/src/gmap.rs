@@ -1,7 +1,9 @@
+use crate::crdt::Crdt;
 use crate::merge::Merge;
+use std::cmp::Ordering;
 use std::collections::{
     hash_map::{Drain, Entry, Iter},
-    HashMap,
+    HashMap, HashSet,
 };
 use std::hash::Hash;
 
@@ -56,9 +58,13 @@ where
 
 impl<K, V> Merge for GMap<K, V>
 where
-    K: Eq + Hash,
-    V: Merge,
+    K: Eq + Hash + Clone,
+    V: Merge + Clone + PartialEq,
 {
+    /// The entries whose value differs from what `since` has, as a `GMap` of
+    /// just those entries. A key `since` hasn't seen yet counts as changed.
+    type Delta = Self;
+
     fn merge(mut self, mut other: Self) -> Self {
         for (k, v) in other.drain() {
             self.insert(k, v)
@@ -66,6 +72,98 @@ where
 
         self
     }
+
+    fn split(&self, since: &Self) -> Self::Delta {
+        Self(
+            self.0
+                .iter()
+                .filter(|&(k, v)| since.get(k) != Some(v))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        )
+    }
+
+    fn merge_delta(self, delta: Self::Delta) -> Self {
+        self.merge(delta)
+    }
+}
+
+impl<K, V> GMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Merge + Clone + PartialEq + crate::sync::Clocked,
+{
+    /// The entries whose clock isn't already covered by `since`—the minimum
+    /// a peer needs to catch up, given only its compact
+    /// [`crate::sync::VersionVector`] rather than a full copy of this map
+    /// the way [`Merge::split`] requires.
+    pub fn delta(&self, since: &crate::sync::VersionVector) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|&(_, v)| !crate::sync::covers(since, v.clock()))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        )
+    }
+
+    /// This map's own [`crate::sync::VersionVector`]: the highest clock
+    /// observed per node across every entry. Send this back alongside a
+    /// `delta` so the peer knows what it can skip asking for next time.
+    #[must_use]
+    pub fn version_vector(&self) -> crate::sync::VersionVector {
+        let mut vector = crate::sync::VersionVector::new();
+
+        for v in self.0.values() {
+            crate::sync::observe(&mut vector, v.clock());
+        }
+
+        vector
+    }
+}
+
+impl<K, V> Crdt for GMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Crdt + Clone + PartialEq,
+{
+    fn bottom() -> Self {
+        Self::new()
+    }
+
+    /// Pointwise comparison over the union of both maps' keys, treating a
+    /// key missing from one side as that side's `V::bottom()`. The maps
+    /// agree only if every shared key's values agree; if some keys favor
+    /// `self` and others favor `other`, the two have diverged.
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        let keys: HashSet<&K> = self.0.keys().chain(other.0.keys()).collect();
+        let mut seen_less = false;
+        let mut seen_greater = false;
+
+        for key in keys {
+            let bottom = V::bottom();
+            let a = self.0.get(key).unwrap_or(&bottom);
+            let b = other.0.get(key).unwrap_or(&bottom);
+
+            match a.compare(b) {
+                Some(Ordering::Less) => seen_less = true,
+                Some(Ordering::Greater) => seen_greater = true,
+                Some(Ordering::Equal) => {}
+                None => return None,
+            }
+
+            if seen_less && seen_greater {
+                return None;
+            }
+        }
+
+        match (seen_less, seen_greater) {
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => Some(Ordering::Equal),
+            (true, true) => unreachable!(),
+        }
+    }
 }
 
 impl<K, V> Default for GMap<K, V>
@@ -216,6 +314,72 @@ mod test {
             ) {
                 crate::merge::test_associative(a, b, c);
             }
+
+            #[test]
+            fn split_merge_delta(a: GMap<u8, Lww<u8>>, b: GMap<u8, Lww<u8>>) {
+                crate::merge::test_split_merge_delta(a, b);
+            }
+
+            #[test]
+            fn compare_consistent_with_merge(a: GMap<u8, Lww<u8>>, b: GMap<u8, Lww<u8>>) {
+                crate::crdt::test_compare_consistent_with_merge(a, b);
+            }
+        }
+    }
+
+    mod delta {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[test]
+        fn omits_entries_the_peer_already_has() {
+            let mut map = GMap::<&str, Lww<u8>>::new();
+            map.insert("seen", Lww::new(1, Hlc::zero()));
+            map.insert("unseen", Lww::new(2, Hlc::zero().next()));
+
+            let vector = crate::sync::VersionVector::from([(
+                Hlc::zero().node().clone(),
+                Hlc::zero(),
+            )]);
+
+            let delta = map.delta(&vector);
+
+            assert_eq!(delta.get(&"seen"), None);
+            assert_eq!(delta.get(&"unseen").unwrap().value(), &2);
+        }
+
+        proptest! {
+            #[test]
+            fn merging_a_delta_converges_to_the_same_result_as_merging_full_state(
+                a: GMap<u8, Lww<u8>>,
+                b: GMap<u8, Lww<u8>>,
+            ) {
+                let delta = b.delta(&a.version_vector());
+
+                let via_delta = a.clone().merge(delta);
+                let via_full_state = a.merge(b);
+
+                prop_assert_eq!(via_delta, via_full_state);
+            }
+        }
+    }
+
+    mod split {
+        use super::*;
+
+        #[test]
+        fn omits_unchanged_entries() {
+            let mut since = GMap::<&str, Lww<u8>>::new();
+            since.insert("unchanged", Lww::new(1, Hlc::zero()));
+
+            let mut current = since.clone();
+            current.insert("new", Lww::new(2, Hlc::zero()));
+
+            let delta = current.split(&since);
+
+            assert_eq!(delta.get(&"unchanged"), None);
+            assert_eq!(delta.get(&"new").unwrap().value(), &2);
         }
     }
 }
@@ -45,12 +45,27 @@ impl<T: Ord> GSet<T> {
     }
 }
 
-impl<T: Ord> Merge for GSet<T> {
+impl<T: Ord + Clone> Merge for GSet<T> {
+    /// The items `since` doesn't have yet. A `GSet` is already the smallest
+    /// possible representation of "what's new," so the delta is just
+    /// another `GSet`.
+    type Delta = Self;
+
     fn merge(mut self, mut other: Self) -> Self {
         self.items.append(&mut other.items);
 
         self
     }
+
+    fn split(&self, since: &Self) -> Self::Delta {
+        Self {
+            items: self.items.difference(&since.items).cloned().collect(),
+        }
+    }
+
+    fn merge_delta(self, delta: Self::Delta) -> Self {
+        self.merge(delta)
+    }
 }
 
 impl<T> fmt::Debug for GSet<T>
@@ -100,6 +115,28 @@ mod test {
             fn test_associative(a: GSet<u8>, b: GSet<u8>, c: GSet<u8>) {
                 crate::merge::test_associative(a, b, c);
             }
+
+            #[test]
+            fn test_split_merge_delta(a: GSet<u8>, b: GSet<u8>) {
+                crate::merge::test_split_merge_delta(a, b);
+            }
+        }
+    }
+
+    mod split {
+        use super::*;
+
+        #[test]
+        fn only_sends_items_since_does_not_have() {
+            let mut since = GSet::new();
+            since.insert(1);
+
+            let mut current = since.clone();
+            current.insert(2);
+
+            let delta = current.split(&since);
+
+            assert_eq!(delta.items, BTreeSet::from([2]));
         }
     }
 }
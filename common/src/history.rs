@@ -0,0 +1,320 @@
+use crate::hlc::Hlc;
+use crate::op::Op;
+
+/// Identifies one user action. Every [`Revision`] recorded under the same
+/// group undoes and redoes as a unit, e.g. the several mutations an
+/// [`crate::replica::Replica::apply_patch`] call makes in one go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct UndoGroup(u64);
+
+/// One entry in a [`History`]: a forward op already applied to the state,
+/// paired with the op that reverses it. `active` says whether `forward`'s
+/// effect is the one currently reflected in state — `true` until `undo`,
+/// `false` until a following `redo` flips it back.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Revision {
+    /// Which user action this revision belongs to.
+    pub group: UndoGroup,
+
+    /// This revision's identity: the clock `forward` was stamped with when
+    /// it was first applied, i.e. the same `Hlc` that appears in the
+    /// [`crate::log::Log`] entry for it.
+    pub clock: Hlc,
+
+    /// The op that was actually applied to state.
+    pub forward: Op,
+
+    /// The op that reverses `forward`, to re-apply (at a fresh clock) on
+    /// undo.
+    pub inverse: Op,
+
+    /// Whether `forward` (`true`) or `inverse` (`false`) is the one
+    /// currently reflected in state.
+    pub active: bool,
+}
+
+/// The undo/redo history for a [`crate::replica::Replica`]: an ordered log
+/// of [`Revision`]s grouped by user action, ported from the idea behind
+/// xi-rope's edit engine. `undo`/`redo` don't rewind state in place —
+/// they toggle a revision's `active` flag and hand back the op
+/// ([`inverse`](Revision::inverse) or [`forward`](Revision::forward),
+/// respectively) the caller should apply at a fresh clock, so the
+/// resulting write merges across replicas exactly like any other one.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct History {
+    revisions: Vec<Revision>,
+    next_group: u64,
+}
+
+impl History {
+    /// An empty history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new undo group. Every [`Self::record`] call made under it
+    /// (before the next `new_group`) undoes and redoes together.
+    pub fn new_group(&mut self) -> UndoGroup {
+        let group = UndoGroup(self.next_group);
+        self.next_group += 1;
+
+        group
+    }
+
+    /// Record a forward op (already applied to state, stamped with `clock`)
+    /// and the inverse that undoes it, under `group`.
+    pub fn record(&mut self, group: UndoGroup, clock: Hlc, forward: Op, inverse: Op) {
+        self.revisions.push(Revision {
+            group,
+            clock,
+            forward,
+            inverse,
+            active: true,
+        });
+    }
+
+    /// The group `undo` would act on next: the most recent still-active
+    /// group, skipping back over any already-undone groups more recent
+    /// than it. `None` if nothing's been recorded yet, or everything
+    /// recorded so far is already undone.
+    #[must_use]
+    pub fn undoable(&self) -> Option<UndoGroup> {
+        self.revisions.iter().rev().find(|r| r.active).map(|r| r.group)
+    }
+
+    /// The group `redo` would act on next: the *oldest* group in the
+    /// contiguous run of already-undone groups at the end of the history.
+    /// Groups undo in most-recent-first order, so the oldest one in that
+    /// trailing run is the one undone last — the next one redo should
+    /// restore. `None` if there's nothing to redo, including when a new
+    /// action has been recorded since the last undo (which invalidates
+    /// the redo stack, same as most editors): that new action is active
+    /// and sits at the very end, so the trailing run is empty.
+    #[must_use]
+    pub fn redoable(&self) -> Option<UndoGroup> {
+        let mut group = None;
+
+        for revision in self.revisions.iter().rev() {
+            if revision.active {
+                break;
+            }
+
+            group = Some(revision.group);
+        }
+
+        group
+    }
+
+    /// Mark every still-active revision in `group` inactive, and return
+    /// their inverse ops, most-recently-recorded first — the order a
+    /// caller should apply them in to unwind the group.
+    pub fn undo(&mut self, group: UndoGroup) -> Vec<Op> {
+        self.revisions
+            .iter_mut()
+            .rev()
+            .filter(|r| r.group == group && r.active)
+            .map(|r| {
+                r.active = false;
+                r.inverse.clone()
+            })
+            .collect()
+    }
+
+    /// Mark every inactive revision in `group` active again, and return
+    /// their forward ops, in the order they were originally recorded.
+    pub fn redo(&mut self, group: UndoGroup) -> Vec<Op> {
+        self.revisions
+            .iter_mut()
+            .filter(|r| r.group == group && !r.active)
+            .map(|r| {
+                r.active = true;
+                r.forward.clone()
+            })
+            .collect()
+    }
+
+    /// Every revision recorded, oldest first, for displaying undo/redo
+    /// affordances or introspecting what's changed.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Revision> {
+        self.revisions.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::node_id::NodeId;
+    use chrono::Utc;
+
+    fn tag(tag: &str) -> Op {
+        Op::TagPing {
+            when: Utc::now(),
+            tag: tag.to_string(),
+        }
+    }
+
+    fn clock() -> Hlc {
+        Hlc::new(NodeId::random())
+    }
+
+    mod undo {
+        use super::*;
+
+        #[test]
+        fn returns_the_inverse_and_marks_the_revision_inactive() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+
+            assert_eq!(history.undo(group), vec![tag("b")]);
+            assert!(!history.revisions[0].active);
+        }
+
+        #[test]
+        fn unwinds_a_multi_revision_group_most_recent_first() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("was-a"));
+            history.record(group, clock(), tag("b"), tag("was-b"));
+
+            assert_eq!(history.undo(group), vec![tag("was-b"), tag("was-a")]);
+        }
+
+        #[test]
+        fn does_nothing_for_an_already_inactive_revision() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+            history.undo(group);
+
+            assert_eq!(history.undo(group), vec![]);
+        }
+    }
+
+    mod redo {
+        use super::*;
+
+        #[test]
+        fn returns_the_forward_op_and_marks_the_revision_active() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+            history.undo(group);
+
+            assert_eq!(history.redo(group), vec![tag("a")]);
+            assert!(history.revisions[0].active);
+        }
+    }
+
+    mod undoable {
+        use super::*;
+
+        #[test]
+        fn none_when_empty() {
+            assert_eq!(History::new().undoable(), None);
+        }
+
+        #[test]
+        fn the_most_recent_group_once_recorded() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+
+            assert_eq!(history.undoable(), Some(group));
+        }
+
+        #[test]
+        fn none_once_the_most_recent_group_is_undone() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+            history.undo(group);
+
+            assert_eq!(history.undoable(), None);
+        }
+
+        #[test]
+        fn the_older_group_once_the_most_recent_one_is_undone() {
+            let mut history = History::new();
+            let first = history.new_group();
+            history.record(first, clock(), tag("a"), tag("was-a"));
+            let second = history.new_group();
+            history.record(second, clock(), tag("b"), tag("was-b"));
+
+            history.undo(second);
+
+            assert_eq!(history.undoable(), Some(first));
+        }
+
+        #[test]
+        fn none_once_both_groups_are_undone() {
+            let mut history = History::new();
+            let first = history.new_group();
+            history.record(first, clock(), tag("a"), tag("was-a"));
+            let second = history.new_group();
+            history.record(second, clock(), tag("b"), tag("was-b"));
+
+            history.undo(second);
+            history.undo(first);
+
+            assert_eq!(history.undoable(), None);
+        }
+    }
+
+    mod redoable {
+        use super::*;
+
+        #[test]
+        fn none_until_something_is_undone() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+
+            assert_eq!(history.redoable(), None);
+        }
+
+        #[test]
+        fn the_undone_group_once_undone() {
+            let mut history = History::new();
+            let group = history.new_group();
+            history.record(group, clock(), tag("a"), tag("b"));
+            history.undo(group);
+
+            assert_eq!(history.redoable(), Some(group));
+        }
+
+        #[test]
+        fn none_once_a_new_action_is_recorded_after_an_undo() {
+            let mut history = History::new();
+            let first = history.new_group();
+            history.record(first, clock(), tag("a"), tag("b"));
+            history.undo(first);
+
+            let second = history.new_group();
+            history.record(second, clock(), tag("c"), tag("d"));
+
+            assert_eq!(history.redoable(), None);
+        }
+
+        #[test]
+        fn the_most_recently_undone_group_first_when_two_are_undone() {
+            let mut history = History::new();
+            let first = history.new_group();
+            history.record(first, clock(), tag("a"), tag("was-a"));
+            let second = history.new_group();
+            history.record(second, clock(), tag("b"), tag("was-b"));
+
+            // Undo order is most-recent-first, so `second` is undone
+            // before `first` — meaning `first` was undone *last*, and
+            // should be the first one redo restores.
+            history.undo(second);
+            history.undo(first);
+
+            assert_eq!(history.redoable(), Some(first));
+
+            history.redo(first);
+
+            assert_eq!(history.redoable(), Some(second));
+        }
+    }
+}
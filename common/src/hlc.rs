@@ -2,7 +2,7 @@ use crate::node_id::NodeId;
 use chrono::{DateTime, Utc};
 use std::fmt::Display;
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Hlc {
     timestamp: DateTime<Utc>,
     counter: u64,
@@ -18,7 +18,9 @@ impl Hlc {
         }
     }
 
-    #[cfg(test)]
+    /// Create a new clock stamped with a specific time, rather than reaching
+    /// for `Utc::now()`. Used when a `Clock` is injected (e.g. by `Replica`)
+    /// so construction stays deterministic in tests.
     pub fn new_at(node: NodeId, timestamp: DateTime<Utc>) -> Self {
         Self {
             timestamp,
@@ -27,6 +29,31 @@ impl Hlc {
         }
     }
 
+    /// A fixed, deterministic clock lower than any clock stamped with a real
+    /// timestamp—useful as a baseline that's always safe to overwrite (see
+    /// `State::new`'s `minutes_per_ping`) and for building test fixtures
+    /// without each call site picking its own arbitrary starting point.
+    pub fn zero() -> Self {
+        use chrono::TimeZone;
+
+        Self::new_at(NodeId::min(), Utc.timestamp_opt(0, 0).unwrap())
+    }
+
+    /// The physical timestamp currently stamped on this clock.
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+
+    /// The logical counter disambiguating clocks that share a timestamp.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// The node that produced this clock reading.
+    pub fn node(&self) -> &NodeId {
+        &self.node
+    }
+
     pub fn increment_at(&mut self, now: DateTime<Utc>) {
         if now > self.timestamp {
             self.timestamp = now;
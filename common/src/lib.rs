@@ -11,6 +11,20 @@
 
 //! Common code across all beeps clients (TUI, WASM in the browser)
 
+/// An injectable source of the current time.
+pub mod clock;
+pub use clock::{Clock, SystemClock};
+
+/// A `Merge` with a bottom element and a partial-order `compare`, so a
+/// caller can tell whether two states have actually diverged before paying
+/// for a full merge.
+pub mod crdt;
+pub use crdt::Crdt;
+
+/// A Kalman-filter estimator for correcting physical clock drift against peers.
+pub mod drift;
+pub use drift::DriftEstimator;
+
 /// A grow-only map (G-Map.) Values must be mergeable.
 pub mod gmap;
 pub use gmap::GMap;
@@ -23,10 +37,23 @@ pub use gset::GSet;
 pub mod hlc;
 pub use hlc::Hlc;
 
+/// Undo/redo over a replica's [`Op`]s, grouped by user action.
+pub mod history;
+pub use history::History;
+
+/// The ordered history of ops a replica has applied, for op-based sync.
+pub mod log;
+pub use log::Log;
+
 /// A Last-Write-Wins (LWW) register.
 pub mod lww;
 pub use lww::Lww;
 
+/// A map whose values merge by last-writer-wins and can be removed, unlike
+/// the grow-only [`GMap`].
+pub mod lww_map;
+pub use lww_map::LwwMap;
+
 /// The interface all CRDTs must implement to merge.
 pub mod merge;
 
@@ -34,6 +61,18 @@ pub mod merge;
 pub mod node_id;
 pub use node_id::NodeId;
 
+/// An observed-remove set (OR-Set), for values that need to support removal.
+pub mod or_set;
+pub use or_set::OrSet;
+
+/// A single mutation to a `State`, as recorded in a `Log`.
+pub mod op;
+pub use op::Op;
+
+/// Apply RFC 6902 JSON Patch / RFC 7386 JSON Merge Patch documents to a
+/// `Replica` as clock-stamped CRDT mutations.
+pub mod patch;
+
 /// A replica (that is, state + node ID)
 pub mod replica;
 pub use replica::Replica;
@@ -46,5 +85,8 @@ pub use state::State;
 pub mod scheduler;
 pub use scheduler::Scheduler;
 
+/// Version vectors for exchanging only the ops a peer is missing.
+pub mod sync;
+
 #[cfg(test)]
 mod test;
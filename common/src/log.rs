@@ -1,41 +1,46 @@
-use std::collections::HashMap;
-
 use crate::hlc::Hlc;
+use crate::node_id::NodeId;
 use crate::op::Op;
 use serde::{Deserialize, Serialize};
 
+/// A single [`Op`], stamped with the [`Hlc`] it was applied under.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TimestampedOp {
+    /// When (and by which replica) this op was applied.
     pub timestamp: Hlc,
+    /// What happened.
     pub op: Op,
 }
 
+/// Ways appending to a [`Log`] can fail.
 #[derive(thiserror::Error, Debug, PartialEq, Eq)]
 pub enum Error {
+    /// The new op is older than the last op logged for its node, which would
+    /// break the per-node ordering a [`crate::sync::VersionVector`] relies on.
     #[error("new operation was before last existing operation")]
     OrderingViolation,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// The ordered history of every [`Op`] a replica has applied, whether typed
+/// locally or received from a peer. Ops from a single node always appear in
+/// increasing [`Hlc`] order; [`Log::push`] is the only way to add one, and
+/// enforces that.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Log {
     ops: Vec<TimestampedOp>,
-
-    #[serde(default)]
-    node: i64,
 }
 
 impl Log {
-    pub fn from_ops(ops: Vec<TimestampedOp>, node: i64) -> Self {
-        Self { ops, node }
-    }
-
-    #[deprecated(note = "use from_ops and then checked pushes")]
-    pub fn push_unchecked(&mut self, op: TimestampedOp) {
-        self.ops.push(op);
+    /// Wrap a list of ops already known to be in order, e.g. the tail of a
+    /// [`crate::replica::Snapshot`], without re-checking it.
+    pub fn from_ops(ops: Vec<TimestampedOp>) -> Self {
+        Self { ops }
     }
 
+    /// Append `op`, rejecting it if it's older than the last op logged for
+    /// the same node.
     pub fn push(&mut self, op: TimestampedOp) -> Result<(), Error> {
-        if let Some(last_op) = self.latest_for_node(op.timestamp.node) {
+        if let Some(last_op) = self.latest_for_node(op.timestamp.node()) {
             if last_op.timestamp > op.timestamp {
                 return Err(Error::OrderingViolation);
             }
@@ -46,65 +51,50 @@ impl Log {
         Ok(())
     }
 
-    fn latest_for_node(&self, node: i64) -> Option<&TimestampedOp> {
-        self.ops.iter().rev().find(|op| op.timestamp.node == node)
+    /// The most recent op logged for `node`, if any.
+    fn latest_for_node(&self, node: &NodeId) -> Option<&TimestampedOp> {
+        self.ops.iter().rev().find(|op| op.timestamp.node() == node)
     }
 
+    /// Every op in this log, oldest first.
     pub fn ops(&self) -> &Vec<TimestampedOp> {
         &self.ops
     }
 
+    /// How many ops are logged.
     pub fn len(&self) -> usize {
         self.ops.len()
     }
 
+    /// Whether the log has no ops at all.
     pub fn is_empty(&self) -> bool {
         self.ops.is_empty()
     }
-
-    #[tracing::instrument(skip(self))]
-    pub fn latest_for_each_node(&self) -> HashMap<i64, Hlc> {
-        let mut latest_ops: HashMap<i64, Hlc> = HashMap::with_capacity(8);
-
-        for op in &self.ops {
-            let node = op.timestamp.node;
-
-            let latest = latest_ops
-                .entry(node)
-                .or_insert_with(|| op.timestamp.clone());
-            if *latest < op.timestamp {
-                *latest = op.timestamp.clone();
-            }
-        }
-
-        latest_ops
-    }
-
-    pub fn node(&self) -> i64 {
-        self.node
-    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use chrono::{Duration, Utc};
+    use chrono::{DateTime, Duration, Utc};
+
+    fn tagged_at(node: NodeId, timestamp: DateTime<Utc>) -> TimestampedOp {
+        TimestampedOp {
+            timestamp: Hlc::new_at(node, timestamp),
+            op: Op::TagPing {
+                when: Utc::now(),
+                tag: "tag".to_string(),
+            },
+        }
+    }
 
     mod push {
-
         use super::*;
 
         #[test]
         fn pushes_first_op() {
             let mut log = Log::default();
 
-            let op = TimestampedOp {
-                timestamp: Hlc::new(1),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
+            let op = tagged_at(NodeId::random(), Utc::now());
 
             assert!(log.push(op).is_ok());
             assert_eq!(log.ops.len(), 1);
@@ -113,23 +103,11 @@ mod test {
         #[test]
         fn rejects_out_of_order_pushes() {
             let mut log = Log::default();
+            let node = NodeId::random();
 
             let ts1 = Utc::now();
-            let op1 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts1),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            let op2 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts1 - Duration::seconds(1)),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
+            let op1 = tagged_at(node.clone(), ts1);
+            let op2 = tagged_at(node, ts1 - Duration::seconds(1));
 
             assert!(log.push(op1).is_ok());
             assert_eq!(log.push(op2).unwrap_err(), Error::OrderingViolation);
@@ -140,101 +118,11 @@ mod test {
             let mut log = Log::default();
 
             let ts1 = Utc::now();
-            let op1 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts1),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            let op2 = TimestampedOp {
-                timestamp: Hlc::new_at(2, ts1 - Duration::seconds(1)),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
+            let op1 = tagged_at(NodeId::min(), ts1);
+            let op2 = tagged_at(NodeId::max(), ts1 - Duration::seconds(1));
 
             assert!(log.push(op1).is_ok());
             assert!(log.push(op2).is_ok());
         }
     }
-
-    mod latest_for_each_node {
-        use super::*;
-
-        #[test]
-        fn returns_latest_op_for_single_node() {
-            let mut log = Log::default();
-
-            let ts1 = Utc::now();
-            let ts2 = ts1 + Duration::seconds(1);
-
-            let op1 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts1),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            let op2 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts2),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            log.push(op1).unwrap();
-            log.push(op2).unwrap();
-
-            let latest = log.latest_for_each_node();
-
-            assert_eq!(latest.get(&1).unwrap(), &Hlc::new_at(1, ts2));
-        }
-
-        #[test]
-        fn returns_latest_op_for_multiple_nodes() {
-            let mut log = Log::default();
-
-            let ts1 = Utc::now();
-            let ts2 = ts1 + Duration::seconds(1);
-            let ts3 = ts1 + Duration::seconds(1);
-
-            let op1 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts1),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            let op2 = TimestampedOp {
-                timestamp: Hlc::new_at(2, ts2),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            let op3 = TimestampedOp {
-                timestamp: Hlc::new_at(1, ts3),
-                op: Op::SetTag {
-                    when: Utc::now(),
-                    tag: "tag".to_string(),
-                },
-            };
-
-            log.push(op1).unwrap();
-            log.push(op2).unwrap();
-            log.push(op3).unwrap();
-
-            let latest = log.latest_for_each_node();
-
-            assert_eq!(latest.get(&1).unwrap(), &Hlc::new_at(1, ts3));
-            assert_eq!(latest.get(&2).unwrap(), &Hlc::new_at(2, ts2));
-        }
-    }
 }
@@ -1,5 +1,6 @@
-use crate::{hlc::Hlc, merge::Merge};
+use crate::{crdt::Crdt, hlc::Hlc, merge::Merge};
 use core::fmt::{self, Debug, Formatter};
+use std::cmp::Ordering;
 
 #[derive(PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
@@ -33,6 +34,10 @@ impl<T> Merge for Lww<T>
 where
     T: Clone,
 {
+    /// `None` means the register hasn't changed since `since`'s clock, so
+    /// there's nothing worth sending.
+    type Delta = Option<Self>;
+
     fn merge(self, other: Self) -> Self {
         if other.clock > self.clock {
             other
@@ -40,6 +45,32 @@ where
             self
         }
     }
+
+    fn split(&self, since: &Self) -> Self::Delta {
+        (self.clock > since.clock).then(|| self.clone())
+    }
+
+    fn merge_delta(self, delta: Self::Delta) -> Self {
+        match delta {
+            Some(other) => self.merge(other),
+            None => self,
+        }
+    }
+}
+
+impl<T> Crdt for Lww<T>
+where
+    T: Clone + PartialEq + Default,
+{
+    /// The register that was never written: the default value, stamped with
+    /// a clock nothing real can predate.
+    fn bottom() -> Self {
+        Self::new(T::default(), Hlc::zero())
+    }
+
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        Some(self.clock().cmp(other.clock()))
+    }
 }
 
 impl<T: Debug> Debug for Lww<T> {
@@ -98,5 +129,37 @@ mod test {
         fn merge_idempotent(a: Lww<bool>) {
             crate::merge::test_idempotent(a)
         }
+
+        #[test]
+        fn split_merge_delta(a: Lww<bool>, b: Lww<bool>) {
+            crate::merge::test_split_merge_delta(a, b)
+        }
+
+        #[test]
+        fn compare_consistent_with_merge(a: Lww<bool>, b: Lww<bool>) {
+            crate::crdt::test_compare_consistent_with_merge(a, b)
+        }
+    }
+
+    mod split {
+        use super::*;
+
+        #[test]
+        fn nothing_to_send_if_not_newer_than_since() {
+            let clock = Hlc::zero();
+            let since = Lww::new(1, clock.clone());
+            let current = Lww::new(2, clock);
+
+            assert_eq!(current.split(&since), None);
+        }
+
+        #[test]
+        fn sends_the_register_if_newer_than_since() {
+            let clock = Hlc::zero();
+            let since = Lww::new(1, clock.clone());
+            let current = Lww::new(2, clock.next());
+
+            assert_eq!(current.split(&since), Some(current));
+        }
     }
 }
@@ -1,13 +1,27 @@
+use crate::crdt::Crdt;
+use crate::hlc::Hlc;
 use crate::lww::Lww;
 use crate::merge::Merge;
+use std::cmp::Ordering;
 use std::collections::{
     hash_map::{Drain, Entry, Iter},
-    HashMap,
+    HashMap, HashSet,
 };
 use std::hash::Hash;
 
-pub struct LwwMap<K, V> {
-    inner: HashMap<K, Lww<V>>,
+/// A map whose values are each an LWW register, so a concurrent write to the
+/// same key resolves by last-writer-wins rather than one replica's update
+/// silently clobbering the other's.
+///
+/// Removal is itself a write: a removed key's value becomes `Lww<None>`
+/// rather than being dropped from the map, so the removal has a clock of
+/// its own and can out-race (or lose to) a concurrent `insert` the same way
+/// any other `Lww` conflict does. `get` and `iter` hide these tombstones;
+/// use `iter_with_tombstones` when a caller (e.g. sync) needs to see them.
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct LwwMap<K: Eq + Hash, V> {
+    /// Each key's LWW register, `None` meaning tombstoned.
+    inner: HashMap<K, Lww<Option<V>>>,
 }
 
 impl<K, V> LwwMap<K, V>
@@ -15,17 +29,37 @@ where
     K: Eq + Hash,
     V: Clone,
 {
+    /// Creates an empty `LwwMap`.
     pub fn new() -> Self {
         Self {
             inner: HashMap::new(),
         }
     }
 
-    pub fn get(&self, key: &K) -> Option<&Lww<V>> {
-        self.inner.get(key)
+    /// Gets the value at `key`, or `None` if it's absent or tombstoned.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.inner.get(key).and_then(|lww| lww.value().as_ref())
     }
 
-    pub fn insert(&mut self, key: K, value: Lww<V>) {
+    /// Sets `key` to `value` at `clock`, merging by last-writer-wins with
+    /// whatever's already there. A newer `remove` still wins over this if
+    /// it raced it.
+    pub fn insert(&mut self, key: K, value: V, clock: Hlc) {
+        self.merge_in(key, Lww::new(Some(value), clock));
+    }
+
+    /// Removes `key` as of `clock`, by overwriting it with a tombstone
+    /// rather than deleting the entry outright, so the removal itself
+    /// merges by last-writer-wins and propagates to peers instead of being
+    /// lost to a concurrent `insert` that hasn't seen it yet.
+    pub fn remove(&mut self, key: K, clock: Hlc) {
+        self.merge_in(key, Lww::new(None, clock));
+    }
+
+    /// Merges `value` into whatever's stored at `key`, inserting it fresh if
+    /// there's nothing there yet. Shared by `insert` and `remove`, which
+    /// only differ in whether they're writing `Some` or `None`.
+    fn merge_in(&mut self, key: K, value: Lww<Option<V>>) {
         match self.inner.entry(key) {
             Entry::Occupied(entry) => {
                 let (key, current) = entry.remove_entry();
@@ -38,37 +72,163 @@ where
         };
     }
 
-    pub fn iter(&self) -> Iter<'_, K, Lww<V>> {
+    /// An iterator over the present (non-tombstoned) entries.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.inner
+            .iter()
+            .filter_map(|(k, lww)| lww.value().as_ref().map(|v| (k, v)))
+    }
+
+    /// All entries, including tombstoned (removed) ones, so a sync exchange
+    /// can ship a removal to a peer instead of it just disappearing.
+    pub(crate) fn iter_with_tombstones(&self) -> Iter<'_, K, Lww<Option<V>>> {
         self.inner.iter()
     }
 
-    /// Private because we can't remove properties from the map. It behaves like
-    /// a G-Set. We will need it to merge, though!
-    fn drain(&mut self) -> Drain<'_, K, Lww<V>> {
+    /// Private because it yields tombstones too; only `merge` should see
+    /// those directly.
+    fn drain(&mut self) -> Drain<'_, K, Lww<Option<V>>> {
         self.inner.drain()
     }
 
+    /// Returns `true` if the map has no entries, including tombstones.
     pub fn is_empty(&self) -> bool {
         self.inner.is_empty()
     }
 
+    /// Returns the number of entries in the map, including tombstones. Use
+    /// `iter().count()` to count only present entries.
     pub fn len(&self) -> usize {
         self.inner.len()
     }
+
+    /// Drops any tombstone strictly older than `stable`, reclaiming the
+    /// memory a never-ending history of removals would otherwise leak.
+    /// `stable` must be a clock every replica is guaranteed to have already
+    /// observed the deletion at or past (e.g. the minimum of the per-node
+    /// high-water marks in a version vector)—otherwise a tombstone we throw
+    /// away here could still have lost to a concurrent insert that hasn't
+    /// reached us yet, and we'd resurrect a key that should stay deleted.
+    /// Live values are never touched, regardless of how old their clock is.
+    pub fn collect_garbage(&mut self, stable: &Hlc) {
+        self.inner
+            .retain(|_, lww| lww.value().is_some() || lww.clock() >= stable);
+    }
 }
 
 impl<K, V> Merge for LwwMap<K, V>
 where
-    K: Eq + Hash,
-    V: Clone,
+    K: Eq + Hash + Clone,
+    V: Clone + PartialEq,
 {
+    /// The entries whose `Lww` differs from what `since` has, as an
+    /// `LwwMap` of just those entries—tombstones included, since a removal
+    /// the peer hasn't seen yet is exactly the kind of change `split` needs
+    /// to carry.
+    type Delta = Self;
+
     fn merge(mut self, mut other: Self) -> Self {
         for (k, v) in other.drain() {
-            self.insert(k, v)
+            self.merge_in(k, v);
         }
 
         self
     }
+
+    fn split(&self, since: &Self) -> Self::Delta {
+        Self {
+            inner: self
+                .inner
+                .iter()
+                .filter(|&(k, v)| since.inner.get(k) != Some(v))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    fn merge_delta(self, delta: Self::Delta) -> Self {
+        self.merge(delta)
+    }
+}
+
+impl<K, V> LwwMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + PartialEq,
+{
+    /// The entries (tombstones included) whose clock isn't already covered
+    /// by `since`—the minimum a peer needs to catch up, given only its
+    /// compact [`crate::sync::VersionVector`] rather than a full copy of
+    /// this map the way [`Merge::split`] requires.
+    pub fn delta(&self, since: &crate::sync::VersionVector) -> Self {
+        Self {
+            inner: self
+                .inner
+                .iter()
+                .filter(|&(_, lww)| !crate::sync::covers(since, lww.clock()))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    /// This map's own [`crate::sync::VersionVector`]: the highest clock
+    /// observed per node across every entry, tombstones included. Send this
+    /// back alongside a `delta` so the peer knows what it can skip asking
+    /// for next time.
+    #[must_use]
+    pub fn version_vector(&self) -> crate::sync::VersionVector {
+        let mut vector = crate::sync::VersionVector::new();
+
+        for lww in self.inner.values() {
+            crate::sync::observe(&mut vector, lww.clock());
+        }
+
+        vector
+    }
+}
+
+impl<K, V> Crdt for LwwMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + PartialEq,
+{
+    fn bottom() -> Self {
+        Self::new()
+    }
+
+    /// Pointwise comparison over the union of both maps' keys (tombstones
+    /// included), treating a key missing from one side as that side's
+    /// `Lww::bottom()`—an absent key and a never-written one look the same
+    /// to the lattice.
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        let keys: HashSet<&K> = self.inner.keys().chain(other.inner.keys()).collect();
+        let mut seen_less = false;
+        let mut seen_greater = false;
+
+        for key in keys {
+            let bottom = Lww::bottom();
+            let a = self.inner.get(key).unwrap_or(&bottom);
+            let b = other.inner.get(key).unwrap_or(&bottom);
+
+            match a.compare(b) {
+                Some(Ordering::Less) => seen_less = true,
+                Some(Ordering::Greater) => seen_greater = true,
+                Some(Ordering::Equal) => {}
+                None => return None,
+            }
+
+            if seen_less && seen_greater {
+                return None;
+            }
+        }
+
+        match (seen_less, seen_greater) {
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => Some(Ordering::Equal),
+            (true, true) => unreachable!(),
+        }
+    }
 }
 
 impl<K, V> Default for LwwMap<K, V>
@@ -81,11 +241,44 @@ where
     }
 }
 
+impl<K, V> Clone for LwwMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> std::fmt::Debug for LwwMap<K, V>
+where
+    K: Eq + Hash + std::fmt::Debug,
+    V: Clone + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LwwMap")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<K, V> PartialEq for LwwMap<K, V>
+where
+    K: Eq + Hash,
+    V: Clone + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::hlc::Hlc;
-    use uuid::Uuid;
+    use crate::node_id::NodeId;
 
     mod get {
         use super::*;
@@ -108,9 +301,9 @@ mod test {
         #[test]
         fn can_insert_from_nothing() {
             let mut map = LwwMap::<&str, i32>::new();
-            map.insert("test", Lww::new(1, Hlc::new(Uuid::nil())));
+            map.insert("test", 1, Hlc::new(NodeId::min()));
 
-            assert_eq!(map.get(&"test").unwrap().value(), &1);
+            assert_eq!(map.get(&"test"), Some(&1));
         }
 
         proptest! {
@@ -120,15 +313,70 @@ mod test {
                 c2 in clock(),
             ) {
                 let mut map = LwwMap::<&str, &str>::new();
-                let lww1 = Lww::new("c1", c1.clone());
-                let lww2 = Lww::new("c2", c2.clone());
 
-                map.insert("test", lww1.clone());
-                map.insert("test", lww2.clone());
+                map.insert("test", "c1", c1.clone());
+                map.insert("test", "c2", c2.clone());
+
+                let result = map.get(&"test");
+
+                prop_assert_eq!(result, if c2 > c1 { Some(&"c2") } else { Some(&"c1") });
+            }
+        }
+    }
+
+    mod remove {
+        use crate::test_utils::clock;
+        use proptest::proptest;
+
+        use super::*;
+
+        #[test]
+        fn removes_an_existing_key() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+
+            map.insert("test", 1, clock.clone());
+            map.remove("test", clock.next());
 
-                let result = map.get(&"test").unwrap();
+            assert_eq!(map.get(&"test"), None);
+        }
+
+        #[test]
+        fn an_older_remove_loses_to_a_newer_insert() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+
+            map.remove("test", clock.clone());
+            map.insert("test", 1, clock.next());
+
+            assert_eq!(map.get(&"test"), Some(&1));
+        }
+
+        #[test]
+        fn hides_from_iter_but_not_iter_with_tombstones() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+
+            map.insert("test", 1, clock.clone());
+            map.remove("test", clock.next());
+
+            assert_eq!(map.iter().count(), 0);
+            assert_eq!(map.iter_with_tombstones().count(), 1);
+        }
 
-                prop_assert_eq!(result, &lww1.merge(lww2));
+        proptest! {
+            #[test]
+            fn remove_follows_lww_rules_against_concurrent_insert(
+                c1 in clock(),
+                c2 in clock(),
+            ) {
+                let mut map = LwwMap::<&str, i32>::new();
+
+                map.insert("test", 1, c1.clone());
+                map.remove("test", c2.clone());
+
+                let expected = if c2 > c1 { None } else { Some(&1) };
+                prop_assert_eq!(map.get(&"test"), expected);
             }
         }
     }
@@ -152,38 +400,243 @@ mod test {
         #[test]
         fn retains_all_keys() {
             let mut map1 = LwwMap::<&str, i32>::new();
-            map1.insert("foo", Lww::new(1, Hlc::new(Uuid::nil())));
+            map1.insert("foo", 1, Hlc::new(NodeId::min()));
 
             let mut map2 = LwwMap::<&str, i32>::new();
-            map2.insert("bar", Lww::new(2, Hlc::new(Uuid::nil())));
+            map2.insert("bar", 2, Hlc::new(NodeId::min()));
 
             let merged = map1.merge(map2);
 
-            assert_eq!(merged.get(&"foo").unwrap().value(), &1);
-            assert_eq!(merged.get(&"bar").unwrap().value(), &2);
+            assert_eq!(merged.get(&"foo"), Some(&1));
+            assert_eq!(merged.get(&"bar"), Some(&2));
+        }
+
+        #[test]
+        fn a_removal_on_one_side_propagates() {
+            let clock = Hlc::zero();
+
+            let mut map1 = LwwMap::<&str, i32>::new();
+            map1.insert("foo", 1, clock.clone());
+
+            let mut map2 = LwwMap::<&str, i32>::new();
+            map2.insert("foo", 1, clock.clone());
+            map2.remove("foo", clock.next());
+
+            let merged = map1.merge(map2);
+
+            assert_eq!(merged.get(&"foo"), None);
         }
 
         proptest! {
             #[test]
-            fn merges_according_to_merge_semantics_of_value(
+            fn merges_according_to_lww_semantics(
                 c1 in clock(),
                 c2 in clock(),
             ) {
                 let mut map1 = LwwMap::<&str, &str>::new();
-                let lww1 = Lww::new("c1", c1.clone());
-                map1.insert("test", lww1.clone());
+                map1.insert("test", "c1", c1.clone());
 
                 let mut map2 = LwwMap::<&str, &str>::new();
-                let lww2 = Lww::new("c2", c2.clone());
-                map2.insert("test", lww2.clone());
+                map2.insert("test", "c2", c2.clone());
+
+                let merged = map1.merge(map2);
+
+                let expected = if c2 > c1 { Some(&"c2") } else { Some(&"c1") };
+                prop_assert_eq!(merged.get(&"test"), expected);
+            }
+
+            #[test]
+            fn deletion_and_resurrection_converge_regardless_of_merge_order(
+                c1 in clock(),
+                c2 in clock(),
+            ) {
+                // One side inserts, the other removes, at two (possibly
+                // concurrent, possibly ordered) clocks. Whichever clock wins
+                // shouldn't depend on which side we merge into which.
+                let mut inserted = LwwMap::<&str, i32>::new();
+                inserted.insert("test", 1, c1.clone());
+
+                let mut removed = LwwMap::<&str, i32>::new();
+                removed.remove("test", c2.clone());
+
+                let merged_one_way = inserted.clone().merge(removed.clone());
+                let merged_other_way = removed.merge(inserted);
+
+                prop_assert_eq!(&merged_one_way, &merged_other_way);
+
+                let expected = if c2 > c1 { None } else { Some(&1) };
+                prop_assert_eq!(merged_one_way.get(&"test"), expected);
+            }
+
+            #[test]
+            fn merge_idempotent(a: LwwMap<u8, u8>) {
+                crate::merge::test_idempotent(a);
+            }
+
+            #[test]
+            fn merge_commutative(a: LwwMap<u8, u8>, b: LwwMap<u8, u8>) {
+                crate::merge::test_commutative(a, b);
+            }
+
+            #[test]
+            fn merge_associative(
+                a: LwwMap<u8, u8>,
+                b: LwwMap<u8, u8>,
+                c: LwwMap<u8, u8>,
+            ) {
+                crate::merge::test_associative(a, b, c);
+            }
+
+            #[test]
+            fn split_merge_delta(a: LwwMap<u8, u8>, b: LwwMap<u8, u8>) {
+                crate::merge::test_split_merge_delta(a, b);
+            }
+
+            #[test]
+            fn compare_consistent_with_merge(a: LwwMap<u8, u8>, b: LwwMap<u8, u8>) {
+                crate::crdt::test_compare_consistent_with_merge(a, b);
+            }
+        }
+    }
+
+    mod delta {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[test]
+        fn omits_entries_the_peer_already_has() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+            map.insert("seen", 1, clock.clone());
+            map.insert("unseen", 2, clock.next());
+
+            let vector = crate::sync::VersionVector::from([(clock.node().clone(), clock)]);
+
+            let delta = map.delta(&vector);
+
+            assert_eq!(delta.get(&"seen"), None);
+            assert_eq!(delta.get(&"unseen"), Some(&2));
+        }
+
+        #[test]
+        fn includes_tombstones_the_peer_hasnt_seen() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+            map.insert("test", 1, clock.clone());
+            map.remove("test", clock.next());
+
+            let delta = map.delta(&crate::sync::VersionVector::new());
 
-                let merged_lww = lww1.merge(lww2);
-                let merged_map = map1.merge(map2);
+            assert_eq!(delta.iter_with_tombstones().count(), 1);
+        }
 
-                let result = merged_map.get(&"test").unwrap();
+        proptest! {
+            #[test]
+            fn merging_a_delta_converges_to_the_same_result_as_merging_full_state(
+                a: LwwMap<u8, u8>,
+                b: LwwMap<u8, u8>,
+            ) {
+                let delta = b.delta(&a.version_vector());
 
-                prop_assert_eq!(result, &merged_lww);
+                let via_delta = a.clone().merge(delta);
+                let via_full_state = a.merge(b);
+
+                prop_assert_eq!(via_delta, via_full_state);
             }
         }
     }
+
+    mod split {
+        use super::*;
+
+        #[test]
+        fn omits_unchanged_entries() {
+            let mut since = LwwMap::<&str, i32>::new();
+            since.insert("unchanged", 1, Hlc::new(NodeId::min()));
+
+            let mut current = since.clone();
+            current.insert("new", 2, Hlc::new(NodeId::min()).next());
+
+            let delta = current.split(&since);
+
+            assert_eq!(delta.get(&"unchanged"), None);
+            assert_eq!(delta.get(&"new"), Some(&2));
+        }
+
+        #[test]
+        fn includes_tombstones_the_peer_hasnt_seen() {
+            let mut since = LwwMap::<&str, i32>::new();
+            since.insert("test", 1, Hlc::new(NodeId::min()));
+
+            let mut current = since.clone();
+            current.remove("test", Hlc::new(NodeId::min()).next());
+
+            let delta = current.split(&since);
+
+            assert_eq!(delta.get(&"test"), None);
+            assert_eq!(delta.iter_with_tombstones().count(), 1);
+        }
+    }
+
+    mod collect_garbage {
+        use super::*;
+
+        #[test]
+        fn drops_tombstones_older_than_stable() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+
+            map.insert("test", 1, clock.clone());
+            map.remove("test", clock.next());
+
+            map.collect_garbage(&clock.next().next().next());
+
+            assert_eq!(map.len(), 0);
+            assert_eq!(map.iter_with_tombstones().count(), 0);
+        }
+
+        #[test]
+        fn keeps_tombstones_at_or_above_stable() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+
+            map.remove("test", clock.clone());
+            map.collect_garbage(&clock);
+
+            assert_eq!(map.iter_with_tombstones().count(), 1);
+        }
+
+        #[test]
+        fn never_drops_live_values_regardless_of_clock() {
+            let mut map = LwwMap::<&str, i32>::new();
+            let clock = Hlc::zero();
+
+            map.insert("test", 1, clock.clone());
+            map.collect_garbage(&clock.next());
+
+            assert_eq!(map.get(&"test"), Some(&1));
+        }
+
+        #[test]
+        fn is_a_no_op_on_convergence() {
+            let clock = Hlc::zero();
+
+            let mut gcd = LwwMap::<&str, i32>::new();
+            gcd.insert("test", 1, clock.clone());
+            gcd.remove("test", clock.next());
+
+            // A peer that hasn't caught up yet and still carries the old
+            // tombstone.
+            let not_yet_gcd = gcd.clone();
+
+            gcd.collect_garbage(&clock.next().next().next());
+            assert_eq!(gcd.iter_with_tombstones().count(), 0);
+
+            let merged = gcd.merge(not_yet_gcd);
+
+            assert_eq!(merged.get(&"test"), None);
+            assert_eq!(merged.iter().count(), 0);
+        }
+    }
 }
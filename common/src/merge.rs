@@ -1,11 +1,30 @@
 /// Merge two CRDTs into one.
-pub trait Merge {
+pub trait Merge
+where
+    Self: Sized,
+{
+    /// The fragment of `Self` that's changed relative to some earlier copy.
+    /// Sending this instead of the whole value is what makes delta-state
+    /// sync cheaper than shipping `Self` on every exchange.
+    type Delta;
+
     /// Merge two `Merge`s into one. This happens when we sync state between
     /// replicas. In order for CRDT semantics to hold, this operation must be
     /// commutative, associative, and idempotent. There are tests to help
     /// guarantee this below.
     #[must_use]
     fn merge(self, other: Self) -> Self;
+
+    /// Everything in `self` that `since` doesn't already have. A peer that's
+    /// caught up to `since` can merge this delta in (via [`Self::merge_delta`])
+    /// and end up exactly where it would have by merging in the whole of
+    /// `self`.
+    #[must_use]
+    fn split(&self, since: &Self) -> Self::Delta;
+
+    /// Apply a delta produced by [`Self::split`].
+    #[must_use]
+    fn merge_delta(self, delta: Self::Delta) -> Self;
 }
 
 /// Test that a Merge implementation is idempotent (needed so that merging
@@ -55,3 +74,20 @@ where
 
     assert_eq!(merged1, merged2, "associativity failure");
 }
+
+/// Test that splitting a delta against some earlier copy and merging it back
+/// in lands on the same value as merging the whole thing would have.
+#[cfg(test)]
+pub fn test_split_merge_delta<T>(since: T, current: T)
+where
+    T: Merge + Clone + PartialEq + std::fmt::Debug,
+{
+    let delta = current.split(&since);
+    let from_delta = since.clone().merge_delta(delta);
+
+    assert_eq!(
+        from_delta,
+        since.merge(current),
+        "split/merge_delta mismatch"
+    );
+}
@@ -1,24 +1,30 @@
-use chrono::Utc;
+use crate::clock::{Clock, SystemClock};
 use rand::Rng;
 use rand_pcg::Pcg32;
 use std::fmt::Display;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 pub struct NodeId(#[cfg_attr(test, proptest(strategy = "0..=3u16"))] u16);
 
 impl NodeId {
+    /// Generate a random node ID, seeded from the given clock instead of
+    /// reaching for the system clock directly.
     #[allow(clippy::cast_sign_loss)]
-    pub fn random() -> Self {
+    pub fn random_with(clock: &dyn Clock) -> Self {
         Self(
             Pcg32::new(
-                Utc::now().timestamp() as u64, // Seed (we're OK with underflow if timestamp is somehow pre-1970)
-                0xa02_bdbf_7bb3_c0a7,          // Stream (default)
+                clock.now().timestamp() as u64, // Seed (we're OK with underflow if timestamp is somehow pre-1970)
+                0xa02_bdbf_7bb3_c0a7,            // Stream (default)
             )
             .gen(),
         )
     }
 
+    pub fn random() -> Self {
+        Self::random_with(&SystemClock)
+    }
+
     pub fn min() -> Self {
         Self(u16::MIN)
     }
@@ -0,0 +1,49 @@
+use crate::hlc::Hlc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A single mutation to a [`crate::State`], as logged by a
+/// [`crate::replica::Replica`] and replayed by [`crate::state::State::apply_op`].
+/// One variant per [`crate::replica::Replica`] mutator, covering the whole
+/// mutable surface of [`crate::state::State`] (including
+/// `set_minutes_per_ping`, clearing a tag, and removing a ping) rather than
+/// just the original `AddPing`/`TagPing` pair.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Op {
+    /// See [`crate::replica::Replica::set_minutes_per_ping`].
+    SetMinutesPerPing {
+        /// The new interval.
+        minutes: u16,
+    },
+
+    /// See [`crate::replica::Replica::add_ping`].
+    AddPing {
+        /// When the ping happened.
+        when: DateTime<Utc>,
+    },
+
+    /// See [`crate::replica::Replica::tag_ping`].
+    TagPing {
+        /// Which ping to tag.
+        when: DateTime<Utc>,
+        /// The tag to apply.
+        tag: String,
+    },
+
+    /// See [`crate::replica::Replica::untag_ping`].
+    UntagPing {
+        /// Which ping to clear the tag from.
+        when: DateTime<Utc>,
+    },
+
+    /// See [`crate::replica::Replica::remove_ping`].
+    RemovePing {
+        /// Which ping to remove.
+        when: DateTime<Utc>,
+        /// Every add-tag this replica had observed for `when` at the time
+        /// of removal, so the removal only tombstones what it actually
+        /// knew about — an add not yet synced in from another replica
+        /// survives it.
+        tags: Vec<Hlc>,
+    },
+}
@@ -0,0 +1,259 @@
+use crate::hlc::Hlc;
+use crate::merge::Merge;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// An Observed-Remove Set (OR-Set) CRDT: like a [`crate::gset::GSet`], but
+/// supports removal. Every insertion is tagged with the unique [`Hlc`] that
+/// produced it (which already bakes in the inserting node, so there's no
+/// need to track `(NodeId, Hlc)` separately); a value is present iff at
+/// least one of its tags hasn't been tombstoned. Removing a value
+/// tombstones every tag for it that *this* replica has observed so far, so
+/// a concurrent insert elsewhere that hasn't synced in yet keeps its own
+/// (different) tag and survives the removal.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct OrSet<T: Ord + Clone> {
+    /// Every value ever added, keyed by the unique clock of its insertion.
+    adds: HashMap<Hlc, T>,
+
+    /// Tags that have been observed-removed, and so no longer count toward
+    /// membership no matter how many times they're merged in again.
+    tombstones: HashSet<Hlc>,
+}
+
+impl<T: Ord + Clone> OrSet<T> {
+    /// Creates an empty `OrSet`.
+    pub fn new() -> Self {
+        Self {
+            adds: HashMap::new(),
+            tombstones: HashSet::new(),
+        }
+    }
+
+    /// Adds `value`, tagged under `clock`. Adding the same value again under
+    /// a different clock (e.g. after it's been removed) makes it present
+    /// again, since the new tag hasn't been tombstoned.
+    pub fn insert(&mut self, value: T, clock: Hlc) {
+        self.adds.insert(clock, value);
+    }
+
+    /// Every tag currently known for `value`, i.e. every add not already
+    /// tombstoned. Pass these to [`Self::tombstone`] (directly, or via a
+    /// logged op) to remove `value`.
+    pub fn tags_for(&self, value: &T) -> Vec<Hlc> {
+        self.adds
+            .iter()
+            .filter(|(tag, v)| *v == value && !self.tombstones.contains(tag))
+            .map(|(tag, _)| tag.clone())
+            .collect()
+    }
+
+    /// Tombstone `tags`, so they no longer count toward membership. Purely
+    /// additive, so merging it in from a peer (or applying it twice) is
+    /// always safe.
+    pub fn tombstone(&mut self, tags: impl IntoIterator<Item = Hlc>) {
+        self.tombstones.extend(tags);
+    }
+
+    /// Every clock tagging an add in this set, e.g. so a caller merging in a
+    /// remote delta can observe each one for drift correction and clock
+    /// advancement without reaching into private fields.
+    pub fn clocks(&self) -> impl Iterator<Item = &Hlc> {
+        self.adds.keys()
+    }
+
+    /// Whether `value` has at least one tag that hasn't been tombstoned.
+    pub fn contains(&self, value: &T) -> bool {
+        self.adds
+            .iter()
+            .any(|(tag, v)| v == value && !self.tombstones.contains(tag))
+    }
+
+    /// An iterator over every distinct value with at least one live tag, in
+    /// ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut present = BTreeSet::new();
+
+        for (tag, value) in &self.adds {
+            if !self.tombstones.contains(tag) {
+                present.insert(value);
+            }
+        }
+
+        present.into_iter()
+    }
+
+    /// How many distinct values are present.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if no values are present.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+impl<T: Ord + Clone> Merge for OrSet<T> {
+    /// The adds and tombstones `since` doesn't have yet, as an `OrSet` of
+    /// just those. Each add is already keyed by its own unique clock, so
+    /// "newer than `since`" is just "not already one of its keys."
+    type Delta = Self;
+
+    fn merge(mut self, mut other: Self) -> Self {
+        self.adds.extend(other.adds.drain());
+        self.tombstones.extend(other.tombstones.drain());
+
+        self
+    }
+
+    fn split(&self, since: &Self) -> Self::Delta {
+        Self {
+            adds: self
+                .adds
+                .iter()
+                .filter(|(tag, _)| !since.adds.contains_key(*tag))
+                .map(|(tag, value)| (tag.clone(), value.clone()))
+                .collect(),
+            tombstones: self
+                .tombstones
+                .difference(&since.tombstones)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn merge_delta(self, delta: Self::Delta) -> Self {
+        self.merge(delta)
+    }
+}
+
+impl<T: Ord + Clone> Default for OrSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T: Ord + Clone> IntoIterator for &'a OrSet<T> {
+    type IntoIter = Box<dyn Iterator<Item = &'a T> + 'a>;
+    type Item = &'a T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::node_id::NodeId;
+    use proptest::prelude::*;
+
+    fn clock() -> Hlc {
+        Hlc::new(NodeId::random())
+    }
+
+    #[test]
+    fn inserted_value_is_present() {
+        let mut set = OrSet::new();
+        set.insert(1, clock());
+
+        assert!(set.contains(&1));
+    }
+
+    #[test]
+    fn removed_value_is_absent() {
+        let mut set = OrSet::new();
+        let clock = clock();
+        set.insert(1, clock.clone());
+
+        let tags = set.tags_for(&1);
+        set.tombstone(tags);
+
+        assert!(!set.contains(&1));
+    }
+
+    #[test]
+    fn concurrent_add_survives_a_remove_it_was_not_observed_by() {
+        let mut local = OrSet::new();
+        local.insert("ping", clock());
+
+        // Remove it locally, based on what `local` has observed so far.
+        let tags = local.tags_for(&"ping");
+        local.tombstone(tags);
+
+        // Meanwhile, a peer re-adds the same value under a fresh tag we
+        // haven't seen yet.
+        let mut remote = OrSet::new();
+        remote.insert("ping", clock());
+
+        let merged = local.merge(remote);
+
+        assert!(merged.contains(&"ping"));
+    }
+
+    #[test]
+    fn remove_then_merge_with_the_same_history_stays_absent() {
+        let mut set = OrSet::new();
+        set.insert("ping", clock());
+
+        let tags = set.tags_for(&"ping");
+        set.tombstone(tags);
+
+        let merged = set.clone().merge(set);
+
+        assert!(!merged.contains(&"ping"));
+    }
+
+    #[test]
+    fn iter_deduplicates_multiple_tags_for_the_same_value() {
+        let mut set = OrSet::new();
+        set.insert(1, clock());
+        set.insert(1, clock());
+
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![&1]);
+    }
+
+    proptest! {
+        #[test]
+        fn merge_idempotent(a: OrSet<u8>) {
+            crate::merge::test_idempotent(a);
+        }
+
+        #[test]
+        fn merge_commutative(a: OrSet<u8>, b: OrSet<u8>) {
+            crate::merge::test_commutative(a, b);
+        }
+
+        #[test]
+        fn merge_associative(a: OrSet<u8>, b: OrSet<u8>, c: OrSet<u8>) {
+            crate::merge::test_associative(a, b, c);
+        }
+
+        #[test]
+        fn split_merge_delta(a: OrSet<u8>, b: OrSet<u8>) {
+            crate::merge::test_split_merge_delta(a, b);
+        }
+    }
+
+    mod split {
+        use super::*;
+
+        #[test]
+        fn only_sends_adds_and_tombstones_since_does_not_have() {
+            let mut since = OrSet::new();
+            since.insert("kept", clock());
+
+            let mut current = since.clone();
+            let tags = current.tags_for(&"kept");
+            current.tombstone(tags.clone());
+            current.insert("new", clock());
+
+            let delta = current.split(&since);
+
+            assert!(!delta.adds.contains_key(&tags[0]));
+            assert!(delta.tombstones.contains(&tags[0]));
+            assert!(delta.adds.values().any(|v| v == &"new"));
+        }
+    }
+}
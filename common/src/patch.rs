@@ -0,0 +1,559 @@
+use crate::state::State;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A single operation from an RFC 6902 JSON Patch document. `move` and
+/// `copy` aren't supported, since nothing in [`State`] has a second path
+/// that could reasonably be "the same value" it was moved or copied from.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Set the value at `path`, same handling as `replace` here since every
+    /// path this patch can reach always "exists" (a ping absent from
+    /// `pings` just reads back as untagged/unset rather than missing).
+    Add {
+        /// An RFC 6901 JSON Pointer to the field being set.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+
+    /// Clear the value at `path`.
+    Remove {
+        /// An RFC 6901 JSON Pointer to the field being cleared.
+        path: String,
+    },
+
+    /// Set the value at `path`. See `Add`.
+    Replace {
+        /// An RFC 6901 JSON Pointer to the field being set.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+
+    /// Fail the whole patch unless `path` currently holds `value`, for
+    /// optimistic-concurrency checks.
+    Test {
+        /// An RFC 6901 JSON Pointer to the field being checked.
+        path: String,
+        /// The value `path` is expected to currently hold.
+        value: Value,
+    },
+}
+
+/// One field this crate already knows how to write, derived from a patch
+/// operation. Kept separate from [`crate::op::Op`] since a patch op doesn't
+/// carry a clock (that's [`crate::replica::Replica`]'s job) and can target
+/// `tags`/`pings` interchangeably with a tag clear vs. a ping removal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    /// See [`crate::replica::Replica::set_minutes_per_ping`].
+    SetMinutesPerPing(u16),
+    /// See [`crate::replica::Replica::add_ping`].
+    AddPing(DateTime<Utc>),
+    /// See [`crate::replica::Replica::tag_ping`].
+    TagPing(DateTime<Utc>, String),
+    /// See [`crate::replica::Replica::untag_ping`].
+    UntagPing(DateTime<Utc>),
+    /// See [`crate::replica::Replica::remove_ping`].
+    RemovePing(DateTime<Utc>),
+}
+
+/// Ways translating a patch into [`Mutation`]s can fail.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Error {
+    /// `path` doesn't address a field a patch can mutate. Only
+    /// `/minutes_per_ping`, `/pings/<rfc3339 timestamp>`, and
+    /// `/tags/<rfc3339 timestamp>` are recognized.
+    #[error("{0:?} does not address a field this patch can mutate")]
+    UnknownPath(String),
+
+    /// The last segment of `path` wasn't a valid RFC 3339 timestamp.
+    #[error("{0:?} does not end in a valid timestamp")]
+    InvalidTimestamp(String),
+
+    /// The value at `path` wasn't the type that field expects.
+    #[error("{path:?} expected a {expected} value, got {actual}")]
+    WrongType {
+        /// The offending pointer.
+        path: String,
+        /// What was expected instead.
+        expected: &'static str,
+        /// What was actually found.
+        actual: Value,
+    },
+
+    /// A `test` op didn't match.
+    #[error("test failed at {path:?}: expected {expected}, got {actual}")]
+    TestFailed {
+        /// The pointer that was tested.
+        path: String,
+        /// The value the `test` op expected.
+        expected: Value,
+        /// The value actually found there.
+        actual: Value,
+    },
+}
+
+/// Translate a whole JSON Patch document into the [`Mutation`]s it implies,
+/// checking every `test` op against `state` as it was *before* this patch
+/// (not against the results of any earlier op in the same patch). That
+/// keeps validation side-effect-free, so a failing `test` anywhere in the
+/// patch reports an error without any `Mutation` having been produced, let
+/// alone applied.
+///
+/// ## Errors
+///
+/// Returns [`Error`] if any path is unaddressable, any value is the wrong
+/// type, or any `test` op doesn't match.
+pub fn translate_patch(ops: &[PatchOp], state: &State) -> Result<Vec<Mutation>, Error> {
+    let mut mutations = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        match op {
+            PatchOp::Test { path, value } => check_test(path, value, state)?,
+            PatchOp::Add { path, value } | PatchOp::Replace { path, value } => {
+                mutations.push(translate_write(path, value)?);
+            }
+            PatchOp::Remove { path } => mutations.push(translate_remove(path)?),
+        }
+    }
+
+    Ok(mutations)
+}
+
+/// Translate an RFC 7386 JSON Merge Patch (a sparse object whose `null`
+/// fields delete) into the [`Mutation`]s it implies. Merge patches have no
+/// `test` op, so unlike [`translate_patch`] this never needs to read the
+/// current [`State`].
+///
+/// ## Errors
+///
+/// Returns [`Error`] if `patch` isn't an object, a field name is
+/// unrecognized, or a value is the wrong type.
+pub fn translate_merge_patch(patch: &Value) -> Result<Vec<Mutation>, Error> {
+    let Some(fields) = patch.as_object() else {
+        return Err(Error::WrongType {
+            path: "/".to_string(),
+            expected: "object",
+            actual: patch.clone(),
+        });
+    };
+
+    let mut mutations = Vec::new();
+
+    for (field, value) in fields {
+        match field.as_str() {
+            "minutes_per_ping" => mutations.push(Mutation::SetMinutesPerPing(as_minutes(
+                "/minutes_per_ping",
+                value,
+            )?)),
+            "tags" => {
+                let Some(tags) = value.as_object() else {
+                    return Err(Error::WrongType {
+                        path: "/tags".to_string(),
+                        expected: "object",
+                        actual: value.clone(),
+                    });
+                };
+
+                for (timestamp, tag) in tags {
+                    let path = format!("/tags/{timestamp}");
+                    let when = parse_timestamp(&path, timestamp)?;
+                    mutations.push(as_tag_mutation(&path, when, tag)?);
+                }
+            }
+            "pings" => {
+                let Some(pings) = value.as_object() else {
+                    return Err(Error::WrongType {
+                        path: "/pings".to_string(),
+                        expected: "object",
+                        actual: value.clone(),
+                    });
+                };
+
+                for (timestamp, presence) in pings {
+                    let path = format!("/pings/{timestamp}");
+                    let when = parse_timestamp(&path, timestamp)?;
+                    mutations.push(if presence.is_null() {
+                        Mutation::RemovePing(when)
+                    } else {
+                        Mutation::AddPing(when)
+                    });
+                }
+            }
+            _ => return Err(Error::UnknownPath(format!("/{field}"))),
+        }
+    }
+
+    Ok(mutations)
+}
+
+/// Translate a single `add`/`replace` op into the [`Mutation`] it implies.
+fn translate_write(path: &str, value: &Value) -> Result<Mutation, Error> {
+    match pointer_segments(path)?.as_slice() {
+        [field] if field == "minutes_per_ping" => {
+            Ok(Mutation::SetMinutesPerPing(as_minutes(path, value)?))
+        }
+        [field, timestamp] if field == "pings" => {
+            let when = parse_timestamp(path, timestamp)?;
+            as_ping_mutation(path, when, value)
+        }
+        [field, timestamp] if field == "tags" => {
+            let when = parse_timestamp(path, timestamp)?;
+            as_tag_mutation(path, when, value)
+        }
+        _ => Err(Error::UnknownPath(path.to_string())),
+    }
+}
+
+/// Translate a single `remove` op into the [`Mutation`] it implies.
+fn translate_remove(path: &str) -> Result<Mutation, Error> {
+    match pointer_segments(path)?.as_slice() {
+        [field, timestamp] if field == "pings" => {
+            Ok(Mutation::RemovePing(parse_timestamp(path, timestamp)?))
+        }
+        [field, timestamp] if field == "tags" => {
+            Ok(Mutation::UntagPing(parse_timestamp(path, timestamp)?))
+        }
+        _ => Err(Error::UnknownPath(path.to_string())),
+    }
+}
+
+/// Check a single `test` op against `state`, erroring with [`Error::TestFailed`]
+/// if the value found there doesn't match `expected`.
+fn check_test(path: &str, expected: &Value, state: &State) -> Result<(), Error> {
+    let actual = match pointer_segments(path)?.as_slice() {
+        [field] if field == "minutes_per_ping" => Value::from(*state.minutes_per_ping.value()),
+        [field, timestamp] if field == "pings" => {
+            let when = parse_timestamp(path, timestamp)?;
+            Value::Bool(state.pings.contains(&when))
+        }
+        [field, timestamp] if field == "tags" => {
+            let when = parse_timestamp(path, timestamp)?;
+            state
+                .tags
+                .get(&when)
+                .and_then(|lww| lww.value().clone())
+                .map_or(Value::Null, Value::String)
+        }
+        _ => return Err(Error::UnknownPath(path.to_string())),
+    };
+
+    if &actual == expected {
+        Ok(())
+    } else {
+        Err(Error::TestFailed {
+            path: path.to_string(),
+            expected: expected.clone(),
+            actual,
+        })
+    }
+}
+
+/// Read `value` as the `u16` `minutes_per_ping` expects.
+fn as_minutes(path: &str, value: &Value) -> Result<u16, Error> {
+    value
+        .as_u64()
+        .and_then(|minutes| u16::try_from(minutes).ok())
+        .ok_or_else(|| Error::WrongType {
+            path: path.to_string(),
+            expected: "u16",
+            actual: value.clone(),
+        })
+}
+
+/// Read `value` as a ping write for `when`: `true` adds the ping, `false`
+/// removes it. (`translate_merge_patch` reads the same field differently:
+/// `null` removes the ping and any other value adds it.)
+fn as_ping_mutation(path: &str, when: DateTime<Utc>, value: &Value) -> Result<Mutation, Error> {
+    match value {
+        Value::Bool(true) => Ok(Mutation::AddPing(when)),
+        Value::Bool(false) => Ok(Mutation::RemovePing(when)),
+        other => Err(Error::WrongType {
+            path: path.to_string(),
+            expected: "bool",
+            actual: other.clone(),
+        }),
+    }
+}
+
+/// Read `value` as a tag write for `when`: a string tags it, `null` clears it.
+fn as_tag_mutation(path: &str, when: DateTime<Utc>, value: &Value) -> Result<Mutation, Error> {
+    match value {
+        Value::Null => Ok(Mutation::UntagPing(when)),
+        Value::String(tag) => Ok(Mutation::TagPing(when, tag.clone())),
+        other => Err(Error::WrongType {
+            path: path.to_string(),
+            expected: "string or null",
+            actual: other.clone(),
+        }),
+    }
+}
+
+/// Split an RFC 6901 JSON Pointer into its unescaped segments, rejecting the
+/// empty pointer (which would address `State` as a whole, not any one
+/// field).
+fn pointer_segments(path: &str) -> Result<Vec<String>, Error> {
+    let rest = path
+        .strip_prefix('/')
+        .filter(|rest| !rest.is_empty())
+        .ok_or_else(|| Error::UnknownPath(path.to_string()))?;
+
+    Ok(rest
+        .split('/')
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Parse `segment` (the last part of `path`) as an RFC 3339 timestamp.
+fn parse_timestamp(path: &str, segment: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(segment)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| Error::InvalidTimestamp(path.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hlc::Hlc;
+    use crate::node_id::NodeId;
+    use serde_json::json;
+
+    fn timestamp() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    mod translate_patch {
+        use super::*;
+
+        #[test]
+        fn add_minutes_per_ping() {
+            let mutations = translate_patch(
+                &[PatchOp::Add { path: "/minutes_per_ping".to_string(), value: json!(30) }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::SetMinutesPerPing(30)]);
+        }
+
+        #[test]
+        fn add_ping() {
+            let when = timestamp();
+            let mutations = translate_patch(
+                &[PatchOp::Add {
+                    path: format!("/pings/{}", when.to_rfc3339()),
+                    value: json!(true),
+                }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::AddPing(when)]);
+        }
+
+        #[test]
+        fn add_tag() {
+            let when = timestamp();
+            let mutations = translate_patch(
+                &[PatchOp::Add {
+                    path: format!("/tags/{}", when.to_rfc3339()),
+                    value: json!("focus"),
+                }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::TagPing(when, "focus".to_string())]);
+        }
+
+        #[test]
+        fn null_tag_value_untags() {
+            let when = timestamp();
+            let mutations = translate_patch(
+                &[PatchOp::Add {
+                    path: format!("/tags/{}", when.to_rfc3339()),
+                    value: Value::Null,
+                }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::UntagPing(when)]);
+        }
+
+        #[test]
+        fn replace_ping_with_false_removes_it() {
+            let when = timestamp();
+            let mutations = translate_patch(
+                &[PatchOp::Replace {
+                    path: format!("/pings/{}", when.to_rfc3339()),
+                    value: json!(false),
+                }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::RemovePing(when)]);
+        }
+
+        #[test]
+        fn non_bool_ping_value_is_a_wrong_type_error() {
+            let when = timestamp();
+            let path = format!("/pings/{}", when.to_rfc3339());
+            let err = translate_patch(
+                &[PatchOp::Add { path: path.clone(), value: json!("nonsense") }],
+                &State::new(),
+            )
+            .unwrap_err();
+
+            assert_eq!(
+                err,
+                Error::WrongType { path, expected: "bool", actual: json!("nonsense") }
+            );
+        }
+
+        #[test]
+        fn remove_ping() {
+            let when = timestamp();
+            let mutations = translate_patch(
+                &[PatchOp::Remove { path: format!("/pings/{}", when.to_rfc3339()) }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::RemovePing(when)]);
+        }
+
+        #[test]
+        fn remove_tag() {
+            let when = timestamp();
+            let mutations = translate_patch(
+                &[PatchOp::Remove { path: format!("/tags/{}", when.to_rfc3339()) }],
+                &State::new(),
+            )
+            .unwrap();
+
+            assert_eq!(mutations, vec![Mutation::UntagPing(when)]);
+        }
+
+        #[test]
+        fn passing_test_produces_no_mutation() {
+            let mut state = State::new();
+            state.set_minutes_per_ping(60, Hlc::new(NodeId::random()));
+
+            let mutations = translate_patch(
+                &[PatchOp::Test { path: "/minutes_per_ping".to_string(), value: json!(60) }],
+                &state,
+            )
+            .unwrap();
+
+            assert!(mutations.is_empty());
+        }
+
+        #[test]
+        fn failing_test_aborts_the_whole_patch() {
+            let when = timestamp();
+
+            let ping_path = format!("/pings/{}", when.to_rfc3339());
+            let err = translate_patch(
+                &[
+                    PatchOp::Add { path: "/minutes_per_ping".to_string(), value: json!(30) },
+                    PatchOp::Test { path: ping_path.clone(), value: json!(true) },
+                ],
+                &State::new(),
+            )
+            .unwrap_err();
+
+            assert_eq!(
+                err,
+                Error::TestFailed { path: ping_path, expected: json!(true), actual: json!(false) }
+            );
+        }
+
+        #[test]
+        fn unknown_path_is_an_error() {
+            let ops = [PatchOp::Add { path: "/nonsense".to_string(), value: json!(1) }];
+            let err = translate_patch(&ops, &State::new()).unwrap_err();
+
+            assert_eq!(err, Error::UnknownPath("/nonsense".to_string()));
+        }
+
+        #[test]
+        fn minutes_per_ping_cannot_be_removed() {
+            let ops = [PatchOp::Remove { path: "/minutes_per_ping".to_string() }];
+            let err = translate_patch(&ops, &State::new()).unwrap_err();
+
+            assert_eq!(err, Error::UnknownPath("/minutes_per_ping".to_string()));
+        }
+    }
+
+    mod translate_merge_patch {
+        use super::*;
+
+        #[test]
+        fn sets_minutes_per_ping_and_clears_a_tag() {
+            let when = timestamp();
+            let mut tags = serde_json::Map::new();
+            tags.insert(when.to_rfc3339(), Value::Null);
+
+            let mut patch = serde_json::Map::new();
+            patch.insert("minutes_per_ping".to_string(), json!(30));
+            patch.insert("tags".to_string(), Value::Object(tags));
+
+            let mutations = translate_merge_patch(&Value::Object(patch)).unwrap();
+
+            assert_eq!(
+                mutations,
+                vec![Mutation::SetMinutesPerPing(30), Mutation::UntagPing(when)]
+            );
+        }
+
+        #[test]
+        fn adds_and_removes_pings() {
+            let added = timestamp();
+            let removed = timestamp() + chrono::Duration::minutes(1);
+
+            let mut pings = serde_json::Map::new();
+            pings.insert(added.to_rfc3339(), json!(true));
+            pings.insert(removed.to_rfc3339(), Value::Null);
+
+            let mut patch = serde_json::Map::new();
+            patch.insert("pings".to_string(), Value::Object(pings));
+
+            let mut mutations = translate_merge_patch(&Value::Object(patch)).unwrap();
+            mutations.sort_by_key(|mutation| format!("{mutation:?}"));
+
+            assert_eq!(
+                mutations,
+                vec![Mutation::AddPing(added), Mutation::RemovePing(removed)]
+            );
+        }
+
+        #[test]
+        fn rejects_a_non_object_patch() {
+            let err = translate_merge_patch(&json!([1, 2, 3])).unwrap_err();
+
+            assert_eq!(
+                err,
+                Error::WrongType {
+                    path: "/".to_string(),
+                    expected: "object",
+                    actual: json!([1, 2, 3]),
+                }
+            );
+        }
+
+        #[test]
+        fn rejects_an_unknown_field() {
+            let err = translate_merge_patch(&json!({"bogus": true})).unwrap_err();
+
+            assert_eq!(err, Error::UnknownPath("/bogus".to_string()));
+        }
+    }
+}
@@ -1,9 +1,18 @@
+use crate::clock::{Clock, SystemClock};
+use crate::drift::DriftEstimator;
+use crate::history::{History, Revision, UndoGroup};
 use crate::hlc::Hlc;
-use crate::lww::Lww;
+use crate::log::{Log, TimestampedOp};
+use crate::merge::Merge;
 use crate::node_id::NodeId;
+use crate::op::Op;
+use crate::patch;
 use crate::scheduler::Scheduler;
 use crate::state::State;
+use crate::sync::{self, VersionVector};
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// The local state of a replica ("who am I" and "what do I know"). Reading the
 /// state should be fairly straightforward.
@@ -13,47 +22,286 @@ pub struct Replica {
 
     /// Data that this replica will write to and sync with peers.
     state: State,
+
+    /// Every op this replica has applied, local or received from a peer, for
+    /// op-based sync with peers that already have most of what we do.
+    log: Log,
+
+    /// Where this replica reads the current time from. Defaults to the
+    /// system clock; swap in a `MockClock` to drive scheduling deterministically.
+    time: Arc<dyn Clock>,
+
+    /// Corrects `time`'s readings against drift observed in peers' clocks
+    /// during merges, so new `Hlc`s stay close to true time even when this
+    /// replica's own clock is skewed.
+    drift: DriftEstimator,
+
+    /// The undo/redo history of `set_minutes_per_ping`/`tag_ping`/
+    /// `untag_ping` revisions this replica has recorded.
+    history: History,
+
+    /// The undo group in-progress mutators should record under, if one of
+    /// `apply_patch`/`apply_merge_patch` has opened one to cover several
+    /// mutations as a single user action. `None` means every mutator call
+    /// records its own fresh group.
+    current_group: Option<UndoGroup>,
+}
+
+/// A replica's whole materialized [`State`] plus the ops a low-water mark
+/// doesn't cover yet, for bootstrapping a [`Replica`] without replaying its
+/// entire history. See [`Replica::compact`] and [`Replica::from_snapshot`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The replica's full state when `compact` ran, tail ops included.
+    pub state: State,
+
+    /// Every op not yet covered by that low-water mark.
+    pub tail: Vec<TimestampedOp>,
 }
 
 impl Replica {
     /// Create a new replica with the given node ID.
     pub fn new(node_id: NodeId) -> Self {
+        Self::new_with(node_id, Arc::new(SystemClock))
+    }
+
+    /// Create a new replica, reading the current time from `time` instead of
+    /// the system clock.
+    pub fn new_with(node_id: NodeId, time: Arc<dyn Clock>) -> Self {
+        let drift = DriftEstimator::default();
         Self {
-            clock: Hlc::new(node_id),
+            clock: Hlc::new_at(node_id, time.now()),
             state: State::default(),
+            log: Log::default(),
+            time,
+            drift,
+            history: History::new(),
+            current_group: None,
         }
     }
 
+    /// Bootstrap a replica from a [`Snapshot`] instead of replaying its
+    /// entire history: start from the materialized state, then resume
+    /// logging on top of the tail.
+    pub fn from_snapshot(node_id: NodeId, snapshot: Snapshot) -> Self {
+        Self::from_snapshot_with(node_id, snapshot, Arc::new(SystemClock))
+    }
+
+    /// Same as [`Self::from_snapshot`], but reads the current time from
+    /// `time` instead of the system clock.
+    pub fn from_snapshot_with(node_id: NodeId, snapshot: Snapshot, time: Arc<dyn Clock>) -> Self {
+        let mut clock = Hlc::new_at(node_id, time.now());
+        for op in &snapshot.tail {
+            clock.mut_receive_at(&op.timestamp, time.now());
+        }
+
+        Self {
+            clock,
+            state: snapshot.state,
+            log: Log::from_ops(snapshot.tail),
+            time,
+            drift: DriftEstimator::default(),
+            // A `Snapshot` doesn't carry undo history (see `Self::compact`),
+            // so a replica rebuilt from one starts with nothing to undo —
+            // the same trade-off `Log::from_ops` already makes for ops
+            // folded into `snapshot.state`.
+            history: History::new(),
+            current_group: None,
+        }
+    }
+
+    /// The current time, corrected for any drift we've estimated against
+    /// peers' clocks.
+    fn now(&mut self) -> DateTime<Utc> {
+        self.drift.correct(self.time.now())
+    }
+
     /// Increment the clock and get the next value you should use. Always use
     /// this when writing to ensure that the replica-level clock is the highest
     /// in the state.
     #[must_use]
     fn next_clock(&mut self) -> Hlc {
-        self.clock.increment();
+        let now = self.now();
+        self.clock.increment_at(now);
         self.clock.clone()
     }
 
+    /// The rolling history of drift-filter states, frequency estimates, and
+    /// applied corrections, for introspection.
+    pub fn drift_diagnostics(&self) -> &crate::drift::Diagnostics {
+        self.drift.diagnostics()
+    }
+
     /// Read the current state.
     pub fn state(&self) -> &State {
         &self.state
     }
 
+    /// The node ID this replica writes under.
+    pub fn node_id(&self) -> &NodeId {
+        self.clock.node()
+    }
+
+    /// The ops this replica has applied, local or received from a peer, in
+    /// the order they were applied.
+    pub fn log(&self) -> &Log {
+        &self.log
+    }
+
     /// Set the average number of minutes between pings.
     pub fn set_minutes_per_ping(&mut self, new: u16) {
+        let previous = *self.state.minutes_per_ping.value();
         let clock = self.next_clock();
-        self.state.minutes_per_ping.set(new, clock);
+        self.apply_local(Op::SetMinutesPerPing { minutes: new }, clock.clone());
+        self.record_revision(
+            clock,
+            Op::SetMinutesPerPing { minutes: new },
+            Op::SetMinutesPerPing { minutes: previous },
+        );
     }
 
     /// Add a ping, likely in coordination with a `Scheduler`.
     pub fn add_ping(&mut self, when: DateTime<Utc>) {
-        self.state.pings.insert(when);
+        let clock = self.next_clock();
+        self.apply_local(Op::AddPing { when }, clock);
     }
 
     /// Tag an existing ping (although there are no guards against tagging a
     /// ping that does not exist!)
     pub fn tag_ping(&mut self, when: DateTime<Utc>, tag: String) {
+        let inverse = Self::untag_inverse(&self.state, when);
+        let clock = self.next_clock();
+        self.apply_local(Op::TagPing { when, tag: tag.clone() }, clock.clone());
+        self.record_revision(clock, Op::TagPing { when, tag }, inverse);
+    }
+
+    /// Clear the tag on a ping, if any, without removing the ping itself.
+    pub fn untag_ping(&mut self, when: DateTime<Utc>) {
+        let inverse = Self::untag_inverse(&self.state, when);
+        let clock = self.next_clock();
+        self.apply_local(Op::UntagPing { when }, clock.clone());
+        self.record_revision(clock, Op::UntagPing { when }, inverse);
+    }
+
+    /// The op that restores whatever tag `when` had before it's next
+    /// changed: its current tag if it has one, or an untag (a no-op if it's
+    /// already untagged) if it doesn't. Shared by `tag_ping` and
+    /// `untag_ping` since both overwrite the same `Lww<Option<String>>` and
+    /// so undo the same way.
+    fn untag_inverse(state: &State, when: DateTime<Utc>) -> Op {
+        match state.tags.get(&when).and_then(|lww| lww.value().clone()) {
+            Some(tag) => Op::TagPing { when, tag },
+            None => Op::UntagPing { when },
+        }
+    }
+
+    /// Record a revision for an op already applied under `clock`, grouping
+    /// it with whatever other ops are part of the same user action (see
+    /// `current_group`).
+    fn record_revision(&mut self, clock: Hlc, forward: Op, inverse: Op) {
+        let group = self
+            .current_group
+            .unwrap_or_else(|| self.history.new_group());
+        self.history.record(group, clock, forward, inverse);
+    }
+
+    /// Remove a ping, tombstoning every add-tag we've observed for it so far
+    /// (so a concurrent add that hasn't synced in yet survives the removal).
+    ///
+    /// `pings` is an add-wins [`crate::or_set::OrSet`] rather than a plain
+    /// [`crate::gset::GSet`] precisely so this can exist: a bogus or
+    /// mistaken ping can actually be taken back, and a concurrent add-vs-
+    /// remove still resolves to "present" instead of the remove winning by
+    /// virtue of running last. `State::remove_ping` drives the ping's
+    /// `tags` entry to a tombstoned [`crate::lww::Lww`] in the same call, so
+    /// a fully-removed ping is never left dangling with a stale tag.
+    pub fn remove_ping(&mut self, when: DateTime<Utc>) {
+        let tags = self.state.pings.tags_for(&when);
         let clock = self.next_clock();
-        self.state.tags.upsert(when, Lww::new(tag, clock));
+        self.apply_local(Op::RemovePing { when, tags }, clock);
+    }
+
+    /// Apply an RFC 6902 JSON Patch: a list of add/remove/replace/test
+    /// operations against [`Self::set_minutes_per_ping`],
+    /// [`Self::add_ping`]/[`Self::remove_ping`], and
+    /// [`Self::tag_ping`]/[`Self::untag_ping`]. Every mutation still goes
+    /// through this replica's own mutators, so it ends up clock-stamped and
+    /// logged exactly like a hand-written call would.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`patch::Error`] if a path doesn't address a field this
+    /// patch can mutate, a value is the wrong type, or a `test` op doesn't
+    /// match what's currently there (see [`patch::translate_patch`] for
+    /// exactly what "currently there" means once a patch has more than one
+    /// op).
+    pub fn apply_patch(&mut self, ops: &[patch::PatchOp]) -> Result<(), patch::Error> {
+        let mutations = patch::translate_patch(ops, &self.state)?;
+        self.apply_mutations_as_one_action(mutations);
+
+        Ok(())
+    }
+
+    /// Apply an RFC 7386 JSON Merge Patch: a sparse object whose `null`
+    /// fields delete (clearing a tag or removing a ping) and whose other
+    /// fields overwrite (`minutes_per_ping`) or set (a tag). See
+    /// [`Self::apply_patch`] for how each mutation ends up clock-stamped
+    /// and logged.
+    ///
+    /// ## Errors
+    ///
+    /// Returns [`patch::Error`] if `merge_patch` isn't an object, or a
+    /// field name or value doesn't address something this patch can
+    /// mutate.
+    pub fn apply_merge_patch(
+        &mut self,
+        merge_patch: &serde_json::Value,
+    ) -> Result<(), patch::Error> {
+        let mutations = patch::translate_merge_patch(merge_patch)?;
+        self.apply_mutations_as_one_action(mutations);
+
+        Ok(())
+    }
+
+    /// Run every [`patch::Mutation`] through the mutator it names, grouping
+    /// whatever revisions they record as a single undoable user action —
+    /// one `apply_patch`/`apply_merge_patch` call undoes and redoes as a
+    /// unit, even though it's several ops under the hood.
+    fn apply_mutations_as_one_action(&mut self, mutations: Vec<patch::Mutation>) {
+        let group = self.history.new_group();
+        self.current_group = Some(group);
+
+        for mutation in mutations {
+            self.apply_mutation(mutation);
+        }
+
+        self.current_group = None;
+    }
+
+    /// Run a single [`patch::Mutation`] through the mutator it names.
+    fn apply_mutation(&mut self, mutation: patch::Mutation) {
+        match mutation {
+            patch::Mutation::SetMinutesPerPing(minutes) => self.set_minutes_per_ping(minutes),
+            patch::Mutation::AddPing(when) => self.add_ping(when),
+            patch::Mutation::TagPing(when, tag) => self.tag_ping(when, tag),
+            patch::Mutation::UntagPing(when) => self.untag_ping(when),
+            patch::Mutation::RemovePing(when) => self.remove_ping(when),
+        }
+    }
+
+    /// Log and apply an op generated by one of our own mutators. Pushing can
+    /// only fail if this replica's own clock somehow went backwards, which
+    /// `next_clock` never allows.
+    fn apply_local(&mut self, op: Op, clock: Hlc) {
+        let logged = TimestampedOp {
+            timestamp: clock,
+            op,
+        };
+
+        self.log
+            .push(logged.clone())
+            .expect("this replica's own clock only moves forward");
+        self.state.apply_op(&logged);
     }
 
     /// Does the same as `schedule_ping` but allows you to specify the cutoff.
@@ -61,8 +309,8 @@ impl Replica {
         let latest_ping = if let Some(ping) = self.state.latest_ping().copied() {
             ping
         } else {
-            let now = Utc::now();
-            self.state.pings.insert(now);
+            let now = self.now();
+            self.add_ping(now);
 
             now
         };
@@ -70,7 +318,7 @@ impl Replica {
         let scheduler = Scheduler::new(*self.state.minutes_per_ping.value(), latest_ping);
 
         for next in scheduler {
-            self.state.pings.insert(next);
+            self.add_ping(next);
 
             // accepting one past the cutoff gets us into the future
             if next > cutoff {
@@ -79,11 +327,193 @@ impl Replica {
         }
     }
 
+    /// The latest `Hlc` we've applied from each node, for telling a peer what
+    /// we're missing.
+    pub fn version_vector(&self) -> VersionVector {
+        sync::version_vector(&self.log)
+    }
+
+    /// Every op we have that `vector` doesn't dominate, to send to a peer
+    /// during a sync exchange.
+    pub fn missing_since(&self, vector: &VersionVector) -> Vec<TimestampedOp> {
+        sync::missing_since(&self.log, vector)
+    }
+
+    /// Apply ops received from a peer during a sync exchange. Ops we already
+    /// have are skipped rather than erroring, since a peer may send more
+    /// than we're strictly missing.
+    ///
+    /// ## Errors
+    ///
+    /// Returns `crate::log::Error::OrderingViolation` if an op that isn't
+    /// covered by our version vector still sorts before the last op we have
+    /// for its node, which would mean the peer sent us ops for that node out
+    /// of order.
+    pub fn merge_ops(&mut self, ops: Vec<TimestampedOp>) -> Result<(), crate::log::Error> {
+        let local_receive = self.time.now();
+        let mut vector = self.version_vector();
+
+        for op in ops {
+            if sync::covers(&vector, &op.timestamp) {
+                continue;
+            }
+
+            self.drift.observe(op.timestamp.timestamp(), local_receive);
+            let now = self.drift.correct(local_receive);
+            self.clock.mut_receive_at(&op.timestamp, now);
+
+            vector
+                .entry(op.timestamp.node().clone())
+                .and_modify(|latest: &mut Hlc| {
+                    if *latest < op.timestamp {
+                        *latest = op.timestamp.clone();
+                    }
+                })
+                .or_insert_with(|| op.timestamp.clone());
+
+            self.state.apply_op(&op);
+            self.log.push(op)?;
+        }
+
+        Ok(())
+    }
+
+    /// Undo the most recent still-active user action: `set_minutes_per_ping`,
+    /// `tag_ping`, `untag_ping`, or an `apply_patch`/`apply_merge_patch`
+    /// call. Rather than rewinding state in place, this emits each
+    /// revision's inverse op at a fresh clock — through the same
+    /// `apply_local` every other write goes through — so the undo merges
+    /// across replicas exactly like any other write, and syncs the same
+    /// way. Returns `false` if there's nothing left to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(group) = self.history.undoable() else {
+            return false;
+        };
+
+        for inverse in self.history.undo(group) {
+            let clock = self.next_clock();
+            self.apply_local(inverse, clock);
+        }
+
+        true
+    }
+
+    /// Redo the most recently undone user action, re-emitting its forward
+    /// op(s) at a fresh clock. Returns `false` if there's nothing to redo,
+    /// e.g. because a new action was recorded since the last undo.
+    pub fn redo(&mut self) -> bool {
+        let Some(group) = self.history.redoable() else {
+            return false;
+        };
+
+        for forward in self.history.redo(group) {
+            let clock = self.next_clock();
+            self.apply_local(forward, clock);
+        }
+
+        true
+    }
+
+    /// Every revision recorded so far, oldest first, for displaying
+    /// undo/redo affordances or introspecting what's changed.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &Revision> {
+        self.history.iter()
+    }
+
+    /// Fold the causally-stable prefix of the log (every op dominated by
+    /// `low_water_mark`, i.e. already acknowledged by every known replica)
+    /// into `state`, keeping only the still-unstable tail. `state` already
+    /// reflects the winning value of any dropped op, so the result is
+    /// unchanged for any replica at or above `low_water_mark`.
+    ///
+    /// Dropping ops below a replica's own watermark would be unsafe: it may
+    /// not have seen them yet, so the caller must compute `low_water_mark`
+    /// from every known replica's version vector, not just this one's.
+    #[must_use]
+    pub fn compact(&self, low_water_mark: &VersionVector) -> Snapshot {
+        Snapshot {
+            state: self.state.clone(),
+            tail: sync::missing_since(&self.log, low_water_mark),
+        }
+    }
+
     /// Schedule pings into the future. We don't just schedule *up to* the given
     /// time, but go one past that. That means that if the given time is the
     /// current time, we end up with the time we should next notify at.
     pub fn schedule_pings(&mut self) {
-        self.schedule_pings_with_cutoff(Utc::now());
+        let cutoff = self.now();
+        self.schedule_pings_with_cutoff(cutoff);
+    }
+
+    /// Merge a remote copy of our state into our own, e.g. after pulling from
+    /// a peer. Every remote `Hlc` we see is also fed to the drift estimator as
+    /// a `(peer_timestamp, local_receive_timestamp)` sample, so our physical
+    /// clock stays close to true time even if it's skewed relative to the
+    /// fleet. We then advance our clock against every `Hlc` we observe in
+    /// `remote`, so that anything we write locally afterwards sorts after
+    /// everything we just learned about.
+    pub fn merge(&mut self, remote: State) {
+        let local_receive = self.time.now();
+
+        self.drift
+            .observe(remote.minutes_per_ping.clock().timestamp(), local_receive);
+        for (_, tag) in remote.tags.iter() {
+            self.drift.observe(tag.clock().timestamp(), local_receive);
+        }
+        for clock in remote.pings.clocks() {
+            self.drift.observe(clock.timestamp(), local_receive);
+        }
+
+        let now = self.drift.correct(local_receive);
+
+        self.clock
+            .mut_receive_at(remote.minutes_per_ping.clock(), now);
+        for (_, tag) in remote.tags.iter() {
+            self.clock.mut_receive_at(tag.clock(), now);
+        }
+        for clock in remote.pings.clocks() {
+            self.clock.mut_receive_at(clock, now);
+        }
+
+        self.state = std::mem::take(&mut self.state).merge(remote);
+    }
+
+    /// Everything in our state that `since` doesn't already have, to send to
+    /// a peer instead of our whole `State`.
+    #[must_use]
+    pub fn split(&self, since: &State) -> <State as Merge>::Delta {
+        self.state.split(since)
+    }
+
+    /// Merge a delta produced by a peer's [`Self::split`] into our own
+    /// state. Same drift/clock handling as [`Self::merge`], just over the
+    /// smaller set of clocks the delta actually carries.
+    pub fn merge_delta(&mut self, delta: <State as Merge>::Delta) {
+        let local_receive = self.time.now();
+
+        if let Some(lww) = &delta.minutes_per_ping {
+            self.drift.observe(lww.clock().timestamp(), local_receive);
+        }
+        for clock in delta.pings.clocks() {
+            self.drift.observe(clock.timestamp(), local_receive);
+        }
+        for (_, tag) in delta.tags.iter() {
+            self.drift.observe(tag.clock().timestamp(), local_receive);
+        }
+
+        let now = self.drift.correct(local_receive);
+
+        if let Some(lww) = &delta.minutes_per_ping {
+            self.clock.mut_receive_at(lww.clock(), now);
+        }
+        for clock in delta.pings.clocks() {
+            self.clock.mut_receive_at(clock, now);
+        }
+        for (_, tag) in delta.tags.iter() {
+            self.clock.mut_receive_at(tag.clock(), now);
+        }
+
+        self.state = std::mem::take(&mut self.state).merge_delta(delta);
     }
 }
 
@@ -91,6 +521,7 @@ impl Replica {
 mod test {
     use super::*;
     use crate::lww::Lww;
+    use chrono::TimeZone;
     use proptest::prelude::*;
     use proptest_state_machine::{prop_state_machine, ReferenceStateMachine, StateMachineTest};
     use std::collections::{HashMap, HashSet};
@@ -114,6 +545,107 @@ mod test {
         assert!(doc.state().pings.contains(&when));
     }
 
+    #[test]
+    fn apply_patch_tags_a_ping() {
+        let mut doc = Replica::new(NodeId::random());
+
+        let when = Utc::now();
+        doc.add_ping(when);
+        doc.apply_patch(&[crate::patch::PatchOp::Add {
+            path: format!("/tags/{}", when.to_rfc3339()),
+            value: serde_json::json!("focus"),
+        }])
+        .unwrap();
+
+        assert_eq!(
+            doc.state().tags.get(&when).and_then(|lww| lww.value().clone()),
+            Some("focus".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_patch_leaves_state_untouched_on_a_failed_test() {
+        let mut doc = Replica::new(NodeId::random());
+        let before = doc.state().clone();
+
+        let err = doc
+            .apply_patch(&[
+                crate::patch::PatchOp::Add {
+                    path: "/minutes_per_ping".to_string(),
+                    value: serde_json::json!(30),
+                },
+                crate::patch::PatchOp::Test {
+                    path: "/minutes_per_ping".to_string(),
+                    value: serde_json::json!(999),
+                },
+            ])
+            .unwrap_err();
+
+        assert!(matches!(err, crate::patch::Error::TestFailed { .. }));
+        assert_eq!(doc.state(), &before);
+    }
+
+    #[test]
+    fn apply_merge_patch_sets_minutes_per_ping() {
+        let mut doc = Replica::new(NodeId::random());
+
+        doc.apply_merge_patch(&serde_json::json!({"minutes_per_ping": 30}))
+            .unwrap();
+
+        assert_eq!(*doc.state().minutes_per_ping.value(), 30);
+    }
+
+    #[test]
+    fn merge_advances_clock_past_remote() {
+        let mut local = Replica::new(NodeId::min());
+
+        let mut remote = State::default();
+        let when = Utc::now();
+        let remote_clock = Hlc::new(NodeId::max()).next().next().next();
+        remote.set_minutes_per_ping(90, remote_clock.clone());
+        remote.pings.insert(when, remote_clock.clone());
+
+        local.merge(remote);
+
+        assert!(local.clock > remote_clock);
+        assert_eq!(*local.state().minutes_per_ping.value(), 90);
+        assert!(local.state().pings.contains(&when));
+    }
+
+    #[test]
+    fn merge_delta_applies_only_what_changed_since_the_given_state() {
+        let mut local = Replica::new(NodeId::min());
+
+        let mut remote = Replica::new(NodeId::max());
+        let already_known = Utc::now();
+        remote.add_ping(already_known);
+        let since = remote.state().clone();
+
+        let when = Utc::now();
+        remote.add_ping(when);
+
+        let delta = remote.split(&since);
+        local.merge_delta(delta);
+
+        assert!(!local.state().pings.contains(&already_known));
+        assert!(local.state().pings.contains(&when));
+    }
+
+    #[test]
+    fn merge_delta_advances_clock_past_remote() {
+        let mut local = Replica::new(NodeId::min());
+
+        let mut remote = Replica::new(NodeId::max());
+        let when = Utc::now();
+        remote.add_ping(when);
+
+        let delta = remote.split(&State::default());
+        local.merge_delta(delta);
+
+        assert!(local.clock > remote.clock);
+        assert!(local.state().pings.contains(&when));
+    }
+
     #[test]
     fn set_ping() {
         let node_id = NodeId::random();
@@ -123,11 +655,295 @@ mod test {
         doc.add_ping(when);
         doc.tag_ping(when, "test".to_string());
         assert_eq!(
-            doc.state().tags.get(&when).map(Lww::value),
+            doc.state()
+                .tags
+                .get(&when)
+                .and_then(|lww| lww.value().as_ref()),
             Some(&"test".to_string())
         );
     }
 
+    mod merge_ops {
+        use super::*;
+
+        #[test]
+        fn applies_ops_we_were_missing() {
+            let mut remote = Replica::new(NodeId::max());
+            let when = Utc::now();
+            remote.add_ping(when);
+            remote.tag_ping(when, "test".to_string());
+
+            let mut local = Replica::new(NodeId::min());
+            local
+                .merge_ops(remote.log().ops().clone())
+                .expect("ops from a correctly-ordered log should apply cleanly");
+
+            assert!(local.state().pings.contains(&when));
+            assert_eq!(
+                local
+                    .state()
+                    .tags
+                    .get(&when)
+                    .and_then(|lww| lww.value().as_ref()),
+                Some(&"test".to_string())
+            );
+        }
+
+        #[test]
+        fn skips_ops_already_covered_by_our_version_vector() {
+            let mut remote = Replica::new(NodeId::max());
+            remote.add_ping(Utc::now());
+
+            let mut local = Replica::new(NodeId::min());
+            local.merge_ops(remote.log().ops().clone()).unwrap();
+
+            let num_ops = local.log().len();
+            local.merge_ops(remote.log().ops().clone()).unwrap();
+
+            assert_eq!(local.log().len(), num_ops);
+        }
+
+        #[test]
+        fn advances_our_clock_past_the_ops_we_apply() {
+            let mut remote = Replica::new(NodeId::max());
+            remote.add_ping(Utc::now());
+            let remote_clock = remote.log().ops()[0].timestamp.clone();
+
+            let mut local = Replica::new(NodeId::min());
+            local.merge_ops(remote.log().ops().clone()).unwrap();
+
+            assert!(local.clock > remote_clock);
+        }
+    }
+
+    mod undo {
+        use super::*;
+
+        #[test]
+        fn reverts_the_most_recent_set_minutes_per_ping() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+            doc.set_minutes_per_ping(60);
+
+            assert!(doc.undo());
+            assert_eq!(*doc.state().minutes_per_ping.value(), 30);
+        }
+
+        #[test]
+        fn reverts_a_tag_back_to_untagged() {
+            let mut doc = Replica::new(NodeId::random());
+            let when = Utc::now();
+            doc.add_ping(when);
+            doc.tag_ping(when, "focus".to_string());
+
+            assert!(doc.undo());
+            assert_eq!(
+                doc.state().tags.get(&when).and_then(|lww| lww.value().as_ref()),
+                None
+            );
+        }
+
+        #[test]
+        fn reverts_a_tag_back_to_its_previous_value() {
+            let mut doc = Replica::new(NodeId::random());
+            let when = Utc::now();
+            doc.add_ping(when);
+            doc.tag_ping(when, "focus".to_string());
+            doc.tag_ping(when, "break".to_string());
+
+            assert!(doc.undo());
+            assert_eq!(
+                doc.state().tags.get(&when).and_then(|lww| lww.value().as_ref()),
+                Some(&"focus".to_string())
+            );
+        }
+
+        #[test]
+        fn reverts_an_untag_back_to_tagged() {
+            let mut doc = Replica::new(NodeId::random());
+            let when = Utc::now();
+            doc.add_ping(when);
+            doc.tag_ping(when, "focus".to_string());
+            doc.untag_ping(when);
+
+            assert!(doc.undo());
+            assert_eq!(
+                doc.state().tags.get(&when).and_then(|lww| lww.value().as_ref()),
+                Some(&"focus".to_string())
+            );
+        }
+
+        #[test]
+        fn undoes_an_entire_patch_as_one_action() {
+            let mut doc = Replica::new(NodeId::random());
+            let when = Utc::now();
+            doc.add_ping(when);
+
+            doc.apply_patch(&[
+                crate::patch::PatchOp::Add {
+                    path: "/minutes_per_ping".to_string(),
+                    value: serde_json::json!(15),
+                },
+                crate::patch::PatchOp::Add {
+                    path: format!("/tags/{}", when.to_rfc3339()),
+                    value: serde_json::json!("focus"),
+                },
+            ])
+            .unwrap();
+
+            assert!(doc.undo());
+            assert_eq!(*doc.state().minutes_per_ping.value(), 45);
+            assert_eq!(
+                doc.state().tags.get(&when).and_then(|lww| lww.value().as_ref()),
+                None
+            );
+        }
+
+        #[test]
+        fn returns_false_once_there_is_nothing_left_to_undo() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+
+            assert!(doc.undo());
+            assert!(!doc.undo());
+        }
+
+        #[test]
+        fn a_second_undo_reaches_back_past_the_first() {
+            let mut doc = Replica::new(NodeId::random());
+            let original = *doc.state().minutes_per_ping.value();
+            doc.set_minutes_per_ping(30);
+            doc.set_minutes_per_ping(60);
+
+            assert!(doc.undo());
+            assert_eq!(*doc.state().minutes_per_ping.value(), 30);
+
+            assert!(doc.undo());
+            assert_eq!(*doc.state().minutes_per_ping.value(), original);
+        }
+
+        #[test]
+        fn an_undo_merges_onto_another_replica_like_any_other_write() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+            doc.set_minutes_per_ping(60);
+            doc.undo();
+
+            let mut peer = Replica::new(NodeId::random());
+            peer.merge(doc.state().clone());
+
+            assert_eq!(*peer.state().minutes_per_ping.value(), 30);
+        }
+    }
+
+    mod redo {
+        use super::*;
+
+        #[test]
+        fn reapplies_an_undone_set_minutes_per_ping() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+            doc.set_minutes_per_ping(60);
+            doc.undo();
+
+            assert!(doc.redo());
+            assert_eq!(*doc.state().minutes_per_ping.value(), 60);
+        }
+
+        #[test]
+        fn returns_false_when_theres_nothing_to_redo() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+
+            assert!(!doc.redo());
+        }
+
+        #[test]
+        fn is_cleared_by_a_new_action_after_an_undo() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+            doc.set_minutes_per_ping(60);
+            doc.undo();
+            doc.set_minutes_per_ping(90);
+
+            assert!(!doc.redo());
+        }
+    }
+
+    mod history {
+        use super::*;
+
+        #[test]
+        fn records_one_revision_per_direct_mutator_call() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.set_minutes_per_ping(30);
+            doc.set_minutes_per_ping(60);
+
+            assert_eq!(doc.history().count(), 2);
+        }
+    }
+
+    mod compact {
+        use super::*;
+
+        #[test]
+        fn drops_ops_covered_by_the_low_water_mark() {
+            let mut doc = Replica::new(NodeId::random());
+            let when = Utc::now();
+            doc.add_ping(when);
+            doc.tag_ping(when, "test".to_string());
+
+            let low_water_mark = doc.version_vector();
+            let snapshot = doc.compact(&low_water_mark);
+
+            assert!(snapshot.tail.is_empty());
+            assert_eq!(
+                snapshot
+                    .state
+                    .tags
+                    .get(&when)
+                    .and_then(|lww| lww.value().as_ref()),
+                Some(&"test".to_string())
+            );
+        }
+
+        #[test]
+        fn keeps_ops_not_yet_covered() {
+            let mut doc = Replica::new(NodeId::random());
+            doc.add_ping(Utc::now());
+
+            let snapshot = doc.compact(&VersionVector::new());
+
+            assert_eq!(snapshot.tail.len(), doc.log().len());
+        }
+    }
+
+    mod from_snapshot {
+        use super::*;
+
+        #[test]
+        fn replays_only_the_tail() {
+            let node_id = NodeId::random();
+            let mut doc = Replica::new(node_id.clone());
+            let when = Utc::now();
+            doc.add_ping(when);
+            doc.tag_ping(when, "test".to_string());
+
+            let snapshot = doc.compact(&doc.version_vector());
+            let reloaded = Replica::from_snapshot(node_id, snapshot);
+
+            assert_eq!(
+                reloaded
+                    .state()
+                    .tags
+                    .get(&when)
+                    .and_then(|lww| lww.value().as_ref()),
+                Some(&"test".to_string())
+            );
+            assert!(reloaded.log().is_empty());
+        }
+    }
+
     mod schedule_pings {
         use super::*;
 
@@ -165,6 +981,29 @@ mod test {
             );
         }
 
+        #[test]
+        fn is_deterministic_given_a_fixed_clock() {
+            let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+            let clock = std::sync::Arc::new(crate::test::MockClock::new(start));
+
+            let mut doc = Replica::new_with(NodeId::random(), clock.clone());
+            doc.set_minutes_per_ping(30);
+            doc.add_ping(start);
+
+            clock.advance(chrono::Duration::days(1));
+            doc.schedule_pings();
+
+            let mut other = Replica::new_with(NodeId::random(), clock.clone());
+            other.set_minutes_per_ping(30);
+            other.add_ping(start);
+            other.schedule_pings();
+
+            assert_eq!(
+                doc.state().pings.iter().collect::<Vec<_>>(),
+                other.state().pings.iter().collect::<Vec<_>>()
+            );
+        }
+
         #[test]
         fn any_dates_filled_are_from_the_scheduler() {
             let mut doc = Replica::new(NodeId::random());
@@ -191,6 +1030,8 @@ mod test {
         SetMinutesPerPing(u16),
         AddPing(chrono::DateTime<Utc>),
         TagPing(chrono::DateTime<Utc>, String),
+        UntagPing(chrono::DateTime<Utc>),
+        RemovePing(chrono::DateTime<Utc>),
     }
 
     #[derive(Debug, Clone)]
@@ -221,6 +1062,8 @@ mod test {
                 10 =>
                     (crate::test::timestamp_range(0..=2i64), "(a|b|c)")
                         .prop_map(|(ts, tag)| Transition::TagPing(ts, tag)),
+                5 => crate::test::timestamp_range(0..=2i64).prop_map(Transition::UntagPing),
+                5 => crate::test::timestamp_range(0..=2i64).prop_map(Transition::RemovePing),
             ]
             .boxed()
         }
@@ -236,6 +1079,13 @@ mod test {
                 Transition::TagPing(when, tag) => {
                     state.tags.insert(*when, tag.clone());
                 }
+                Transition::UntagPing(when) => {
+                    state.tags.remove(when);
+                }
+                Transition::RemovePing(when) => {
+                    state.pings.remove(when);
+                    state.tags.remove(when);
+                }
             }
 
             state
@@ -246,6 +1096,8 @@ mod test {
                 Transition::SetMinutesPerPing(_) => true,
                 Transition::AddPing(when) => !state.pings.contains(when),
                 Transition::TagPing(when, _) => state.pings.contains(when),
+                Transition::UntagPing(when) => state.pings.contains(when),
+                Transition::RemovePing(when) => state.pings.contains(when),
             }
         }
     }
@@ -289,10 +1141,31 @@ mod test {
                     state.tag_ping(when, tag.clone());
 
                     assert_eq!(
-                        state.state().tags.get(&when).map(Lww::value),
+                        state
+                            .state()
+                            .tags
+                            .get(&when)
+                            .and_then(|lww| lww.value().as_ref()),
                         ref_state.tags.get(&when),
                     );
                 }
+                Transition::UntagPing(when) => {
+                    state.untag_ping(when);
+
+                    assert_eq!(
+                        state
+                            .state()
+                            .tags
+                            .get(&when)
+                            .and_then(|lww| lww.value().as_ref()),
+                        None,
+                    );
+                }
+                Transition::RemovePing(when) => {
+                    state.remove_ping(when);
+
+                    assert!(!state.state().pings.contains(&when));
+                }
             }
 
             state
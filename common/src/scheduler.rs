@@ -2,6 +2,11 @@ use chrono::{DateTime, Duration, Utc};
 use rand::Rng;
 use rand_pcg::Pcg32;
 
+/// Shared by every replica, so that replaying the same `(seed, anchor)` pair
+/// always produces the same universal ping sequence, regardless of which
+/// node generates it or when.
+const SEED: u64 = 0x5d47_c2b1_9e3a_11af;
+
 #[derive(Clone)]
 pub struct Scheduler {
     average_pings_per_minute: f64,
@@ -11,14 +16,14 @@ pub struct Scheduler {
 impl Scheduler {
     // only temporary in test-only
     #[cfg(test)]
-    fn new(average_minutes_between_pings: u16, ping: DateTime<Utc>) -> Self {
+    fn new(average_minutes_between_pings: u16, anchor: DateTime<Utc>) -> Self {
         // We want to eventually find out how many minutes we should wait for the
         // next ping. To do that, we need to know the rate of pings per minute.
         let average_pings_per_minute = 1.0 / average_minutes_between_pings as f64;
 
         Self {
             average_pings_per_minute,
-            ping,
+            ping: anchor,
         }
     }
 }
@@ -28,16 +33,18 @@ impl Iterator for Scheduler {
 
     #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
     fn next(&mut self) -> Option<Self::Item> {
-        // Next, we'll generate a random number based seeded with the time of the
-        // last ping. We do this because it allows us to generate the same sequence,
-        // no matter which node it comes from.
+        // Next, we'll generate a random number seeded with the time of the last
+        // ping, mixed with the shared seed above. This is memoryless (each gap
+        // only depends on where the last ping landed, not on how we got there),
+        // so it allows us to generate the exact same sequence no matter which
+        // node computes it, or which ping in that sequence it starts from.
         let mut rng = Pcg32::new(
             // A Chrono timestamp is an i64. If that's a negative number (e.g.
             // before 1970) that will underflow to a very high u64 value. This seems
             // like it could cause a problem, but is actually fine—we're just using
             // this as a seed, so we can accept whatever behavior we like *as long
             // as it's consistent*.
-            self.ping.timestamp() as u64,
+            (self.ping.timestamp() as u64) ^ SEED,
             0xa02_bdbf_7bb3_c0a7, // Default stream
         );
 
@@ -75,16 +82,26 @@ mod test {
 
         let dates = scheduler.take(5).collect::<Vec<_>>();
         let expected = vec![
-            Utc.with_ymd_and_hms(2024, 1, 1, 0, 17, 29).unwrap(),
-            Utc.with_ymd_and_hms(2024, 1, 1, 0, 56, 45).unwrap(),
-            Utc.with_ymd_and_hms(2024, 1, 1, 2, 19, 23).unwrap(),
-            Utc.with_ymd_and_hms(2024, 1, 1, 3, 28, 26).unwrap(),
-            Utc.with_ymd_and_hms(2024, 1, 1, 4, 20, 39).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 1, 33, 44).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 2, 7, 37).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 3, 21, 57).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 3, 59, 34).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 4, 37, 52).unwrap(),
         ];
 
         assert_eq!(dates, expected);
     }
 
+    #[test]
+    fn same_anchor_produces_the_same_universal_sequence() {
+        let anchor = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+
+        let a = Scheduler::new(30, anchor).take(20).collect::<Vec<_>>();
+        let b = Scheduler::new(30, anchor).take(20).collect::<Vec<_>>();
+
+        assert_eq!(a, b, "two schedulers anchored at the same point should agree on every ping");
+    }
+
     proptest! {
         #[test]
         fn next_is_later_than_last_ping(
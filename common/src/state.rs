@@ -1,7 +1,10 @@
+use crate::gmap::GMap;
 use crate::hlc::Hlc;
+use crate::log::TimestampedOp;
 use crate::lww::Lww;
 use crate::merge::Merge;
-use crate::{gmap::GMap, gset::GSet};
+use crate::op::Op;
+use crate::or_set::OrSet;
 use chrono::{DateTime, Utc};
 
 /// The state that gets synced between replicas.
@@ -11,13 +14,18 @@ pub struct State {
     /// The average number of minutes between each ping.
     pub minutes_per_ping: Lww<u16>,
 
-    /// The pings that have been filled into this struct.
+    /// The pings that have been filled into this struct. An observed-remove
+    /// set rather than a plain [`crate::gset::GSet`], so a ping can be
+    /// removed (see [`Self::remove_ping`]) without the removal being lost
+    /// whenever this state merges with a peer that hasn't seen it yet.
     #[cfg_attr(test, proptest(strategy = "pings()"))]
-    pub pings: GSet<DateTime<Utc>>,
+    pub pings: OrSet<DateTime<Utc>>,
 
-    /// The tag (if any) set for each ping.
+    /// The tag (if any) set for each ping. `None` means untagged, rather
+    /// than the key being absent, since a [`GMap`] can't un-know a key it's
+    /// already seen.
     #[cfg_attr(test, proptest(strategy = "tags()"))]
-    pub tags: GMap<DateTime<Utc>, Lww<String>>,
+    pub tags: GMap<DateTime<Utc>, Lww<Option<String>>>,
 }
 
 impl State {
@@ -26,7 +34,7 @@ impl State {
     pub fn new() -> Self {
         Self {
             minutes_per_ping: Lww::new(45, Hlc::zero()),
-            pings: GSet::new(),
+            pings: OrSet::new(),
             tags: GMap::new(),
         }
     }
@@ -42,9 +50,11 @@ impl State {
         self.minutes_per_ping.set(new, clock);
     }
 
-    /// Add a ping, likely in coordination with a `Scheduler`.
-    pub fn add_ping(&mut self, when: DateTime<Utc>) {
-        self.pings.insert(when);
+    /// Add a ping, likely in coordination with a `Scheduler`. `clock` also
+    /// serves as this ping's unique add-tag in `pings`, so there's no
+    /// separate identifier to thread through.
+    pub fn add_ping(&mut self, when: DateTime<Utc>, clock: Hlc) {
+        self.pings.insert(when, clock);
     }
 
     /// Tag an existing ping (returns false if the ping cannot be tagged because
@@ -54,9 +64,49 @@ impl State {
             return false;
         }
 
-        self.tags.upsert(when, Lww::new(tag, clock));
+        self.tags.insert(when, Lww::new(Some(tag), clock));
         true
     }
+
+    /// Clear the tag on a ping, if any, without removing the ping itself.
+    pub fn untag_ping(&mut self, when: DateTime<Utc>, clock: Hlc) {
+        self.tags.insert(when, Lww::new(None, clock));
+    }
+
+    /// Remove a ping, tombstoning every add-tag in `tags` (ordinarily every
+    /// tag `OrSet::tags_for` currently knows about for `when`, so the
+    /// removal only affects what's actually been observed). Also clears the
+    /// ping's tag, if it has one, so a removed ping is never reported as
+    /// still tagged.
+    pub fn remove_ping(&mut self, tags: Vec<Hlc>, when: DateTime<Utc>, clock: Hlc) {
+        self.pings.tombstone(tags);
+
+        if self
+            .tags
+            .get(&when)
+            .is_some_and(|lww| lww.value().is_some())
+        {
+            self.untag_ping(when, clock);
+        }
+    }
+
+    /// Apply a single logged op, e.g. when replaying a peer's ops during
+    /// [`crate::replica::Replica::merge_ops`].
+    pub fn apply_op(&mut self, op: &TimestampedOp) {
+        match &op.op {
+            Op::SetMinutesPerPing { minutes } => {
+                self.set_minutes_per_ping(*minutes, op.timestamp.clone());
+            }
+            Op::AddPing { when } => self.add_ping(*when, op.timestamp.clone()),
+            Op::TagPing { when, tag } => {
+                self.tag_ping(*when, tag.clone(), op.timestamp.clone());
+            }
+            Op::UntagPing { when } => self.untag_ping(*when, op.timestamp.clone()),
+            Op::RemovePing { when, tags } => {
+                self.remove_ping(tags.clone(), *when, op.timestamp.clone());
+            }
+        }
+    }
 }
 
 impl Default for State {
@@ -65,10 +115,44 @@ impl Default for State {
     }
 }
 
+/// The fragment of a [`State`] that's changed relative to some earlier copy,
+/// as produced by [`State::split`]. Send this instead of a whole `State` to
+/// catch a peer up without resending what it already has.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StateDelta {
+    /// See [`State::minutes_per_ping`].
+    pub minutes_per_ping: <Lww<u16> as Merge>::Delta,
+
+    /// See [`State::pings`].
+    pub pings: <OrSet<DateTime<Utc>> as Merge>::Delta,
+
+    /// See [`State::tags`].
+    pub tags: <GMap<DateTime<Utc>, Lww<Option<String>>> as Merge>::Delta,
+}
+
 impl Merge for State {
+    type Delta = StateDelta;
+
     fn merge(mut self, other: Self) -> Self {
         self.minutes_per_ping = self.minutes_per_ping.merge(other.minutes_per_ping);
         self.pings = self.pings.merge(other.pings);
+        self.tags = self.tags.merge(other.tags);
+
+        self
+    }
+
+    fn split(&self, since: &Self) -> Self::Delta {
+        StateDelta {
+            minutes_per_ping: self.minutes_per_ping.split(&since.minutes_per_ping),
+            pings: self.pings.split(&since.pings),
+            tags: self.tags.split(&since.tags),
+        }
+    }
+
+    fn merge_delta(mut self, delta: Self::Delta) -> Self {
+        self.minutes_per_ping = self.minutes_per_ping.merge_delta(delta.minutes_per_ping);
+        self.pings = self.pings.merge_delta(delta.pings);
+        self.tags = self.tags.merge_delta(delta.tags);
 
         self
     }
@@ -79,15 +163,19 @@ proptest::prop_compose! {
     // TODO: we're going to all this hassle just to be able to use the timestamp
     // as a key. I'm not the happiest about that. Is there any way to make this
     // more succinct?
-    fn pings()(items in proptest::collection::btree_set(crate::test::timestamp(), 1..5)) -> GSet<DateTime<Utc>> {
-        GSet { items }
+    fn pings()(items in proptest::collection::vec((crate::test::timestamp(), proptest::prelude::any::<Hlc>()), 1..5)) -> OrSet<DateTime<Utc>> {
+        let mut set = OrSet::new();
+        for (when, clock) in items {
+            set.insert(when, clock);
+        }
+        set
     }
 }
 
 #[cfg(test)]
 proptest::prop_compose! {
     // Same here
-    fn tags()(items in proptest::collection::hash_map(crate::test::timestamp(), proptest::prelude::any::<Lww<String>>(), 1..5)) -> GMap<DateTime<Utc>, Lww<String>> {
+    fn tags()(items in proptest::collection::hash_map(crate::test::timestamp(), proptest::prelude::any::<Lww<Option<String>>>(), 1..5)) -> GMap<DateTime<Utc>, Lww<Option<String>>> {
         GMap(items)
     }
 }
@@ -113,6 +201,11 @@ mod test {
         fn test_merge_associative(a: State, b: State, c: State) {
             crate::merge::test_associative(a, b, c);
         }
+
+        #[test]
+        fn test_split_merge_delta(a: State, b: State) {
+            crate::merge::test_split_merge_delta(a, b);
+        }
     }
 
     mod state_machine {
@@ -124,8 +217,10 @@ mod test {
         #[derive(Debug, Clone)]
         enum Transition {
             SetMinutesPerPing(u16, Hlc),
-            AddPing(chrono::DateTime<Utc>),
+            AddPing(chrono::DateTime<Utc>, Hlc),
             TagPing(chrono::DateTime<Utc>, String, Hlc),
+            UntagPing(chrono::DateTime<Utc>, Hlc),
+            RemovePing(chrono::DateTime<Utc>, Hlc),
         }
 
         #[derive(Debug, Clone)]
@@ -159,10 +254,15 @@ mod test {
 
                 prop_oneof![
                     1 => (1..=4u16).prop_map(move |i| Transition::SetMinutesPerPing(i * 15, Hlc::new(node_id))),
-                    10 => crate::test::timestamp_range(0..=2i64).prop_map(Transition::AddPing),
+                    10 => crate::test::timestamp_range(0..=2i64)
+                        .prop_map(move |ts| Transition::AddPing(ts, Hlc::new(node_id))),
                     10 =>
                         (crate::test::timestamp_range(0..=2i64), "(a|b|c)")
                             .prop_map(move |(ts, tag)| Transition::TagPing(ts, tag, Hlc::new(node_id))),
+                    5 => crate::test::timestamp_range(0..=2i64)
+                        .prop_map(move |ts| Transition::UntagPing(ts, Hlc::new(node_id))),
+                    5 => crate::test::timestamp_range(0..=2i64)
+                        .prop_map(move |ts| Transition::RemovePing(ts, Hlc::new(node_id))),
                 ]
                 .boxed()
             }
@@ -172,12 +272,19 @@ mod test {
                     Transition::SetMinutesPerPing(new, _) => {
                         state.minutes_per_ping = *new;
                     }
-                    Transition::AddPing(when) => {
+                    Transition::AddPing(when, _) => {
                         state.pings.insert(*when);
                     }
                     Transition::TagPing(when, tag, _) => {
                         state.tags.insert(*when, tag.clone());
                     }
+                    Transition::UntagPing(when, _) => {
+                        state.tags.remove(when);
+                    }
+                    Transition::RemovePing(when, _) => {
+                        state.pings.remove(when);
+                        state.tags.remove(when);
+                    }
                 }
 
                 state
@@ -214,8 +321,8 @@ mod test {
                             "minutes_per_ping was not the same. Actual: `{actual}`, reference: `{reference}`"
                         );
                     }
-                    Transition::AddPing(when) => {
-                        state.add_ping(when);
+                    Transition::AddPing(when, clock) => {
+                        state.add_ping(when, clock);
 
                         let actual = state.pings.contains(&when);
                         let reference = ref_state.pings.contains(&when);
@@ -224,7 +331,7 @@ mod test {
                     }
                     Transition::TagPing(when, tag, clock) => {
                         if state.tag_ping(when, tag.clone(), clock) {
-                            let actual = state.tags.get(&when).map(Lww::value);
+                            let actual = state.tags.get(&when).and_then(|lww| lww.value().as_ref());
                             let reference = ref_state.tags.get(&when);
 
                             assert_eq!(
@@ -234,6 +341,24 @@ mod test {
                             );
                         }
                     }
+                    Transition::UntagPing(when, clock) => {
+                        state.untag_ping(when, clock);
+
+                        let actual = state.tags.get(&when).and_then(|lww| lww.value().as_ref());
+                        assert!(
+                            actual.is_none(),
+                            "expected {when} to be untagged, got `{actual:?}`"
+                        );
+                    }
+                    Transition::RemovePing(when, clock) => {
+                        let tags = state.pings.tags_for(&when);
+                        state.remove_ping(tags, when, clock);
+
+                        assert!(
+                            !state.pings.contains(&when),
+                            "expected {when} to be removed"
+                        );
+                    }
                 }
 
                 state
@@ -244,11 +369,13 @@ mod test {
                 _: &<Self::Reference as ReferenceStateMachine>::State,
             ) {
                 // consistency property: if a ping is tagged, it must exist in the pings set as well
-                for ping in state.tags.keys() {
-                    assert!(
-                        state.pings.contains(ping),
-                        "tagged ping {ping} does not exist in pings set"
-                    );
+                for (ping, tag) in state.tags.iter() {
+                    if tag.value().is_some() {
+                        assert!(
+                            state.pings.contains(ping),
+                            "tagged ping {ping} does not exist in pings set"
+                        );
+                    }
                 }
             }
         }
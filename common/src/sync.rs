@@ -0,0 +1,219 @@
+use crate::hlc::Hlc;
+use crate::log::{Log, TimestampedOp};
+use crate::node_id::NodeId;
+use std::collections::{HashMap, HashSet};
+
+/// The latest [`Hlc`] we've applied from each node, so a peer can be asked
+/// for only the [`TimestampedOp`]s it's missing instead of its whole [`Log`].
+///
+/// A node absent from the vector is treated as "send everything you have for
+/// that node."
+pub type VersionVector = HashMap<NodeId, Hlc>;
+
+/// Record that we've observed `clock`, advancing `vector`'s entry for its
+/// node if `clock` is newer than what's already there.
+pub(crate) fn observe(vector: &mut VersionVector, clock: &Hlc) {
+    vector
+        .entry(clock.node().clone())
+        .and_modify(|latest: &mut Hlc| {
+            if *latest < *clock {
+                *latest = clock.clone();
+            }
+        })
+        .or_insert_with(|| clock.clone());
+}
+
+/// Fold over `log.ops()` and record the latest [`Hlc`] seen for each node.
+#[must_use]
+pub fn version_vector(log: &Log) -> VersionVector {
+    let mut vector = VersionVector::with_capacity(8);
+
+    for op in log.ops() {
+        observe(&mut vector, &op.timestamp);
+    }
+
+    vector
+}
+
+/// A mergeable value that carries its own [`Hlc`], so a map of them (e.g.
+/// [`crate::gmap::GMap`], [`crate::lww_map::LwwMap`]) can be filtered down to
+/// a [`VersionVector`]-relative delta without caring what the value
+/// underneath actually is.
+pub trait Clocked {
+    /// The clock this value was last written at.
+    fn clock(&self) -> &Hlc;
+}
+
+impl<T> Clocked for crate::lww::Lww<T> {
+    fn clock(&self) -> &Hlc {
+        self.clock()
+    }
+}
+
+/// Whether `vector` already covers `timestamp`, i.e. its node has a recorded
+/// `Hlc` at or beyond `timestamp` itself.
+pub(crate) fn covers(vector: &VersionVector, timestamp: &Hlc) -> bool {
+    vector
+        .get(timestamp.node())
+        .is_some_and(|latest| latest >= timestamp)
+}
+
+/// The element-wise minimum `Hlc` per node across `vectors`, i.e. the latest
+/// point every known replica has acknowledged. A node missing from any one
+/// vector is dropped from the mark entirely, since that replica hasn't heard
+/// of it yet and there's no `Hlc` low enough to stand in for "nothing."
+///
+/// Ops at or below this mark can never affect a future merge with any of
+/// these replicas, so they're safe for [`crate::replica::Replica::compact`]
+/// to drop.
+#[must_use]
+pub fn low_water_mark(vectors: &[VersionVector]) -> VersionVector {
+    let nodes: HashSet<&NodeId> = vectors.iter().flat_map(VersionVector::keys).collect();
+
+    nodes
+        .into_iter()
+        .filter_map(|node| {
+            let min = vectors.iter().map(|vector| vector.get(node)).min()?;
+
+            min.cloned().map(|hlc| (node.clone(), hlc))
+        })
+        .collect()
+}
+
+/// Every op in `log` whose `Hlc` isn't dominated by `vector`.
+///
+/// Run this on both sides of a sync exchange: each side calls it with the
+/// other's vector to find what to send. Because [`crate::state::State::apply_op`]
+/// is idempotent, it's always safe to send an op the peer turns out to
+/// already have.
+#[must_use]
+pub fn missing_since(log: &Log, vector: &VersionVector) -> Vec<TimestampedOp> {
+    log.ops()
+        .iter()
+        .filter(|op| !covers(vector, &op.timestamp))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::op::Op;
+    use chrono::Utc;
+
+    fn op_at(node: NodeId, counter: u64) -> TimestampedOp {
+        let ts = Utc::now();
+        let mut timestamp = Hlc::new_at(node, ts);
+        for _ in 0..counter {
+            timestamp.increment_at(ts);
+        }
+
+        TimestampedOp {
+            timestamp,
+            op: Op::AddPing { when: ts },
+        }
+    }
+
+    mod version_vector {
+        use super::*;
+
+        #[test]
+        fn empty_log_has_empty_vector() {
+            assert_eq!(version_vector(&Log::default()), VersionVector::new());
+        }
+
+        #[test]
+        fn tracks_the_latest_hlc_per_node() {
+            let a = NodeId::min();
+            let b = NodeId::max();
+
+            let mut log = Log::default();
+            log.push(op_at(a.clone(), 0)).unwrap();
+            let latest_a = op_at(a.clone(), 1);
+            log.push(latest_a.clone()).unwrap();
+            let latest_b = op_at(b.clone(), 0);
+            log.push(latest_b.clone()).unwrap();
+
+            let vector = version_vector(&log);
+
+            assert_eq!(vector.get(&a), Some(&latest_a.timestamp));
+            assert_eq!(vector.get(&b), Some(&latest_b.timestamp));
+        }
+    }
+
+    mod low_water_mark {
+        use super::*;
+
+        #[test]
+        fn takes_the_minimum_across_replicas() {
+            let node = NodeId::random();
+            let low = op_at(node.clone(), 0).timestamp;
+            let high = op_at(node.clone(), 1).timestamp;
+
+            let a = VersionVector::from([(node.clone(), high)]);
+            let b = VersionVector::from([(node.clone(), low.clone())]);
+
+            let mark = low_water_mark(&[a, b]);
+
+            assert_eq!(mark.get(&node), Some(&low));
+        }
+
+        #[test]
+        fn a_node_missing_from_one_replica_is_excluded() {
+            let node = NodeId::random();
+            let a = VersionVector::from([(node.clone(), op_at(node.clone(), 0).timestamp)]);
+            let b = VersionVector::new();
+
+            let mark = low_water_mark(&[a, b]);
+
+            assert_eq!(mark.get(&node), None);
+        }
+
+        #[test]
+        fn no_replicas_means_no_mark() {
+            assert_eq!(low_water_mark(&[]), VersionVector::new());
+        }
+    }
+
+    mod missing_since {
+        use super::*;
+
+        #[test]
+        fn an_unknown_node_sends_everything_for_it() {
+            let mut log = Log::default();
+            log.push(op_at(NodeId::random(), 0)).unwrap();
+
+            let missing = missing_since(&log, &VersionVector::new());
+
+            assert_eq!(missing.len(), 1);
+        }
+
+        #[test]
+        fn a_covered_node_sends_only_whats_newer() {
+            let node = NodeId::random();
+
+            let mut log = Log::default();
+            let first = op_at(node.clone(), 0);
+            log.push(first.clone()).unwrap();
+            let second = op_at(node.clone(), 1);
+            log.push(second.clone()).unwrap();
+
+            let vector = VersionVector::from([(node, first.timestamp)]);
+            let missing = missing_since(&log, &vector);
+
+            assert_eq!(missing.len(), 1);
+            assert_eq!(missing[0].timestamp, second.timestamp);
+        }
+
+        #[test]
+        fn resending_an_already_applied_op_is_a_no_op() {
+            let mut log = Log::default();
+            log.push(op_at(NodeId::random(), 0)).unwrap();
+
+            let vector = version_vector(&log);
+            let missing = missing_since(&log, &vector);
+
+            assert!(missing.is_empty());
+        }
+    }
+}
@@ -1,7 +1,36 @@
-use chrono::{DateTime, TimeZone, Utc};
+use crate::clock::Clock;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::sync::Mutex;
 
 proptest::prop_compose! {
     pub fn timestamp()(unix in 1_700_000_000..1_800_000_000_000i64) -> DateTime<Utc> {
         Utc.timestamp_opt(unix, 0).unwrap()
     }
 }
+
+/// A `Clock` whose time is set explicitly, so scheduling and clock logic can
+/// be driven deterministically.
+pub struct MockClock(Mutex<DateTime<Utc>>);
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Mutex::new(now))
+    }
+
+    /// Jump to a specific time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    /// Move time forward (or backward, with a negative duration).
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
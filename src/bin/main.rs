@@ -1,5 +1,6 @@
+use beeps::crypto;
 use beeps::document::Document;
-use beeps::log::Log;
+use beeps::sync::VersionVector;
 use chrono::{Local, Utc};
 use clap::Parser;
 use color_eyre::{
@@ -14,6 +15,15 @@ use tracing::level_filters::LevelFilter;
 struct Cli {
     #[clap(long = "log-level", default_value = "error")]
     log_level: LevelFilter,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Re-seal the data file under a new passphrase.
+    RotateKey,
 }
 
 impl Cli {
@@ -26,8 +36,14 @@ impl Cli {
         }
     }
 
-    #[tracing::instrument(skip(self))]
-    fn read(&self) -> Result<Document> {
+    /// Prompt for the passphrase that protects the data file, without
+    /// echoing it to the terminal.
+    fn passphrase(&self, prompt: &str) -> Result<String> {
+        rpassword::prompt_password(prompt).wrap_err("could not read passphrase")
+    }
+
+    #[tracing::instrument(skip(self, passphrase))]
+    fn read(&self, passphrase: &str) -> Result<Document> {
         let dirs = self.dirs()?;
         let path = dirs.data_dir().join("data.json");
 
@@ -36,28 +52,68 @@ impl Cli {
             return Ok(Document::empty());
         }
 
-        let data = std::fs::read_to_string(path).wrap_err("could not read data")?;
-        let ops = serde_json::from_str(&data).wrap_err("could not deserialize data")?;
+        let sealed = std::fs::read(path).wrap_err("could not read data")?;
+        let data = crypto::open(passphrase, &sealed).wrap_err("could not decrypt data")?;
+        let snapshot = serde_json::from_slice(&data).wrap_err("could not deserialize data")?;
 
-        Ok(Document::from_ops(ops))
+        Ok(Document::from_snapshot(snapshot))
     }
 
-    #[tracing::instrument(skip(self, document))]
-    fn save(&self, document: &Log) -> Result<()> {
+    #[tracing::instrument(skip(self, document, passphrase))]
+    fn save(&self, document: &mut Document, passphrase: &str) -> Result<()> {
         let dirs = self.dirs()?;
         let path = dirs.data_dir().join("data.json");
-        let data = serde_json::to_string(document).wrap_err("could not serialize data")?;
+
+        // We're the only replica we know about, so we have no version
+        // vector to safely compact against. Checkpoint anything old enough
+        // that a peer we haven't synced with yet is vanishingly unlikely to
+        // still hand us an op older than it, so the log doesn't grow
+        // without bound even though nothing ever covers it in a version
+        // vector.
+        document.checkpoint(Utc::now() - chrono::Duration::days(1));
+
+        let snapshot = document.compact(&VersionVector::new());
+        let data = serde_json::to_vec(&snapshot).wrap_err("could not serialize data")?;
+        let sealed = crypto::seal(passphrase, &data).wrap_err("could not encrypt data")?;
 
         std::fs::create_dir_all(dirs.data_dir()).wrap_err("could not create directory")?;
-        std::fs::write(path, data).wrap_err("could not write data")?;
+        std::fs::write(path, sealed).wrap_err("could not write data")?;
 
         tracing::info!("saved");
 
         Ok(())
     }
 
+    /// Re-seal the data file under a new passphrase, so a compromised old
+    /// passphrase no longer opens it.
+    #[tracing::instrument(skip(self))]
+    fn rotate_key(&self) -> Result<()> {
+        let dirs = self.dirs()?;
+        let path = dirs.data_dir().join("data.json");
+
+        let sealed = std::fs::read(&path).wrap_err("could not read data")?;
+
+        let old_passphrase = self.passphrase("Current passphrase: ")?;
+        let data = crypto::open(&old_passphrase, &sealed).wrap_err("could not decrypt data")?;
+
+        let new_passphrase = self.passphrase("New passphrase: ")?;
+        let confirm_passphrase = self.passphrase("Confirm new passphrase: ")?;
+        if new_passphrase != confirm_passphrase {
+            return Err(eyre::eyre!("new passphrases did not match"));
+        }
+
+        let resealed = crypto::seal(&new_passphrase, &data).wrap_err("could not encrypt data")?;
+        std::fs::write(&path, resealed).wrap_err("could not write data")?;
+
+        tracing::info!("rotated key");
+        println!("Key rotated.");
+
+        Ok(())
+    }
+
     fn run(&self) -> Result<()> {
-        let mut loaded = self.read().wrap_err("could not load document")?;
+        let passphrase = self.passphrase("Passphrase: ")?;
+        let mut loaded = self.read(&passphrase).wrap_err("could not load document")?;
 
         loop {
             loaded.fill(Utc).wrap_err("could not fill")?;
@@ -77,7 +133,7 @@ impl Cli {
                 }
             }
 
-            self.save(loaded.log()).wrap_err("could not save")?;
+            self.save(&mut loaded, &passphrase).wrap_err("could not save")?;
 
             // fill again, just in case we waited forever to fill out the current ping
             loaded.fill(Utc).wrap_err("could not fill")?;
@@ -114,7 +170,12 @@ fn main() {
         .with_max_level(cli.log_level)
         .init();
 
-    if let Err(err) = cli.run() {
+    let result = match &cli.command {
+        Some(Command::RotateKey) => cli.rotate_key(),
+        None => cli.run(),
+    };
+
+    if let Err(err) = result {
         eprintln!("{:?}", err);
         std::process::exit(1);
     }
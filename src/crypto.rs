@@ -0,0 +1,121 @@
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("could not derive key from passphrase: {0}")]
+    KeyDerivation(argon2::Error),
+
+    #[error("could not encrypt data")]
+    Seal,
+
+    #[error("could not decrypt data; wrong passphrase, or the data is corrupted")]
+    Open,
+
+    #[error("sealed data is too short to contain a salt and nonce")]
+    Truncated,
+}
+
+/// Derive a 256-bit AES key from `passphrase`, salted with `salt`, using
+/// Argon2id. `salt` is stored alongside the ciphertext (see [`seal`]) rather
+/// than being a fixed or user-supplied value, so the same passphrase never
+/// derives the same key twice.
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], Error> {
+    let mut key = [0u8; KEY_LEN];
+
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(Error::KeyDerivation)?;
+
+    Ok(key)
+}
+
+/// Seal `plaintext` under `passphrase`. The output is `salt || nonce ||
+/// ciphertext`: both the Argon2 salt and the AES-GCM nonce are random and
+/// prepended, so [`open`] can derive the same key and nonce back out without
+/// anything else being stored alongside the file.
+pub fn seal(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = Aes256Gcm::new_from_slice(&key).expect("derived key is the right length");
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|_| Error::Seal)?;
+
+    let mut sealed = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce_bytes);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(sealed)
+}
+
+/// Open a blob produced by [`seal`], returning the original plaintext.
+pub fn open(passphrase: &str, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(Error::Truncated);
+    }
+
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce_bytes, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt)?;
+    let cipher = Aes256Gcm::new_from_slice(&key).expect("derived key is the right length");
+    let nonce = Nonce::from_slice(nonce_bytes);
+
+    cipher.decrypt(nonce, ciphertext).map_err(|_| Error::Open)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod seal_and_open {
+        use super::*;
+
+        #[test]
+        fn roundtrips() {
+            let sealed = seal("correct horse battery staple", b"hello world").unwrap();
+            let opened = open("correct horse battery staple", &sealed).unwrap();
+
+            assert_eq!(opened, b"hello world");
+        }
+
+        #[test]
+        fn fails_with_the_wrong_passphrase() {
+            let sealed = seal("correct horse battery staple", b"hello world").unwrap();
+
+            assert!(matches!(open("wrong passphrase", &sealed), Err(Error::Open)));
+        }
+
+        #[test]
+        fn fails_on_truncated_data() {
+            assert!(matches!(
+                open("correct horse battery staple", b"too short"),
+                Err(Error::Truncated)
+            ));
+        }
+
+        #[test]
+        fn uses_a_different_nonce_and_salt_each_time() {
+            let a = seal("correct horse battery staple", b"hello world").unwrap();
+            let b = seal("correct horse battery staple", b"hello world").unwrap();
+
+            assert_ne!(a, b);
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use crate::hlc::Hlc;
-use crate::log::{self, Log, TimestampedOp};
+use crate::log::{self, Landed, Log, TimestampedOp};
 use crate::lww::Lww;
 use crate::op::Op;
 use crate::state::{Ping, State};
+use crate::sync::{self, VersionVector};
 use chrono::{DateTime, Utc};
 use color_eyre::{
     eyre::{OptionExt, WrapErr},
@@ -10,13 +11,22 @@ use color_eyre::{
 };
 use rand_core::RngCore;
 use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct Document {
     log: Log,
     clock: Hlc,
     lambda: Lww<f64>,
-    state: State,
+
+    /// Every op `log` has had folded away by a checkpoint: permanent, and
+    /// never rolled back. `tentative` is rebuilt from this plus `log`
+    /// whenever an op arrives out of HLC order; see `Self::push`.
+    committed: State,
+
+    /// `committed` plus every op still in `log`, applied in HLC order. This
+    /// is the state every read (`current`, `future`, `get_ping`, ...) sees.
+    tentative: State,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -25,20 +35,53 @@ pub enum FillError {
     LogError(#[from] log::Error),
 }
 
+/// The on-disk compacted form of a `Document`: its whole merged `State`
+/// (tail ops included), plus the ops the low-water mark doesn't cover yet
+/// (see `Document::compact`). Loading this is cheaper than `from_ops` once
+/// the log has grown large, since only the tail is replayed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub state: State,
+    pub tail: Vec<TimestampedOp>,
+}
+
 impl Document {
     #[tracing::instrument(skip(log), level = "trace")]
     pub fn from_ops(log: Log) -> Self {
-        let mut state = State::default();
+        let mut tentative = State::default();
 
         for op in log.ops() {
-            state.apply_op(op);
+            tentative.apply_op(op);
         }
 
         Self {
             log,
             clock: Hlc::new(0), // TODO: allow setting a node ID, maybe from log?
             lambda: Lww::new(1.0 / 45.0),
-            state,
+            committed: State::default(),
+            tentative,
+        }
+    }
+
+    /// Rebuild a `Document` from a compacted `Snapshot`: its `state` becomes
+    /// `committed`, and `tentative` is that plus its tail replayed on top,
+    /// instead of the whole log. `state` already reflects the tail ops too,
+    /// so `committed` includes them, and replaying them changes nothing.
+    #[tracing::instrument(skip(snapshot), level = "trace")]
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        let committed = snapshot.state;
+        let mut tentative = committed.clone();
+
+        for op in &snapshot.tail {
+            tentative.apply_op(op);
+        }
+
+        Self {
+            log: Log::from_ops(snapshot.tail),
+            clock: Hlc::new(0), // TODO: allow setting a node ID, maybe from log?
+            lambda: Lww::new(1.0 / 45.0),
+            committed,
+            tentative,
         }
     }
 
@@ -50,7 +93,7 @@ impl Document {
     pub fn fill(&mut self, wall_clock: impl WallClock) -> Result<(), FillError> {
         let now = wall_clock.now();
 
-        if self.state.pings.is_empty() {
+        if self.tentative.pings.is_empty() {
             tracing::debug!(when = ?now, "pings is empty, adding initial ping");
             self.add_ping(&now).map_err(FillError::LogError)?;
         }
@@ -92,15 +135,42 @@ impl Document {
     }
 
     fn latest(&self) -> Option<&Ping> {
-        self.state.latest()
+        self.tentative.latest()
     }
 
     pub fn current(&self) -> Option<&Ping> {
-        self.state.current()
+        self.tentative.current()
     }
 
     pub fn future(&self) -> Option<&Ping> {
-        self.state.future()
+        self.tentative.future()
+    }
+
+    /// Push `op` onto the log and keep `tentative` in sync with wherever it
+    /// landed. Landing in order (the common case) is cheap: just apply it on
+    /// top of what we already had. An out-of-order arrival means `tentative`
+    /// was built in the wrong HLC order, so roll it back to `committed` and
+    /// replay the whole (now correctly sorted) log deterministically —
+    /// reaching the same state a per-op undo log would have, since every
+    /// `Op` this crate has is idempotent and order-independent to apply.
+    fn push(&mut self, op: TimestampedOp) -> Result<(), log::Error> {
+        match self.log.push(op)? {
+            Landed::InOrder => {
+                let op = self.log.ops().last().expect("an op was just pushed");
+                self.tentative.apply_op(op);
+            }
+
+            Landed::OutOfOrder => {
+                self.tentative = self.committed.clone();
+                for op in self.log.ops() {
+                    self.tentative.apply_op(op);
+                }
+            }
+
+            Landed::Duplicate => {}
+        }
+
+        Ok(())
     }
 
     #[tracing::instrument(skip(self))]
@@ -109,15 +179,10 @@ impl Document {
 
         self.clock = self.clock.next(self.clock.node);
 
-        let op = TimestampedOp {
+        self.push(TimestampedOp {
             timestamp: self.clock.clone(),
             op: Op::AddPing { when: *when },
-        };
-
-        self.state.apply_op(&op);
-        self.log.push(op)?;
-
-        Ok(())
+        })
     }
 
     #[tracing::instrument(skip(self))]
@@ -125,7 +190,7 @@ impl Document {
         tracing::debug!("setting tag"); // arguments are added by tracing::instrument
 
         let ping = self
-            .state
+            .tentative
             .get_ping(when)
             .ok_or_eyre("provided ping does not exist")?;
 
@@ -133,20 +198,124 @@ impl Document {
             .clock
             .next_tiebreak(ping.tag.timestamp(), self.clock.node);
 
-        let op = TimestampedOp {
+        self.push(TimestampedOp {
             timestamp: self.clock.clone(),
             op: Op::SetTag { when: *when, tag },
-        };
+        })
+        .wrap_err("could not push operation")
+    }
 
-        self.state.apply_op(&op);
-        self.log.push(op).wrap_err("could not push operation")?;
+    #[tracing::instrument(skip(self))]
+    pub fn clear_tag(&mut self, when: &DateTime<Utc>) -> Result<()> {
+        tracing::debug!("clearing tag");
 
-        Ok(())
+        let ping = self
+            .tentative
+            .get_ping(when)
+            .ok_or_eyre("provided ping does not exist")?;
+
+        self.clock = self
+            .clock
+            .next_tiebreak(ping.tag.timestamp(), self.clock.node);
+
+        self.push(TimestampedOp {
+            timestamp: self.clock.clone(),
+            op: Op::ClearTag { when: *when },
+        })
+        .wrap_err("could not push operation")
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn remove_ping(&mut self, when: &DateTime<Utc>) -> Result<()> {
+        tracing::debug!("removing ping");
+
+        let ping = self
+            .tentative
+            .get_ping(when)
+            .ok_or_eyre("provided ping does not exist")?;
+
+        self.clock = self
+            .clock
+            .next_tiebreak(ping.deleted.timestamp(), self.clock.node);
+
+        self.push(TimestampedOp {
+            timestamp: self.clock.clone(),
+            op: Op::RemovePing { when: *when },
+        })
+        .wrap_err("could not push operation")
     }
 
     pub fn log(&self) -> &Log {
         &self.log
     }
+
+    /// The highest counter we've applied from each node, for use in a sync
+    /// exchange with a peer.
+    pub fn version_vector(&self) -> VersionVector {
+        sync::version_vector(&self.log)
+    }
+
+    /// Every op we have that `vector` doesn't dominate, to send to a peer
+    /// during a sync exchange.
+    pub fn missing_since(&self, vector: &VersionVector) -> Vec<TimestampedOp> {
+        sync::missing_since(&self.log, vector)
+    }
+
+    /// Apply ops received from a peer during a sync exchange. Ops we already
+    /// have are skipped rather than erroring, since a peer may send more
+    /// than we're strictly missing.
+    pub fn merge_ops(&mut self, ops: Vec<TimestampedOp>) -> Result<(), log::Error> {
+        let mut vector = self.version_vector();
+
+        for op in ops {
+            if sync::covers(&vector, &op.timestamp) {
+                continue;
+            }
+
+            vector.insert(op.timestamp.node, op.timestamp.counter);
+            self.push(op)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fold the causally-stable prefix of the log (every op dominated by
+    /// `low_water_mark`, i.e. already acknowledged by every known replica)
+    /// into `tentative`, keeping only the still-unstable tail. `tentative`
+    /// already reflects the winning value of any dropped `SetTag`, since it
+    /// only keeps the latest tag per ping, so the merged result is unchanged
+    /// for any replica at or above `low_water_mark`.
+    ///
+    /// Dropping ops below a replica's own watermark would be unsafe: it may
+    /// not have seen them yet, so the caller must compute `low_water_mark`
+    /// from every known replica's version vector, not just this document's.
+    pub fn compact(&self, low_water_mark: &VersionVector) -> Snapshot {
+        let tail = self
+            .log
+            .ops()
+            .iter()
+            .filter(|op| !sync::covers(low_water_mark, &op.timestamp))
+            .cloned()
+            .collect();
+
+        Snapshot {
+            state: self.tentative.clone(),
+            tail,
+        }
+    }
+
+    /// Fold every op at or before `horizon` into `committed`, permanently
+    /// advancing the watermark below which `tentative` can never be rolled
+    /// back: once an op crosses it, `log` drops it for good. `tentative`
+    /// doesn't need to change here, since it already reflects every op ever
+    /// pushed, checkpointed or not.
+    ///
+    /// Only safe with a `horizon` old enough that no in-flight op (ours or a
+    /// peer's) could still show up with an earlier timestamp; see
+    /// `Log::checkpoint`.
+    pub fn checkpoint(&mut self, horizon: DateTime<Utc>) {
+        self.log.checkpoint(horizon, &mut self.committed);
+    }
 }
 
 trait WallClock {
@@ -191,7 +360,7 @@ mod test {
 
             doc.fill(Utc).unwrap();
 
-            assert_eq!(doc.state.pings.len(), 2);
+            assert_eq!(doc.tentative.pings.len(), 2);
         }
 
         #[test]
@@ -205,7 +374,7 @@ mod test {
 
             doc.fill(clock).unwrap();
 
-            assert_eq!(doc.state.pings.len(), 1);
+            assert_eq!(doc.tentative.pings.len(), 1);
         }
 
         #[test]
@@ -283,8 +452,8 @@ mod test {
             let now = Utc::now();
             doc.add_ping(&now).unwrap();
 
-            assert_eq!(doc.state.pings.len(), 1);
-            assert_eq!(*doc.state.pings[&now].tag, None);
+            assert_eq!(doc.tentative.pings.len(), 1);
+            assert_eq!(*doc.tentative.pings[&now].tag, None);
         }
 
         #[test]
@@ -311,7 +480,7 @@ mod test {
 
             doc.set_tag(&now, "test".to_string()).unwrap();
 
-            assert_eq!(*doc.state.pings[&now].tag, Some("test".to_string()));
+            assert_eq!(*doc.tentative.pings[&now].tag, Some("test".to_string()));
         }
 
         #[test]
@@ -339,4 +508,144 @@ mod test {
             assert!(doc.clock > orig_clock, "{:?} <= {orig_clock:?}", doc.clock);
         }
     }
+
+    mod merge_ops {
+        use super::*;
+
+        #[test]
+        fn reconciles_an_op_that_arrives_out_of_hlc_order() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+
+            let t1 = Hlc::new_at(1, Utc::now());
+            let t2 = Hlc::new_at(2, t1.timestamp + chrono::Duration::seconds(1));
+
+            // The newer op arrives first, then the older one: the older one
+            // lands out of HLC order, so `tentative` is rolled back and the
+            // log replayed in HLC order, and the newer tag still wins.
+            doc.merge_ops(vec![TimestampedOp {
+                timestamp: t2.clone(),
+                op: Op::SetTag {
+                    when: now,
+                    tag: "newer".to_string(),
+                },
+            }])
+            .unwrap();
+
+            doc.merge_ops(vec![TimestampedOp {
+                timestamp: t1,
+                op: Op::SetTag {
+                    when: now,
+                    tag: "older".to_string(),
+                },
+            }])
+            .unwrap();
+
+            assert!(doc.log.ops().windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+            assert_eq!(*doc.tentative.pings[&now].tag, Some("newer".to_string()));
+        }
+
+        #[test]
+        fn skips_ops_already_covered_by_our_version_vector() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+
+            let ops_before = doc.log.len();
+            let already_seen = doc.log.ops().first().unwrap().clone();
+
+            doc.merge_ops(vec![already_seen]).unwrap();
+
+            assert_eq!(doc.log.len(), ops_before);
+        }
+    }
+
+    mod compact {
+        use super::*;
+
+        #[test]
+        fn drops_ops_covered_by_the_low_water_mark() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+            doc.set_tag(&now, "test".to_string()).unwrap();
+
+            let low_water_mark = doc.version_vector();
+            let snapshot = doc.compact(&low_water_mark);
+
+            assert!(snapshot.tail.is_empty());
+            assert_eq!(*snapshot.state.pings[&now].tag, Some("test".to_string()));
+        }
+
+        #[test]
+        fn keeps_ops_not_yet_covered() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+
+            let snapshot = doc.compact(&VersionVector::new());
+
+            assert_eq!(snapshot.tail.len(), doc.log.len());
+        }
+    }
+
+    mod checkpoint {
+        use super::*;
+
+        #[test]
+        fn drops_ops_at_or_before_the_horizon() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+            doc.set_tag(&now, "test".to_string()).unwrap();
+
+            // The horizon is compared with each op's HLC timestamp, which is
+            // taken after `now`, so checkpoint at a time after both ops.
+            doc.checkpoint(Utc::now());
+
+            assert!(doc.log.is_empty());
+        }
+
+        #[test]
+        fn does_not_change_what_state_reports() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+            doc.set_tag(&now, "test".to_string()).unwrap();
+
+            doc.checkpoint(Utc::now());
+
+            assert_eq!(*doc.tentative.pings[&now].tag, Some("test".to_string()));
+        }
+
+        #[test]
+        fn keeps_ops_after_the_horizon() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+
+            doc.checkpoint(now - chrono::Duration::seconds(1));
+
+            assert_eq!(doc.log.len(), 1);
+        }
+    }
+
+    mod from_snapshot {
+        use super::*;
+
+        #[test]
+        fn replays_only_the_tail() {
+            let mut doc = Document::empty();
+            let now = Utc::now();
+            doc.add_ping(&now).unwrap();
+            doc.set_tag(&now, "test".to_string()).unwrap();
+
+            let snapshot = doc.compact(&doc.version_vector());
+            let reloaded = Document::from_snapshot(snapshot);
+
+            assert_eq!(*reloaded.tentative.pings[&now].tag, Some("test".to_string()));
+            assert!(reloaded.log.is_empty());
+        }
+    }
 }
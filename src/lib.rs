@@ -0,0 +1,8 @@
+pub mod crypto;
+pub mod document;
+pub mod hlc;
+pub mod log;
+pub mod lww;
+pub mod op;
+pub mod state;
+pub mod sync;
@@ -1,6 +1,9 @@
 use crate::hlc::Hlc;
 use crate::op::Op;
+use crate::state::State;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TimestampedOp {
@@ -10,18 +13,48 @@ pub struct TimestampedOp {
 
 #[derive(thiserror::Error, Debug, PartialEq, Eq)]
 pub enum Error {
-    #[error("new operation was before last existing operation")]
-    OrderingViolation,
+    #[error("operation is older than this log's checkpointed frontier")]
+    Checkpointed,
+}
+
+/// Where `Log::push` placed an incoming op relative to what was already
+/// buffered, so a caller keeping a separately-materialized state (see
+/// `Document`) knows whether it can just apply the new op on top of what it
+/// had, or needs to rebuild from scratch against the corrected order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Landed {
+    /// Went on the end: the newest op this log has seen so far.
+    InOrder,
+
+    /// Landed before at least one op already buffered. A tentative state
+    /// built by applying ops as they arrived is now stale in HLC order and
+    /// needs rebuilding from the committed baseline plus the whole log.
+    OutOfOrder,
+
+    /// An op with this exact HLC was already present. `push` didn't change
+    /// anything; applying an op is idempotent, so there's nothing to redo.
+    Duplicate,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Log {
+    /// Always kept sorted by `TimestampedOp::timestamp`, so a peer's ops can
+    /// arrive in any order and still end up applied in HLC order.
     ops: Vec<TimestampedOp>,
+
+    /// Per node, the timestamp of the newest op `checkpoint` has folded away
+    /// and dropped. `push` rejects anything at or below a node's floor here,
+    /// since it's no longer in `ops` to dedupe against and would otherwise
+    /// let a stale duplicate back in.
+    floor: HashMap<u8, Hlc>,
 }
 
 impl Log {
     pub fn from_ops(ops: Vec<TimestampedOp>) -> Self {
-        Self { ops }
+        Self {
+            ops,
+            floor: HashMap::new(),
+        }
     }
 
     #[deprecated(note = "use from_ops and then checked pushes")]
@@ -29,20 +62,39 @@ impl Log {
         self.ops.push(op);
     }
 
-    pub fn push(&mut self, op: TimestampedOp) -> Result<(), Error> {
-        if let Some(last_op) = self.latest_for_node(op.timestamp.node) {
-            if last_op.timestamp > op.timestamp {
-                return Err(Error::OrderingViolation);
+    /// Insert `op` at its correct position in HLC order, dropping it if an
+    /// op with the same timestamp is already present (apply is idempotent,
+    /// so there's nothing new to insert). See `Landed` for what the result
+    /// means to a caller maintaining a materialized state alongside this
+    /// log.
+    pub fn push(&mut self, op: TimestampedOp) -> Result<Landed, Error> {
+        if let Some(floor) = self.floor.get(&op.timestamp.node) {
+            if *floor >= op.timestamp {
+                return Err(Error::Checkpointed);
             }
         }
 
-        self.ops.push(op);
+        let position = self
+            .ops
+            .partition_point(|existing| existing.timestamp < op.timestamp);
 
-        Ok(())
-    }
+        if self
+            .ops
+            .get(position)
+            .is_some_and(|existing| existing.timestamp == op.timestamp)
+        {
+            return Ok(Landed::Duplicate);
+        }
 
-    fn latest_for_node(&self, node: u8) -> Option<&TimestampedOp> {
-        self.ops.iter().rev().find(|op| op.timestamp.node == node)
+        let landed = if position == self.ops.len() {
+            Landed::InOrder
+        } else {
+            Landed::OutOfOrder
+        };
+
+        self.ops.insert(position, op);
+
+        Ok(landed)
     }
 
     pub fn ops(&self) -> &Vec<TimestampedOp> {
@@ -56,6 +108,50 @@ impl Log {
     pub fn is_empty(&self) -> bool {
         self.ops.is_empty()
     }
+
+    /// Fold every op at or before `horizon` into `committed`, dropping them
+    /// from the log in place and keeping only the still-unstable tail.
+    /// `State::apply_op` is commutative and idempotent (plain `AddPing`s and
+    /// LWW `SetTag`s), so folding them in is safe regardless of the order
+    /// they were originally received in, or what `committed` already has
+    /// folded into it from an earlier checkpoint.
+    ///
+    /// This is the "committed-sequence watermark" in the op-log replication
+    /// scheme: once an op crosses it, it's permanent, and `Document` can
+    /// always get back to it by rolling `tentative` back to `committed`
+    /// instead of keeping a separate undo record per op.
+    ///
+    /// Raises each folded op's node's floor to the newest timestamp seen for
+    /// it here, so a late, stale push for that node can't sneak back in once
+    /// the op it would have conflicted with is gone; see `Self::push`.
+    ///
+    /// Only safe to call with a `horizon` old enough that no op still in
+    /// flight (from this node or a peer we haven't synced with yet) could
+    /// arrive with an earlier timestamp: once an op is folded in here, it's
+    /// gone, and a late arrival older than `horizon` would have no op left
+    /// to order itself against.
+    pub fn checkpoint(&mut self, horizon: DateTime<Utc>, committed: &mut State) {
+        let mut tail = Vec::new();
+
+        for op in self.ops.drain(..) {
+            if op.timestamp.timestamp <= horizon {
+                self.floor
+                    .entry(op.timestamp.node)
+                    .and_modify(|floor: &mut Hlc| {
+                        if *floor < op.timestamp {
+                            *floor = op.timestamp.clone();
+                        }
+                    })
+                    .or_insert_with(|| op.timestamp.clone());
+
+                committed.apply_op(&op);
+            } else {
+                tail.push(op);
+            }
+        }
+
+        self.ops = tail;
+    }
 }
 
 #[cfg(test)]
@@ -84,7 +180,7 @@ mod test {
         }
 
         #[test]
-        fn rejects_out_of_order_pushes() {
+        fn inserts_an_out_of_order_push_before_the_newer_op() {
             let mut log = Log::default();
 
             let ts1 = Utc::now();
@@ -104,8 +200,26 @@ mod test {
                 },
             };
 
-            assert!(log.push(op1).is_ok());
-            assert_eq!(log.push(op2).unwrap_err(), Error::OrderingViolation);
+            assert_eq!(log.push(op1).unwrap(), Landed::InOrder);
+            assert_eq!(log.push(op2.clone()).unwrap(), Landed::OutOfOrder);
+            assert_eq!(log.ops[0].timestamp, op2.timestamp);
+        }
+
+        #[test]
+        fn dedupes_a_push_with_an_hlc_already_present() {
+            let mut log = Log::default();
+
+            let op = TimestampedOp {
+                timestamp: Hlc::new(1),
+                op: Op::SetTag {
+                    when: Utc::now(),
+                    tag: "tag".to_string(),
+                },
+            };
+
+            assert_eq!(log.push(op.clone()).unwrap(), Landed::InOrder);
+            assert_eq!(log.push(op).unwrap(), Landed::Duplicate);
+            assert_eq!(log.ops.len(), 1);
         }
 
         #[test]
@@ -133,4 +247,101 @@ mod test {
             assert!(log.push(op2).is_ok());
         }
     }
+
+    mod checkpoint {
+        use super::*;
+
+        #[test]
+        fn folds_ops_at_or_before_the_horizon_into_committed() {
+            let mut log = Log::default();
+            let when = Utc::now();
+
+            log.push(TimestampedOp {
+                timestamp: Hlc::new_at(1, when),
+                op: Op::AddPing { when },
+            })
+            .unwrap();
+
+            let mut committed = State::default();
+            log.checkpoint(when, &mut committed);
+
+            assert_eq!(committed.pings.len(), 1);
+            assert!(log.is_empty());
+        }
+
+        #[test]
+        fn keeps_ops_after_the_horizon_in_the_log() {
+            let mut log = Log::default();
+            let when = Utc::now();
+
+            log.push(TimestampedOp {
+                timestamp: Hlc::new_at(1, when),
+                op: Op::AddPing { when },
+            })
+            .unwrap();
+
+            let mut committed = State::default();
+            log.checkpoint(when - Duration::seconds(1), &mut committed);
+
+            assert_eq!(committed.pings.len(), 0);
+            assert_eq!(log.len(), 1);
+        }
+
+        #[test]
+        fn checkpointing_twice_folds_both_rounds_into_the_same_committed_state() {
+            let when1 = Utc::now();
+            let when2 = when1 + Duration::minutes(5);
+
+            let mut whole = State::default();
+            whole.apply_op(&TimestampedOp {
+                timestamp: Hlc::new_at(1, when1),
+                op: Op::AddPing { when: when1 },
+            });
+            whole.apply_op(&TimestampedOp {
+                timestamp: Hlc::new_at(1, when2),
+                op: Op::AddPing { when: when2 },
+            });
+
+            let mut log = Log::default();
+            log.push(TimestampedOp {
+                timestamp: Hlc::new_at(1, when1),
+                op: Op::AddPing { when: when1 },
+            })
+            .unwrap();
+            log.push(TimestampedOp {
+                timestamp: Hlc::new_at(1, when2),
+                op: Op::AddPing { when: when2 },
+            })
+            .unwrap();
+
+            let mut committed = State::default();
+            log.checkpoint(when1, &mut committed);
+            log.checkpoint(when2, &mut committed);
+
+            assert_eq!(committed.pings.len(), whole.pings.len());
+            for (when, ping) in &whole.pings {
+                assert_eq!(committed.pings.get(when).map(|p| &p.tag), Some(&ping.tag));
+            }
+        }
+
+        #[test]
+        fn rejects_a_push_at_or_below_a_checkpointed_nodes_floor() {
+            let mut log = Log::default();
+            let when = Utc::now();
+
+            log.push(TimestampedOp {
+                timestamp: Hlc::new_at(1, when),
+                op: Op::AddPing { when },
+            })
+            .unwrap();
+            log.checkpoint(when, &mut State::default());
+
+            let stale = TimestampedOp {
+                timestamp: Hlc::new_at(1, when - Duration::seconds(1)),
+                op: Op::AddPing { when },
+            };
+
+            assert_eq!(log.push(stale).unwrap_err(), Error::Checkpointed);
+        }
+    }
 }
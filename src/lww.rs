@@ -1,6 +1,7 @@
 use std::ops::Deref;
 
 use crate::hlc::Hlc;
+use serde::{Deserialize, Serialize};
 
 /// A last-write-wins register. The lifecycle goes like this:
 ///
@@ -10,7 +11,7 @@ use crate::hlc::Hlc;
 /// 2. Update the register with a new value and a timestamp. If the timestamp
 ///    is "later" than the current timestamp (or the current timestamp is
 ///    blank) then the new value will be used.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Lww<T> {
     value: T,
     timestamp: Option<Hlc>,
@@ -8,4 +8,8 @@ pub enum Op {
 
     // Tags
     SetTag { when: DateTime<Utc>, tag: String },
+    ClearTag { when: DateTime<Utc> },
+
+    // Pings
+    RemovePing { when: DateTime<Utc> },
 }
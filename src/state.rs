@@ -2,9 +2,13 @@ use crate::log::TimestampedOp;
 use crate::lww::Lww;
 use crate::op::Op;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Default)]
+/// The merged view of every op applied so far. This is what a compacted
+/// snapshot serializes, so that `Document::from_snapshot` doesn't need to
+/// replay the whole log to rebuild it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct State {
     pub pings: HashMap<DateTime<Utc>, Ping>,
 }
@@ -23,6 +27,21 @@ impl State {
                 let ping = self.add_ping(when);
                 ping.tag.update(&op.timestamp, Some(tag.clone()));
             }
+
+            Op::ClearTag { when } => {
+                let ping = self.add_ping(when);
+                ping.tag.update(&op.timestamp, None);
+            }
+
+            Op::RemovePing { when } => {
+                // Tombstone rather than remove the entry: this makes the
+                // removal a fact about `when` that sticks around, so a
+                // `AddPing` for the same `when` that's merged in later (from
+                // a peer that raced the removal) can't undo it by finding
+                // the key absent and re-inserting a fresh, undeleted `Ping`.
+                let ping = self.add_ping(when);
+                ping.deleted.update(&op.timestamp, true);
+            }
         }
     }
 
@@ -31,12 +50,17 @@ impl State {
         self.pings.entry(*when).or_insert(Ping {
             time: *when,
             tag: Lww::new(None),
+            deleted: Lww::new(false),
         })
     }
 
     #[tracing::instrument(skip(self))]
     pub fn latest(&self) -> Option<&Ping> {
-        self.pings.iter().max_by_key(|(k, _)| *k).map(|(_, v)| v)
+        self.pings
+            .iter()
+            .filter(|(_, v)| !*v.deleted)
+            .max_by_key(|(k, _)| *k)
+            .map(|(_, v)| v)
     }
 
     #[tracing::instrument(skip(self))]
@@ -45,7 +69,7 @@ impl State {
 
         self.pings
             .iter()
-            .filter(|(_, v)| v.time <= now)
+            .filter(|(_, v)| v.time <= now && !*v.deleted)
             .max_by_key(|(k, _)| *k)
             .map(|(_, v)| v)
     }
@@ -56,21 +80,26 @@ impl State {
 
         self.pings
             .iter()
-            .filter(|(_, v)| v.time > now)
+            .filter(|(_, v)| v.time > now && !*v.deleted)
             .max_by_key(|(k, _)| *k)
             .map(|(_, v)| v)
     }
 
     #[tracing::instrument(skip(self))]
     pub fn get_ping(&self, when: &DateTime<Utc>) -> Option<&Ping> {
-        self.pings.get(when)
+        self.pings.get(when).filter(|p| !*p.deleted)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Ping {
     pub time: DateTime<Utc>,
     pub tag: Lww<Option<String>>,
+
+    /// Whether this ping has been removed. A tombstone rather than an
+    /// absent entry, so the removal survives merging in an older `AddPing`
+    /// for the same `when`; see `State::apply_op`'s `RemovePing` handling.
+    pub deleted: Lww<bool>,
 }
 
 impl Default for Ping {
@@ -78,6 +107,7 @@ impl Default for Ping {
         Self {
             time: Utc::now(),
             tag: Lww::new(None),
+            deleted: Lww::new(false),
         }
     }
 }
@@ -140,6 +170,61 @@ mod test {
                 Some("test".into())
             )
         }
+
+        #[test]
+        fn clear_tag() {
+            let mut state = State::default();
+            let when = Utc::now();
+
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0),
+                op: Op::SetTag {
+                    when,
+                    tag: "test".into(),
+                },
+            });
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0).next(0),
+                op: Op::ClearTag { when },
+            });
+
+            assert_eq!(state.pings.get(&when).and_then(|p| p.tag.clone()), None)
+        }
+
+        #[test]
+        fn remove_ping() {
+            let mut state = State::default();
+            let when = Utc::now();
+
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0),
+                op: Op::AddPing { when },
+            });
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0).next(0),
+                op: Op::RemovePing { when },
+            });
+
+            assert!(state.get_ping(&when).is_none());
+            assert_eq!(state.pings.len(), 1);
+        }
+
+        #[test]
+        fn remove_ping_before_add_ping_still_sticks() {
+            let mut state = State::default();
+            let when = Utc::now();
+
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0),
+                op: Op::RemovePing { when },
+            });
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0).next(0),
+                op: Op::AddPing { when },
+            });
+
+            assert!(state.get_ping(&when).is_none());
+        }
     }
 
     mod latest {
@@ -163,6 +248,28 @@ mod test {
 
             assert_eq!(state.latest().map(|p| p.time), Some(later));
         }
+
+        #[test]
+        fn skips_a_removed_ping() {
+            let mut state = State::default();
+            let now = Utc::now();
+            let later = now + chrono::Duration::seconds(1);
+
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0),
+                op: Op::AddPing { when: now },
+            });
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0),
+                op: Op::AddPing { when: later },
+            });
+            state.apply_op(&TimestampedOp {
+                timestamp: Hlc::new(0).next(0),
+                op: Op::RemovePing { when: later },
+            });
+
+            assert_eq!(state.latest().map(|p| p.time), Some(now));
+        }
     }
 
     mod current {
@@ -0,0 +1,201 @@
+use crate::hlc::Hlc;
+use crate::log::{Log, TimestampedOp};
+use std::collections::HashMap;
+
+/// The highest HLC counter we've applied from each node, so a peer can ask
+/// for only the `TimestampedOp`s it's missing instead of the whole `Log`.
+///
+/// A node absent from the vector covers none of that node's ops, i.e. "send
+/// everything you have for that node."
+pub type VersionVector = HashMap<u8, u64>;
+
+/// Fold over `log.ops()` and record the highest counter seen for each node.
+pub fn version_vector(log: &Log) -> VersionVector {
+    let mut vector = VersionVector::with_capacity(8);
+
+    for op in log.ops() {
+        vector
+            .entry(op.timestamp.node)
+            .and_modify(|counter| *counter = (*counter).max(op.timestamp.counter))
+            .or_insert(op.timestamp.counter);
+    }
+
+    vector
+}
+
+/// Whether `vector` already covers `timestamp`, i.e. its node has a recorded
+/// counter at or beyond `timestamp`'s own counter.
+pub(crate) fn covers(vector: &VersionVector, timestamp: &Hlc) -> bool {
+    vector
+        .get(&timestamp.node)
+        .is_some_and(|&counter| timestamp.counter <= counter)
+}
+
+/// The element-wise minimum counter per node across `vectors`, i.e. the
+/// highest point every known replica has acknowledged. A node missing from
+/// any one vector contributes a 0 for that node, since that replica hasn't
+/// heard of it yet.
+///
+/// Ops at or below this mark can never affect a future merge with any of
+/// these replicas, so they're safe for [`crate::document::Document::compact`]
+/// to drop.
+pub fn low_water_mark(vectors: &[VersionVector]) -> VersionVector {
+    let nodes = vectors.iter().flat_map(VersionVector::keys).copied();
+
+    nodes
+        .map(|node| {
+            let min = vectors
+                .iter()
+                .map(|vector| vector.get(&node).copied().unwrap_or(0))
+                .min()
+                .unwrap_or(0);
+
+            (node, min)
+        })
+        .collect()
+}
+
+/// Every op in `log` whose `(node, counter)` isn't dominated by `vector`.
+///
+/// Run this on both sides of a sync: the server calls it with the client's
+/// vector to find what to send down, and the client calls it with the
+/// server's vector to find what to send up. Because `State::apply_op` is
+/// idempotent, it's always safe to send an op the peer turns out to already
+/// have.
+pub fn missing_since(log: &Log, vector: &VersionVector) -> Vec<TimestampedOp> {
+    log.ops()
+        .iter()
+        .filter(|op| !covers(vector, &op.timestamp))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::op::Op;
+    use chrono::{Duration, Utc};
+
+    fn op(node: u8, counter: u64) -> TimestampedOp {
+        TimestampedOp {
+            timestamp: Hlc {
+                timestamp: Utc::now(),
+                counter,
+                node,
+            },
+            op: Op::AddPing { when: Utc::now() },
+        }
+    }
+
+    mod version_vector {
+        use super::*;
+
+        #[test]
+        fn empty_log_has_empty_vector() {
+            assert_eq!(version_vector(&Log::default()), VersionVector::new());
+        }
+
+        #[test]
+        fn tracks_the_highest_counter_per_node() {
+            let mut log = Log::default();
+            log.push(op(1, 0)).unwrap();
+            log.push(op(1, 1)).unwrap();
+            log.push(op(2, 0)).unwrap();
+
+            let vector = version_vector(&log);
+
+            assert_eq!(vector.get(&1), Some(&1));
+            assert_eq!(vector.get(&2), Some(&0));
+        }
+    }
+
+    mod low_water_mark {
+        use super::*;
+
+        #[test]
+        fn takes_the_minimum_across_replicas() {
+            let a = VersionVector::from([(1, 5), (2, 3)]);
+            let b = VersionVector::from([(1, 2), (2, 9)]);
+
+            let mark = low_water_mark(&[a, b]);
+
+            assert_eq!(mark.get(&1), Some(&2));
+            assert_eq!(mark.get(&2), Some(&3));
+        }
+
+        #[test]
+        fn a_node_missing_from_one_replica_pins_the_mark_at_zero() {
+            let a = VersionVector::from([(1, 5)]);
+            let b = VersionVector::new();
+
+            let mark = low_water_mark(&[a, b]);
+
+            assert_eq!(mark.get(&1), Some(&0));
+        }
+
+        #[test]
+        fn no_replicas_means_no_mark() {
+            assert_eq!(low_water_mark(&[]), VersionVector::new());
+        }
+    }
+
+    mod missing_since {
+        use super::*;
+
+        #[test]
+        fn an_unknown_node_sends_everything_for_it() {
+            let mut log = Log::default();
+            log.push(op(1, 0)).unwrap();
+
+            let missing = missing_since(&log, &VersionVector::new());
+
+            assert_eq!(missing.len(), 1);
+        }
+
+        #[test]
+        fn a_covered_node_sends_only_whats_newer() {
+            let mut log = Log::default();
+            log.push(op(1, 0)).unwrap();
+            log.push(op(1, 1)).unwrap();
+
+            let vector = VersionVector::from([(1, 0)]);
+            let missing = missing_since(&log, &vector);
+
+            assert_eq!(missing.len(), 1);
+            assert_eq!(missing[0].timestamp.counter, 1);
+        }
+
+        #[test]
+        fn resending_an_already_applied_op_is_a_no_op_to_recompute() {
+            let mut log = Log::default();
+            log.push(op(1, 0)).unwrap();
+
+            let vector = version_vector(&log);
+            let missing = missing_since(&log, &vector);
+
+            assert!(missing.is_empty());
+        }
+
+        #[test]
+        fn ties_break_on_the_existing_node_order() {
+            // Two ops with the same (timestamp, counter) but different nodes
+            // are different entries in the vector, so they're compared
+            // independently rather than needing a special tiebreak here -
+            // `Hlc`'s own `Ord` impl is what breaks ties when ops are
+            // otherwise concurrent.
+            let ts = Utc::now() - Duration::seconds(1);
+            let a = Hlc {
+                timestamp: ts,
+                counter: 0,
+                node: 1,
+            };
+            let b = Hlc {
+                timestamp: ts,
+                counter: 0,
+                node: 2,
+            };
+
+            assert!(a < b);
+        }
+    }
+}
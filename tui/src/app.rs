@@ -77,6 +77,24 @@ impl App {
 
                 None
             }
+            Action::Key(key)
+                if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('p') =>
+            {
+                match std::mem::replace(&mut self.state, AppState::Unloaded) {
+                    AppState::Loaded(replica) => Some(Effect::Pull(replica)),
+                    other => {
+                        self.state = other;
+
+                        None
+                    }
+                }
+            }
+            Action::Pulled(replica) => {
+                self.state = AppState::Loaded(replica);
+                self.status_line = Some("Pulled from peer".to_owned());
+
+                None
+            }
             Action::Key(key) => {
                 self.status_line = Some(format!("Unknown key {key:?}"));
 
@@ -105,6 +123,9 @@ pub enum Action {
     /// We loaded replica data from disk
     LoadedReplica(Replica),
 
+    /// We pulled whatever a peer had that we didn't, and merged it in
+    Pulled(Replica),
+
     /// The user did something on the keyboard
     Key(KeyEvent),
 
@@ -116,17 +137,22 @@ pub enum Action {
 pub enum Effect {
     /// Load replica state from disk
     Load,
+
+    /// Pull whatever a peer has that we're missing, and merge it into our
+    /// replica. Only the ops our version vector doesn't already cover are
+    /// applied, but the peer's whole stored replica is read to find them.
+    Pull(Replica),
 }
 
 impl Effect {
-    pub async fn run(&self, config: Arc<Config>) -> Action {
+    pub async fn run(self, config: Arc<Config>) -> Action {
         match self.run_inner(config).await {
             Ok(action) => action,
             Err(problem) => Action::Problem(problem.to_string()),
         }
     }
 
-    async fn run_inner(&self, config: Arc<Config>) -> Result<Action, io::Error> {
+    async fn run_inner(self, config: Arc<Config>) -> Result<Action, io::Error> {
         match self {
             Self::Load => {
                 let base = config.data_dir();
@@ -146,6 +172,31 @@ impl Effect {
                     Ok(Action::LoadedReplica(Replica::new(NodeId::random())))
                 }
             }
+
+            Self::Pull(mut replica) => {
+                let Some(peer_dir) = config.peer_dir() else {
+                    return Ok(Action::Problem(
+                        "no --peer-dir configured to pull from".to_owned(),
+                    ));
+                };
+
+                let store = peer_dir.join("store.json");
+
+                if !fs::try_exists(&store).await? {
+                    return Ok(Action::Problem(format!(
+                        "no peer replica found at {}",
+                        store.display()
+                    )));
+                }
+
+                let data = fs::read(&store).await?;
+                let peer: Replica = serde_json::from_slice(&data)?;
+
+                let ops = peer.missing_since(&replica.version_vector());
+                replica.merge_ops(ops)?;
+
+                Ok(Action::Pulled(replica))
+            }
         }
     }
 }
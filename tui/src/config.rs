@@ -1,5 +1,5 @@
 use clap::Parser;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// A TUI for collecting and tagging pings
 #[derive(Parser)]
@@ -7,6 +7,12 @@ pub struct Config {
     /// Where should we store data?
     #[clap(long)]
     data_dir: Option<PathBuf>,
+
+    /// Where does a peer replica we can sync with keep its data? If set,
+    /// pulling reads that peer's `store.json` and merges in whatever ops
+    /// our version vector shows we're missing.
+    #[clap(long)]
+    peer_dir: Option<PathBuf>,
 }
 
 impl Config {
@@ -19,4 +25,8 @@ impl Config {
             })
             .unwrap_or_else(|| PathBuf::from("."))
     }
+
+    fn peer_dir(&self) -> Option<&Path> {
+        self.peer_dir.as_deref()
+    }
 }